@@ -0,0 +1,9 @@
+-- Migration 010: Subscription Payment Failure Tracking
+-- Records why a subscription renewal failed so the app can warn the user before downgrading
+-- Populated by the invoice.payment_failed webhook handler
+
+ALTER TABLE profiles ADD COLUMN IF NOT EXISTS subscription_payment_failure_reason TEXT;
+ALTER TABLE profiles ADD COLUMN IF NOT EXISTS subscription_payment_failed_at TIMESTAMPTZ;
+
+-- Webhook handlers look profiles up by their Stripe customer
+CREATE INDEX IF NOT EXISTS idx_profiles_stripe_customer_id ON profiles(stripe_customer_id);
@@ -0,0 +1,43 @@
+-- Migration 011: Atomic Token Spending
+-- consume_user_tokens reads the balance before updating it, so two concurrent spends
+-- can both pass the check and push tokens_remaining negative.
+-- spend_user_tokens does the check and the decrement in a single conditional UPDATE.
+
+CREATE OR REPLACE FUNCTION spend_user_tokens(
+    p_user_id UUID,
+    p_token_amount BIGINT,
+    p_description TEXT DEFAULT 'Token usage',
+    p_metadata JSONB DEFAULT '{}'::jsonb
+) RETURNS TABLE (
+    total_tokens BIGINT,
+    tokens_remaining BIGINT,
+    tokens_used BIGINT
+) AS $$
+BEGIN
+    IF p_token_amount IS NULL OR p_token_amount <= 0 THEN
+        RAISE EXCEPTION 'Token amount must be positive, got %', p_token_amount;
+    END IF;
+
+    -- Only matches when the balance covers the spend; the row lock serializes concurrent spends
+    RETURN QUERY
+    UPDATE profiles p
+    SET
+        tokens_remaining = p.tokens_remaining - p_token_amount,
+        tokens_used = COALESCE(p.tokens_used, 0) + p_token_amount,
+        updated_at = NOW()
+    WHERE p.id = p_user_id
+      AND p.tokens_remaining >= p_token_amount
+    RETURNING p.total_tokens, p.tokens_remaining, p.tokens_used;
+
+    -- No row updated means the balance was insufficient (or the profile is missing)
+    IF NOT FOUND THEN
+        RETURN;
+    END IF;
+
+    INSERT INTO user_token_transactions (
+        user_id, transaction_type, token_amount, description, metadata
+    ) VALUES (
+        p_user_id, 'usage', -p_token_amount, p_description, p_metadata
+    );
+END;
+$$ language 'plpgsql';
@@ -0,0 +1,34 @@
+-- Migration 012: Token Ledger
+-- Per-action record of token spends and credits with the resulting balance,
+-- used to render the usage history screen.
+-- Rows are append-only; the profile aggregates remain the source of truth for the balance.
+
+CREATE TABLE IF NOT EXISTS token_ledger (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+
+    amount BIGINT NOT NULL, -- Positive for credits, negative for spends
+    reason TEXT NOT NULL,
+    balance_after BIGINT NOT NULL, -- tokens_remaining after this entry was applied
+
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- History is always read newest first for a single user
+CREATE INDEX IF NOT EXISTS idx_token_ledger_user_created_at ON token_ledger(user_id, created_at DESC);
+
+-- Enable Row Level Security (RLS)
+ALTER TABLE token_ledger ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policies for token_ledger (no update/delete: the ledger is append-only)
+DROP POLICY IF EXISTS "Users can view own token ledger" ON token_ledger;
+CREATE POLICY "Users can view own token ledger" ON token_ledger
+    FOR SELECT USING (auth.uid() = user_id);
+
+DROP POLICY IF EXISTS "Users can insert own token ledger entries" ON token_ledger;
+CREATE POLICY "Users can insert own token ledger entries" ON token_ledger
+    FOR INSERT WITH CHECK (auth.uid() = user_id);
+
+DROP POLICY IF EXISTS "Service role can manage token ledger" ON token_ledger;
+CREATE POLICY "Service role can manage token ledger" ON token_ledger
+    FOR ALL USING (current_setting('role') = 'service_role');
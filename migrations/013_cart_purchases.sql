@@ -0,0 +1,11 @@
+-- Migration 013: Multi-Item Cart Purchases
+-- A cart payment intent pays for several package prices at once and is recorded as one
+-- purchase row per unit, so the purchase stats trigger credits each package's tokens.
+-- Rows for the same payment intent are told apart by line_item_index.
+
+ALTER TABLE purchases ADD COLUMN IF NOT EXISTS line_item_index INTEGER NOT NULL DEFAULT 0;
+
+-- Replace the one-row-per-payment-intent constraint with one row per cart line
+ALTER TABLE purchases DROP CONSTRAINT IF EXISTS purchases_stripe_payment_intent_id_key;
+CREATE UNIQUE INDEX IF NOT EXISTS idx_purchases_payment_intent_line
+    ON purchases(stripe_payment_intent_id, line_item_index);
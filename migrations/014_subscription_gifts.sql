@@ -0,0 +1,41 @@
+-- Migration 014: Gifted Subscriptions
+-- Records a subscription one user paid for on behalf of another.
+-- The gifter is charged once for the first period; the recipient's subscription
+-- is created on their own Stripe customer and ends at the close of that period.
+
+CREATE TABLE IF NOT EXISTS subscription_gifts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    gifter_user_id UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+    recipient_user_id UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+
+    -- Stripe references
+    stripe_subscription_id TEXT UNIQUE NOT NULL, -- Subscription on the recipient's customer
+    stripe_payment_intent_id TEXT UNIQUE NOT NULL, -- Charge on the gifter's payment method
+    stripe_price_id TEXT NOT NULL,
+
+    amount_paid BIGINT NOT NULL, -- Amount in cents charged to the gifter
+    currency TEXT NOT NULL,
+
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+
+    CONSTRAINT no_self_gift CHECK (gifter_user_id <> recipient_user_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_subscription_gifts_gifter ON subscription_gifts(gifter_user_id);
+CREATE INDEX IF NOT EXISTS idx_subscription_gifts_recipient ON subscription_gifts(recipient_user_id);
+
+-- Enable Row Level Security (RLS)
+ALTER TABLE subscription_gifts ENABLE ROW LEVEL SECURITY;
+
+-- Both sides of a gift can see it; only the gifter can create it
+DROP POLICY IF EXISTS "Users can view gifts they sent or received" ON subscription_gifts;
+CREATE POLICY "Users can view gifts they sent or received" ON subscription_gifts
+    FOR SELECT USING (auth.uid() = gifter_user_id OR auth.uid() = recipient_user_id);
+
+DROP POLICY IF EXISTS "Users can insert gifts they pay for" ON subscription_gifts;
+CREATE POLICY "Users can insert gifts they pay for" ON subscription_gifts
+    FOR INSERT WITH CHECK (auth.uid() = gifter_user_id);
+
+DROP POLICY IF EXISTS "Service role can manage subscription gifts" ON subscription_gifts;
+CREATE POLICY "Service role can manage subscription gifts" ON subscription_gifts
+    FOR ALL USING (current_setting('role') = 'service_role');
@@ -0,0 +1,43 @@
+-- Migration 015: Admin Document Review
+-- Adds an admin flag on profiles and lets admins read contractor documents across
+-- all contractors so pending uploads can be worked through in a review queue.
+
+ALTER TABLE profiles ADD COLUMN IF NOT EXISTS is_admin BOOLEAN DEFAULT false;
+
+-- Whether the calling user is an admin. SECURITY DEFINER so RLS policies on other
+-- tables can use it without needing read access to every profile.
+CREATE OR REPLACE FUNCTION is_admin()
+RETURNS BOOLEAN AS $$
+    SELECT COALESCE((SELECT is_admin FROM profiles WHERE id = auth.uid()), false);
+$$ LANGUAGE sql STABLE SECURITY DEFINER SET search_path = public;
+
+-- Users can update their own profile, so stop them from granting themselves admin
+CREATE OR REPLACE FUNCTION prevent_is_admin_self_grant()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.is_admin IS DISTINCT FROM OLD.is_admin AND current_setting('role') != 'service_role' THEN
+        RAISE EXCEPTION 'is_admin can only be changed by the service role';
+    END IF;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+DROP TRIGGER IF EXISTS prevent_is_admin_self_grant ON profiles;
+CREATE TRIGGER prevent_is_admin_self_grant
+    BEFORE UPDATE ON profiles
+    FOR EACH ROW
+    EXECUTE FUNCTION prevent_is_admin_self_grant();
+
+-- Admin read access for the review queue
+DROP POLICY IF EXISTS "Admins can view all document uploads" ON contractor_document_uploads;
+CREATE POLICY "Admins can view all document uploads" ON contractor_document_uploads
+    FOR SELECT USING (is_admin());
+
+DROP POLICY IF EXISTS "Admins can view all contractors" ON contractors;
+CREATE POLICY "Admins can view all contractors" ON contractors
+    FOR SELECT USING (is_admin());
+
+-- Review queue reads pending documents oldest first
+CREATE INDEX IF NOT EXISTS idx_contractor_document_uploads_pending_created_at
+    ON contractor_document_uploads(created_at)
+    WHERE verification_status = 'pending';
@@ -0,0 +1,27 @@
+-- Migration 016: Document Reviewer Tracking
+-- Records which admin approved or rejected a contractor document, and when.
+
+ALTER TABLE contractor_document_uploads ADD COLUMN IF NOT EXISTS reviewed_by UUID REFERENCES auth.users(id) ON DELETE SET NULL;
+ALTER TABLE contractor_document_uploads ADD COLUMN IF NOT EXISTS reviewed_at TIMESTAMPTZ;
+
+-- Admins record review decisions on any contractor's documents
+DROP POLICY IF EXISTS "Admins can review document uploads" ON contractor_document_uploads;
+CREATE POLICY "Admins can review document uploads" ON contractor_document_uploads
+    FOR UPDATE USING (is_admin());
+
+-- The reviewer is whoever is signed in, never a value the client sends
+CREATE OR REPLACE FUNCTION set_document_reviewer()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.reviewed_at IS DISTINCT FROM OLD.reviewed_at OR NEW.reviewed_by IS DISTINCT FROM OLD.reviewed_by THEN
+        NEW.reviewed_by := auth.uid();
+    END IF;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+DROP TRIGGER IF EXISTS set_document_reviewer ON contractor_document_uploads;
+CREATE TRIGGER set_document_reviewer
+    BEFORE UPDATE ON contractor_document_uploads
+    FOR EACH ROW
+    EXECUTE FUNCTION set_document_reviewer();
@@ -0,0 +1,116 @@
+-- Migration 017: Charge Disputes
+-- Records chargebacks reported by the charge.dispute.created webhook, marks the
+-- related purchase as disputed and freezes the user's token spending until resolved.
+
+CREATE TABLE IF NOT EXISTS disputes (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID REFERENCES auth.users(id) ON DELETE SET NULL,
+    purchase_id UUID REFERENCES purchases(id) ON DELETE SET NULL,
+
+    -- Stripe references
+    stripe_dispute_id TEXT UNIQUE NOT NULL,
+    stripe_charge_id TEXT NOT NULL,
+    stripe_payment_intent_id TEXT,
+
+    amount BIGINT NOT NULL, -- Disputed amount in cents
+    currency TEXT NOT NULL,
+    reason TEXT NOT NULL, -- Stripe dispute reason, e.g. 'fraudulent', 'product_not_received'
+    status TEXT NOT NULL, -- Stripe dispute status, e.g. 'needs_response', 'won', 'lost'
+
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_disputes_user_id ON disputes(user_id);
+CREATE INDEX IF NOT EXISTS idx_disputes_stripe_payment_intent_id ON disputes(stripe_payment_intent_id);
+
+ALTER TABLE disputes ENABLE ROW LEVEL SECURITY;
+
+DROP POLICY IF EXISTS "Users can view own disputes" ON disputes;
+CREATE POLICY "Users can view own disputes" ON disputes
+    FOR SELECT USING (auth.uid() = user_id);
+
+DROP POLICY IF EXISTS "Service role can manage disputes" ON disputes;
+CREATE POLICY "Service role can manage disputes" ON disputes
+    FOR ALL USING (current_setting('role') = 'service_role');
+
+DROP TRIGGER IF EXISTS update_disputes_updated_at ON disputes;
+CREATE TRIGGER update_disputes_updated_at
+    BEFORE UPDATE ON disputes
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Token spending is frozen while a dispute is open
+ALTER TABLE profiles ADD COLUMN IF NOT EXISTS tokens_frozen BOOLEAN DEFAULT false;
+
+-- A spend on a frozen profile is skipped rather than applied, so spend_user_tokens
+-- matches no row and returns nothing, the same as for an insufficient balance
+CREATE OR REPLACE FUNCTION skip_token_spend_while_frozen()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF COALESCE(NEW.tokens_frozen, false)
+       AND COALESCE(NEW.tokens_used, 0) > COALESCE(OLD.tokens_used, 0) THEN
+        RETURN NULL;
+    END IF;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+DROP TRIGGER IF EXISTS skip_token_spend_while_frozen ON profiles;
+CREATE TRIGGER skip_token_spend_while_frozen
+    BEFORE UPDATE ON profiles
+    FOR EACH ROW
+    EXECUTE FUNCTION skip_token_spend_while_frozen();
+
+-- Users can update their own profile, so stop them from lifting a freeze themselves
+CREATE OR REPLACE FUNCTION prevent_tokens_frozen_self_clear()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF COALESCE(OLD.tokens_frozen, false) AND NOT COALESCE(NEW.tokens_frozen, false)
+       AND current_setting('role') != 'service_role' THEN
+        RAISE EXCEPTION 'tokens_frozen can only be cleared by the service role';
+    END IF;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+DROP TRIGGER IF EXISTS prevent_tokens_frozen_self_clear ON profiles;
+CREATE TRIGGER prevent_tokens_frozen_self_clear
+    BEFORE UPDATE ON profiles
+    FOR EACH ROW
+    EXECUTE FUNCTION prevent_tokens_frozen_self_clear();
+
+-- Users can update their own purchases, so the only status change they can make is
+-- flagging one as disputed; everything else (including resolving a dispute) is the service role's
+CREATE OR REPLACE FUNCTION prevent_purchase_status_self_change()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.status IS DISTINCT FROM OLD.status AND NEW.status != 'disputed'
+       AND current_setting('role') != 'service_role' THEN
+        RAISE EXCEPTION 'Purchase status can only be changed by the service role';
+    END IF;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+DROP TRIGGER IF EXISTS prevent_purchase_status_self_change ON purchases;
+CREATE TRIGGER prevent_purchase_status_self_change
+    BEFORE UPDATE ON purchases
+    FOR EACH ROW
+    EXECUTE FUNCTION prevent_purchase_status_self_change();
+
+-- A disputed purchase that goes back to completed (dispute won) must not credit tokens again,
+-- so the stats trigger no longer fires for updates coming out of 'disputed'.
+-- WHEN can't reference OLD on INSERT, hence separate insert and update triggers.
+DROP TRIGGER IF EXISTS update_profile_stats_on_purchase_completion ON purchases;
+CREATE TRIGGER update_profile_stats_on_purchase_completion
+    AFTER INSERT ON purchases
+    FOR EACH ROW
+    EXECUTE FUNCTION update_profile_purchase_stats();
+
+DROP TRIGGER IF EXISTS update_profile_stats_on_purchase_update ON purchases;
+CREATE TRIGGER update_profile_stats_on_purchase_update
+    AFTER UPDATE ON purchases
+    FOR EACH ROW
+    WHEN (OLD.status IS DISTINCT FROM 'disputed')
+    EXECUTE FUNCTION update_profile_purchase_stats();
@@ -0,0 +1,32 @@
+-- Migration 018: Account Deletion
+-- Lets a signed-in user close their account from the app.
+-- SECURITY DEFINER because users have no delete access to most of their rows or to auth.users.
+-- Purchases are deleted with the account; Stripe keeps the payment records needed for accounting.
+
+CREATE OR REPLACE FUNCTION delete_user_account(p_user_id UUID)
+RETURNS VOID AS $$
+BEGIN
+    IF auth.uid() IS NULL OR auth.uid() <> p_user_id THEN
+        RAISE EXCEPTION 'Users can only delete their own account';
+    END IF;
+
+    -- Contractor data (addresses, bank accounts, owners, representatives and
+    -- document uploads cascade from contractors)
+    UPDATE profiles SET contractor_id = NULL WHERE id = p_user_id;
+    DELETE FROM contractors WHERE user_id = p_user_id;
+    DELETE FROM contractor_kyc_form_data WHERE user_id = p_user_id;
+
+    -- Token and purchase history; purchases go before the payment methods they reference
+    DELETE FROM token_ledger WHERE user_id = p_user_id;
+    DELETE FROM user_token_transactions WHERE user_id = p_user_id;
+    DELETE FROM purchases WHERE user_id = p_user_id;
+    DELETE FROM payment_methods WHERE user_id = p_user_id;
+    DELETE FROM subscriptions WHERE user_id = p_user_id;
+
+    DELETE FROM profiles WHERE id = p_user_id;
+    DELETE FROM auth.users WHERE id = p_user_id;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+REVOKE ALL ON FUNCTION delete_user_account(UUID) FROM PUBLIC, anon;
+GRANT EXECUTE ON FUNCTION delete_user_account(UUID) TO authenticated;
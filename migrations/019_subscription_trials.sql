@@ -0,0 +1,5 @@
+-- Migration 019: Subscription Trials
+-- Subscriptions created for a price with trial_period_days start as 'trialing';
+-- the trial end is kept on the profile so the app can show when billing starts.
+
+ALTER TABLE profiles ADD COLUMN IF NOT EXISTS subscription_trial_end BIGINT; -- Unix timestamp, NULL when not trialing
@@ -0,0 +1,5 @@
+-- Migration 020: Trial Ending Notice
+-- Set by the customer.subscription.trial_will_end webhook handler so the app can show
+-- a "your trial ends soon" prompt; cleared when a new subscription is created.
+
+ALTER TABLE profiles ADD COLUMN IF NOT EXISTS subscription_trial_ending BOOLEAN DEFAULT false;
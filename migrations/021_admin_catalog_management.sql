@@ -0,0 +1,11 @@
+-- Migration 021: Admin Catalog Management
+-- Lets admins manage packages and package prices from the app (archiving, seeding)
+-- instead of only through the service role. Inactive rows stay visible to admins.
+
+DROP POLICY IF EXISTS "Admins can manage packages" ON packages;
+CREATE POLICY "Admins can manage packages" ON packages
+    FOR ALL USING (is_admin()) WITH CHECK (is_admin());
+
+DROP POLICY IF EXISTS "Admins can manage package prices" ON package_prices;
+CREATE POLICY "Admins can manage package prices" ON package_prices
+    FOR ALL USING (is_admin()) WITH CHECK (is_admin());
@@ -0,0 +1,7 @@
+-- Migration 022: Subscription Plan Tracking
+-- Records which price and plan the user is subscribed to, kept current by the
+-- customer.subscription.updated webhook when the plan is changed in Stripe
+-- (e.g. from the Billing Portal).
+
+ALTER TABLE profiles ADD COLUMN IF NOT EXISTS subscription_price_id TEXT;
+ALTER TABLE profiles ADD COLUMN IF NOT EXISTS subscription_plan_id UUID REFERENCES subscription_plans(id) ON DELETE SET NULL;
@@ -0,0 +1,7 @@
+-- Migration 023: Connect Capabilities
+-- Per-capability status for a contractor's Connect account (e.g.
+-- {"card_payments": "active", "transfers": "inactive"}), kept current by the
+-- capability.updated webhook so the app can say exactly what a contractor can't do yet.
+
+ALTER TABLE contractors ADD COLUMN IF NOT EXISTS stripe_capabilities JSONB DEFAULT '{}'::jsonb;
+ALTER TABLE contractors ADD COLUMN IF NOT EXISTS stripe_capabilities_updated_at TIMESTAMP WITH TIME ZONE;
@@ -0,0 +1,5 @@
+-- Migration 024: Contractor Support Email
+-- Support email shown to customers on the contractor's Connect account, mirrored from
+-- Stripe's business_profile.support_email by update_connect_business_profile.
+
+ALTER TABLE contractors ADD COLUMN IF NOT EXISTS business_support_email TEXT;
@@ -0,0 +1,35 @@
+-- Migration 025: Subscription Events
+-- Append-only history of subscription changes (created, upgraded, downgraded, canceled,
+-- resumed) with the price before and after, used to render the billing timeline.
+-- The profile's subscription columns remain the source of truth for the current state.
+
+CREATE TABLE IF NOT EXISTS subscription_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+
+    subscription_id TEXT,
+    action TEXT NOT NULL CHECK (action IN ('created', 'upgraded', 'downgraded', 'canceled', 'resumed')),
+    old_price_id TEXT,
+    new_price_id TEXT,
+
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- History is always read newest first for a single user
+CREATE INDEX IF NOT EXISTS idx_subscription_events_user_created_at ON subscription_events(user_id, created_at DESC);
+
+-- Enable Row Level Security (RLS)
+ALTER TABLE subscription_events ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policies for subscription_events (no update/delete: the history is append-only)
+DROP POLICY IF EXISTS "Users can view own subscription events" ON subscription_events;
+CREATE POLICY "Users can view own subscription events" ON subscription_events
+    FOR SELECT USING (auth.uid() = user_id);
+
+DROP POLICY IF EXISTS "Users can insert own subscription events" ON subscription_events;
+CREATE POLICY "Users can insert own subscription events" ON subscription_events
+    FOR INSERT WITH CHECK (auth.uid() = user_id);
+
+DROP POLICY IF EXISTS "Service role can manage subscription events" ON subscription_events;
+CREATE POLICY "Service role can manage subscription events" ON subscription_events
+    FOR ALL USING (current_setting('role') = 'service_role');
@@ -0,0 +1,5 @@
+-- Migration 026: Subscription Items
+-- The full set of items on the user's subscription (base plan plus add-ons) as
+-- [{"item_id", "price_id", "quantity"}]. subscription_price_id keeps tracking the base plan.
+
+ALTER TABLE profiles ADD COLUMN IF NOT EXISTS subscription_items JSONB DEFAULT '[]'::jsonb;
@@ -0,0 +1,33 @@
+-- Migration 027: Processed Checkout Sessions
+-- One row per completed Stripe Checkout Session, written once its purchase or
+-- subscription has been recorded, so redelivered checkout.session.completed webhooks
+-- are acknowledged without being applied twice.
+
+CREATE TABLE IF NOT EXISTS checkout_sessions (
+    session_id TEXT PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES auth.users(id) ON DELETE CASCADE,
+
+    mode TEXT NOT NULL CHECK (mode IN ('payment', 'subscription')),
+    stripe_payment_intent_id TEXT,
+    subscription_id TEXT,
+
+    processed_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_checkout_sessions_user_id ON checkout_sessions(user_id);
+
+-- Enable Row Level Security (RLS)
+ALTER TABLE checkout_sessions ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policies for checkout_sessions
+DROP POLICY IF EXISTS "Users can view own checkout sessions" ON checkout_sessions;
+CREATE POLICY "Users can view own checkout sessions" ON checkout_sessions
+    FOR SELECT USING (auth.uid() = user_id);
+
+DROP POLICY IF EXISTS "Users can insert own checkout sessions" ON checkout_sessions;
+CREATE POLICY "Users can insert own checkout sessions" ON checkout_sessions
+    FOR INSERT WITH CHECK (auth.uid() = user_id);
+
+DROP POLICY IF EXISTS "Service role can manage checkout sessions" ON checkout_sessions;
+CREATE POLICY "Service role can manage checkout sessions" ON checkout_sessions
+    FOR ALL USING (current_setting('role') = 'service_role');
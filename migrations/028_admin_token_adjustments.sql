@@ -0,0 +1,60 @@
+-- Migration 028: Admin Token Adjustments
+-- Lets support comp tokens or correct a balance by a signed delta. The adjustment,
+-- the transaction and the ledger entry are written together, and a correction that
+-- would take tokens_remaining below zero changes nothing. The admin recorded on the
+-- transaction is the caller, never a value the client supplies.
+
+-- Ledger entries written by an admin are tagged so they stand out in the history
+ALTER TABLE token_ledger ADD COLUMN IF NOT EXISTS entry_type TEXT;
+
+CREATE OR REPLACE FUNCTION admin_adjust_user_tokens(
+    p_user_id UUID,
+    p_delta BIGINT,
+    p_description TEXT
+) RETURNS TABLE (
+    total_tokens BIGINT,
+    tokens_remaining BIGINT,
+    tokens_used BIGINT
+) AS $$
+DECLARE
+    v_tokens_remaining BIGINT;
+BEGIN
+    IF NOT is_admin() THEN
+        RAISE EXCEPTION 'Admin access required';
+    END IF;
+
+    IF p_delta IS NULL OR p_delta = 0 THEN
+        RAISE EXCEPTION 'Token adjustment must be non-zero, got %', p_delta;
+    END IF;
+
+    -- Only matches when the balance stays non-negative; the row lock serializes concurrent changes
+    RETURN QUERY
+    UPDATE profiles p
+    SET
+        tokens_remaining = COALESCE(p.tokens_remaining, 0) + p_delta,
+        total_tokens = GREATEST(COALESCE(p.total_tokens, 0) + p_delta, 0),
+        updated_at = NOW()
+    WHERE p.id = p_user_id
+      AND COALESCE(p.tokens_remaining, 0) + p_delta >= 0
+    RETURNING p.total_tokens, p.tokens_remaining, p.tokens_used;
+
+    -- No row updated means the correction would underflow (or the profile is missing)
+    IF NOT FOUND THEN
+        RETURN;
+    END IF;
+
+    SELECT p.tokens_remaining INTO v_tokens_remaining FROM profiles p WHERE p.id = p_user_id;
+
+    INSERT INTO user_token_transactions (
+        user_id, transaction_type, token_amount, description, metadata
+    ) VALUES (
+        p_user_id, 'admin_adjustment', p_delta, p_description, jsonb_build_object('admin_id', auth.uid())
+    );
+
+    INSERT INTO token_ledger (
+        user_id, amount, reason, balance_after, entry_type
+    ) VALUES (
+        p_user_id, p_delta, p_description, v_tokens_remaining, 'admin_adjustment'
+    );
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
@@ -0,0 +1,31 @@
+-- Migration 029: Token Price Map
+-- Tokens credited for a purchase amount, so pricing can change without an app release.
+-- A matching row takes precedence over package_prices.token_amount; amounts with no
+-- row fall back to the price's token_amount and then to the app's built-in table.
+
+CREATE TABLE IF NOT EXISTS token_price_map (
+    amount_cents BIGINT NOT NULL CHECK (amount_cents > 0),
+    currency TEXT NOT NULL CHECK (currency = LOWER(currency)),
+    tokens BIGINT NOT NULL CHECK (tokens > 0),
+
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+
+    PRIMARY KEY (amount_cents, currency)
+);
+
+-- Enable Row Level Security (RLS)
+ALTER TABLE token_price_map ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policies for token_price_map
+DROP POLICY IF EXISTS "Anyone can view token price map" ON token_price_map;
+CREATE POLICY "Anyone can view token price map" ON token_price_map
+    FOR SELECT USING (true);
+
+DROP POLICY IF EXISTS "Admins can manage token price map" ON token_price_map;
+CREATE POLICY "Admins can manage token price map" ON token_price_map
+    FOR ALL USING (is_admin()) WITH CHECK (is_admin());
+
+DROP POLICY IF EXISTS "Service role can manage token price map" ON token_price_map;
+CREATE POLICY "Service role can manage token price map" ON token_price_map
+    FOR ALL USING (current_setting('role') = 'service_role');
@@ -0,0 +1,56 @@
+-- Migration 030: Stripe Event Audit Log
+-- One row per delivery of a verified webhook event with how it was processed, for
+-- incident investigation. Full payloads are only stored when the app opts in.
+-- Rows are only ever added, through record_stripe_event, so the log can't be rewritten.
+
+CREATE TABLE IF NOT EXISTS stripe_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    event_id TEXT NOT NULL, -- Repeats when Stripe redelivers an event
+    event_type TEXT NOT NULL,
+    stripe_created BIGINT NOT NULL, -- Unix timestamp Stripe created the event at
+
+    status TEXT NOT NULL CHECK (status IN ('handled', 'ignored', 'failed')),
+    message TEXT,
+    payload JSONB, -- NULL unless webhook payload storage is enabled
+
+    received_at TIMESTAMPTZ DEFAULT NOW(),
+    processed_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- The audit view is read newest first
+CREATE INDEX IF NOT EXISTS idx_stripe_events_received_at ON stripe_events(received_at DESC);
+CREATE INDEX IF NOT EXISTS idx_stripe_events_event_id ON stripe_events(event_id);
+
+-- Enable Row Level Security (RLS)
+ALTER TABLE stripe_events ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policies for stripe_events
+DROP POLICY IF EXISTS "Admins can view stripe events" ON stripe_events;
+CREATE POLICY "Admins can view stripe events" ON stripe_events
+    FOR SELECT USING (is_admin());
+
+DROP POLICY IF EXISTS "Service role can manage stripe events" ON stripe_events;
+CREATE POLICY "Service role can manage stripe events" ON stripe_events
+    FOR ALL USING (current_setting('role') = 'service_role');
+
+-- Webhooks are processed with the signed-in user's session. Users get no INSERT or UPDATE
+-- policy; this function is the only way in and it can only add rows.
+CREATE OR REPLACE FUNCTION record_stripe_event(
+    p_event_id TEXT,
+    p_event_type TEXT,
+    p_stripe_created BIGINT,
+    p_status TEXT,
+    p_message TEXT,
+    p_payload JSONB
+) RETURNS VOID AS $$
+BEGIN
+    IF auth.uid() IS NULL THEN
+        RAISE EXCEPTION 'Authentication required';
+    END IF;
+
+    INSERT INTO stripe_events (event_id, event_type, stripe_created, status, message, payload)
+    VALUES (p_event_id, p_event_type, p_stripe_created, p_status, p_message, p_payload);
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+GRANT EXECUTE ON FUNCTION record_stripe_event(TEXT, TEXT, BIGINT, TEXT, TEXT, JSONB) TO authenticated;
@@ -0,0 +1,28 @@
+-- Migration 031: KYC Form Data Versions
+-- Auto-save can send overlapping saves. Each save carries a client version and only
+-- replaces the stored form data when it is newer, so a late stale save can't
+-- overwrite a newer one.
+
+ALTER TABLE contractor_kyc_form_data ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 0;
+
+-- Runs as the caller, so the existing row level security policies still apply.
+-- Returns false when the stored form data is already at this version or newer.
+CREATE OR REPLACE FUNCTION save_kyc_form_data(
+    p_user_id UUID,
+    p_kyc_data JSONB,
+    p_version BIGINT
+) RETURNS BOOLEAN AS $$
+BEGIN
+    INSERT INTO contractor_kyc_form_data (user_id, kyc_data, version)
+    VALUES (p_user_id, p_kyc_data, p_version)
+    ON CONFLICT (user_id) DO UPDATE
+    SET
+        kyc_data = EXCLUDED.kyc_data,
+        version = EXCLUDED.version
+    WHERE contractor_kyc_form_data.version < EXCLUDED.version;
+
+    RETURN FOUND;
+END;
+$$ LANGUAGE plpgsql SET search_path = public;
+
+GRANT EXECUTE ON FUNCTION save_kyc_form_data(UUID, JSONB, BIGINT) TO authenticated;
@@ -0,0 +1,51 @@
+-- Migration 032: Gift Recipient RPC
+-- The gifter can't update the recipient's profile under RLS, so the recipient side of a
+-- gift used to be silently dropped. The gift row and the recipient's subscription fields
+-- are now written together by one function, with the gifter taken from the session.
+
+-- Gift rows are only written by record_subscription_gift
+DROP POLICY IF EXISTS "Users can insert gifts they pay for" ON subscription_gifts;
+
+CREATE OR REPLACE FUNCTION record_subscription_gift(
+    p_recipient_user_id UUID,
+    p_stripe_subscription_id TEXT,
+    p_stripe_payment_intent_id TEXT,
+    p_stripe_price_id TEXT,
+    p_amount_paid BIGINT,
+    p_currency TEXT,
+    p_recipient_customer_id TEXT,
+    p_subscription_status TEXT,
+    p_subscription_period_end BIGINT
+) RETURNS VOID AS $$
+BEGIN
+    IF auth.uid() IS NULL THEN
+        RAISE EXCEPTION 'Authentication required';
+    END IF;
+
+    -- The unique payment intent and subscription ids stop one charge being recorded twice
+    INSERT INTO subscription_gifts (
+        gifter_user_id, recipient_user_id, stripe_subscription_id,
+        stripe_payment_intent_id, stripe_price_id, amount_paid, currency
+    ) VALUES (
+        auth.uid(), p_recipient_user_id, p_stripe_subscription_id,
+        p_stripe_payment_intent_id, p_stripe_price_id, p_amount_paid, p_currency
+    );
+
+    -- Keep a customer the recipient already has
+    UPDATE profiles
+    SET
+        stripe_customer_id = COALESCE(stripe_customer_id, p_recipient_customer_id),
+        subscription_id = p_stripe_subscription_id,
+        subscription_status = p_subscription_status,
+        subscription_period_end = p_subscription_period_end,
+        subscription_price_id = p_stripe_price_id,
+        updated_at = NOW()
+    WHERE id = p_recipient_user_id;
+
+    IF NOT FOUND THEN
+        RAISE EXCEPTION 'Recipient profile % not found', p_recipient_user_id;
+    END IF;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+GRANT EXECUTE ON FUNCTION record_subscription_gift(UUID, TEXT, TEXT, TEXT, BIGINT, TEXT, TEXT, TEXT, BIGINT) TO authenticated;
@@ -0,0 +1,13 @@
+-- Migration 033: Bank Account Delete Policy
+-- replace_connect_bank_account clears the old bank account rows before storing the new one,
+-- but without a DELETE policy RLS matched no rows and the old records were left behind.
+
+DROP POLICY IF EXISTS "Users can delete own bank accounts" ON contractor_bank_accounts;
+CREATE POLICY "Users can delete own bank accounts" ON contractor_bank_accounts
+    FOR DELETE USING (
+        EXISTS (
+            SELECT 1 FROM contractors 
+            WHERE contractors.id = contractor_bank_accounts.contractor_id 
+            AND contractors.user_id = auth.uid()
+        )
+    );
@@ -0,0 +1,63 @@
+-- Migration 034: Contractor KYC Status Guard
+-- Contractors can update their own row, so stop them from setting kyc_status directly.
+-- The only change an owner may make is submitting a pending or rejected contractor, and
+-- a business is only accepted once it has a representative with signing authority and
+-- its beneficial owners add up to no more than 100%. Everything else is service role only.
+
+-- The same check as validate_contractor_completeness in the app
+CREATE OR REPLACE FUNCTION contractor_is_complete(p_contractor_id UUID, p_contractor_type TEXT)
+RETURNS BOOLEAN AS $$
+BEGIN
+    IF p_contractor_type IS DISTINCT FROM 'business' THEN
+        RETURN true;
+    END IF;
+
+    IF NOT EXISTS (
+        SELECT 1 FROM contractor_representatives
+        WHERE contractor_id = p_contractor_id AND is_authorized_signatory
+    ) THEN
+        RETURN false;
+    END IF;
+
+    RETURN COALESCE((
+        SELECT SUM(ownership_percentage) FROM contractor_beneficial_owners
+        WHERE contractor_id = p_contractor_id
+    ), 0) <= 100;
+END;
+$$ LANGUAGE plpgsql STABLE SECURITY DEFINER SET search_path = public;
+
+CREATE OR REPLACE FUNCTION prevent_kyc_status_self_change()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF current_setting('role') = 'service_role' THEN
+        RETURN NEW;
+    END IF;
+
+    IF TG_OP = 'INSERT' THEN
+        IF NEW.kyc_status = 'pending' THEN
+            RETURN NEW;
+        END IF;
+        IF NEW.kyc_status = 'submitted' AND contractor_is_complete(NEW.id, NEW.contractor_type::TEXT) THEN
+            RETURN NEW;
+        END IF;
+        RAISE EXCEPTION 'New contractors must start as pending, or submitted once complete';
+    END IF;
+
+    IF NEW.kyc_status IS DISTINCT FROM OLD.kyc_status THEN
+        IF NEW.kyc_status <> 'submitted' OR OLD.kyc_status NOT IN ('pending', 'rejected') THEN
+            RAISE EXCEPTION 'kyc_status can only be changed to % by the service role', NEW.kyc_status;
+        END IF;
+        IF NOT contractor_is_complete(NEW.id, NEW.contractor_type::TEXT) THEN
+            RAISE EXCEPTION 'Contractor is missing details required for verification';
+        END IF;
+    END IF;
+
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+DROP TRIGGER IF EXISTS prevent_kyc_status_self_change ON contractors;
+CREATE TRIGGER prevent_kyc_status_self_change
+    BEFORE INSERT OR UPDATE ON contractors
+    FOR EACH ROW
+    EXECUTE FUNCTION prevent_kyc_status_self_change();
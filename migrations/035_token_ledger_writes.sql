@@ -0,0 +1,117 @@
+-- Migration 035: Token Ledger Writes
+-- Clients could insert their own token_ledger rows, so history could be forged. Entries
+-- are now only written by SECURITY DEFINER functions: spend_user_tokens for spends, a
+-- trigger on purchases for credits, and admin_adjust_user_tokens (migration 028).
+-- The ledger is a history view, so a failed ledger insert is logged and skipped rather
+-- than blocking the spend or credit it describes.
+
+DROP POLICY IF EXISTS "Users can insert own token ledger entries" ON token_ledger;
+
+-- SECURITY DEFINER so it can write the ledger entry, which means it checks the caller
+-- owns the profile itself
+CREATE OR REPLACE FUNCTION spend_user_tokens(
+    p_user_id UUID,
+    p_token_amount BIGINT,
+    p_description TEXT DEFAULT 'Token usage',
+    p_metadata JSONB DEFAULT '{}'::jsonb
+) RETURNS TABLE (
+    total_tokens BIGINT,
+    tokens_remaining BIGINT,
+    tokens_used BIGINT
+) AS $$
+DECLARE
+    v_total_tokens BIGINT;
+    v_tokens_remaining BIGINT;
+    v_tokens_used BIGINT;
+BEGIN
+    IF p_user_id IS DISTINCT FROM auth.uid() AND current_setting('role') != 'service_role' THEN
+        RAISE EXCEPTION 'Tokens can only be spent from your own account';
+    END IF;
+
+    IF p_token_amount IS NULL OR p_token_amount <= 0 THEN
+        RAISE EXCEPTION 'Token amount must be positive, got %', p_token_amount;
+    END IF;
+
+    -- Only matches when the balance covers the spend; the row lock serializes concurrent spends
+    UPDATE profiles p
+    SET
+        tokens_remaining = p.tokens_remaining - p_token_amount,
+        tokens_used = COALESCE(p.tokens_used, 0) + p_token_amount,
+        updated_at = NOW()
+    WHERE p.id = p_user_id
+      AND p.tokens_remaining >= p_token_amount
+    RETURNING p.total_tokens, p.tokens_remaining, p.tokens_used
+    INTO v_total_tokens, v_tokens_remaining, v_tokens_used;
+
+    -- No row updated means the balance was insufficient (or the profile is missing)
+    IF NOT FOUND THEN
+        RETURN;
+    END IF;
+
+    INSERT INTO user_token_transactions (
+        user_id, transaction_type, token_amount, description, metadata
+    ) VALUES (
+        p_user_id, 'usage', -p_token_amount, p_description, p_metadata
+    );
+
+    BEGIN
+        INSERT INTO token_ledger (
+            user_id, amount, reason, balance_after
+        ) VALUES (
+            p_user_id, -p_token_amount, p_description, v_tokens_remaining
+        );
+    EXCEPTION WHEN others THEN
+        RAISE WARNING 'Token ledger entry for spend by % not written: %', p_user_id, SQLERRM;
+    END;
+
+    RETURN QUERY SELECT v_total_tokens, v_tokens_remaining, v_tokens_used;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+GRANT EXECUTE ON FUNCTION spend_user_tokens(UUID, BIGINT, TEXT, JSONB) TO authenticated;
+
+-- Credit entry for a completed purchase. The triggers are named to sort after the
+-- update_profile_stats_on_purchase_* triggers (migration 017), which fire first and
+-- credit the profile, and skip disputes reopened as completed in the same way.
+CREATE OR REPLACE FUNCTION record_purchase_token_ledger_entry()
+RETURNS TRIGGER AS $$
+DECLARE
+    v_tokens BIGINT;
+    v_tokens_remaining BIGINT;
+BEGIN
+    IF (TG_OP = 'INSERT' AND NEW.status = 'completed') OR
+       (TG_OP = 'UPDATE' AND NEW.status = 'completed' AND OLD.status != 'completed') THEN
+        BEGIN
+            SELECT COALESCE(NULLIF(NEW.tokens_purchased, 0), pp.token_amount, 0) INTO v_tokens
+            FROM package_prices pp
+            WHERE pp.id = NEW.package_price_id;
+
+            SELECT p.tokens_remaining INTO v_tokens_remaining FROM profiles p WHERE p.id = NEW.user_id;
+
+            INSERT INTO token_ledger (
+                user_id, amount, reason, balance_after
+            ) VALUES (
+                NEW.user_id, COALESCE(v_tokens, NEW.tokens_purchased, 0),
+                'Token purchase: ' || COALESCE(NEW.stripe_payment_intent_id, NEW.id::TEXT),
+                COALESCE(v_tokens_remaining, 0)
+            );
+        EXCEPTION WHEN others THEN
+            RAISE WARNING 'Token ledger entry for purchase % not written: %', NEW.id, SQLERRM;
+        END;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+DROP TRIGGER IF EXISTS update_token_ledger_on_purchase_completion ON purchases;
+CREATE TRIGGER update_token_ledger_on_purchase_completion
+    AFTER INSERT ON purchases
+    FOR EACH ROW
+    EXECUTE FUNCTION record_purchase_token_ledger_entry();
+
+DROP TRIGGER IF EXISTS update_token_ledger_on_purchase_update ON purchases;
+CREATE TRIGGER update_token_ledger_on_purchase_update
+    AFTER UPDATE ON purchases
+    FOR EACH ROW
+    WHEN (OLD.status IS DISTINCT FROM 'disputed')
+    EXECUTE FUNCTION record_purchase_token_ledger_entry();
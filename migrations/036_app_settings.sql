@@ -0,0 +1,44 @@
+-- Migration 036: App Settings
+-- Settings admins change at runtime that apply to every user, such as how long a
+-- past_due subscription keeps access. Kept in the database so every device reads the
+-- same value and a user can't change it by editing their local store.
+
+CREATE TABLE IF NOT EXISTS app_settings (
+    key TEXT PRIMARY KEY,
+    value JSONB NOT NULL,
+
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW(),
+
+    -- Matches MAX_SUBSCRIPTION_GRACE_PERIOD_DAYS in the app
+    CONSTRAINT subscription_grace_period_days_range CHECK (
+        key <> 'subscription_grace_period_days'
+        OR (jsonb_typeof(value) = 'number' AND (value #>> '{}')::NUMERIC BETWEEN 0 AND 30)
+    )
+);
+
+INSERT INTO app_settings (key, value)
+VALUES ('subscription_grace_period_days', '7'::jsonb)
+ON CONFLICT (key) DO NOTHING;
+
+DROP TRIGGER IF EXISTS update_app_settings_updated_at ON app_settings;
+CREATE TRIGGER update_app_settings_updated_at
+    BEFORE UPDATE ON app_settings
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- Enable Row Level Security (RLS)
+ALTER TABLE app_settings ENABLE ROW LEVEL SECURITY;
+
+-- RLS Policies for app_settings
+DROP POLICY IF EXISTS "Anyone can view app settings" ON app_settings;
+CREATE POLICY "Anyone can view app settings" ON app_settings
+    FOR SELECT USING (true);
+
+DROP POLICY IF EXISTS "Admins can manage app settings" ON app_settings;
+CREATE POLICY "Admins can manage app settings" ON app_settings
+    FOR ALL USING (is_admin()) WITH CHECK (is_admin());
+
+DROP POLICY IF EXISTS "Service role can manage app settings" ON app_settings;
+CREATE POLICY "Service role can manage app settings" ON app_settings
+    FOR ALL USING (current_setting('role') = 'service_role');
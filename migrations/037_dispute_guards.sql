@@ -0,0 +1,39 @@
+-- Migration 037: Dispute Guards
+-- Migration 017 still let users mark their own purchases as disputed, and skipped a
+-- frozen spend silently so the app had to guess why nothing was deducted. Disputes are
+-- now only recorded by the service role (the charge.dispute.created webhook), and a
+-- frozen spend raises an error.
+
+-- Users can update their own purchases, but any status change is the service role's
+CREATE OR REPLACE FUNCTION prevent_purchase_status_self_change()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.status IS DISTINCT FROM OLD.status AND current_setting('role') != 'service_role' THEN
+        RAISE EXCEPTION 'Purchase status can only be changed by the service role'
+            USING ERRCODE = 'insufficient_privilege';
+    END IF;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+-- SQLSTATE AU001 is how spend_tokens tells a freeze apart from other failures
+CREATE OR REPLACE FUNCTION reject_token_spend_while_frozen()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF COALESCE(NEW.tokens_frozen, false)
+       AND COALESCE(NEW.tokens_used, 0) > COALESCE(OLD.tokens_used, 0) THEN
+        RAISE EXCEPTION 'Token spending is frozen while a payment dispute is open'
+            USING ERRCODE = 'AU001';
+    END IF;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+DROP TRIGGER IF EXISTS skip_token_spend_while_frozen ON profiles;
+DROP FUNCTION IF EXISTS skip_token_spend_while_frozen();
+
+DROP TRIGGER IF EXISTS reject_token_spend_while_frozen ON profiles;
+CREATE TRIGGER reject_token_spend_while_frozen
+    BEFORE UPDATE ON profiles
+    FOR EACH ROW
+    EXECUTE FUNCTION reject_token_spend_while_frozen();
@@ -0,0 +1,65 @@
+-- Migration 038: Username Check Limits
+-- The username check rate limit was only counted in a local store, which a user can
+-- reset by clearing it or deleting and recreating their account. Checks now go through
+-- check_usernames, which counts them per user in the database before looking anything up.
+
+CREATE TABLE IF NOT EXISTS username_check_limits (
+    user_id UUID PRIMARY KEY REFERENCES auth.users(id) ON DELETE CASCADE,
+    window_start TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    check_count INTEGER NOT NULL DEFAULT 0
+);
+
+-- Enable Row Level Security (RLS). There are no policies: the table is only read and
+-- written by check_usernames.
+ALTER TABLE username_check_limits ENABLE ROW LEVEL SECURITY;
+
+-- Returns which of p_usernames are taken. Every username counts as one check, and either
+-- all of them fit in the caller's current window or none are counted. Over the limit it
+-- raises SQLSTATE AU002 with the seconds until the window resets as the hint.
+-- The limit matches USERNAME_CHECK_LIMIT and USERNAME_CHECK_WINDOW_MS in the app.
+CREATE OR REPLACE FUNCTION check_usernames(p_usernames TEXT[])
+RETURNS TABLE (username TEXT) AS $$
+DECLARE
+    v_limit CONSTANT INTEGER := 20;
+    v_window CONSTANT INTERVAL := INTERVAL '60 seconds';
+    v_user_id UUID := auth.uid();
+    v_checks INTEGER := COALESCE(cardinality(p_usernames), 0);
+    v_window_start TIMESTAMPTZ;
+    v_check_count INTEGER;
+BEGIN
+    IF v_user_id IS NULL THEN
+        RAISE EXCEPTION 'Username checks require a signed-in user'
+            USING ERRCODE = 'insufficient_privilege';
+    END IF;
+
+    INSERT INTO username_check_limits (user_id, window_start, check_count)
+    VALUES (v_user_id, NOW(), 0)
+    ON CONFLICT (user_id) DO NOTHING;
+
+    -- The row lock serializes concurrent checks by the same user
+    SELECT l.window_start, l.check_count INTO v_window_start, v_check_count
+    FROM username_check_limits l
+    WHERE l.user_id = v_user_id
+    FOR UPDATE;
+
+    IF NOW() >= v_window_start + v_window OR NOW() < v_window_start THEN
+        v_window_start := NOW();
+        v_check_count := 0;
+    END IF;
+
+    IF v_check_count + v_checks > v_limit THEN
+        RAISE EXCEPTION 'Too many username checks'
+            USING ERRCODE = 'AU002',
+                  HINT = CEIL(EXTRACT(EPOCH FROM (v_window_start + v_window - NOW())))::INTEGER::TEXT;
+    END IF;
+
+    UPDATE username_check_limits
+    SET window_start = v_window_start, check_count = v_check_count + v_checks
+    WHERE user_id = v_user_id;
+
+    RETURN QUERY
+    SELECT p.username FROM profiles p WHERE p.username = ANY(p_usernames);
+END;
+$$ LANGUAGE plpgsql SECURITY DEFINER SET search_path = public;
+
+GRANT EXECUTE ON FUNCTION check_usernames(TEXT[]) TO authenticated;
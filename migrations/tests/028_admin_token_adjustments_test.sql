@@ -0,0 +1,80 @@
+-- Checks for migration 028. Run against a migrated local Supabase database:
+--   psql "$DATABASE_URL" -v ON_ERROR_STOP=1 -f migrations/tests/028_admin_token_adjustments_test.sql
+-- Each check is a DO block that fails with the ASSERT message; everything is rolled back.
+
+BEGIN;
+
+-- An admin grant adds to both balances and leaves a tagged ledger entry
+DO $$
+DECLARE
+    v_admin_id UUID := gen_random_uuid();
+    v_user_id UUID := gen_random_uuid();
+    v_remaining BIGINT;
+BEGIN
+    INSERT INTO auth.users (id, email) VALUES (v_admin_id, v_admin_id || '@example.test');
+    INSERT INTO auth.users (id, email) VALUES (v_user_id, v_user_id || '@example.test');
+    PERFORM set_config('role', 'service_role', true);
+    UPDATE profiles SET is_admin = true WHERE id = v_admin_id;
+    PERFORM set_config('role', 'none', true);
+    UPDATE profiles SET total_tokens = 100, tokens_remaining = 40, tokens_used = 60 WHERE id = v_user_id;
+
+    PERFORM set_config('request.jwt.claims', json_build_object('sub', v_admin_id, 'role', 'authenticated')::TEXT, true);
+    PERFORM set_config('role', 'authenticated', true);
+    SELECT tokens_remaining INTO v_remaining FROM admin_adjust_user_tokens(v_user_id, 50, 'Goodwill grant');
+    PERFORM set_config('role', 'none', true);
+
+    ASSERT v_remaining = 90, 'the grant should return the new balance';
+    ASSERT (SELECT total_tokens FROM profiles WHERE id = v_user_id) = 150, 'the grant should add to total_tokens';
+    ASSERT (SELECT entry_type FROM token_ledger WHERE user_id = v_user_id AND amount = 50) = 'admin_adjustment',
+        'the grant should leave an admin_adjustment ledger entry';
+END $$;
+
+-- A correction that would take the balance below zero is rejected and changes nothing
+DO $$
+DECLARE
+    v_admin_id UUID := gen_random_uuid();
+    v_user_id UUID := gen_random_uuid();
+    v_rows INTEGER;
+BEGIN
+    INSERT INTO auth.users (id, email) VALUES (v_admin_id, v_admin_id || '@example.test');
+    INSERT INTO auth.users (id, email) VALUES (v_user_id, v_user_id || '@example.test');
+    PERFORM set_config('role', 'service_role', true);
+    UPDATE profiles SET is_admin = true WHERE id = v_admin_id;
+    PERFORM set_config('role', 'none', true);
+    UPDATE profiles SET total_tokens = 100, tokens_remaining = 40, tokens_used = 60 WHERE id = v_user_id;
+
+    PERFORM set_config('request.jwt.claims', json_build_object('sub', v_admin_id, 'role', 'authenticated')::TEXT, true);
+    PERFORM set_config('role', 'authenticated', true);
+    SELECT count(*) INTO v_rows FROM admin_adjust_user_tokens(v_user_id, -41, 'Correction');
+    PERFORM set_config('role', 'none', true);
+
+    ASSERT v_rows = 0, 'an underflowing correction should return no balance';
+    ASSERT (SELECT tokens_remaining FROM profiles WHERE id = v_user_id) = 40, 'tokens_remaining should be unchanged';
+    ASSERT (SELECT total_tokens FROM profiles WHERE id = v_user_id) = 100, 'total_tokens should be unchanged';
+    ASSERT NOT EXISTS (SELECT 1 FROM token_ledger WHERE user_id = v_user_id), 'no ledger entry should be written';
+    ASSERT NOT EXISTS (SELECT 1 FROM user_token_transactions WHERE user_id = v_user_id),
+        'no transaction should be written';
+END $$;
+
+-- Users who aren't admins can't adjust balances
+DO $$
+DECLARE
+    v_user_id UUID := gen_random_uuid();
+    v_raised BOOLEAN := false;
+BEGIN
+    INSERT INTO auth.users (id, email) VALUES (v_user_id, v_user_id || '@example.test');
+
+    PERFORM set_config('request.jwt.claims', json_build_object('sub', v_user_id, 'role', 'authenticated')::TEXT, true);
+    PERFORM set_config('role', 'authenticated', true);
+    BEGIN
+        PERFORM admin_adjust_user_tokens(v_user_id, 1000, 'Self grant');
+    EXCEPTION WHEN raise_exception THEN
+        v_raised := true;
+    END;
+    PERFORM set_config('role', 'none', true);
+
+    ASSERT v_raised, 'a non-admin adjustment should raise';
+    ASSERT (SELECT COALESCE(tokens_remaining, 0) FROM profiles WHERE id = v_user_id) = 0, 'nothing should be granted';
+END $$;
+
+ROLLBACK;
@@ -0,0 +1,71 @@
+-- Checks for migration 035. Run against a migrated local Supabase database:
+--   psql "$DATABASE_URL" -v ON_ERROR_STOP=1 -f migrations/tests/035_token_ledger_writes_test.sql
+-- Each check is a DO block that fails with the ASSERT message; everything is rolled back.
+
+BEGIN;
+
+-- A spend writes a ledger row
+DO $$
+DECLARE
+    v_user_id UUID := gen_random_uuid();
+    v_balance RECORD;
+    v_entry RECORD;
+BEGIN
+    INSERT INTO auth.users (id, email) VALUES (v_user_id, v_user_id || '@example.test');
+    UPDATE profiles SET total_tokens = 100, tokens_remaining = 100, tokens_used = 0 WHERE id = v_user_id;
+
+    -- Spend as the user themselves, the way the app calls it
+    PERFORM set_config('request.jwt.claims', json_build_object('sub', v_user_id, 'role', 'authenticated')::TEXT, true);
+    PERFORM set_config('role', 'authenticated', true);
+    SELECT * INTO v_balance FROM spend_user_tokens(v_user_id, 30, 'Test spend');
+    PERFORM set_config('role', 'none', true);
+
+    ASSERT v_balance.tokens_remaining = 70, 'spend should leave 70 tokens';
+
+    SELECT * INTO v_entry FROM token_ledger WHERE user_id = v_user_id;
+    ASSERT FOUND, 'spend should write a ledger row';
+    ASSERT v_entry.amount = -30, 'ledger row should record the spend as negative';
+    ASSERT v_entry.balance_after = 70, 'ledger row should record the balance after the spend';
+    ASSERT v_entry.reason = 'Test spend', 'ledger row should keep the description';
+END $$;
+
+-- A ledger insert that fails doesn't stop the spend
+DO $$
+DECLARE
+    v_user_id UUID := gen_random_uuid();
+    v_balance RECORD;
+BEGIN
+    INSERT INTO auth.users (id, email) VALUES (v_user_id, v_user_id || '@example.test');
+    UPDATE profiles SET total_tokens = 100, tokens_remaining = 100, tokens_used = 0 WHERE id = v_user_id;
+    ALTER TABLE token_ledger ADD CONSTRAINT token_ledger_test_reject_all CHECK (false) NOT VALID;
+
+    PERFORM set_config('request.jwt.claims', json_build_object('sub', v_user_id, 'role', 'authenticated')::TEXT, true);
+    PERFORM set_config('role', 'authenticated', true);
+    SELECT * INTO v_balance FROM spend_user_tokens(v_user_id, 30, 'Test spend');
+    PERFORM set_config('role', 'none', true);
+
+    ASSERT v_balance.tokens_remaining = 70, 'spend should go through without a ledger row';
+    ASSERT NOT EXISTS (SELECT 1 FROM token_ledger WHERE user_id = v_user_id), 'ledger row should have been rejected';
+
+    ALTER TABLE token_ledger DROP CONSTRAINT token_ledger_test_reject_all;
+END $$;
+
+-- Users can no longer write their own ledger rows
+DO $$
+DECLARE
+    v_user_id UUID := gen_random_uuid();
+BEGIN
+    INSERT INTO auth.users (id, email) VALUES (v_user_id, v_user_id || '@example.test');
+
+    PERFORM set_config('request.jwt.claims', json_build_object('sub', v_user_id, 'role', 'authenticated')::TEXT, true);
+    PERFORM set_config('role', 'authenticated', true);
+    BEGIN
+        INSERT INTO token_ledger (user_id, amount, reason, balance_after) VALUES (v_user_id, 1000, 'Forged', 1000);
+        RAISE EXCEPTION 'forged ledger row was accepted';
+    EXCEPTION WHEN insufficient_privilege THEN
+        NULL;
+    END;
+    PERFORM set_config('role', 'none', true);
+END $$;
+
+ROLLBACK;
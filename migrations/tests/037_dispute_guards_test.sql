@@ -0,0 +1,74 @@
+-- Checks for migration 037. Run against a migrated local Supabase database:
+--   psql "$DATABASE_URL" -v ON_ERROR_STOP=1 -f migrations/tests/037_dispute_guards_test.sql
+-- Each check is a DO block that fails with the ASSERT message; everything is rolled back.
+
+BEGIN;
+
+-- A user can't mark their own purchase as disputed
+DO $$
+DECLARE
+    v_user_id UUID := gen_random_uuid();
+    v_purchase_id UUID;
+BEGIN
+    INSERT INTO auth.users (id, email) VALUES (v_user_id, v_user_id || '@example.test');
+    INSERT INTO purchases (user_id, stripe_payment_intent_id, stripe_price_id, stripe_product_id, amount_paid, currency, status)
+    VALUES (v_user_id, 'pi_test_dispute', 'price_test', 'prod_test', 999, 'usd', 'pending')
+    RETURNING id INTO v_purchase_id;
+
+    PERFORM set_config('request.jwt.claims', json_build_object('sub', v_user_id, 'role', 'authenticated')::TEXT, true);
+    PERFORM set_config('role', 'authenticated', true);
+    BEGIN
+        UPDATE purchases SET status = 'disputed' WHERE id = v_purchase_id;
+        RAISE EXCEPTION 'user marked their own purchase as disputed';
+    EXCEPTION WHEN insufficient_privilege THEN
+        NULL;
+    END;
+    PERFORM set_config('role', 'none', true);
+
+    ASSERT (SELECT status FROM purchases WHERE id = v_purchase_id) = 'pending',
+        'purchase status should be unchanged';
+END $$;
+
+-- The service role can
+DO $$
+DECLARE
+    v_user_id UUID := gen_random_uuid();
+    v_purchase_id UUID;
+BEGIN
+    INSERT INTO auth.users (id, email) VALUES (v_user_id, v_user_id || '@example.test');
+    INSERT INTO purchases (user_id, stripe_payment_intent_id, stripe_price_id, stripe_product_id, amount_paid, currency, status)
+    VALUES (v_user_id, 'pi_test_dispute_service', 'price_test', 'prod_test', 999, 'usd', 'pending')
+    RETURNING id INTO v_purchase_id;
+
+    PERFORM set_config('role', 'service_role', true);
+    UPDATE purchases SET status = 'disputed' WHERE id = v_purchase_id;
+    PERFORM set_config('role', 'none', true);
+
+    ASSERT (SELECT status FROM purchases WHERE id = v_purchase_id) = 'disputed',
+        'service role should be able to mark a purchase as disputed';
+END $$;
+
+-- A spend while frozen raises AU001 instead of silently doing nothing
+DO $$
+DECLARE
+    v_user_id UUID := gen_random_uuid();
+    v_raised BOOLEAN := false;
+BEGIN
+    INSERT INTO auth.users (id, email) VALUES (v_user_id, v_user_id || '@example.test');
+    UPDATE profiles SET total_tokens = 100, tokens_remaining = 100, tokens_used = 0, tokens_frozen = true
+    WHERE id = v_user_id;
+
+    PERFORM set_config('request.jwt.claims', json_build_object('sub', v_user_id, 'role', 'authenticated')::TEXT, true);
+    PERFORM set_config('role', 'authenticated', true);
+    BEGIN
+        PERFORM spend_user_tokens(v_user_id, 30, 'Test spend');
+    EXCEPTION WHEN SQLSTATE 'AU001' THEN
+        v_raised := true;
+    END;
+    PERFORM set_config('role', 'none', true);
+
+    ASSERT v_raised, 'frozen spend should raise AU001';
+    ASSERT (SELECT tokens_remaining FROM profiles WHERE id = v_user_id) = 100, 'frozen spend should deduct nothing';
+END $$;
+
+ROLLBACK;
@@ -0,0 +1,117 @@
+-- Checks for migration 038. Run against a migrated local Supabase database:
+--   psql "$DATABASE_URL" -v ON_ERROR_STOP=1 -f migrations/tests/038_username_check_limits_test.sql
+-- Each check is a DO block that fails with the ASSERT message; everything is rolled back.
+
+BEGIN;
+
+-- Taken usernames are returned, free ones aren't
+DO $$
+DECLARE
+    v_user_id UUID := gen_random_uuid();
+    v_taken TEXT[];
+BEGIN
+    INSERT INTO auth.users (id, email) VALUES (v_user_id, v_user_id || '@example.test');
+    UPDATE profiles SET username = 'taken_name' WHERE id = v_user_id;
+
+    PERFORM set_config('request.jwt.claims', json_build_object('sub', v_user_id, 'role', 'authenticated')::TEXT, true);
+    PERFORM set_config('role', 'authenticated', true);
+    SELECT array_agg(username) INTO v_taken FROM check_usernames(ARRAY['taken_name', 'free_name']);
+    PERFORM set_config('role', 'none', true);
+
+    ASSERT v_taken = ARRAY['taken_name'], 'only the taken username should be returned';
+END $$;
+
+-- The 21st check in a window is rejected with AU002 and the seconds to wait
+DO $$
+DECLARE
+    v_user_id UUID := gen_random_uuid();
+    v_hint TEXT;
+    v_raised BOOLEAN := false;
+BEGIN
+    INSERT INTO auth.users (id, email) VALUES (v_user_id, v_user_id || '@example.test');
+
+    PERFORM set_config('request.jwt.claims', json_build_object('sub', v_user_id, 'role', 'authenticated')::TEXT, true);
+    PERFORM set_config('role', 'authenticated', true);
+    FOR i IN 1..20 LOOP
+        PERFORM check_usernames(ARRAY['name_' || i]);
+    END LOOP;
+    BEGIN
+        PERFORM check_usernames(ARRAY['one_too_many']);
+    EXCEPTION WHEN SQLSTATE 'AU002' THEN
+        GET STACKED DIAGNOSTICS v_hint = PG_EXCEPTION_HINT;
+        v_raised := true;
+    END;
+    PERFORM set_config('role', 'none', true);
+
+    ASSERT v_raised, 'the 21st check should raise AU002';
+    ASSERT v_hint::INTEGER BETWEEN 1 AND 60, 'the hint should be the seconds until the window resets';
+END $$;
+
+-- A bulk check that doesn't fit counts none of its usernames
+DO $$
+DECLARE
+    v_user_id UUID := gen_random_uuid();
+    v_raised BOOLEAN := false;
+BEGIN
+    INSERT INTO auth.users (id, email) VALUES (v_user_id, v_user_id || '@example.test');
+
+    PERFORM set_config('request.jwt.claims', json_build_object('sub', v_user_id, 'role', 'authenticated')::TEXT, true);
+    PERFORM set_config('role', 'authenticated', true);
+    PERFORM check_usernames(ARRAY(SELECT 'bulk_' || i FROM generate_series(1, 18) i));
+    BEGIN
+        PERFORM check_usernames(ARRAY['a_name', 'b_name', 'c_name']);
+    EXCEPTION WHEN SQLSTATE 'AU002' THEN
+        v_raised := true;
+    END;
+    PERFORM check_usernames(ARRAY['d_name', 'e_name']);
+    PERFORM set_config('role', 'none', true);
+
+    ASSERT v_raised, 'three checks should not fit in the two left';
+    ASSERT (SELECT check_count FROM username_check_limits WHERE user_id = v_user_id) = 20,
+        'the rejected bulk check should not have been counted';
+END $$;
+
+-- An expired window starts over, and the limit is per user
+DO $$
+DECLARE
+    v_user_id UUID := gen_random_uuid();
+    v_other_id UUID := gen_random_uuid();
+BEGIN
+    INSERT INTO auth.users (id, email) VALUES (v_user_id, v_user_id || '@example.test');
+    INSERT INTO auth.users (id, email) VALUES (v_other_id, v_other_id || '@example.test');
+    INSERT INTO username_check_limits (user_id, window_start, check_count)
+    VALUES (v_user_id, NOW() - INTERVAL '61 seconds', 20);
+
+    PERFORM set_config('request.jwt.claims', json_build_object('sub', v_user_id, 'role', 'authenticated')::TEXT, true);
+    PERFORM set_config('role', 'authenticated', true);
+    PERFORM check_usernames(ARRAY['fresh_window']);
+
+    PERFORM set_config('request.jwt.claims', json_build_object('sub', v_other_id, 'role', 'authenticated')::TEXT, true);
+    PERFORM check_usernames(ARRAY['other_user']);
+    PERFORM set_config('role', 'none', true);
+
+    ASSERT (SELECT check_count FROM username_check_limits WHERE user_id = v_user_id) = 1,
+        'an expired window should start over';
+    ASSERT (SELECT check_count FROM username_check_limits WHERE user_id = v_other_id) = 1,
+        'another user should have their own window';
+END $$;
+
+-- Users can't reset their own counter
+DO $$
+DECLARE
+    v_user_id UUID := gen_random_uuid();
+BEGIN
+    INSERT INTO auth.users (id, email) VALUES (v_user_id, v_user_id || '@example.test');
+    INSERT INTO username_check_limits (user_id, window_start, check_count) VALUES (v_user_id, NOW(), 20);
+
+    PERFORM set_config('request.jwt.claims', json_build_object('sub', v_user_id, 'role', 'authenticated')::TEXT, true);
+    PERFORM set_config('role', 'authenticated', true);
+    UPDATE username_check_limits SET check_count = 0 WHERE user_id = v_user_id;
+    DELETE FROM username_check_limits WHERE user_id = v_user_id;
+    PERFORM set_config('role', 'none', true);
+
+    ASSERT (SELECT check_count FROM username_check_limits WHERE user_id = v_user_id) = 20,
+        'the counter should only change through check_usernames';
+END $$;
+
+ROLLBACK;
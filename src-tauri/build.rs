@@ -12,8 +12,39 @@ fn main() {
         println!("cargo:warning=STRIPE_PUBLISHABLE_KEY not found, using empty default");
         String::new()
     });
+    // The webhook secret is optional - only needed when webhook events are relayed to the app
+    let stripe_webhook_secret = std::env::var("STRIPE_WEBHOOK_SECRET").unwrap_or_default();
     println!("cargo:rustc-env=STRIPE_SECRET_KEY={}", stripe_secret);
     println!("cargo:rustc-env=STRIPE_PUBLISHABLE_KEY={}", stripe_publishable);
+    println!("cargo:rustc-env=STRIPE_WEBHOOK_SECRET={}", stripe_webhook_secret);
+    
+    // Optional per-mode keys; the single-key variables above are the fallback
+    for var in [
+        "STRIPE_SECRET_KEY_TEST",
+        "STRIPE_SECRET_KEY_LIVE",
+        "STRIPE_PUBLISHABLE_KEY_TEST",
+        "STRIPE_PUBLISHABLE_KEY_LIVE",
+        "STRIPE_WEBHOOK_SECRET_TEST",
+        "STRIPE_WEBHOOK_SECRET_LIVE",
+    ] {
+        println!("cargo:rustc-env={}={}", var, std::env::var(var).unwrap_or_default());
+    }
+    
+    // Optional currency for prices and payments that don't specify one (e.g. "aud")
+    println!("cargo:rustc-env=AURA_DEFAULT_CURRENCY={}", std::env::var("AURA_DEFAULT_CURRENCY").unwrap_or_default());
+    
+    // Optional per-operation HTTP timeouts in seconds
+    for var in [
+        "AURA_HTTP_TIMEOUT_READ_SECS",
+        "AURA_HTTP_TIMEOUT_WRITE_SECS",
+        "AURA_HTTP_TIMEOUT_UPLOAD_SECS",
+        "AURA_HTTP_TIMEOUT_STRIPE_SECS",
+    ] {
+        println!("cargo:rustc-env={}={}", var, std::env::var(var).unwrap_or_default());
+    }
+    
+    // Embed the migrations so the debug snapshot can probe for what each one adds
+    write_migration_list();
     
     // Print build info
     if !stripe_secret.is_empty() && !stripe_publishable.is_empty() {
@@ -24,3 +55,27 @@ fn main() {
     
     tauri_build::build()
 }
+
+// Write `MIGRATIONS`, each migration's name and SQL in file order, for diagnostics.rs
+fn write_migration_list() {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is set by cargo");
+    let dir = std::path::Path::new(&manifest_dir).join("../migrations");
+    println!("cargo:rerun-if-changed={}", dir.display());
+    
+    let mut paths: Vec<_> = std::fs::read_dir(&dir)
+        .map(|entries| entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect())
+        .unwrap_or_default();
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "sql"));
+    paths.sort();
+    
+    let entries: String = paths
+        .iter()
+        .map(|path| {
+            let name = path.file_stem().unwrap_or_default().to_string_lossy();
+            format!("    ({:?}, include_str!({:?})),\n", name, path.display().to_string())
+        })
+        .collect();
+    let out = std::path::Path::new(&std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo")).join("migrations.rs");
+    std::fs::write(out, format!("const MIGRATIONS: &[(&str, &str)] = &[\n{}];\n", entries))
+        .expect("failed to write the migration list");
+}
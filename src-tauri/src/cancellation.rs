@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// How often a cancellable sleep wakes up to check its token
+const CANCEL_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Set by cancel_request; long-running commands check it between steps
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    fn same_as(&self, other: &CancellationToken) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Sleep for `duration`, waking early if cancelled. Returns false when cancelled.
+    pub async fn sleep(&self, duration: Duration) -> bool {
+        let mut remaining = duration;
+        while !remaining.is_zero() {
+            if self.is_cancelled() {
+                return false;
+            }
+            let step = remaining.min(CANCEL_CHECK_INTERVAL);
+            tokio::time::sleep(step).await;
+            remaining -= step;
+        }
+        !self.is_cancelled()
+    }
+}
+
+/// In-flight cancellable requests keyed by the request id the frontend passed in. Two
+/// requests started with the same id each keep their own token. Managed as Tauri state.
+#[derive(Debug, Default)]
+pub struct CancellationRegistry {
+    tokens: Mutex<HashMap<String, Vec<CancellationToken>>>,
+}
+
+impl CancellationRegistry {
+    /// Track a request until the returned guard is dropped. Requests without an id
+    /// get a token nothing can cancel.
+    pub fn register(&self, request_id: Option<&str>) -> RegisteredRequest<'_> {
+        let token = CancellationToken::default();
+        if let (Some(request_id), Ok(mut tokens)) = (request_id, self.tokens.lock()) {
+            tokens.entry(request_id.to_string()).or_default().push(token.clone());
+        }
+
+        RegisteredRequest {
+            registry: self,
+            request_id: request_id.map(String::from),
+            token,
+        }
+    }
+
+    fn cancel(&self, request_id: &str) -> bool {
+        let running = self.tokens.lock().ok()
+            .and_then(|tokens| tokens.get(request_id).cloned())
+            .unwrap_or_default();
+        for token in &running {
+            token.cancel();
+        }
+        !running.is_empty()
+    }
+}
+
+/// Removes the request from the registry when the command finishes
+pub struct RegisteredRequest<'a> {
+    registry: &'a CancellationRegistry,
+    request_id: Option<String>,
+    pub token: CancellationToken,
+}
+
+impl Drop for RegisteredRequest<'_> {
+    fn drop(&mut self) {
+        if let (Some(request_id), Ok(mut tokens)) = (&self.request_id, self.registry.tokens.lock()) {
+            // Only this request's token; another request may still be using the same id
+            if let Some(running) = tokens.get_mut(request_id) {
+                running.retain(|token| !token.same_as(&self.token));
+                if running.is_empty() {
+                    tokens.remove(request_id);
+                }
+            }
+        }
+    }
+}
+
+/// Ask every running command started with this request id to stop. Returns false if no
+/// such request is running (it may already have finished).
+#[tauri::command]
+pub fn cancel_request(
+    request_id: String,
+    registry: tauri::State<'_, CancellationRegistry>,
+) -> bool {
+    registry.cancel(&request_id)
+}
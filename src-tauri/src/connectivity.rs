@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::Emitter;
+use tauri_plugin_store::StoreExt;
+
+// Probed alongside Supabase so a Supabase outage isn't mistaken for being offline
+const NEUTRAL_PROBE_URL: &str = "https://www.gstatic.com/generate_204";
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+const CACHE_TTL: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+// Polls double up to this while the device stays online, and drop back on any change
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+static LAST_STATUS: Mutex<Option<(Instant, ConnectivityStatus)>> = Mutex::new(None);
+
+// Polling pauses while the app is in the background; the monitor is woken when it returns
+static IN_FOREGROUND: AtomicBool = AtomicBool::new(true);
+static FOREGROUND_CHANGED: tokio::sync::Notify = tokio::sync::Notify::const_new();
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectivityStatus {
+    pub online: bool,
+    pub supabase_reachable: bool,
+    pub internet_reachable: bool,
+    /// Fastest successful probe round trip, None when offline
+    pub latency_ms: Option<u64>,
+    pub checked_at: i64,
+}
+
+/// Whether the device can reach Supabase and the wider internet. Results are cached
+/// for a few seconds so the frontend can call this before every network action.
+#[tauri::command]
+pub async fn get_connectivity(app: tauri::AppHandle) -> Result<ConnectivityStatus, String> {
+    if let Ok(last) = LAST_STATUS.lock() {
+        if let Some((checked, status)) = last.as_ref() {
+            if checked.elapsed() < CACHE_TTL {
+                return Ok(status.clone());
+            }
+        }
+    }
+
+    Ok(refresh_connectivity(&app).await)
+}
+
+/// Poll connectivity while the app is in the foreground and emit "connectivity-changed"
+/// when the device goes online or offline. Coming back online also flushes purchases
+/// queued while offline.
+pub fn start_connectivity_monitor(app: &tauri::AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let mut was_online: Option<bool> = None;
+        let mut interval = POLL_INTERVAL;
+        loop {
+            if !IN_FOREGROUND.load(Ordering::SeqCst) {
+                FOREGROUND_CHANGED.notified().await;
+                // The connection may have changed while paused, so check again promptly
+                interval = POLL_INTERVAL;
+                continue;
+            }
+
+            let status = refresh_connectivity(&app).await;
+
+            let changed = was_online.is_some_and(|online| online != status.online);
+            if changed {
+                let _ = app.emit("connectivity-changed", status.clone());
+                if status.online {
+                    let _ = crate::stripe::flush_pending_purchases(app.clone()).await;
+                }
+            }
+            was_online = Some(status.online);
+            interval = next_poll_interval(interval, changed, status.online);
+
+            // Wakes early when the app moves to or from the background
+            let _ = tokio::time::timeout(interval, FOREGROUND_CHANGED.notified()).await;
+        }
+    });
+}
+
+/// Called from window focus events, which is how mobile reports the app moving to and
+/// from the background
+pub fn set_foreground(foreground: bool) {
+    if IN_FOREGROUND.swap(foreground, Ordering::SeqCst) != foreground {
+        FOREGROUND_CHANGED.notify_one();
+    }
+}
+
+// Offline keeps the short interval so reconnecting is noticed quickly
+fn next_poll_interval(current: Duration, changed: bool, online: bool) -> Duration {
+    if changed || !online {
+        POLL_INTERVAL
+    } else {
+        (current * 2).min(MAX_POLL_INTERVAL)
+    }
+}
+
+async fn refresh_connectivity(app: &tauri::AppHandle) -> ConnectivityStatus {
+    // The health endpoint needs no session, so read the project URL straight from the store
+    let supabase_health_url = app.store("database.store").ok()
+        .and_then(|store| store.get("database_url"))
+        .and_then(|v| v.as_str().map(|url| format!("{}/auth/v1/health", url)));
+
+    let supabase_latency = match supabase_health_url {
+        Some(url) => probe(&url).await,
+        None => None,
+    };
+    let neutral_latency = probe(NEUTRAL_PROBE_URL).await;
+
+    let status = classify_connectivity(supabase_latency, neutral_latency, chrono::Utc::now().timestamp());
+    if let Ok(mut last) = LAST_STATUS.lock() {
+        *last = Some((Instant::now(), status.clone()));
+    }
+    status
+}
+
+// Any HTTP response counts as reachable; only connection failures and timeouts don't.
+// Probes use their own short timeout rather than the read timeout, so a dead network is
+// noticed quickly.
+async fn probe(url: &str) -> Option<Duration> {
+    let started = Instant::now();
+    crate::http::client().get(url).timeout(PROBE_TIMEOUT).send().await.ok()?;
+    Some(started.elapsed())
+}
+
+fn classify_connectivity(
+    supabase_latency: Option<Duration>,
+    neutral_latency: Option<Duration>,
+    checked_at: i64,
+) -> ConnectivityStatus {
+    let latency = match (supabase_latency, neutral_latency) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    };
+
+    ConnectivityStatus {
+        online: latency.is_some(),
+        supabase_reachable: supabase_latency.is_some(),
+        internet_reachable: neutral_latency.is_some(),
+        latency_ms: latency.map(|latency| latency.as_millis() as u64),
+        checked_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn either_probe_succeeding_means_online_with_the_faster_latency() {
+        let status = classify_connectivity(Some(Duration::from_millis(120)), Some(Duration::from_millis(40)), 1_700_000_000);
+        assert_eq!(status, ConnectivityStatus {
+            online: true,
+            supabase_reachable: true,
+            internet_reachable: true,
+            latency_ms: Some(40),
+            checked_at: 1_700_000_000,
+        });
+
+        let supabase_down = classify_connectivity(None, Some(Duration::from_millis(80)), 0);
+        assert!(supabase_down.online && !supabase_down.supabase_reachable && supabase_down.internet_reachable);
+        assert_eq!(supabase_down.latency_ms, Some(80));
+
+        let neutral_blocked = classify_connectivity(Some(Duration::from_millis(90)), None, 0);
+        assert!(neutral_blocked.online && neutral_blocked.supabase_reachable && !neutral_blocked.internet_reachable);
+    }
+
+    #[test]
+    fn both_probes_failing_means_offline() {
+        let status = classify_connectivity(None, None, 0);
+        assert!(!status.online && !status.supabase_reachable && !status.internet_reachable);
+        assert_eq!(status.latency_ms, None);
+    }
+
+    #[test]
+    fn poll_interval_backs_off_while_stable_online_and_resets_otherwise() {
+        assert_eq!(next_poll_interval(POLL_INTERVAL, false, true), POLL_INTERVAL * 2);
+        assert_eq!(next_poll_interval(MAX_POLL_INTERVAL, false, true), MAX_POLL_INTERVAL);
+        assert_eq!(next_poll_interval(Duration::from_secs(200), false, true), MAX_POLL_INTERVAL);
+        assert_eq!(next_poll_interval(MAX_POLL_INTERVAL, true, true), POLL_INTERVAL);
+        assert_eq!(next_poll_interval(POLL_INTERVAL * 4, false, false), POLL_INTERVAL);
+    }
+}
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
 use tauri::command;
 use tauri_plugin_store::StoreExt;
 
@@ -15,16 +16,417 @@ pub struct Profile {
     pub subscription_id: Option<String>,
     pub subscription_status: Option<String>,
     pub subscription_period_end: Option<i64>,
+    pub subscription_price_id: Option<String>,
+    pub subscription_plan_id: Option<String>,
+    pub subscription_trial_end: Option<i64>,
+    pub subscription_trial_ending: Option<bool>,
+    pub subscription_payment_failure_reason: Option<String>,
+    pub subscription_payment_failed_at: Option<String>,
     // Token balance fields
     pub total_tokens: Option<i64>,
     pub tokens_remaining: Option<i64>,
     pub tokens_used: Option<i64>,
+    pub tokens_frozen: Option<bool>,
     // Purchase tracking fields
     pub total_purchases: Option<i32>,
     pub total_spent_cents: Option<i64>,
     pub last_purchase_at: Option<String>,
 }
 
+/// The fields of a profile that are safe to show to other users.
+/// Use this for anything that isn't the signed-in user's own profile; `Profile`
+/// also carries their Stripe, subscription and token data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicProfile {
+    pub id: String,
+    pub username: Option<String>,
+    pub full_name: Option<String>,
+    pub avatar_url: Option<String>,
+    pub is_contractor: Option<bool>,
+}
+
+// Column list for PublicProfile, so other users' billing and token data is never fetched
+const PUBLIC_PROFILE_COLUMNS: &str = "id,username,full_name,avatar_url,is_contractor";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenBalance {
+    pub total_tokens: i64,
+    pub tokens_remaining: i64,
+    pub tokens_used: i64,
+}
+
+/// Server balance returned by reconcile_token_balance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenReconciliation {
+    pub balance: TokenBalance,
+    /// Server tokens_remaining minus the client's local value (0 when none was given)
+    pub drift: i64,
+}
+
+// Raw balance columns as stored on the profile, any of which may be null
+#[derive(Debug, Deserialize)]
+struct TokenBalanceRow {
+    total_tokens: Option<i64>,
+    tokens_remaining: Option<i64>,
+    tokens_used: Option<i64>,
+}
+
+impl From<TokenBalanceRow> for TokenBalance {
+    fn from(row: TokenBalanceRow) -> Self {
+        TokenBalance {
+            total_tokens: row.total_tokens.unwrap_or(0),
+            tokens_remaining: row.tokens_remaining.unwrap_or(0),
+            tokens_used: row.tokens_used.unwrap_or(0),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenLedgerEntry {
+    pub id: String,
+    pub user_id: String,
+    pub amount: i64,
+    pub reason: String,
+    pub balance_after: i64,
+    /// admin_adjustment for entries written by admin_adjust_tokens, otherwise None
+    pub entry_type: Option<String>,
+    pub created_at: Option<String>,
+}
+
+/// One delivery of a verified Stripe webhook event and how it was processed
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookEvent {
+    pub id: String,
+    /// Repeats across rows when Stripe redelivers an event
+    pub event_id: String,
+    pub event_type: String,
+    /// When Stripe created the event, as a Unix timestamp
+    pub stripe_created: i64,
+    /// handled, ignored or failed
+    pub status: String,
+    pub message: Option<String>,
+    /// Only stored while webhook payload storage is enabled
+    pub payload: Option<serde_json::Value>,
+    pub received_at: Option<String>,
+    pub processed_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubscriptionEvent {
+    pub id: String,
+    pub user_id: String,
+    pub subscription_id: Option<String>,
+    /// created, upgraded, downgraded, canceled or resumed
+    pub action: String,
+    pub old_price_id: Option<String>,
+    pub new_price_id: Option<String>,
+    pub created_at: Option<String>,
+    /// `created_at` in the zone the caller asked for, for display
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at_local: Option<String>,
+}
+
+/// Error returned by token commands, tagged by `kind` so the frontend can branch on it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TokenError {
+    /// The balance does not cover the requested spend; nothing was deducted
+    InsufficientTokens { requested: i64, available: i64 },
+    /// Spending is suspended while a chargeback on one of the user's purchases is open
+    TokensFrozen { message: String },
+    /// No session, or the access token was rejected
+    NotAuthenticated { message: String },
+    /// init_database hasn't stored the Supabase URL or anon key
+    NotConfigured { message: String },
+    Other { message: String },
+}
+
+impl std::fmt::Display for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenError::InsufficientTokens { requested, available } => write!(
+                f,
+                "Insufficient tokens: requested {}, available {}",
+                requested, available
+            ),
+            TokenError::TokensFrozen { message }
+            | TokenError::NotAuthenticated { message }
+            | TokenError::NotConfigured { message }
+            | TokenError::Other { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<DbConfigError> for TokenError {
+    fn from(error: DbConfigError) -> Self {
+        DatabaseError::from(error).into()
+    }
+}
+
+impl From<DatabaseError> for TokenError {
+    fn from(error: DatabaseError) -> Self {
+        match error {
+            DatabaseError::NotAuthenticated { message } => TokenError::NotAuthenticated { message },
+            DatabaseError::NotConfigured { message } => TokenError::NotConfigured { message },
+            other => TokenError::Other { message: other.to_string() },
+        }
+    }
+}
+
+crate::errors::command_error!(TokenError);
+
+/// Error returned by payment method commands, tagged by `kind` so the frontend can branch on it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PaymentMethodError {
+    /// The payment method is not stored for this user
+    Forbidden { message: String },
+    /// No session, or the access token was rejected
+    NotAuthenticated { message: String },
+    /// init_database hasn't stored the Supabase URL or anon key
+    NotConfigured { message: String },
+    Other { message: String },
+}
+
+crate::errors::command_error!(PaymentMethodError { Forbidden, NotAuthenticated, NotConfigured, Other });
+
+impl From<DbConfigError> for PaymentMethodError {
+    fn from(error: DbConfigError) -> Self {
+        match DatabaseError::from(error) {
+            DatabaseError::NotAuthenticated { message } => PaymentMethodError::NotAuthenticated { message },
+            DatabaseError::NotConfigured { message } => PaymentMethodError::NotConfigured { message },
+            other => PaymentMethodError::Other { message: other.to_string() },
+        }
+    }
+}
+
+/// Which piece of the Supabase connection is missing, tagged by `kind` so the frontend can
+/// send the user to re-run init_database (URL, anon key) or to sign in again (access token)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DbConfigError {
+    NoDatabaseUrl { message: String },
+    NoAnonKey { message: String },
+    NoAccessToken { message: String },
+    /// The store file itself couldn't be opened
+    Store { message: String },
+}
+
+impl std::fmt::Display for DbConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DbConfigError::NoDatabaseUrl { message }
+            | DbConfigError::NoAnonKey { message }
+            | DbConfigError::NoAccessToken { message }
+            | DbConfigError::Store { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<DbConfigError> for String {
+    fn from(error: DbConfigError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Error returned when Supabase refuses a request, tagged by `kind` so the frontend can tell
+/// an expired session (sign in again) apart from an RLS denial (the row isn't theirs)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DatabaseError {
+    /// No session, or the access token was rejected
+    NotAuthenticated { message: String },
+    /// Signed in, but row level security doesn't allow this on `table`
+    Forbidden { message: String, table: String },
+    /// init_database hasn't stored the Supabase URL or anon key
+    NotConfigured { message: String },
+    /// Input was rejected before anything was sent; `errors` names each bad field
+    Validation { message: String, errors: Vec<FieldError> },
+    Other { message: String },
+}
+
+/// One invalid input, so the UI can highlight the field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+crate::errors::command_error!(DatabaseError { NotAuthenticated, Forbidden, NotConfigured, Validation, Other });
+
+impl From<DbConfigError> for DatabaseError {
+    fn from(error: DbConfigError) -> Self {
+        match error {
+            DbConfigError::NoAccessToken { message } => DatabaseError::NotAuthenticated { message },
+            DbConfigError::NoDatabaseUrl { message } | DbConfigError::NoAnonKey { message } => {
+                DatabaseError::NotConfigured { message }
+            },
+            DbConfigError::Store { message } => DatabaseError::Other { message },
+        }
+    }
+}
+
+impl DatabaseError {
+    fn not_authenticated() -> Self {
+        DatabaseError::NotAuthenticated {
+            message: "You're not signed in or your session has expired - please sign in again".to_string(),
+        }
+    }
+}
+
+/// Classify a failed PostgREST response as an auth failure, if it is one.
+/// 401 means the token was missing or rejected; 403 or Postgres' insufficient_privilege
+/// code (42501) means RLS denied a signed-in user.
+pub(crate) fn access_error(status: u16, body: &str, table: &str) -> Option<DatabaseError> {
+    let code = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|value| value.get("code").and_then(|c| c.as_str()).map(str::to_string));
+
+    let error = if status == 401 && code.as_deref() != Some("42501") {
+        DatabaseError::not_authenticated()
+    } else if status == 401 || status == 403 || code.as_deref() == Some("42501") {
+        DatabaseError::Forbidden {
+            message: format!("You don't have permission to access {} - the record may belong to another account", table),
+            table: table.to_string(),
+        }
+    } else {
+        return None;
+    };
+
+    println!("Supabase denied access to {} (HTTP {}): {}", table, status, body);
+    Some(error)
+}
+
+/// Turn a failed Supabase response on `table` into a DatabaseError
+pub(crate) async fn response_error(response: reqwest::Response, table: &str) -> DatabaseError {
+    let status = response.status();
+    let error_text = response.text().await.unwrap_or_default();
+    access_error(status.as_u16(), &error_text, table).unwrap_or_else(|| DatabaseError::Other {
+        message: format!("Database error on {}: {}", table, error_text),
+    })
+}
+
+// Username checks allowed per window, so the endpoint can't be used to enumerate taken usernames
+const USERNAME_CHECK_LIMIT: u32 = 20;
+const USERNAME_CHECK_WINDOW_MS: i64 = 60_000;
+
+// Most usernames one bulk check may look up
+const MAX_USERNAMES_PER_CHECK: usize = 10;
+
+// Serializes the read-modify-write of the rate limit window in the store
+static USERNAME_CHECK_LOCK: Mutex<()> = Mutex::new(());
+
+// How long token_price_map rows are reused before the table is read again
+const TOKEN_PRICE_MAP_CACHE_TTL_SECS: i64 = 300;
+
+// token_price_map rows as tokens keyed by (amount_cents, currency)
+type TokenPriceMap = HashMap<(i64, String), i64>;
+
+// The token price map with the timestamp it was read at
+static TOKEN_PRICE_MAP_CACHE: Mutex<Option<(i64, TokenPriceMap)>> = Mutex::new(None);
+
+// How long a past_due subscription keeps access after its period end, unless configured
+const DEFAULT_SUBSCRIPTION_GRACE_PERIOD_DAYS: u64 = 7;
+const MAX_SUBSCRIPTION_GRACE_PERIOD_DAYS: u64 = 30;
+const SUBSCRIPTION_GRACE_PERIOD_SETTING: &str = "subscription_grace_period_days";
+
+// How long the app_settings grace period is reused before it's read again
+const GRACE_PERIOD_CACHE_TTL_SECS: i64 = 300;
+
+// The grace period in days, with the timestamp it was read at
+static GRACE_PERIOD_CACHE: Mutex<Option<(i64, u64)>> = Mutex::new(None);
+
+// Matches the username_length constraint on profiles
+const MIN_USERNAME_LENGTH: usize = 3;
+
+/// Result of checking one username in a bulk availability check
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsernameAvailability {
+    Available,
+    Taken,
+    /// Fails format validation, so it was not looked up
+    Invalid,
+}
+
+/// Error returned by username availability checks, tagged by `kind` like TokenError
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum UsernameCheckError {
+    /// Too many checks this window; try again after `retry_after_secs`
+    RateLimited { retry_after_secs: u64 },
+    /// No session, or the access token was rejected
+    NotAuthenticated { message: String },
+    /// init_database hasn't stored the Supabase URL or anon key
+    NotConfigured { message: String },
+    Other { message: String },
+}
+
+impl std::fmt::Display for UsernameCheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UsernameCheckError::RateLimited { retry_after_secs } => write!(
+                f,
+                "Too many username checks, try again in {} seconds",
+                retry_after_secs
+            ),
+            UsernameCheckError::NotAuthenticated { message }
+            | UsernameCheckError::NotConfigured { message }
+            | UsernameCheckError::Other { message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl From<DbConfigError> for UsernameCheckError {
+    fn from(error: DbConfigError) -> Self {
+        match DatabaseError::from(error) {
+            DatabaseError::NotAuthenticated { message } => UsernameCheckError::NotAuthenticated { message },
+            DatabaseError::NotConfigured { message } => UsernameCheckError::NotConfigured { message },
+            other => UsernameCheckError::Other { message: other.to_string() },
+        }
+    }
+}
+
+crate::errors::command_error!(UsernameCheckError);
+
+/// Outcome of one step of closing an account
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountDeletionStep {
+    pub step: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// What delete_account managed to do, in the order the steps ran
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountDeletionReport {
+    pub steps: Vec<AccountDeletionStep>,
+    /// Whether the user's database records and auth user are gone
+    pub account_deleted: bool,
+}
+
+impl AccountDeletionReport {
+    fn record<E: std::fmt::Display>(&mut self, step: &str, result: Result<(), E>) -> bool {
+        let success = result.is_ok();
+        self.steps.push(AccountDeletionStep {
+            step: step.to_string(),
+            success,
+            error: result.err().map(|e| e.to_string()),
+        });
+        success
+    }
+}
+
+// Local stores holding the user's own data, cleared once their account is deleted. The
+// rate limit store is left alone, as store_clear_all does, so deleting an account and
+// signing up again doesn't reset the username check limit.
+const USER_DATA_STORES: [&str; 4] = [
+    "app_data.store",
+    "app_config.store",
+    "ui_state.store",
+    "api_cache.store",
+];
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DatabaseConfig {
     pub database_url: String,
@@ -49,18 +451,6 @@ pub struct PaymentMethod {
     pub last_used_at: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CreatePaymentMethodRequest {
-    pub user_id: String,
-    pub stripe_customer_id: String,
-    pub stripe_payment_method_id: String,
-    pub card_brand: String,
-    pub card_last4: String,
-    pub card_exp_month: i32,
-    pub card_exp_year: i32,
-    pub is_default: Option<bool>,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Purchase {
     pub id: String,
@@ -77,14 +467,30 @@ pub struct Purchase {
     pub completed_at: Option<String>,
     pub created_at: Option<String>,
     pub updated_at: Option<String>,
+    /// `completed_at` and `created_at` in the zone the caller asked for, for display
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completed_at_local: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at_local: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UpdatePaymentMethodRequest {
-    pub payment_method_id: String,
-    pub user_id: String,
-    pub is_default: Option<bool>,
-    pub is_active: Option<bool>,
+/// Lifetime purchase totals plus a month by month breakdown, oldest month first.
+/// Amounts are in cents, keyed by lowercase currency code, since cents of different
+/// currencies can't be added together.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpendSummary {
+    pub total_spent_cents: BTreeMap<String, i64>,
+    pub total_purchases: i64,
+    pub total_tokens_purchased: i64,
+    pub monthly: Vec<MonthlySpend>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MonthlySpend {
+    pub month: String, // YYYY-MM
+    pub spent_cents: BTreeMap<String, i64>,
+    pub purchases: i64,
+    pub tokens_purchased: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -100,6 +506,36 @@ pub struct SubscriptionPlan {
     pub updated_at: Option<String>,
 }
 
+/// What the user's plan unlocks, normalized so the frontend checks fields instead of
+/// parsing plan features itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entitlements {
+    /// None on the free tier
+    pub plan_id: Option<String>,
+    pub plan_name: Option<String>,
+    /// None means unlimited
+    pub max_projects: Option<u32>,
+    pub priority_support: bool,
+    pub advanced_export: bool,
+    pub tokens_remaining: i64,
+}
+
+// Project limit for users without an active subscription
+const FREE_TIER_MAX_PROJECTS: u32 = 3;
+
+impl Entitlements {
+    fn free(tokens_remaining: i64) -> Self {
+        Entitlements {
+            plan_id: None,
+            plan_name: None,
+            max_projects: Some(FREE_TIER_MAX_PROJECTS),
+            priority_support: false,
+            advanced_export: false,
+            tokens_remaining,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ContractorKycFormData {
     #[serde(rename = "contractorType", alias = "contractor_type")]
@@ -165,6 +601,9 @@ pub struct Contractor {
     pub stripe_connect_account_id: Option<String>,
     pub stripe_connect_account_status: Option<String>,
     pub stripe_connect_requirements_completed: Option<bool>,
+    /// Capability name to status, e.g. {"transfers": "inactive"}
+    #[serde(default)]
+    pub stripe_capabilities: Option<serde_json::Value>,
     
     // Business information
     pub business_name: Option<String>,
@@ -172,6 +611,8 @@ pub struct Contractor {
     pub business_website_url: Option<String>,
     pub business_description: Option<String>,
     pub industry_mcc_code: Option<String>,
+    #[serde(default)]
+    pub business_support_email: Option<String>,
     pub company_registration_number: Option<String>,
     pub company_structure: Option<String>,
     
@@ -187,6 +628,30 @@ pub struct Contractor {
     pub updated_at: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KycFormSaveResult {
+    /// False when newer form data was already saved, so this save was skipped as stale
+    pub applied: bool,
+    pub version: i64,
+}
+
+/// Outcome of create_contractor_profile. The Connect account and contractor row are
+/// required, so the command fails without them; the address, profile flag and draft
+/// cleanup are reported here instead, so the frontend knows what to retry.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContractorCreationResult {
+    pub contractor: Contractor,
+    pub connect_account_created: bool,
+    pub contractor_created: bool,
+    /// None when the KYC data had no address
+    pub address_created: Option<bool>,
+    pub profile_updated: bool,
+    /// Whether the saved KYC draft was removed
+    pub kyc_draft_deleted: bool,
+    /// Why each failed step failed
+    pub failures: Vec<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubscriptionPrice {
     pub id: String,
@@ -243,6 +708,123 @@ pub struct PackageWithPrices {
     pub prices: Vec<PackagePrice>,
 }
 
+/// A PostgREST filter on one column, e.g. `{ "column": "status", "op": "eq", "value": "completed" }`.
+/// For `in` the value is a comma separated list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Filter {
+    pub column: String,
+    pub op: FilterOp,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterOp {
+    Eq,
+    Neq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Like,
+    Ilike,
+    Is,
+    In,
+}
+
+impl Filter {
+    /// The query parameter for this filter. Values are quoted so commas and
+    /// parentheses can't smuggle in extra conditions.
+    fn to_query_pair(&self) -> Result<(String, String), String> {
+        if !is_identifier(&self.column) {
+            return Err(format!("Invalid filter column: {}", self.column));
+        }
+
+        let quote = |value: &str| format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""));
+        let value = match self.op {
+            FilterOp::Eq => format!("eq.{}", quote(&self.value)),
+            FilterOp::Neq => format!("neq.{}", quote(&self.value)),
+            FilterOp::Gt => format!("gt.{}", quote(&self.value)),
+            FilterOp::Gte => format!("gte.{}", quote(&self.value)),
+            FilterOp::Lt => format!("lt.{}", quote(&self.value)),
+            FilterOp::Lte => format!("lte.{}", quote(&self.value)),
+            FilterOp::Like => format!("like.{}", quote(&self.value)),
+            FilterOp::Ilike => format!("ilike.{}", quote(&self.value)),
+            FilterOp::Is => match self.value.as_str() {
+                "null" | "true" | "false" => format!("is.{}", self.value),
+                other => return Err(format!("is filters only accept null, true or false, got {}", other)),
+            },
+            FilterOp::In => format!(
+                "in.({})",
+                self.value.split(',').map(|value| quote(value.trim())).collect::<Vec<_>>().join(",")
+            ),
+        };
+
+        Ok((self.column.clone(), value))
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// Tables supabase_query may read. Row level security still applies to every query.
+const QUERYABLE_TABLES: &[&str] = &[
+    "packages",
+    "package_prices",
+    "subscription_plans",
+    "subscription_prices",
+    "purchases",
+    "token_ledger",
+    "subscription_gifts",
+];
+
+/// One active price ranked by how many tokens it gives per cent spent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageValue {
+    pub rank: usize, // 1 is the best value
+    pub package_id: String,
+    pub package_name: String,
+    pub package_price_id: String,
+    pub stripe_price_id: String,
+    pub amount_cents: i64,
+    pub currency: String,
+    pub token_amount: i64,
+    /// None for free prices, which are ranked last rather than dividing by zero
+    pub tokens_per_cent: Option<f64>,
+}
+
+/// A package and its prices to create or update in seed_catalog
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogPackageDefinition {
+    pub name: String,
+    pub description: Option<String>,
+    pub stripe_product_id: String,
+    pub features: Option<serde_json::Value>,
+    pub sort_order: Option<i32>,
+    pub is_active: Option<bool>,
+    pub prices: Vec<CatalogPriceDefinition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogPriceDefinition {
+    pub stripe_price_id: String,
+    pub amount_cents: i64,
+    pub currency: String,
+    pub interval_type: Option<String>, // one_time (default), month, year
+    pub interval_count: Option<i32>,
+    pub token_amount: i64,
+    pub is_active: Option<bool>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CatalogSeedResult {
+    pub packages_created: usize,
+    pub packages_updated: usize,
+    pub prices_created: usize,
+    pub prices_updated: usize,
+}
+
 /// Initialize database connection with authentication
 /// Note: For Supabase, this stores connection config only
 /// The schema should be set up directly in Supabase SQL Editor
@@ -273,26 +855,36 @@ pub async fn init_database(
 }
 
 /// Get authenticated database connection
-pub async fn get_authenticated_db(app: &tauri::AppHandle) -> Result<DatabaseConfig, String> {
-    // Get database URL from database store
-    let db_store = app.store("database.store").map_err(|e| e.to_string())?;
-    let database_url = db_store
-        .get("database_url")
-        .and_then(|v| v.as_str().map(String::from))
-        .ok_or_else(|| "Database not initialized".to_string())?;
-
-    // Get access token from session store
-    let session_store = app.store("session.store").map_err(|e| e.to_string())?;
-    let access_token = session_store
-        .get("sb-access-token")
-        .and_then(|v| v.as_str().map(String::from))
-        .ok_or_else(|| "No authentication token found in session store".to_string())?;
+pub async fn get_authenticated_db<R: tauri::Runtime>(app: &tauri::AppHandle<R>) -> Result<DatabaseConfig, DbConfigError> {
+    let store_error = |e: tauri_plugin_store::Error| DbConfigError::Store { message: e.to_string() };
+
+    let db_store = app.store("database.store").map_err(store_error)?;
+    let session_store = app.store("session.store").map_err(store_error)?;
+    let string = |value: Option<serde_json::Value>| value.and_then(|v| v.as_str().map(String::from));
+
+    // The URL and anon key come from init_database, the access token from the session
+    database_config(
+        string(db_store.get("database_url")),
+        string(session_store.get("sb-access-token")),
+        string(db_store.get("anon_key")),
+    )
+}
 
-    // Get anon key from database store
-    let anon_key = db_store
-        .get("anon_key")
-        .and_then(|v| v.as_str().map(String::from))
-        .ok_or_else(|| "No anon key found in database store".to_string())?;
+// The first missing piece of the connection decides the error
+fn database_config(
+    database_url: Option<String>,
+    access_token: Option<String>,
+    anon_key: Option<String>,
+) -> Result<DatabaseConfig, DbConfigError> {
+    let database_url = database_url.ok_or_else(|| DbConfigError::NoDatabaseUrl {
+        message: "Database not initialized".to_string(),
+    })?;
+    let access_token = access_token.ok_or_else(|| DbConfigError::NoAccessToken {
+        message: "No authentication token found in session store".to_string(),
+    })?;
+    let anon_key = anon_key.ok_or_else(|| DbConfigError::NoAnonKey {
+        message: "No anon key found in database store".to_string(),
+    })?;
 
     Ok(DatabaseConfig {
         database_url,
@@ -301,11 +893,40 @@ pub async fn get_authenticated_db(app: &tauri::AppHandle) -> Result<DatabaseConf
     })
 }
 
-/// Get user profile with authentication check
+/// Email of the signed-in Supabase user, or None when the session belongs to a
+/// different user or the account has no email
+pub(crate) async fn get_auth_user_email(
+    user_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<Option<String>, String> {
+    let user = crate::session::get_auth_user(app.clone()).await?;
+
+    if user.id != user_id {
+        return Ok(None);
+    }
+
+    Ok(user.email.filter(|email| !email.is_empty()))
+}
+
+/// Fail unless `user_id` is the signed-in user, as confirmed by Supabase Auth. Commands
+/// that act on a user's billing with the Stripe secret key call this first, since
+/// profiles (and so customer and subscription ids) are readable by other users.
+pub(crate) async fn ensure_session_user(user_id: &str, app: &tauri::AppHandle) -> Result<(), String> {
+    let user = crate::session::get_auth_user(app.clone()).await?;
+
+    if user.id != user_id {
+        return Err("You can only do this for your own account".to_string());
+    }
+
+    Ok(())
+}
+
+/// Get the signed-in user's own profile with authentication check.
+/// For other users' profiles use `get_public_profile`.
 #[command]
-pub async fn get_user_profile(
+pub async fn get_user_profile<R: tauri::Runtime>(
     user_id: String,
-    app: tauri::AppHandle,
+    app: tauri::AppHandle<R>,
 ) -> Result<Option<Profile>, String> {
     let db_config = get_authenticated_db(&app).await?;
 
@@ -316,13 +937,14 @@ pub async fn get_user_profile(
     }
 
     // Use HTTP request to Supabase REST API
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     
     let url = format!("{}/rest/v1/profiles", db_config.database_url);
     let auth_header = format!("Bearer {}", db_config.access_token);
 
     let response = client
         .get(&url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", &auth_header)
         .header("apikey", &db_config.anon_key)
         .query(&[("id", format!("eq.{}", user_id))])
@@ -347,6 +969,58 @@ pub async fn get_user_profile(
     Ok(profiles.into_iter().next())
 }
 
+/// Get another user's public profile by id
+#[command]
+pub async fn get_public_profile(
+    user_id: String,
+    app: tauri::AppHandle,
+) -> Result<Option<PublicProfile>, String> {
+    fetch_public_profile("id", &user_id, &app).await
+}
+
+/// Get another user's public profile by username
+#[command]
+pub async fn get_profile_by_username(
+    username: String,
+    app: tauri::AppHandle,
+) -> Result<Option<PublicProfile>, String> {
+    fetch_public_profile("username", &username, &app).await
+}
+
+async fn fetch_public_profile(
+    column: &str,
+    value: &str,
+    app: &tauri::AppHandle,
+) -> Result<Option<PublicProfile>, String> {
+    let db_config = get_authenticated_db(app).await?;
+
+    let client = crate::http::client();
+
+    let response = client
+        .get(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[(column, format!("eq.{}", value))])
+        .query(&[("select", PUBLIC_PROFILE_COLUMNS)])
+        .send()
+        .await
+        .map_err(|e| format!("HTTP request failed: {}", e))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let error_body = response.text().await.unwrap_or_else(|_| "Could not read error body".to_string());
+        return Err(format!("Database query failed: {} - {}", status, error_body));
+    }
+
+    let profiles: Vec<PublicProfile> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse response: {}", e))?;
+
+    Ok(profiles.into_iter().next())
+}
+
 /// Update user profile with authentication check
 #[command]
 pub async fn update_user_profile(
@@ -365,6 +1039,8 @@ pub async fn update_user_profile(
         return Err("Authentication required".to_string());
     }
 
+    let name_changed = username.is_some() || full_name.is_some();
+
     // Build update payload
     let mut update_data = serde_json::Map::new();
     if let Some(username) = username {
@@ -393,10 +1069,11 @@ pub async fn update_user_profile(
         serde_json::Value::String("now()".to_string()),
     );
 
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
 
     let response = client
-        .patch(&format!("{}/rest/v1/profiles", db_config.database_url))
+        .patch(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header(
             "Authorization",
             format!("Bearer {}", db_config.access_token),
@@ -420,20 +1097,29 @@ pub async fn update_user_profile(
         .await
         .map_err(|e| format!("Failed to parse response: {}", e))?;
 
-    profiles
+    let profile = profiles
         .into_iter()
         .next()
-        .ok_or_else(|| "Profile not found or access denied".to_string())
+        .ok_or_else(|| "Profile not found or access denied".to_string())?;
+
+    // Keep the Stripe customer's name in step; the profile update itself already succeeded
+    if name_changed && profile.stripe_customer_id.is_some() {
+        if let Err(e) = crate::stripe::sync_customer_metadata(user_id, app.clone()).await {
+            println!("Failed to sync Stripe customer after profile update: {}", e);
+        }
+    }
+
+    Ok(profile)
 }
 
 /// Create user profile (typically called after signup)
 #[command]
-pub async fn create_user_profile(
+pub async fn create_user_profile<R: tauri::Runtime>(
     user_id: String,
     full_name: Option<String>,
     avatar_url: Option<String>,
     onboarding_complete: Option<bool>,
-    app: tauri::AppHandle,
+    app: tauri::AppHandle<R>,
 ) -> Result<Profile, String> {
     let db_config = get_authenticated_db(&app).await?;
 
@@ -465,10 +1151,11 @@ pub async fn create_user_profile(
         );
     }
 
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
 
     let response = client
-        .post(&format!("{}/rest/v1/profiles", db_config.database_url))
+        .post(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header(
             "Authorization",
             format!("Bearer {}", db_config.access_token),
@@ -502,41 +1189,395 @@ pub async fn create_user_profile(
 pub async fn check_username_availability(
     username: String,
     app: tauri::AppHandle,
-) -> Result<bool, String> {
+) -> Result<bool, UsernameCheckError> {
     let db_config = get_authenticated_db(&app).await?;
 
     // Verify user is authenticated
     let session_check = crate::session::check_session(app.clone()).await?;
 
     if !session_check {
-        return Err("Authentication required".to_string());
+        return Err("Authentication required".into());
     }
 
-    let client = reqwest::Client::new();
+    consume_username_checks(&app, 1)?;
 
-    let response = client
-        .get(&format!("{}/rest/v1/profiles", db_config.database_url))
-        .header(
-            "Authorization",
-            format!("Bearer {}", db_config.access_token),
-        )
-        .header("apikey", db_config.anon_key.clone())
-        .query(&[("username", format!("eq.{}", username))])
-        .query(&[("select", "id")])
+    let taken = taken_usernames(std::slice::from_ref(&username), &db_config, &app).await?;
+    Ok(taken.is_empty())
+}
+
+/// Check several usernames with a single query, e.g. to suggest alternatives.
+/// At most 10 usernames per call; each one looked up counts as a check against the rate limit.
+#[command]
+pub async fn check_usernames_availability(
+    usernames: Vec<String>,
+    app: tauri::AppHandle,
+) -> Result<HashMap<String, UsernameAvailability>, UsernameCheckError> {
+    let mut results = HashMap::new();
+    let mut to_check = Vec::new();
+
+    for username in usernames {
+        if !is_valid_username(&username) {
+            results.insert(username, UsernameAvailability::Invalid);
+        } else if !to_check.contains(&username) {
+            to_check.push(username);
+        }
+    }
+
+    if to_check.is_empty() {
+        return Ok(results);
+    }
+    if to_check.len() > MAX_USERNAMES_PER_CHECK {
+        return Err(format!("At most {} usernames can be checked at once", MAX_USERNAMES_PER_CHECK).into());
+    }
+
+    let db_config = get_authenticated_db(&app).await?;
+
+    // Verify user is authenticated
+    let session_check = crate::session::check_session(app.clone()).await?;
+
+    if !session_check {
+        return Err("Authentication required".into());
+    }
+
+    consume_username_checks(&app, to_check.len() as u32)?;
+
+    let taken = taken_usernames(&to_check, &db_config, &app).await?;
+
+    for username in to_check {
+        let availability = if taken.contains(&username) {
+            UsernameAvailability::Taken
+        } else {
+            UsernameAvailability::Available
+        };
+        results.insert(username, availability);
+    }
+
+    Ok(results)
+}
+
+// Which of `usernames` are taken, through check_usernames (migration 038), which counts
+// each one against the user's rate limit in the database before looking them up
+async fn taken_usernames(
+    usernames: &[String],
+    db_config: &DatabaseConfig,
+    app: &tauri::AppHandle,
+) -> Result<Vec<String>, UsernameCheckError> {
+    let response = crate::http::client()
+        .post(format!("{}/rest/v1/rpc/check_usernames", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .json(&serde_json::json!({ "p_usernames": usernames }))
         .send()
         .await
         .map_err(|e| format!("HTTP request failed: {}", e))?;
 
     if !response.status().is_success() {
-        return Err(format!("Username check failed: {}", response.status()));
+        let status = response.status().as_u16();
+        let body = response.text().await.unwrap_or_default();
+        return Err(username_check_error(status, &body));
     }
 
-    let profiles: Vec<serde_json::Value> = response
+    let taken: Vec<serde_json::Value> = response
         .json()
         .await
         .map_err(|e| format!("Failed to parse response: {}", e))?;
+    Ok(taken.iter().filter_map(|row| row["username"].as_str().map(String::from)).collect())
+}
+
+// SQLSTATE check_usernames raises over the limit, with the seconds to wait as the hint
+const USERNAME_CHECKS_LIMITED_SQLSTATE: &str = "AU002";
+
+// Classify a failed check_usernames call from its status and PostgREST error body
+fn username_check_error(status: u16, body: &str) -> UsernameCheckError {
+    let error: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+    if error["code"].as_str() == Some(USERNAME_CHECKS_LIMITED_SQLSTATE) {
+        let retry_after_secs = error["hint"].as_str()
+            .and_then(|hint| hint.trim().parse().ok())
+            .unwrap_or((USERNAME_CHECK_WINDOW_MS / 1000) as u64);
+        return UsernameCheckError::RateLimited { retry_after_secs };
+    }
+
+    match access_error(status, body, "profiles") {
+        Some(DatabaseError::NotAuthenticated { message }) => UsernameCheckError::NotAuthenticated { message },
+        _ => format!("Username check failed: {} - {}", status, body).into(),
+    }
+}
+
+fn is_valid_username(username: &str) -> bool {
+    username.chars().count() >= MIN_USERNAME_LENGTH
+        && !username.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+// Count `checks` username checks against the current window, persisted in the rate limit store.
+// Either all of them fit in the window or none are counted. check_usernames enforces the same
+// limit in the database; counting here as well saves a request once the app knows it's over.
+fn consume_username_checks(app: &tauri::AppHandle, checks: u32) -> Result<(), UsernameCheckError> {
+    let _guard = USERNAME_CHECK_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+    let store = app.store("rate_limits.store").map_err(|e| e.to_string())?;
+    let window = store.get("username_checks");
+    let window_start = window.as_ref().and_then(|w| w["window_start"].as_i64()).unwrap_or(0);
+    let count = window.as_ref().and_then(|w| w["count"].as_u64()).unwrap_or(0) as u32;
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let (window_start, count) = next_rate_limit_window(
+        window_start,
+        count,
+        checks,
+        now,
+        USERNAME_CHECK_LIMIT,
+        USERNAME_CHECK_WINDOW_MS,
+    )
+    .map_err(|retry_after_secs| UsernameCheckError::RateLimited { retry_after_secs })?;
+
+    store.set("username_checks", serde_json::json!({
+        "window_start": window_start,
+        "count": count,
+    }));
+    store.save().map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+// Fixed-window counter: returns the window after counting `calls` more calls, or the seconds
+// until the window resets when they don't fit. An expired window starts over at `now`.
+fn next_rate_limit_window(
+    window_start: i64,
+    count: u32,
+    calls: u32,
+    now: i64,
+    limit: u32,
+    window_ms: i64,
+) -> Result<(i64, u32), u64> {
+    let window_end = window_start + window_ms;
+    if now >= window_end || now < window_start {
+        return Ok((now, calls));
+    }
+
+    if count.saturating_add(calls) > limit {
+        let remaining_ms = (window_end - now) as u64;
+        return Err(remaining_ms.div_ceil(1000));
+    }
+
+    Ok((window_start, count + calls))
+}
+
+/// Close the user's account. Stripe is dealt with first so a failure there never leaves
+/// a subscription billing a user whose data is gone; the remaining steps are reported
+/// individually so a partial failure can be retried.
+#[command]
+pub async fn delete_account(
+    user_id: String,
+    app: tauri::AppHandle,
+) -> Result<AccountDeletionReport, String> {
+    // Verify user is authenticated
+    let session_check = crate::session::check_session(app.clone()).await?;
+    if !session_check {
+        return Err("Authentication required".to_string());
+    }
+
+    // Nothing in Stripe is touched until the account is known to be the caller's own
+    ensure_session_user(&user_id, &app).await?;
+
+    let profile = get_user_profile(user_id.clone(), app.clone()).await?
+        .ok_or_else(|| "Profile not found".to_string())?;
+
+    let mut report = AccountDeletionReport {
+        steps: Vec::new(),
+        account_deleted: false,
+    };
+
+    // 1. Stop billing. Nothing else is touched if this fails.
+    if let Some(subscription_id) = profile.subscription_id.as_deref() {
+        let result = crate::stripe::cancel_subscription_now(subscription_id).await;
+        if !report.record("cancel_subscription", result) {
+            return Ok(report);
+        }
+    }
+
+    // 2. Detach saved cards so they can't be charged again (best effort)
+    if let Some(customer_id) = profile.stripe_customer_id.clone() {
+        let result = detach_all_payment_methods(customer_id).await;
+        report.record("detach_payment_methods", result);
+    }
 
-    Ok(profiles.is_empty())
+    // 3. Delete the user's rows and auth user in one transaction
+    let result = delete_user_account_records(&user_id, &app).await;
+    if !report.record("delete_account_records", result) {
+        // Keep the local session so the user can retry
+        return Ok(report);
+    }
+    report.account_deleted = true;
+
+    // 4. Clear what's cached on this device, then sign out
+    let result = clear_user_data_stores(&app).await;
+    report.record("clear_local_data", result);
+
+    Ok(report)
+}
+
+async fn detach_all_payment_methods(customer_id: String) -> Result<(), String> {
+    let methods = crate::stripe::get_customer_payment_methods(customer_id).await?;
+
+    let mut failures = Vec::new();
+    for method in methods {
+        if let Err(e) = crate::stripe::delete_payment_method(method.id.clone()).await {
+            failures.push(format!("{}: {}", method.id, e));
+        }
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Failed to detach payment methods: {}", failures.join(", ")))
+    }
+}
+
+async fn delete_user_account_records(user_id: &str, app: &tauri::AppHandle) -> Result<(), String> {
+    let db_config = get_authenticated_db(app).await?;
+
+    let client = crate::http::client();
+
+    let response = client
+        .post(format!("{}/rest/v1/rpc/delete_user_account", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .json(&serde_json::json!({ "p_user_id": user_id }))
+        .send()
+        .await
+        .map_err(|e| format!("HTTP request failed: {}", e))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let error_body = response.text().await.unwrap_or_else(|_| "Could not read error body".to_string());
+        return Err(format!("Account deletion failed: {} - {}", status, error_body));
+    }
+
+    Ok(())
+}
+
+async fn clear_user_data_stores(app: &tauri::AppHandle) -> Result<(), String> {
+    let mut failures = Vec::new();
+
+    for store_file in USER_DATA_STORES {
+        let result = app.store(store_file)
+            .map_err(|e| e.to_string())
+            .and_then(|store| {
+                store.clear();
+                store.save().map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            failures.push(format!("{}: {}", store_file, e));
+        }
+    }
+
+    if let Err(e) = crate::session::logout(app.clone()).await {
+        failures.push(format!("session.store: {}", e));
+    }
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Failed to clear local data: {}", failures.join(", ")))
+    }
+}
+
+/// Gather everything stored about the user into one JSON document for a data export
+/// request. Only the user's own records are included, and no keys or session tokens;
+/// government ID and bank numbers are cut down to their last four characters.
+#[command]
+pub async fn export_user_data(
+    user_id: String,
+    app: tauri::AppHandle,
+) -> Result<serde_json::Value, String> {
+    let profile = get_user_profile(user_id.clone(), app.clone()).await?
+        .ok_or_else(|| "Profile not found".to_string())?;
+
+    let subscription = serde_json::json!({
+        "subscription_id": profile.subscription_id,
+        "status": profile.subscription_status,
+        "period_end": profile.subscription_period_end,
+        "payment_failure_reason": profile.subscription_payment_failure_reason,
+        "payment_failed_at": profile.subscription_payment_failed_at,
+    });
+
+    let all_statuses = PURCHASE_STATUSES.iter().map(|status| status.to_string()).collect();
+    let purchases = get_user_purchases(user_id.clone(), Some(all_statuses), None, app.clone()).await?;
+    let payment_methods = get_user_payment_methods(user_id.clone(), app.clone()).await?;
+
+    // The ledger is paged, so read it until a short page comes back
+    let mut token_ledger = Vec::new();
+    loop {
+        let page = get_token_ledger(user_id.clone(), Some(200), Some(token_ledger.len() as u32), app.clone()).await?;
+        let done = page.len() < 200;
+        token_ledger.extend(page);
+        if done {
+            break;
+        }
+    }
+
+    let contractor = match get_contractor_profile(user_id.clone(), app.clone()).await? {
+        Some(contractor) => {
+            let contractor_id = contractor.id.clone();
+            serde_json::json!({
+                "profile": contractor,
+                "kyc_form_data": load_kyc_form_data(user_id.clone(), app.clone()).await?,
+                "beneficial_owners": get_beneficial_owners(contractor_id.clone(), app.clone()).await?,
+                "representatives": get_representatives(contractor_id.clone(), app.clone()).await?,
+                "documents": get_document_uploads(contractor_id, app.clone()).await?,
+            })
+        }
+        None => serde_json::Value::Null,
+    };
+
+    let mut export = serde_json::json!({
+        "exported_at": chrono::Utc::now().to_rfc3339(),
+        "user_id": user_id,
+        "profile": profile,
+        "subscription": subscription,
+        "purchases": purchases,
+        "payment_methods": payment_methods,
+        "token_ledger": token_ledger,
+        "contractor": contractor,
+    });
+    mask_sensitive_numbers(&mut export);
+
+    Ok(export)
+}
+
+// Fields holding ID or bank numbers, under both the database and the form data names
+const MASKED_EXPORT_FIELDS: [&str; 6] = [
+    "national_id_number",
+    "nationalIdNumber",
+    "account_number",
+    "accountNumber",
+    "routing_number",
+    "routingNumber",
+];
+
+// Reduce national ID and bank numbers anywhere in the document to their last four
+// characters, the same as the stored bank account row
+fn mask_sensitive_numbers(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                if MASKED_EXPORT_FIELDS.contains(&key.as_str()) {
+                    if let Some(number) = field.as_str() {
+                        let visible: String = number.chars().skip(number.chars().count().saturating_sub(4)).collect();
+                        *field = serde_json::json!(format!("****{}", visible));
+                    }
+                } else {
+                    mask_sensitive_numbers(field);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(mask_sensitive_numbers),
+        _ => {}
+    }
 }
 
 /// Get database connection status
@@ -559,6 +1600,17 @@ pub async fn get_database_status(app: tauri::AppHandle) -> Result<HashMap<String
     status.insert("has_database_url".to_string(), has_db_url.to_string());
     status.insert("has_session_tokens".to_string(), has_tokens.to_string());
 
+    // Name the first missing piece so the frontend knows whether to re-init or re-login
+    if let Err(error) = get_authenticated_db(&app).await {
+        let missing = match error {
+            DbConfigError::NoDatabaseUrl { .. } => "database_url",
+            DbConfigError::NoAnonKey { .. } => "anon_key",
+            DbConfigError::NoAccessToken { .. } => "access_token",
+            DbConfigError::Store { .. } => "store",
+        };
+        status.insert("missing".to_string(), missing.to_string());
+    }
+
     if has_config {
         // Check authentication
         let session_check = crate::session::check_session(app.clone()).await?;
@@ -576,18 +1628,222 @@ pub async fn get_database_status(app: tauri::AppHandle) -> Result<HashMap<String
     Ok(status)
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigCheck {
+    pub name: String,
+    pub passed: bool,
+    pub message: String,
+    pub suggested_fix: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigDiagnosis {
+    pub healthy: bool,
+    pub checks: Vec<ConfigCheck>,
+    /// Fixes that were applied to database.store because auto_fix was set
+    pub applied_fixes: Vec<String>,
+}
+
+/// Trim what people commonly paste along with the project URL: whitespace,
+/// trailing slashes and the REST path (e.g. "https://x.supabase.co/rest/v1/")
+fn normalize_database_url(database_url: &str) -> String {
+    let mut url = database_url.trim().trim_end_matches('/').to_string();
+    if let Some(stripped) = url.strip_suffix("/rest/v1") {
+        url = stripped.trim_end_matches('/').to_string();
+    }
+    url
+}
+
+fn config_check(name: &str, passed: bool, message: String, suggested_fix: Option<String>) -> ConfigCheck {
+    ConfigCheck {
+        name: name.to_string(),
+        passed,
+        message,
+        suggested_fix,
+    }
+}
+
+/// Check the Supabase URL and anon key saved by init_database and say what to fix.
+/// With `auto_fix`, a URL that only needs normalizing is rewritten in place.
+#[command]
+pub async fn diagnose_config(
+    auto_fix: Option<bool>,
+    app: tauri::AppHandle,
+) -> Result<ConfigDiagnosis, String> {
+    let store = app.store("database.store").map_err(|e| e.to_string())?;
+    let stored_url = store.get("database_url").and_then(|v| v.as_str().map(String::from));
+    let anon_key = store.get("anon_key").and_then(|v| v.as_str().map(String::from));
+
+    let mut checks = Vec::new();
+    let mut applied_fixes = Vec::new();
+
+    let database_url = match stored_url {
+        None => {
+            checks.push(config_check(
+                "database_url",
+                false,
+                "No Supabase URL is stored".to_string(),
+                Some("Call init_database with your project URL".to_string()),
+            ));
+            None
+        },
+        Some(stored_url) => {
+            let normalized = normalize_database_url(&stored_url);
+            let valid_scheme = normalized.starts_with("https://")
+                || normalized.starts_with("http://localhost")
+                || normalized.starts_with("http://127.0.0.1");
+            let parses = reqwest::Url::parse(&normalized).is_ok();
+
+            if !parses || !valid_scheme {
+                checks.push(config_check(
+                    "database_url",
+                    false,
+                    format!("{} is not a valid Supabase URL", stored_url),
+                    Some("Use the project URL from Supabase settings, e.g. https://<project>.supabase.co".to_string()),
+                ));
+            } else if normalized != stored_url {
+                if auto_fix.unwrap_or(false) {
+                    store.set("database_url", serde_json::json!(normalized));
+                    store.save().map_err(|e| e.to_string())?;
+                    applied_fixes.push(format!("Rewrote database_url to {}", normalized));
+                    checks.push(config_check("database_url", true, format!("URL normalized to {}", normalized), None));
+                } else {
+                    checks.push(config_check(
+                        "database_url",
+                        false,
+                        format!("{} has a trailing slash, path or whitespace", stored_url),
+                        Some(format!("Use {}", normalized)),
+                    ));
+                }
+            } else {
+                checks.push(config_check("database_url", true, "URL is well formed".to_string(), None));
+            }
+
+            if parses && valid_scheme { Some(normalized) } else { None }
+        },
+    };
+
+    match &anon_key {
+        None => checks.push(config_check(
+            "anon_key",
+            false,
+            "No anon key is stored".to_string(),
+            Some("Call init_database with the anon key from Supabase settings".to_string()),
+        )),
+        Some(key) if key.split('.').count() != 3 && !key.starts_with("sb_publishable_") => checks.push(config_check(
+            "anon_key",
+            false,
+            "The anon key doesn't look like a Supabase API key".to_string(),
+            Some("Copy the anon (public) key from Supabase settings, not the service role key or project id".to_string()),
+        )),
+        Some(_) => checks.push(config_check("anon_key", true, "Anon key is present".to_string(), None)),
+    }
+
+    if let Some(database_url) = &database_url {
+        let client = crate::http::client();
+        let read_timeout = crate::http::timeout(crate::http::OperationClass::Read, &app);
+
+        // Any HTTP response means the host is right; only connection failures fail this
+        let reachable = client.get(format!("{}/rest/v1/", database_url))
+            .timeout(read_timeout)
+            .header("apikey", anon_key.as_deref().unwrap_or_default())
+            .send()
+            .await;
+        checks.push(match &reachable {
+            Ok(response) => config_check(
+                "rest_reachable",
+                true,
+                format!("REST API responded with HTTP {}", response.status()),
+                None,
+            ),
+            Err(e) => config_check(
+                "rest_reachable",
+                false,
+                format!("Could not reach {}: {}", database_url, e),
+                Some("Check the project URL and your connection".to_string()),
+            ),
+        });
+
+        // The auth settings endpoint is public but still requires a valid apikey
+        if let (Ok(_), Some(key)) = (&reachable, &anon_key) {
+            let accepted = client.get(format!("{}/auth/v1/settings", database_url))
+                .timeout(read_timeout)
+                .header("apikey", key)
+                .send()
+                .await
+                .map(|response| response.status());
+            checks.push(match accepted {
+                Ok(status) if status.is_success() => {
+                    config_check("anon_key_accepted", true, "Supabase accepted the anon key".to_string(), None)
+                },
+                Ok(status) => config_check(
+                    "anon_key_accepted",
+                    false,
+                    format!("Supabase rejected the anon key with HTTP {}", status),
+                    Some("The key may belong to another project or have been rotated".to_string()),
+                ),
+                Err(e) => config_check(
+                    "anon_key_accepted",
+                    false,
+                    format!("Could not verify the anon key: {}", e),
+                    None,
+                ),
+            });
+        }
+    }
+
+    Ok(ConfigDiagnosis {
+        healthy: checks.iter().all(|check| check.passed),
+        checks,
+        applied_fixes,
+    })
+}
+
+/// Link a Stripe customer to the user's profile
+pub(crate) async fn set_stripe_customer_id(
+    user_id: &str,
+    stripe_customer_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<(), String> {
+    let db_config = get_authenticated_db(app).await?;
+
+    let response = crate::http::client()
+        .patch(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=minimal")
+        .query(&[("id", format!("eq.{}", user_id))])
+        .json(&serde_json::json!({
+            "stripe_customer_id": stripe_customer_id,
+            "updated_at": chrono::Utc::now().to_rfc3339()
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send profile update request: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to save Stripe customer id: {} - {}", status, error_text));
+    }
+
+    Ok(())
+}
+
 /// Update user subscription status
 #[command]
-pub async fn update_subscription_status(
+pub async fn update_subscription_status<R: tauri::Runtime>(
     user_id: String,
     stripe_customer_id: String,
     subscription_id: String,
     subscription_status: String,
     subscription_period_end: i64,
-    app: tauri::AppHandle,
+    app: tauri::AppHandle<R>,
 ) -> Result<(), String> {
     let db_config = get_authenticated_db(&app).await?;
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     
     let url = format!("{}/rest/v1/profiles", db_config.database_url);
     
@@ -600,6 +1856,7 @@ pub async fn update_subscription_status(
     
     let response = client
         .patch(&url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -619,87 +1876,433 @@ pub async fn update_subscription_status(
     Ok(())
 }
 
-/// Store payment method metadata after successful Stripe setup
+/// Whether the user's subscription should unlock paid features. Active and trialing
+/// subscriptions count, and so does past_due until the grace period after
+/// subscription_period_end runs out. Gate features on this rather than on the raw status.
 #[command]
-pub async fn store_payment_method(
+pub async fn is_subscription_active(
     user_id: String,
-    stripe_customer_id: String,
-    stripe_payment_method_id: String,
-    card_brand: String,
-    card_last4: String,
-    card_exp_month: i32,
-    card_exp_year: i32,
-    is_default: Option<bool>,
     app: tauri::AppHandle,
-) -> Result<PaymentMethod, String> {
-    let db_config = get_authenticated_db(&app).await
-        .map_err(|e| format!("Database authentication failed: {}", e))?;
-    
-    let client = reqwest::Client::new();
-    
-    // Check if this is the user's first payment method
-    let existing_methods = get_user_payment_methods(user_id.clone(), app.clone()).await?;
-    let should_be_default = is_default.unwrap_or(false) || existing_methods.is_empty();
-    
-    let url = format!("{}/rest/v1/payment_methods", db_config.database_url);
-    
-    let payload = serde_json::json!({
-        "user_id": user_id,
-        "stripe_customer_id": stripe_customer_id,
-        "stripe_payment_method_id": stripe_payment_method_id,
-        "card_brand": card_brand,
-        "card_last4": card_last4,
-        "card_exp_month": card_exp_month,
-        "card_exp_year": card_exp_year,
-        "is_default": should_be_default,
-        "is_active": true
-    });
-    
-    // If this is set as default, first unset all other defaults for this user
-    if should_be_default {
-        let _ = unset_all_default_payment_methods(user_id.clone(), app.clone()).await;
+) -> Result<bool, String> {
+    let profile = get_user_profile(user_id, app.clone()).await?
+        .ok_or_else(|| "Profile not found".to_string())?;
+
+    Ok(subscription_grants_access(
+        profile.subscription_status.as_deref(),
+        profile.subscription_period_end,
+        chrono::Utc::now().timestamp(),
+        subscription_grace_period_secs(&app).await,
+    ))
+}
+
+/// Read a plan's features into entitlements. Features are either an object of flags
+/// (`{"max_projects": 10, "priority_support": true}`) or the older list of display strings
+/// (`["Priority support", "Unlimited projects"]`), which are matched by name.
+/// Paid plans get unlimited projects unless they set max_projects.
+fn entitlements_from_plan(plan: &SubscriptionPlan, tokens_remaining: i64) -> Entitlements {
+    let mut entitlements = Entitlements {
+        plan_id: Some(plan.id.clone()),
+        plan_name: Some(plan.name.clone()),
+        max_projects: None,
+        ..Entitlements::free(tokens_remaining)
+    };
+
+    match &plan.features {
+        Some(serde_json::Value::Object(flags)) => {
+            if let Some(max_projects) = flags.get("max_projects") {
+                entitlements.max_projects = max_projects.as_u64().map(|n| n.min(u32::MAX as u64) as u32);
+            }
+            entitlements.priority_support = flags.get("priority_support").and_then(|v| v.as_bool()).unwrap_or(false);
+            entitlements.advanced_export = flags.get("advanced_export").and_then(|v| v.as_bool()).unwrap_or(false);
+        },
+        Some(serde_json::Value::Array(names)) => {
+            for name in names.iter().filter_map(|v| v.as_str()) {
+                match name.trim().to_lowercase().replace(' ', "_").as_str() {
+                    "priority_support" => entitlements.priority_support = true,
+                    "advanced_export" => entitlements.advanced_export = true,
+                    _ => {},
+                }
+            }
+        },
+        _ => {},
     }
-    
-    let response = client
-        .post(&url)
+
+    entitlements
+}
+
+/// Get what the user's subscription unlocks. Users without an active subscription
+/// (see is_subscription_active) get the free tier.
+#[command]
+pub async fn get_entitlements(
+    user_id: String,
+    app: tauri::AppHandle,
+) -> Result<Entitlements, String> {
+    let profile = get_user_profile(user_id, app.clone()).await?
+        .ok_or_else(|| "Profile not found".to_string())?;
+    let tokens_remaining = profile.tokens_remaining.unwrap_or(0);
+
+    let active = subscription_grants_access(
+        profile.subscription_status.as_deref(),
+        profile.subscription_period_end,
+        chrono::Utc::now().timestamp(),
+        subscription_grace_period_secs(&app).await,
+    );
+    if !active {
+        return Ok(Entitlements::free(tokens_remaining));
+    }
+
+    // Profiles subscribed before plan tracking only have the price
+    let plan_id = match (&profile.subscription_plan_id, &profile.subscription_price_id) {
+        (Some(plan_id), _) => Some(plan_id.clone()),
+        (None, Some(price_id)) => get_subscription_price(price_id, &app).await?
+            .map(|price| price.subscription_plan_id),
+        (None, None) => None,
+    };
+    let plan_id = match plan_id {
+        Some(plan_id) => plan_id,
+        None => return Ok(Entitlements::free(tokens_remaining)),
+    };
+
+    Ok(match get_subscription_plan(&plan_id, &app).await? {
+        Some(plan) => entitlements_from_plan(&plan, tokens_remaining),
+        None => Entitlements::free(tokens_remaining),
+    })
+}
+
+/// Look up a subscription_plans row by id
+pub(crate) async fn get_subscription_plan(
+    plan_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<Option<SubscriptionPlan>, String> {
+    let db_config = get_authenticated_db(app).await?;
+    let response = crate::http::client()
+        .get(format!("{}/rest/v1/subscription_plans", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
-        .header("Content-Type", "application/json")
-        .header("Prefer", "return=representation")
-        .json(&payload)
+        .query(&[("id", format!("eq.{}", plan_id))])
         .send()
         .await
-        .map_err(|e| format!("Failed to store payment method: {}", e))?;
-    
+        .map_err(|e| format!("Failed to fetch subscription plan: {}", e))?;
+
     if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("Database error storing payment method: {}", error_text));
+        return Err(response_error(response, "subscription_plans").await.into());
     }
-    
-    let payment_methods: Vec<PaymentMethod> = response
+
+    let plans: Vec<SubscriptionPlan> = response
         .json()
         .await
-        .map_err(|e| format!("Failed to parse payment method response: {}", e))?;
-    
-    payment_methods
-        .into_iter()
-        .next()
-        .ok_or_else(|| "No payment method returned from database".to_string())
+        .map_err(|e| format!("Failed to parse subscription plan response: {}", e))?;
+
+    Ok(plans.into_iter().next())
 }
 
-/// Get user's payment methods from database
+/// Set how many days (0 to 30) a past_due subscription keeps access after its period ends.
+/// Admin only. Stored in app_settings, so it applies to every user and device.
 #[command]
-pub async fn get_user_payment_methods(
-    user_id: String,
+pub async fn set_subscription_grace_period(
+    days: u64,
     app: tauri::AppHandle,
-) -> Result<Vec<PaymentMethod>, String> {
+) -> Result<(), String> {
+    assert_admin(&app).await?;
+
+    if days > MAX_SUBSCRIPTION_GRACE_PERIOD_DAYS {
+        return Err(format!(
+            "Grace period must be at most {} days, got {}",
+            MAX_SUBSCRIPTION_GRACE_PERIOD_DAYS, days
+        ));
+    }
+
     let db_config = get_authenticated_db(&app).await?;
-    let client = reqwest::Client::new();
-    
-    let url = format!("{}/rest/v1/payment_methods", db_config.database_url);
+    let response = crate::http::client()
+        .post(format!("{}/rest/v1/app_settings", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "resolution=merge-duplicates")
+        .json(&serde_json::json!({
+            "key": SUBSCRIPTION_GRACE_PERIOD_SETTING,
+            "value": days,
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to save grace period: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(response_error(response, "app_settings").await.into());
+    }
+
+    if let Ok(mut cache) = GRACE_PERIOD_CACHE.lock() {
+        *cache = Some((chrono::Utc::now().timestamp(), days));
+    }
+
+    Ok(())
+}
+
+// The configured grace period, falling back to the default when app_settings can't be read
+async fn subscription_grace_period_secs(app: &tauri::AppHandle) -> i64 {
+    let days = subscription_grace_period_days(app).await
+        .unwrap_or(DEFAULT_SUBSCRIPTION_GRACE_PERIOD_DAYS)
+        .min(MAX_SUBSCRIPTION_GRACE_PERIOD_DAYS);
+
+    days as i64 * 24 * 60 * 60
+}
+
+async fn subscription_grace_period_days(app: &tauri::AppHandle) -> Result<u64, String> {
+    let now = chrono::Utc::now().timestamp();
+
+    if let Ok(cache) = GRACE_PERIOD_CACHE.lock() {
+        if let Some((cached_at, days)) = *cache {
+            if now - cached_at < GRACE_PERIOD_CACHE_TTL_SECS {
+                return Ok(days);
+            }
+        }
+    }
+
+    let db_config = get_authenticated_db(app).await?;
+    let response = crate::http::client()
+        .get(format!("{}/rest/v1/app_settings", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("key", format!("eq.{}", SUBSCRIPTION_GRACE_PERIOD_SETTING))])
+        .query(&[("select", "value")])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch grace period: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(response_error(response, "app_settings").await.into());
+    }
+
+    let rows: Vec<serde_json::Value> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse grace period response: {}", e))?;
+
+    let days = rows.first()
+        .and_then(|row| row["value"].as_u64())
+        .unwrap_or(DEFAULT_SUBSCRIPTION_GRACE_PERIOD_DAYS);
+    if let Ok(mut cache) = GRACE_PERIOD_CACHE.lock() {
+        *cache = Some((now, days));
+    }
+
+    Ok(days)
+}
+
+// past_due keeps access up to and including period_end + grace; without a known
+// period end there is nothing to measure the grace from, so access stops
+fn subscription_grants_access(
+    status: Option<&str>,
+    period_end: Option<i64>,
+    now: i64,
+    grace_secs: i64,
+) -> bool {
+    match status {
+        Some("active") | Some("trialing") => true,
+        Some("past_due") => period_end.is_some_and(|end| now <= end.saturating_add(grace_secs)),
+        _ => false,
+    }
+}
+
+/// Profile columns recording the subscribed Stripe price and the plan it belongs to.
+/// The plan is null when the price hasn't been synced into subscription_prices.
+pub(crate) async fn subscription_price_fields(
+    stripe_price_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<serde_json::Value, String> {
+    let plan_id = get_subscription_price(stripe_price_id, app).await?
+        .map(|price| price.subscription_plan_id);
+
+    Ok(serde_json::json!({
+        "subscription_price_id": stripe_price_id,
+        "subscription_plan_id": plan_id
+    }))
+}
+
+/// Record the price and plan the user is subscribed to
+pub(crate) async fn update_subscription_price(
+    user_id: &str,
+    stripe_price_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<(), String> {
+    let update_data = subscription_price_fields(stripe_price_id, app).await?;
+
+    let db_config = get_authenticated_db(app).await?;
+    let response = crate::http::client()
+        .patch(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=minimal")
+        .query(&[("id", format!("eq.{}", user_id))])
+        .json(&update_data)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send subscription price update request: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to update subscription price: {} - {}", status, error_text));
+    }
+
+    Ok(())
+}
+
+/// Store every item (base plan and add-ons) on the user's subscription
+pub(crate) async fn update_subscription_items(
+    user_id: &str,
+    items: serde_json::Value,
+    app: &tauri::AppHandle,
+) -> Result<(), String> {
+    let db_config = get_authenticated_db(app).await?;
+    let response = crate::http::client()
+        .patch(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=minimal")
+        .query(&[("id", format!("eq.{}", user_id))])
+        .json(&serde_json::json!({
+            "subscription_items": items,
+            "updated_at": chrono::Utc::now().to_rfc3339()
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send subscription items update request: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to update subscription items: {} - {}", status, error_text));
+    }
+
+    Ok(())
+}
+
+/// Record when the user's free trial ends (None once there is no trial).
+/// Also clears the trial-ending flag left by an earlier trial.
+pub(crate) async fn update_subscription_trial_end(
+    user_id: &str,
+    trial_end: Option<i64>,
+    app: &tauri::AppHandle,
+) -> Result<(), String> {
+    let db_config = get_authenticated_db(app).await?;
+    let client = crate::http::client();
+
+    let response = client
+        .patch(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=minimal")
+        .query(&[("id", format!("eq.{}", user_id))])
+        .json(&serde_json::json!({
+            "subscription_trial_end": trial_end,
+            "subscription_trial_ending": false
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send trial end update request: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to update trial end: {} - {}", status, error_text));
+    }
+
+    Ok(())
+}
+
+/// Store payment method metadata after successful Stripe setup
+#[command]
+#[allow(clippy::too_many_arguments)]
+pub async fn store_payment_method(
+    user_id: String,
+    stripe_customer_id: String,
+    stripe_payment_method_id: String,
+    card_brand: String,
+    card_last4: String,
+    card_exp_month: i32,
+    card_exp_year: i32,
+    is_default: Option<bool>,
+    app: tauri::AppHandle,
+) -> Result<PaymentMethod, String> {
+    let db_config = get_authenticated_db(&app).await
+        .map_err(|e| format!("Database authentication failed: {}", e))?;
+    
+    let client = crate::http::client();
+    
+    // Check if this is the user's first payment method
+    let existing_methods = get_user_payment_methods(user_id.clone(), app.clone()).await?;
+    let should_be_default = is_default.unwrap_or(false) || existing_methods.is_empty();
+    
+    let url = format!("{}/rest/v1/payment_methods", db_config.database_url);
+    
+    let payload = serde_json::json!({
+        "user_id": user_id,
+        "stripe_customer_id": stripe_customer_id,
+        "stripe_payment_method_id": stripe_payment_method_id,
+        "card_brand": card_brand,
+        "card_last4": card_last4,
+        "card_exp_month": card_exp_month,
+        "card_exp_year": card_exp_year,
+        "is_default": should_be_default,
+        "is_active": true
+    });
+    
+    // If this is set as default, first unset all other defaults for this user
+    if should_be_default {
+        let _ = unset_all_default_payment_methods(user_id.clone(), app.clone()).await;
+    }
+    
+    let response = client
+        .post(&url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=representation")
+        .json(&payload)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to store payment method: {}", e))?;
+    
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Database error storing payment method: {}", error_text));
+    }
+    
+    let payment_methods: Vec<PaymentMethod> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse payment method response: {}", e))?;
+    
+    payment_methods
+        .into_iter()
+        .next()
+        .ok_or_else(|| "No payment method returned from database".to_string())
+}
+
+/// Get user's payment methods from database
+#[command]
+pub async fn get_user_payment_methods(
+    user_id: String,
+    app: tauri::AppHandle,
+) -> Result<Vec<PaymentMethod>, String> {
+    let db_config = get_authenticated_db(&app).await?;
+    let client = crate::http::client();
+    
+    let url = format!("{}/rest/v1/payment_methods", db_config.database_url);
     
     let response = client
         .get(&url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .query(&[
@@ -723,6 +2326,46 @@ pub async fn get_user_payment_methods(
     Ok(payment_methods)
 }
 
+/// Fail with `Forbidden` unless the payment method is stored against `user_id`.
+/// Call this before any command that changes a payment method by a client-supplied id.
+pub(crate) async fn assert_payment_method_owned(
+    payment_method_id: &str,
+    user_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<(), PaymentMethodError> {
+    let db_config = get_authenticated_db(app).await?;
+
+    let response = crate::http::client()
+        .get(format!("{}/rest/v1/payment_methods", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("stripe_payment_method_id", format!("eq.{}", payment_method_id))])
+        .query(&[("select", "user_id")])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch payment method: {}", e))?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+        return Err(format!("Database error fetching payment method: {}", error_text).into());
+    }
+
+    let rows: Vec<serde_json::Value> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse payment method response: {}", e))?;
+
+    // RLS hides other users' rows, so a missing row is treated the same as a foreign one
+    if rows.first().and_then(|row| row["user_id"].as_str()) != Some(user_id) {
+        return Err(PaymentMethodError::Forbidden {
+            message: format!("Payment method {} does not belong to this user", payment_method_id),
+        });
+    }
+
+    Ok(())
+}
+
 /// Update payment method (e.g., set as default, deactivate)
 #[command]
 pub async fn update_payment_method(
@@ -731,9 +2374,11 @@ pub async fn update_payment_method(
     is_default: Option<bool>,
     is_active: Option<bool>,
     app: tauri::AppHandle,
-) -> Result<PaymentMethod, String> {
+) -> Result<PaymentMethod, PaymentMethodError> {
+    assert_payment_method_owned(&payment_method_id, &user_id, &app).await?;
+
     let db_config = get_authenticated_db(&app).await?;
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     
     // If setting as default, first unset all other defaults
     if is_default == Some(true) {
@@ -753,6 +2398,7 @@ pub async fn update_payment_method(
     
     let response = client
         .patch(&url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -768,7 +2414,7 @@ pub async fn update_payment_method(
     
     if !response.status().is_success() {
         let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(format!("Database error updating payment method: {}", error_text));
+        return Err(format!("Database error updating payment method: {}", error_text).into());
     }
     
     let payment_methods: Vec<PaymentMethod> = response
@@ -779,7 +2425,7 @@ pub async fn update_payment_method(
     payment_methods
         .into_iter()
         .next()
-        .ok_or_else(|| "No payment method returned from database".to_string())
+        .ok_or_else(|| "No payment method returned from database".into())
 }
 
 /// Ensure that if there's only one payment method, it's set as default
@@ -812,14 +2458,17 @@ pub async fn delete_payment_method_from_db(
     payment_method_id: String,
     user_id: String,
     app: tauri::AppHandle,
-) -> Result<String, String> {
+) -> Result<String, PaymentMethodError> {
+    assert_payment_method_owned(&payment_method_id, &user_id, &app).await?;
+
     let db_config = get_authenticated_db(&app).await?;
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     
     let url = format!("{}/rest/v1/payment_methods", db_config.database_url);
     
     let response = client
         .delete(&url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -833,7 +2482,7 @@ pub async fn delete_payment_method_from_db(
     
     if !response.status().is_success() {
         let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(format!("Database error deleting payment method: {}", error_text));
+        return Err(format!("Database error deleting payment method: {}", error_text).into());
     }
     
     // After deletion, ensure remaining payment method (if any) is set as default
@@ -848,9 +2497,11 @@ pub async fn mark_payment_method_used(
     payment_method_id: String,
     user_id: String,
     app: tauri::AppHandle,
-) -> Result<String, String> {
+) -> Result<String, PaymentMethodError> {
+    assert_payment_method_owned(&payment_method_id, &user_id, &app).await?;
+
     let db_config = get_authenticated_db(&app).await?;
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     
     let url = format!("{}/rest/v1/payment_methods", db_config.database_url);
     
@@ -861,6 +2512,7 @@ pub async fn mark_payment_method_used(
     
     let response = client
         .patch(&url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -875,7 +2527,7 @@ pub async fn mark_payment_method_used(
     
     if !response.status().is_success() {
         let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(format!("Database error marking payment method as used: {}", error_text));
+        return Err(format!("Database error marking payment method as used: {}", error_text).into());
     }
     
     Ok("Payment method marked as used".to_string())
@@ -887,7 +2539,7 @@ async fn unset_all_default_payment_methods(
     app: tauri::AppHandle,
 ) -> Result<(), String> {
     let db_config = get_authenticated_db(&app).await?;
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     
     let url = format!("{}/rest/v1/payment_methods", db_config.database_url);
     
@@ -898,6 +2550,7 @@ async fn unset_all_default_payment_methods(
     
     let response = client
         .patch(&url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -925,11 +2578,12 @@ pub async fn get_subscription_plans_with_prices(
     app: tauri::AppHandle,
 ) -> Result<Vec<SubscriptionPlanWithPrices>, String> {
     let db_config = get_authenticated_db(&app).await?;
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     
     // Query subscription plans
     let plans_response = client
-        .get(&format!("{}/rest/v1/subscription_plans?is_active=eq.true&order=sort_order", db_config.database_url))
+        .get(format!("{}/rest/v1/subscription_plans?is_active=eq.true&order=sort_order", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -949,7 +2603,8 @@ pub async fn get_subscription_plans_with_prices(
     
     // Query subscription prices
     let prices_response = client
-        .get(&format!("{}/rest/v1/subscription_prices?is_active=eq.true", db_config.database_url))
+        .get(format!("{}/rest/v1/subscription_prices?is_active=eq.true", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -985,17 +2640,49 @@ pub async fn get_subscription_plans_with_prices(
     Ok(result)
 }
 
+/// Look up the subscription_prices row for a Stripe price, if it has been synced
+pub(crate) async fn get_subscription_price(
+    stripe_price_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<Option<SubscriptionPrice>, String> {
+    let db_config = get_authenticated_db(app).await?;
+    let client = crate::http::client();
+
+    let response = client
+        .get(format!("{}/rest/v1/subscription_prices", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("stripe_price_id", format!("eq.{}", stripe_price_id))])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to query subscription price: {}", e))?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+        return Err(format!("Database error fetching subscription price: {}", error_text));
+    }
+
+    let prices: Vec<SubscriptionPrice> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse subscription price response: {}", e))?;
+
+    Ok(prices.into_iter().next())
+}
+
 /// Get packages with their associated prices from the database
 #[command]
 pub async fn get_packages_with_prices(
     app: tauri::AppHandle,
 ) -> Result<Vec<PackageWithPrices>, String> {
     let db_config = get_authenticated_db(&app).await?;
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     
     // Query packages
     let packages_response = client
-        .get(&format!("{}/rest/v1/packages?is_active=eq.true&order=sort_order", db_config.database_url))
+        .get(format!("{}/rest/v1/packages?is_active=eq.true&order=sort_order", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -1015,73 +2702,1031 @@ pub async fn get_packages_with_prices(
     
     // Query package prices
     let prices_response = client
-        .get(&format!("{}/rest/v1/package_prices?is_active=eq.true&order=amount_cents.asc", db_config.database_url))
+        .get(format!("{}/rest/v1/package_prices?is_active=eq.true&order=amount_cents.asc", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .send()
+        .await
+        .map_err(|e| format!("Failed to query package prices: {}", e))?;
+    
+    if !prices_response.status().is_success() {
+        let error_text = prices_response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+        return Err(format!("Database error fetching package prices: {}", error_text));
+    }
+    
+    let prices: Vec<PackagePrice> = prices_response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse package prices response: {}", e))?;
+    
+    // Group prices by package
+    let mut packages_with_prices = Vec::new();
+    for package in packages {
+        let package_prices: Vec<PackagePrice> = prices
+            .iter()
+            .filter(|p| p.package_id == package.id)
+            .cloned()
+            .collect();
+        
+        packages_with_prices.push(PackageWithPrices {
+            package,
+            prices: package_prices,
+        });
+    }
+    
+    Ok(packages_with_prices)
+}
+
+/// Create or update packages and package prices from a list of definitions.
+/// Rows are matched on their Stripe ids, so running the same seed again only updates them.
+#[command]
+pub async fn seed_catalog(
+    definitions: Vec<CatalogPackageDefinition>,
+    app: tauri::AppHandle,
+) -> Result<CatalogSeedResult, String> {
+    assert_admin(&app).await?;
+
+    let mut result = CatalogSeedResult::default();
+    if definitions.is_empty() {
+        return Ok(result);
+    }
+
+    // A single upsert can't touch the same row twice, so reject repeated ids up front
+    let mut seen = std::collections::HashSet::new();
+    let stripe_ids = definitions.iter().flat_map(|definition| {
+        std::iter::once(&definition.stripe_product_id)
+            .chain(definition.prices.iter().map(|price| &price.stripe_price_id))
+    });
+    for stripe_id in stripe_ids {
+        if !seen.insert(stripe_id) {
+            return Err(format!("Stripe id {} appears more than once in the catalog definitions", stripe_id));
+        }
+    }
+
+    // PostgREST bulk upserts need every row to carry the same keys, so defaults are filled in here
+    let package_rows: Vec<serde_json::Value> = definitions.iter()
+        .map(|definition| serde_json::json!({
+            "name": definition.name,
+            "description": definition.description,
+            "stripe_product_id": definition.stripe_product_id,
+            "features": definition.features.clone().unwrap_or_else(|| serde_json::json!([])),
+            "sort_order": definition.sort_order.unwrap_or(0),
+            "is_active": definition.is_active.unwrap_or(true),
+            "updated_at": chrono::Utc::now().to_rfc3339()
+        }))
+        .collect();
+
+    let (packages, created) = upsert_catalog_rows("packages", "stripe_product_id", package_rows, &app).await?;
+    result.packages_created = created;
+    result.packages_updated = packages.len() - created;
+
+    let package_ids: HashMap<&str, &str> = packages.iter()
+        .filter_map(|row| Some((row["stripe_product_id"].as_str()?, row["id"].as_str()?)))
+        .collect();
+
+    let mut price_rows = Vec::new();
+    for definition in &definitions {
+        let package_id = package_ids.get(definition.stripe_product_id.as_str())
+            .ok_or_else(|| format!("Package {} was not returned by the upsert", definition.stripe_product_id))?;
+
+        for price in &definition.prices {
+            price_rows.push(serde_json::json!({
+                "package_id": package_id,
+                "stripe_price_id": price.stripe_price_id,
+                "amount_cents": price.amount_cents,
+                "currency": price.currency.to_lowercase(),
+                "interval_type": price.interval_type.clone().unwrap_or_else(|| "one_time".to_string()),
+                "interval_count": price.interval_count.unwrap_or(1),
+                "token_amount": price.token_amount,
+                "is_active": price.is_active.unwrap_or(true),
+                "updated_at": chrono::Utc::now().to_rfc3339()
+            }));
+        }
+    }
+
+    if !price_rows.is_empty() {
+        let (prices, created) = upsert_catalog_rows("package_prices", "stripe_price_id", price_rows, &app).await?;
+        result.prices_created = created;
+        result.prices_updated = prices.len() - created;
+    }
+
+    Ok(result)
+}
+
+/// Id of the package for a Stripe product, or None when the product isn't part of our catalog
+pub(crate) async fn find_catalog_package_id(
+    stripe_product_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<Option<String>, String> {
+    let db_config = get_authenticated_db(app).await?;
+
+    let response = crate::http::client()
+        .get(format!("{}/rest/v1/packages", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("stripe_product_id", format!("eq.{}", stripe_product_id))])
+        .query(&[("select", "id")])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to query package: {}", e))?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Database error fetching package: {}", error_text));
+    }
+
+    let packages: Vec<serde_json::Value> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse package response: {}", e))?;
+
+    Ok(packages.first()
+        .and_then(|package| package["id"].as_str())
+        .map(String::from))
+}
+
+/// token_amount of the package_prices row for a Stripe price, or None if there is no row yet
+pub(crate) async fn find_package_price_tokens(
+    stripe_price_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<Option<i64>, String> {
+    let db_config = get_authenticated_db(app).await?;
+
+    let response = crate::http::client()
+        .get(format!("{}/rest/v1/package_prices", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("stripe_price_id", format!("eq.{}", stripe_price_id))])
+        .query(&[("select", "token_amount")])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to query package price: {}", e))?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Database error fetching package price: {}", error_text));
+    }
+
+    let prices: Vec<serde_json::Value> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse package price response: {}", e))?;
+
+    Ok(prices.first().map(|price| price["token_amount"].as_i64().unwrap_or(0)))
+}
+
+/// Upsert rows keyed on a unique Stripe id column. Returns the stored rows and how
+/// many of them did not exist before.
+pub(crate) async fn upsert_catalog_rows(
+    table: &str,
+    key_column: &str,
+    rows: Vec<serde_json::Value>,
+    app: &tauri::AppHandle,
+) -> Result<(Vec<serde_json::Value>, usize), String> {
+    let db_config = get_authenticated_db(app).await?;
+    let client = crate::http::client();
+    let url = format!("{}/rest/v1/{}", db_config.database_url, table);
+
+    let keys: Vec<&str> = rows.iter().filter_map(|row| row[key_column].as_str()).collect();
+
+    let existing_response = client
+        .get(&url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[(key_column, format!("in.({})", keys.join(",")))])
+        .query(&[("select", key_column)])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to query existing {}: {}", table, e))?;
+
+    if !existing_response.status().is_success() {
+        let error_text = existing_response.text().await.unwrap_or_default();
+        return Err(format!("Database error fetching existing {}: {}", table, error_text));
+    }
+
+    let existing: Vec<serde_json::Value> = existing_response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse existing {} response: {}", table, e))?;
+    let existing_count = existing.len();
+
+    let response = client
+        .post(&url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=representation,resolution=merge-duplicates")
+        .query(&[("on_conflict", key_column)])
+        .json(&rows)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to upsert {}: {}", table, e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to upsert {}: {} - {}", table, status, error_text));
+    }
+
+    let stored: Vec<serde_json::Value> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse {} upsert response: {}", table, e))?;
+
+    let created = stored.len().saturating_sub(existing_count);
+    Ok((stored, created))
+}
+
+const PURCHASE_STATUSES: &[&str] = &["pending", "completed", "failed", "refunded", "disputed"];
+
+/// Every active package price ranked by tokens per cent, best value first, to drive the
+/// "best value" badge. Amounts are compared as stored, so the ranking assumes one currency.
+#[command]
+pub async fn get_package_value_comparison(
+    app: tauri::AppHandle,
+) -> Result<Vec<PackageValue>, String> {
+    let packages = get_packages_with_prices(app).await?;
+    Ok(rank_package_values(&packages))
+}
+
+fn rank_package_values(packages: &[PackageWithPrices]) -> Vec<PackageValue> {
+    let mut values: Vec<PackageValue> = packages.iter()
+        .flat_map(|entry| entry.prices.iter().map(move |price| PackageValue {
+            rank: 0,
+            package_id: entry.package.id.clone(),
+            package_name: entry.package.name.clone(),
+            package_price_id: price.id.clone(),
+            stripe_price_id: price.stripe_price_id.clone(),
+            amount_cents: price.amount_cents,
+            currency: price.currency.clone(),
+            token_amount: price.token_amount,
+            tokens_per_cent: (price.amount_cents > 0)
+                .then(|| price.token_amount as f64 / price.amount_cents as f64),
+        }))
+        .collect();
+
+    // Descending by ratio with free prices (None) at the end
+    values.sort_by(|a, b| match (a.tokens_per_cent, b.tokens_per_cent) {
+        (Some(a), Some(b)) => b.total_cmp(&a),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+    for (index, value) in values.iter_mut().enumerate() {
+        value.rank = index + 1;
+    }
+
+    values
+}
+
+// Columns, aliases and casts only. Parentheses and `!` would let PostgREST embed other
+// tables (e.g. `*,payment_methods(*)`) and get around QUERYABLE_TABLES.
+fn is_plain_select(select: &str) -> bool {
+    select.chars().all(|c| c.is_ascii_alphanumeric() || "_,*:.".contains(c))
+}
+
+// The PostgREST query for supabase_query, or why the table, select or a filter isn't allowed
+fn supabase_query_params(
+    table: &str,
+    filters: &[Filter],
+    select: Option<String>,
+) -> Result<Vec<(String, String)>, String> {
+    if !QUERYABLE_TABLES.contains(&table) {
+        return Err(format!("Table {} is not available to supabase_query", table));
+    }
+
+    let select = select.unwrap_or_else(|| "*".to_string());
+    if !is_plain_select(&select) {
+        return Err(format!("Invalid select: {}", select));
+    }
+
+    let mut query = vec![("select".to_string(), select)];
+    for filter in filters {
+        query.push(filter.to_query_pair()?);
+    }
+    Ok(query)
+}
+
+/// Read rows from an allowlisted table with the signed-in user's credentials, for
+/// one-off frontend queries that don't warrant a dedicated command. Returns the raw JSON array.
+#[command]
+pub async fn supabase_query(
+    table: String,
+    filters: Vec<Filter>,
+    select: Option<String>,
+    app: tauri::AppHandle,
+) -> Result<serde_json::Value, String> {
+    let query = supabase_query_params(&table, &filters, select)?;
+    let db_config = get_authenticated_db(&app).await?;
+
+    let response = crate::http::client()
+        .get(format!("{}/rest/v1/{}", db_config.database_url, table))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&query)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to query {}: {}", table, e))?;
+
+    if !response.status().is_success() {
+        return Err(response_error(response, &table).await.into());
+    }
+
+    response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse {} response: {}", table, e))
+}
+
+/// Append a subscription change to the user's billing history. Callers treat this as
+/// best-effort: a missing history row shouldn't fail the change itself.
+pub(crate) async fn record_subscription_event<R: tauri::Runtime>(
+    user_id: &str,
+    subscription_id: &str,
+    action: &str,
+    old_price_id: Option<&str>,
+    new_price_id: Option<&str>,
+    app: &tauri::AppHandle<R>,
+) -> Result<(), String> {
+    let db_config = get_authenticated_db(app).await?;
+    
+    let response = crate::http::client()
+        .post(format!("{}/rest/v1/subscription_events", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=minimal")
+        .json(&serde_json::json!({
+            "user_id": user_id,
+            "subscription_id": subscription_id,
+            "action": action,
+            "old_price_id": old_price_id,
+            "new_price_id": new_price_id
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to record subscription event: {}", e))?;
+    
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_body = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to record subscription event: {} - {}", status, error_body));
+    }
+    
+    Ok(())
+}
+
+/// Record a verified webhook event and its outcome in the stripe_events audit log.
+/// Each delivery adds its own row, so a redelivered event never rewrites an earlier outcome.
+pub(crate) async fn record_webhook_event(
+    event_id: &str,
+    event_type: &str,
+    stripe_created: i64,
+    status: &str,
+    message: &str,
+    payload: Option<serde_json::Value>,
+    app: &tauri::AppHandle,
+) -> Result<(), String> {
+    let db_config = get_authenticated_db(app).await?;
+
+    // Users can't write stripe_events directly; the insert-only function records it
+    let response = crate::http::client()
+        .post(format!("{}/rest/v1/rpc/record_stripe_event", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .json(&serde_json::json!({
+            "p_event_id": event_id,
+            "p_event_type": event_type,
+            "p_stripe_created": stripe_created,
+            "p_status": status,
+            "p_message": message,
+            "p_payload": payload
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to record webhook event: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_body = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to record webhook event: {} - {}", status, error_body));
+    }
+
+    Ok(())
+}
+
+/// List received webhook events, newest first, for auditing. Admin only.
+#[command]
+pub async fn list_webhook_events(
+    limit: Option<u32>,
+    app: tauri::AppHandle,
+) -> Result<Vec<WebhookEvent>, String> {
+    assert_admin(&app).await?;
+    let db_config = get_authenticated_db(&app).await?;
+
+    let response = crate::http::client()
+        .get(format!("{}/rest/v1/stripe_events", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[
+            ("order", "received_at.desc".to_string()),
+            ("limit", limit.unwrap_or(50).min(200).to_string()),
+        ])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch webhook events: {}", e))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let error_body = response.text().await.unwrap_or_else(|_| "Could not read error body".to_string());
+        return Err(format!("Database query failed: {} - {}", status, error_body));
+    }
+
+    let events: Vec<WebhookEvent> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse webhook events response: {}", e))?;
+
+    Ok(events)
+}
+
+/// Opt in to (or out of) storing full webhook payloads in the audit log. Off by default,
+/// since payloads carry customer details.
+#[command]
+pub async fn set_webhook_payload_storage(
+    enabled: bool,
+    app: tauri::AppHandle,
+) -> Result<(), String> {
+    let settings = app.store("settings.store").map_err(|e| e.to_string())?;
+    settings.set("store_webhook_payloads", serde_json::json!(enabled));
+    settings.save().map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+pub(crate) fn webhook_payload_storage_enabled(app: &tauri::AppHandle) -> bool {
+    app.store("settings.store")
+        .ok()
+        .and_then(|settings| settings.get("store_webhook_payloads"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// Whether a completed Checkout Session has already been applied
+pub(crate) async fn is_checkout_session_processed(
+    session_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<bool, String> {
+    let db_config = get_authenticated_db(app).await?;
+
+    let response = crate::http::client()
+        .get(format!("{}/rest/v1/checkout_sessions", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("session_id", format!("eq.{}", session_id))])
+        .query(&[("select", "session_id")])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to query checkout session: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_body = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to query checkout session: {} - {}", status, error_body));
+    }
+
+    let sessions: Vec<serde_json::Value> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse checkout session response: {}", e))?;
+
+    Ok(!sessions.is_empty())
+}
+
+/// Mark a completed Checkout Session as applied so a redelivered webhook is skipped
+pub(crate) async fn record_checkout_session(
+    session_id: &str,
+    user_id: &str,
+    mode: &str,
+    stripe_payment_intent_id: Option<&str>,
+    subscription_id: Option<&str>,
+    app: &tauri::AppHandle,
+) -> Result<(), String> {
+    let db_config = get_authenticated_db(app).await?;
+
+    let response = crate::http::client()
+        .post(format!("{}/rest/v1/checkout_sessions", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=minimal,resolution=ignore-duplicates")
+        .query(&[("on_conflict", "session_id")])
+        .json(&serde_json::json!({
+            "session_id": session_id,
+            "user_id": user_id,
+            "mode": mode,
+            "stripe_payment_intent_id": stripe_payment_intent_id,
+            "subscription_id": subscription_id
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to record checkout session: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_body = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to record checkout session: {} - {}", status, error_body));
+    }
+
+    Ok(())
+}
+
+/// Get the user's subscription changes, newest first. With `tz` (an IANA zone such as
+/// "Europe/Paris") each event also gets `created_at_local` for display.
+#[command]
+pub async fn get_subscription_history(
+    user_id: String,
+    tz: Option<String>,
+    app: tauri::AppHandle,
+) -> Result<Vec<SubscriptionEvent>, String> {
+    let tz = parse_timezone(tz.as_deref())?;
+    let db_config = get_authenticated_db(&app).await?;
+    
+    let response = crate::http::client()
+        .get(format!("{}/rest/v1/subscription_events", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[
+            ("user_id", format!("eq.{}", user_id)),
+            ("order", "created_at.desc".to_string()),
+            ("select", "id,user_id,subscription_id,action,old_price_id,new_price_id,created_at".to_string())
+        ])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch subscription history: {}", e))?;
+    
+    if !response.status().is_success() {
+        return Err(response_error(response, "subscription_events").await.into());
+    }
+    
+    let mut events: Vec<SubscriptionEvent> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse subscription history response: {}", e))?;
+
+    if let Some(tz) = tz {
+        for event in &mut events {
+            event.created_at_local = localize_timestamp(event.created_at.as_deref(), tz);
+        }
+    }
+
+    Ok(events)
+}
+
+// None when no zone was asked for; an unknown zone name is an error
+fn parse_timezone(tz: Option<&str>) -> Result<Option<chrono_tz::Tz>, String> {
+    tz.map(|name| {
+        name.trim().parse::<chrono_tz::Tz>()
+            .map_err(|_| format!("Unknown timezone: {}", name))
+    }).transpose()
+}
+
+// An RFC3339 timestamp as local time in `tz`, e.g. "2025-03-01 14:30 CET".
+// Timestamps that don't parse are left without a local form.
+fn localize_timestamp(timestamp: Option<&str>, tz: chrono_tz::Tz) -> Option<String> {
+    let parsed = chrono::DateTime::parse_from_rfc3339(timestamp?).ok()?;
+    Some(parsed.with_timezone(&tz).format("%Y-%m-%d %H:%M %Z").to_string())
+}
+
+/// Get user's purchase history from database.
+/// Only completed purchases are returned unless `status_filter` asks for others,
+/// e.g. ["completed", "refunded", "failed", "disputed"] for the full history.
+/// With `tz` (an IANA zone such as "Europe/Paris") each purchase also gets local
+/// `completed_at_local` and `created_at_local` fields for display; the UTC fields stay as they are.
+#[command]
+pub async fn get_user_purchases(
+    user_id: String,
+    status_filter: Option<Vec<String>>,
+    tz: Option<String>,
+    app: tauri::AppHandle,
+) -> Result<Vec<Purchase>, String> {
+    let tz = parse_timezone(tz.as_deref())?;
+    let statuses = status_filter.unwrap_or_else(|| vec!["completed".to_string()]);
+    if statuses.is_empty() {
+        return Err("status_filter must contain at least one status".to_string());
+    }
+    if let Some(unknown) = statuses.iter().find(|status| !PURCHASE_STATUSES.contains(&status.as_str())) {
+        return Err(format!("Unknown purchase status: {}", unknown));
+    }
+
+    let db_config = get_authenticated_db(&app).await?;
+
+    // Verify user is authenticated by checking if they have a valid session
+    let session_check = crate::session::check_session(app.clone()).await?;
+    if !session_check {
+        return Err("Authentication required".to_string());
+    }
+
+    let client = crate::http::client();
+    
+    let url = format!("{}/rest/v1/purchases", db_config.database_url);
+    
+    let response = client
+        .get(&url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[
+            ("user_id", format!("eq.{}", user_id)),
+            ("status", format!("in.({})", statuses.join(","))),
+            // Failed and pending purchases never get a completed_at
+            ("order", "completed_at.desc.nullslast,created_at.desc".to_string()),
+            ("select", "id,user_id,stripe_payment_intent_id,stripe_price_id,stripe_product_id,package_id,package_price_id,amount_paid,currency,tokens_purchased,status,completed_at,created_at,updated_at".to_string())
+        ])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch purchases: {}", e))?;
+    
+    let status = response.status();
+    if !status.is_success() {
+        let error_body = response.text().await.unwrap_or_else(|_| "Could not read error body".to_string());
+        return Err(format!("Database query failed: {} - {}", status, error_body));
+    }
+    
+    let mut purchases: Vec<Purchase> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse purchases response: {}", e))?;
+
+    if let Some(tz) = tz {
+        for purchase in &mut purchases {
+            purchase.completed_at_local = localize_timestamp(purchase.completed_at.as_deref(), tz);
+            purchase.created_at_local = localize_timestamp(purchase.created_at.as_deref(), tz);
+        }
+    }
+    
+    Ok(purchases)
+}
+
+/// Totals over the user's completed purchases, with one entry per month for the last
+/// 12 months (months without purchases are empty). Amounts are summed per currency.
+#[command]
+pub async fn get_spend_summary(
+    user_id: String,
+    app: tauri::AppHandle,
+) -> Result<SpendSummary, String> {
+    let purchases = get_user_purchases(user_id, None, None, app).await?;
+    Ok(summarize_spend(&purchases, chrono::Utc::now()))
+}
+
+fn summarize_spend(purchases: &[Purchase], now: chrono::DateTime<chrono::Utc>) -> SpendSummary {
+    use chrono::Datelike;
+
+    let (mut year, mut month) = (now.year(), now.month());
+    let mut monthly = Vec::with_capacity(12);
+    for _ in 0..12 {
+        monthly.push(MonthlySpend {
+            month: format!("{:04}-{:02}", year, month),
+            spent_cents: BTreeMap::new(),
+            purchases: 0,
+            tokens_purchased: 0,
+        });
+        if month == 1 {
+            year -= 1;
+            month = 12;
+        } else {
+            month -= 1;
+        }
+    }
+    monthly.reverse();
+
+    let mut summary = SpendSummary {
+        total_spent_cents: BTreeMap::new(),
+        total_purchases: 0,
+        total_tokens_purchased: 0,
+        monthly,
+    };
+
+    for purchase in purchases {
+        let tokens = purchase.tokens_purchased.unwrap_or(0);
+        let currency = purchase.currency.to_lowercase();
+        *summary.total_spent_cents.entry(currency.clone()).or_default() += purchase.amount_paid;
+        summary.total_purchases += 1;
+        summary.total_tokens_purchased += tokens;
+
+        // Timestamps come back from Postgres in UTC, so the YYYY-MM prefix is the month
+        let month = purchase.completed_at.as_deref()
+            .or(purchase.created_at.as_deref())
+            .and_then(|timestamp| timestamp.get(..7));
+        if let Some(bucket) = summary.monthly.iter_mut().find(|bucket| Some(bucket.month.as_str()) == month) {
+            *bucket.spent_cents.entry(currency).or_default() += purchase.amount_paid;
+            bucket.purchases += 1;
+            bucket.tokens_purchased += tokens;
+        }
+    }
+
+    summary
+}
+
+/// Get the user's token balance without fetching the whole profile
+#[command]
+pub async fn get_token_balance(
+    user_id: String,
+    app: tauri::AppHandle,
+) -> Result<TokenBalance, String> {
+    let db_config = get_authenticated_db(&app).await?;
+    let client = crate::http::client();
+    
+    let response = client
+        .get(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("id", format!("eq.{}", user_id))])
+        .query(&[("select", "total_tokens,tokens_remaining,tokens_used")])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch token balance: {}", e))?;
+    
+    let status = response.status();
+    if !status.is_success() {
+        let error_body = response.text().await.unwrap_or_else(|_| "Could not read error body".to_string());
+        return Err(format!("Database query failed: {} - {}", status, error_body));
+    }
+    
+    let rows: Vec<TokenBalanceRow> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse token balance response: {}", e))?;
+    
+    rows.into_iter()
+        .next()
+        .map(TokenBalance::from)
+        .ok_or_else(|| format!("No profile found for user {}", user_id))
+}
+
+/// Re-read the authoritative balance so the client can correct an optimistic local
+/// balance. `local_tokens_remaining` is what the client currently shows; the returned
+/// drift is how far the server differs from it.
+#[command]
+pub async fn reconcile_token_balance(
+    user_id: String,
+    local_tokens_remaining: Option<i64>,
+    app: tauri::AppHandle,
+) -> Result<TokenReconciliation, String> {
+    let balance = get_token_balance(user_id, app).await?;
+    let drift = local_tokens_remaining
+        .map(|local| balance.tokens_remaining - local)
+        .unwrap_or(0);
+
+    Ok(TokenReconciliation { balance, drift })
+}
+
+/// Spend tokens from the user's balance. The check and decrement happen in one
+/// conditional UPDATE (spend_user_tokens), so concurrent spends can't overdraw.
+///
+/// The UI may decrement its local balance before calling this so it feels instant.
+/// The returned balance is the server's value right after the spend and replaces the
+/// local one; on failure the client should call `reconcile_token_balance` to undo
+/// its optimistic decrement.
+#[command]
+pub async fn spend_tokens(
+    user_id: String,
+    amount: i64,
+    reason: Option<String>,
+    app: tauri::AppHandle,
+) -> Result<TokenBalance, TokenError> {
+    if amount <= 0 {
+        return Err(format!("Token amount must be positive, got {}", amount).into());
+    }
+    
+    let reason = reason.unwrap_or_else(|| "Token usage".to_string());
+    
+    let db_config = get_authenticated_db(&app).await?;
+    let client = crate::http::client();
+    
+    let response = client
+        .post(format!("{}/rest/v1/rpc/spend_user_tokens", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .json(&serde_json::json!({
+            "p_user_id": user_id,
+            "p_token_amount": amount,
+            "p_description": reason
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to spend tokens: {}", e))?;
+    
+    let status = response.status();
+    if !status.is_success() {
+        let error_body = response.text().await.unwrap_or_else(|_| "Could not read error body".to_string());
+        return Err(spend_error(status.as_u16(), &error_body));
+    }
+    
+    let rows: Vec<TokenBalanceRow> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse token spend response: {}", e))?;
+    
+    match rows.into_iter().next() {
+        Some(row) => {
+            // spend_user_tokens wrote the ledger entry along with the spend
+            Ok(TokenBalance::from(row))
+        },
+        None => {
+            // Nothing was deducted and a freeze would have raised, so the balance was short
+            let profile = get_user_profile(user_id.clone(), app.clone()).await?
+                .ok_or_else(|| format!("No profile found for user {}", user_id))?;
+            Err(TokenError::InsufficientTokens {
+                requested: amount,
+                available: profile.tokens_remaining.unwrap_or(0),
+            })
+        }
+    }
+}
+
+// Raised by the reject_token_spend_while_frozen trigger (migration 037)
+const TOKENS_FROZEN_SQLSTATE: &str = "AU001";
+
+// Classify a failed spend_user_tokens call from its status and PostgREST error body
+fn spend_error(status: u16, body: &str) -> TokenError {
+    let error: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+    if error["code"].as_str() == Some(TOKENS_FROZEN_SQLSTATE) {
+        return TokenError::TokensFrozen {
+            message: error["message"].as_str()
+                .unwrap_or("Token spending is frozen while a payment dispute is open")
+                .to_string(),
+        };
+    }
+
+    match access_error(status, body, "profiles") {
+        Some(error) => error.into(),
+        None => format!("Token spend failed: {} - {}", status, body).into(),
+    }
+}
+
+/// Grant (positive `delta`) or take back (negative `delta`) tokens on another user's
+/// balance, for support to comp tokens or correct mistakes. Admin only. The change and
+/// its `admin_adjustment` ledger entry are applied together by admin_adjust_user_tokens,
+/// and a correction that would take tokens_remaining below zero is rejected unapplied.
+#[command]
+pub async fn admin_adjust_tokens(
+    target_user_id: String,
+    delta: i64,
+    reason: String,
+    app: tauri::AppHandle,
+) -> Result<TokenBalance, TokenError> {
+    assert_admin(&app).await?;
+
+    if delta == 0 {
+        return Err("Token adjustment must be non-zero".into());
+    }
+    if reason.trim().is_empty() {
+        return Err("A reason is required for token adjustments".into());
+    }
+
+    let db_config = get_authenticated_db(&app).await?;
+
+    let response = crate::http::client()
+        .post(format!("{}/rest/v1/rpc/admin_adjust_user_tokens", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .json(&serde_json::json!({
+            "p_user_id": target_user_id,
+            "p_delta": delta,
+            "p_description": reason.trim()
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to adjust tokens: {}", e))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let error_body = response.text().await.unwrap_or_else(|_| "Could not read error body".to_string());
+        return Err(format!("Token adjustment failed: {} - {}", status, error_body).into());
+    }
+
+    let rows: Vec<TokenBalanceRow> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse token adjustment response: {}", e))?;
+
+    match rows.into_iter().next() {
+        Some(row) => Ok(TokenBalance::from(row)),
+        None => {
+            // Nothing was changed; report what the correction would have needed
+            let balance = get_token_balance(target_user_id, app).await?;
+            Err(TokenError::InsufficientTokens {
+                requested: -delta,
+                available: balance.tokens_remaining,
+            })
+        }
+    }
+}
+
+/// Tokens credited for paying `amount_cents` in `currency`, from the token_price_map table.
+/// None when the amount has no row, so callers fall back to the price's token_amount.
+/// The table is cached for a few minutes since every purchase line looks it up.
+pub(crate) async fn resolve_token_amount<R: tauri::Runtime>(
+    amount_cents: i64,
+    currency: &str,
+    app: &tauri::AppHandle<R>,
+) -> Result<Option<i64>, String> {
+    let key = (amount_cents, currency.trim().to_lowercase());
+    let now = chrono::Utc::now().timestamp();
+
+    if let Ok(cache) = TOKEN_PRICE_MAP_CACHE.lock() {
+        if let Some((cached_at, map)) = cache.as_ref() {
+            if now - cached_at < TOKEN_PRICE_MAP_CACHE_TTL_SECS {
+                return Ok(map.get(&key).copied());
+            }
+        }
+    }
+
+    let db_config = get_authenticated_db(app).await?;
+
+    let response = crate::http::client()
+        .get(format!("{}/rest/v1/token_price_map", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
-        .header("Content-Type", "application/json")
+        .query(&[("select", "amount_cents,currency,tokens")])
         .send()
         .await
-        .map_err(|e| format!("Failed to query package prices: {}", e))?;
-    
-    if !prices_response.status().is_success() {
-        let error_text = prices_response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-        return Err(format!("Database error fetching package prices: {}", error_text));
+        .map_err(|e| format!("Failed to fetch token price map: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_body = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to fetch token price map: {} - {}", status, error_body));
     }
-    
-    let prices: Vec<PackagePrice> = prices_response
+
+    let rows: Vec<serde_json::Value> = response
         .json()
         .await
-        .map_err(|e| format!("Failed to parse package prices response: {}", e))?;
-    
-    // Group prices by package
-    let mut packages_with_prices = Vec::new();
-    for package in packages {
-        let package_prices: Vec<PackagePrice> = prices
-            .iter()
-            .filter(|p| p.package_id == package.id)
-            .cloned()
-            .collect();
-        
-        packages_with_prices.push(PackageWithPrices {
-            package,
-            prices: package_prices,
-        });
+        .map_err(|e| format!("Failed to parse token price map response: {}", e))?;
+
+    let map: TokenPriceMap = rows.iter()
+        .filter_map(|row| {
+            let amount_cents = row["amount_cents"].as_i64()?;
+            let currency = row["currency"].as_str()?.to_lowercase();
+            Some(((amount_cents, currency), row["tokens"].as_i64()?))
+        })
+        .collect();
+
+    let tokens = map.get(&key).copied();
+    if let Ok(mut cache) = TOKEN_PRICE_MAP_CACHE.lock() {
+        *cache = Some((now, map));
     }
-    
-    Ok(packages_with_prices)
+
+    Ok(tokens)
 }
 
-/// Get user's purchase history from database
+/// Get the user's token ledger, newest first
 #[command]
-pub async fn get_user_purchases(
+pub async fn get_token_ledger(
     user_id: String,
+    limit: Option<u32>,
+    offset: Option<u32>,
     app: tauri::AppHandle,
-) -> Result<Vec<Purchase>, String> {
+) -> Result<Vec<TokenLedgerEntry>, String> {
     let db_config = get_authenticated_db(&app).await?;
-
-    // Verify user is authenticated by checking if they have a valid session
-    let session_check = crate::session::check_session(app.clone()).await?;
-    if !session_check {
-        return Err("Authentication required".to_string());
-    }
-
-    let client = reqwest::Client::new();
-    
-    let url = format!("{}/rest/v1/purchases", db_config.database_url);
+    let client = crate::http::client();
     
     let response = client
-        .get(&url)
+        .get(format!("{}/rest/v1/token_ledger", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .query(&[
             ("user_id", format!("eq.{}", user_id)),
-            ("status", "eq.completed".to_string()),
-            ("order", "completed_at.desc".to_string()),
-            ("select", "id,user_id,stripe_payment_intent_id,stripe_price_id,stripe_product_id,package_id,package_price_id,amount_paid,currency,tokens_purchased,status,completed_at,created_at,updated_at".to_string())
+            ("order", "created_at.desc".to_string()),
+            ("limit", limit.unwrap_or(50).min(200).to_string()),
+            ("offset", offset.unwrap_or(0).to_string()),
+            ("select", "id,user_id,amount,reason,balance_after,entry_type,created_at".to_string())
         ])
         .send()
         .await
-        .map_err(|e| format!("Failed to fetch purchases: {}", e))?;
+        .map_err(|e| format!("Failed to fetch token ledger: {}", e))?;
     
     let status = response.status();
     if !status.is_success() {
@@ -1089,76 +3734,124 @@ pub async fn get_user_purchases(
         return Err(format!("Database query failed: {} - {}", status, error_body));
     }
     
-    let purchases: Vec<Purchase> = response
+    let entries: Vec<TokenLedgerEntry> = response
         .json()
         .await
-        .map_err(|e| format!("Failed to parse purchases response: {}", e))?;
+        .map_err(|e| format!("Failed to parse token ledger response: {}", e))?;
     
-    Ok(purchases)
+    Ok(entries)
 }
 
-/// Save contractor KYC form data for auto-save functionality
+/// Save contractor KYC form data for auto-save functionality. `version` must increase
+/// with every save (e.g. the time the form changed, in milliseconds); a save older than
+/// what is stored is skipped, so overlapping auto-saves can't roll the form back.
+/// The national ID and bank account numbers are encrypted with the vault key before
+/// they are stored, and load_kyc_form_data decrypts them. Where the vault isn't
+/// available (e.g. on Android) those fields are left out of the draft instead.
 #[command]
 pub async fn save_kyc_form_data(
     user_id: String,
     kyc_data: ContractorKycFormData,
+    version: Option<i64>,
     app: tauri::AppHandle,
-) -> Result<String, String> {
+) -> Result<KycFormSaveResult, DatabaseError> {
     let db_config = get_authenticated_db(&app).await?;
 
     // Verify user is authenticated
     let session_check = crate::session::check_session(app.clone()).await?;
     if !session_check {
-        return Err("User not authenticated".to_string());
+        return Err(DatabaseError::not_authenticated());
     }
 
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     
     // Convert form data to JSON
-    let kyc_json = serde_json::to_value(&kyc_data)
+    let mut kyc_json = serde_json::to_value(&kyc_data)
         .map_err(|e| format!("Failed to serialize KYC data: {}", e))?;
+    seal_kyc_fields(&mut kyc_json);
+    let version = version.unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
 
-    // Use UPSERT with ON CONFLICT clause for proper update/insert behavior
+    // Conditional upsert: only replaces stored data with a lower version
     let response = client
-        .post(&format!("{}/rest/v1/contractor_kyc_form_data?on_conflict=user_id", db_config.database_url))
+        .post(format!("{}/rest/v1/rpc/save_kyc_form_data", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
-        .header("Prefer", "resolution=merge-duplicates")
         .json(&serde_json::json!({
-            "user_id": user_id,
-            "kyc_data": kyc_json
+            "p_user_id": user_id,
+            "p_kyc_data": kyc_json,
+            "p_version": version
         }))
         .send()
         .await
         .map_err(|e| format!("Failed to save KYC form data: {}", e))?;
 
     if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("Database error: {}", error_text));
+        return Err(response_error(response, "contractor_kyc_form_data").await);
+    }
+
+    let applied: bool = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse KYC form data save response: {}", e))?;
+
+    Ok(KycFormSaveResult { applied, version })
+}
+
+/// Delete the user's saved KYC draft, which holds sensitive fields that aren't needed
+/// once onboarding is submitted. Deleting a draft that doesn't exist succeeds.
+#[command]
+pub async fn delete_kyc_form_data(
+    user_id: String,
+    app: tauri::AppHandle,
+) -> Result<String, DatabaseError> {
+    let db_config = get_authenticated_db(&app).await?;
+
+    // Verify user is authenticated
+    let session_check = crate::session::check_session(app.clone()).await?;
+    if !session_check {
+        return Err(DatabaseError::not_authenticated());
+    }
+
+    let response = crate::http::client()
+        .delete(format!("{}/rest/v1/contractor_kyc_form_data", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("user_id", format!("eq.{}", user_id))])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to delete KYC form data: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(response_error(response, "contractor_kyc_form_data").await);
     }
 
-    Ok("KYC form data saved successfully".to_string())
+    Ok("KYC form data deleted successfully".to_string())
 }
 
-/// Load contractor KYC form data
+/// Load contractor KYC form data. A draft whose encrypted fields can't be decrypted,
+/// e.g. because the vault key was rotated since it was saved, is unavailable and loads
+/// as no draft rather than an error.
 #[command]
 pub async fn load_kyc_form_data(
     user_id: String,
     app: tauri::AppHandle,
-) -> Result<Option<ContractorKycFormData>, String> {
+) -> Result<Option<ContractorKycFormData>, DatabaseError> {
     let db_config = get_authenticated_db(&app).await?;
 
     // Verify user is authenticated
     let session_check = crate::session::check_session(app.clone()).await?;
     if !session_check {
-        return Err("User not authenticated".to_string());
+        return Err(DatabaseError::not_authenticated());
     }
 
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     
     let response = client
-        .get(&format!("{}/rest/v1/contractor_kyc_form_data", db_config.database_url))
+        .get(format!("{}/rest/v1/contractor_kyc_form_data", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .query(&[("user_id", format!("eq.{}", user_id))])
@@ -1168,8 +3861,7 @@ pub async fn load_kyc_form_data(
         .map_err(|e| format!("Failed to load KYC form data: {}", e))?;
 
     if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("Database error: {}", error_text));
+        return Err(response_error(response, "contractor_kyc_form_data").await);
     }
 
     let form_data_records: Vec<serde_json::Value> = response
@@ -1179,7 +3871,12 @@ pub async fn load_kyc_form_data(
 
     if let Some(record) = form_data_records.first() {
         if let Some(kyc_data) = record.get("kyc_data") {
-            let form_data: ContractorKycFormData = serde_json::from_value(kyc_data.clone())
+            let mut kyc_data = kyc_data.clone();
+            if let Err(e) = open_kyc_fields(&mut kyc_data) {
+                eprintln!("Saved KYC draft is unavailable: {}", e);
+                return Ok(None);
+            }
+            let form_data: ContractorKycFormData = serde_json::from_value(kyc_data)
                 .map_err(|e| format!("Failed to deserialize KYC data: {}", e))?;
             return Ok(Some(form_data));
         }
@@ -1188,19 +3885,140 @@ pub async fn load_kyc_form_data(
     Ok(None)
 }
 
-/// Create contractor profile and Stripe Connect account
+// Draft KYC fields encrypted with the vault key before they reach the database, as
+// paths into the serialized form data. Everything else stays readable.
+const SEALED_KYC_FIELDS: [&[&str]; 2] = [&["nationalIdNumber"], &["bankAccount", "accountNumber"]];
+
+// A field that can't be encrypted is blanked, so it's never stored in plaintext
+fn seal_kyc_fields(kyc_data: &mut serde_json::Value) {
+    for path in SEALED_KYC_FIELDS {
+        if let Some(field) = kyc_field(kyc_data, path) {
+            if field.as_str().is_some_and(|value| !value.is_empty()) {
+                *field = match crate::vault::seal(field) {
+                    Ok(sealed) => serde_json::json!(sealed),
+                    Err(e) => {
+                        eprintln!("Leaving {} out of the KYC draft: {}", path.join("."), e);
+                        serde_json::json!("")
+                    },
+                };
+            }
+        }
+    }
+}
+
+// Drafts saved before encryption was added hold these fields in plaintext and pass through
+fn open_kyc_fields(kyc_data: &mut serde_json::Value) -> Result<(), String> {
+    for path in SEALED_KYC_FIELDS {
+        if let Some(field) = kyc_field(kyc_data, path) {
+            if let Some(sealed) = field.as_str().filter(|value| crate::vault::is_sealed(value)) {
+                *field = crate::vault::open(sealed)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn kyc_field<'a>(kyc_data: &'a mut serde_json::Value, path: &[&str]) -> Option<&'a mut serde_json::Value> {
+    path.iter().try_fold(kyc_data, |value, key| value.get_mut(*key))
+}
+
+// Replace each encrypted field with what `reseal` returns for it; None leaves it as is.
+// Returns whether any field changed.
+fn reseal_kyc_fields(
+    kyc_data: &mut serde_json::Value,
+    reseal: impl Fn(&str) -> Result<Option<String>, String>,
+) -> Result<bool, String> {
+    let mut changed = false;
+    for path in SEALED_KYC_FIELDS {
+        if let Some(field) = kyc_field(kyc_data, path) {
+            if let Some(sealed) = field.as_str().filter(|value| crate::vault::is_sealed(value)) {
+                if let Some(resealed) = reseal(sealed)? {
+                    *field = serde_json::json!(resealed);
+                    changed = true;
+                }
+            }
+        }
+    }
+    Ok(changed)
+}
+
+/// Re-encrypt the signed-in user's saved KYC draft during a vault key rotation, so it
+/// stays readable once the old key is gone. Does nothing when no one is signed in or
+/// there is no draft. The draft is only replaced if it hasn't been saved again since it
+/// was read; a newer save is already encrypted with the new key.
+pub(crate) async fn reseal_kyc_draft(
+    app: &tauri::AppHandle,
+    reseal: impl Fn(&str) -> Result<Option<String>, String>,
+) -> Result<(), DatabaseError> {
+    let user = match crate::session::get_auth_user(app.clone()).await {
+        Ok(user) => user,
+        Err(_) => return Ok(()),
+    };
+    let db_config = get_authenticated_db(app).await?;
+
+    let response = crate::http::client()
+        .get(format!("{}/rest/v1/contractor_kyc_form_data", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("user_id", format!("eq.{}", user.id))])
+        .query(&[("select", "kyc_data,version")])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to load KYC form data: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(response_error(response, "contractor_kyc_form_data").await);
+    }
+
+    let records: Vec<serde_json::Value> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse KYC form data response: {}", e))?;
+    let Some(record) = records.first() else {
+        return Ok(());
+    };
+
+    let mut kyc_data = record["kyc_data"].clone();
+    if !reseal_kyc_fields(&mut kyc_data, reseal)? {
+        return Ok(());
+    }
+
+    let response = crate::http::client()
+        .patch(format!("{}/rest/v1/contractor_kyc_form_data", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .query(&[("user_id", format!("eq.{}", user.id))])
+        .query(&[("version", format!("eq.{}", record["version"].as_i64().unwrap_or(0)))])
+        .json(&serde_json::json!({ "kyc_data": kyc_data }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to save KYC form data: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(response_error(response, "contractor_kyc_form_data").await);
+    }
+
+    Ok(())
+}
+
+/// Create contractor profile and Stripe Connect account, then delete the saved KYC draft.
+/// Fails if the account or profile can't be created; the address, profile flag and draft
+/// cleanup are best-effort and reported in the result.
 #[command]
 pub async fn create_contractor_profile(
     user_id: String,
     kyc_data: ContractorKycFormData,
     app: tauri::AppHandle,
-) -> Result<Contractor, String> {
+) -> Result<ContractorCreationResult, DatabaseError> {
     let db_config = get_authenticated_db(&app).await?;
 
     // Verify user is authenticated
     let session_check = crate::session::check_session(app.clone()).await?;
     if !session_check {
-        return Err("User not authenticated".to_string());
+        return Err(DatabaseError::not_authenticated());
     }
 
     // Get user profile to link contractor
@@ -1216,19 +4034,21 @@ pub async fn create_contractor_profile(
         app.clone(),
     ).await.map_err(|e| {
         println!("❌ Stripe Connect account creation failed: {}", e);
-        e
+        e.to_string()
     })?;
     
     println!("✅ Stripe Connect account created: {}", connect_response.account_id);
 
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     
     // Create contractor record
     let contractor_data = serde_json::json!({
         "user_id": user_id,
         "profile_id": profile.id,
         "contractor_type": kyc_data.contractor_type,
-        "kyc_status": "submitted",
+        // Businesses add representatives and owners afterwards, so they start as pending
+        // and are submitted once validate_contractor_completeness passes
+        "kyc_status": if kyc_data.contractor_type == "business" { "pending" } else { "submitted" },
         "is_active": true,
         "stripe_connect_account_id": connect_response.account_id,
         "stripe_connect_account_status": "pending",
@@ -1246,7 +4066,8 @@ pub async fn create_contractor_profile(
     println!("   - business_tax_id: {:?}", kyc_data.business_tax_id);
 
     let response = client
-        .post(&format!("{}/rest/v1/contractors", db_config.database_url))
+        .post(format!("{}/rest/v1/contractors", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -1266,12 +4087,12 @@ pub async fn create_contractor_profile(
             println!("🔍 Constraint violation - contractor may already exist for this user");
         } else if status.as_u16() == 422 {
             println!("🔍 Schema validation error - check required fields and data types");
-        } else if status.as_u16() == 401 || status.as_u16() == 403 {
-            println!("🔍 Authentication/authorization error - check RLS policies");
+        } else if let Some(error) = access_error(status.as_u16(), &error_text, "contractors") {
+            return Err(error);
         }
         
         return Err(format!("Failed to create contractor record: HTTP {} {}", status, 
-                          if error_text.is_empty() { status.canonical_reason().unwrap_or("Unknown error") } else { &error_text }));
+                          if error_text.is_empty() { status.canonical_reason().unwrap_or("Unknown error") } else { &error_text }).into());
     }
 
     let contractors: Vec<Contractor> = response
@@ -1284,48 +4105,67 @@ pub async fn create_contractor_profile(
 
     println!("✅ Contractor record created successfully with ID: {}", contractor.id);
 
+    let mut failures = Vec::new();
+
     // Create contractor address record
-    if let Some(address) = kyc_data.address {
-        println!("🏠 Creating contractor address record for contractor ID: {}", contractor.id);
-        let address_data = serde_json::json!({
-            "contractor_id": contractor.id,
-            "address_type": "residential",
-            "street_address": address.line1,
-            "street_address_2": address.line2,
-            "city": address.city,
-            "state_province": address.state,
-            "postal_code": address.postal_code,
-            "country": address.country,
-            "is_verified": false
-        });
-        
-        println!("📋 Address data: {:?}", address_data);
-
-        let address_response = client
-            .post(&format!("{}/rest/v1/contractor_addresses", db_config.database_url))
-            .header("Authorization", format!("Bearer {}", db_config.access_token))
-            .header("apikey", &db_config.anon_key)
-            .header("Content-Type", "application/json")
-            .json(&address_data)
-            .send()
-            .await
-            .map_err(|e| format!("Failed to create contractor address: {}", e))?;
-            
-        if !address_response.status().is_success() {
-            let status = address_response.status();
-            let error_text = address_response.text().await.unwrap_or_default();
-            println!("❌ Failed to create contractor address: HTTP {} - {}", status, error_text);
+    let address_created = match kyc_data.address {
+        Some(address) => {
+            println!("🏠 Creating contractor address record for contractor ID: {}", contractor.id);
+            let address_data = serde_json::json!({
+                "contractor_id": contractor.id,
+                "address_type": "residential",
+                "street_address": address.line1,
+                "street_address_2": address.line2,
+                "city": address.city,
+                "state_province": address.state,
+                "postal_code": address.postal_code,
+                "country": address.country,
+                "is_verified": false
+            });
+
+            println!("📋 Address data: {:?}", address_data);
+
+            let address_result = client
+                .post(format!("{}/rest/v1/contractor_addresses", db_config.database_url))
+                .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
+                .header("Authorization", format!("Bearer {}", db_config.access_token))
+                .header("apikey", &db_config.anon_key)
+                .header("Content-Type", "application/json")
+                .json(&address_data)
+                .send()
+                .await;
+
             // Don't fail the entire process for address creation failure
-            println!("⚠️ Continuing without address record");
-        } else {
-            println!("✅ Contractor address created successfully");
-        }
-    }
+            let failure = match address_result {
+                Ok(response) if response.status().is_success() => None,
+                Ok(response) => {
+                    let status = response.status();
+                    let error_text = response.text().await.unwrap_or_default();
+                    Some(format!("Failed to create contractor address: HTTP {} - {}", status, error_text))
+                },
+                Err(e) => Some(format!("Failed to create contractor address: {}", e)),
+            };
+            match failure {
+                Some(failure) => {
+                    println!("❌ {}", failure);
+                    println!("⚠️ Continuing without address record");
+                    failures.push(failure);
+                    Some(false)
+                },
+                None => {
+                    println!("✅ Contractor address created successfully");
+                    Some(true)
+                },
+            }
+        },
+        None => None,
+    };
 
     // Update profile to mark as contractor
     println!("👤 Updating profile to mark as contractor: profile_id={}, contractor_id={}", profile.id, contractor.id);
-    let profile_update_response = client
-        .patch(&format!("{}/rest/v1/profiles", db_config.database_url))
+    let profile_update_result = client
+        .patch(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -1335,20 +4175,50 @@ pub async fn create_contractor_profile(
             "contractor_id": contractor.id
         }))
         .send()
-        .await
-        .map_err(|e| format!("Failed to update profile: {}", e))?;
-        
-    if !profile_update_response.status().is_success() {
-        let status = profile_update_response.status();
-        let error_text = profile_update_response.text().await.unwrap_or_default();
-        println!("❌ Failed to update profile: HTTP {} - {}", status, error_text);
-        // Don't fail the entire process for profile update failure
-        println!("⚠️ Continuing without profile update");
-    } else {
-        println!("✅ Profile updated successfully");
-    }
-
-    Ok(contractor)
+        .await;
+
+    // Don't fail the entire process for profile update failure
+    let failure = match profile_update_result {
+        Ok(response) if response.status().is_success() => None,
+        Ok(response) => {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            Some(format!("Failed to update profile: HTTP {} - {}", status, error_text))
+        },
+        Err(e) => Some(format!("Failed to update profile: {}", e)),
+    };
+    let profile_updated = match failure {
+        Some(failure) => {
+            println!("❌ {}", failure);
+            println!("⚠️ Continuing without profile update");
+            failures.push(failure);
+            false
+        },
+        None => {
+            println!("✅ Profile updated successfully");
+            true
+        },
+    };
+
+    // The draft is no longer needed once the contractor exists
+    let kyc_draft_deleted = match delete_kyc_form_data(user_id, app).await {
+        Ok(_) => true,
+        Err(e) => {
+            println!("⚠️ Failed to delete KYC draft: {}", e);
+            failures.push(format!("Failed to delete KYC draft: {}", e));
+            false
+        },
+    };
+
+    Ok(ContractorCreationResult {
+        contractor,
+        connect_account_created: true,
+        contractor_created: true,
+        address_created,
+        profile_updated,
+        kyc_draft_deleted,
+        failures,
+    })
 }
 
 /// Get contractor profile for user
@@ -1356,19 +4226,20 @@ pub async fn create_contractor_profile(
 pub async fn get_contractor_profile(
     user_id: String,
     app: tauri::AppHandle,
-) -> Result<Option<Contractor>, String> {
+) -> Result<Option<Contractor>, DatabaseError> {
     let db_config = get_authenticated_db(&app).await?;
 
     // Verify user is authenticated
     let session_check = crate::session::check_session(app.clone()).await?;
     if !session_check {
-        return Err("User not authenticated".to_string());
+        return Err(DatabaseError::not_authenticated());
     }
 
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     
     let response = client
-        .get(&format!("{}/rest/v1/contractors", db_config.database_url))
+        .get(format!("{}/rest/v1/contractors", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .query(&[("user_id", format!("eq.{}", user_id))])
@@ -1377,8 +4248,7 @@ pub async fn get_contractor_profile(
         .map_err(|e| format!("Failed to get contractor profile: {}", e))?;
 
     if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("Database error: {}", error_text));
+        return Err(response_error(response, "contractors").await);
     }
 
     let contractors: Vec<Contractor> = response
@@ -1474,16 +4344,128 @@ pub struct DocumentUpload {
     pub verification_status: String,
     pub verification_notes: Option<String>,
     pub verified_at: Option<String>,
+    pub reviewed_by: Option<String>,
+    pub reviewed_at: Option<String>,
     pub required_for_capability: Option<Vec<String>>,
     pub requirement_id: Option<String>,
     pub created_at: Option<String>,
     pub updated_at: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingDocument {
+    #[serde(flatten)]
+    pub document: DocumentUpload,
+    pub contractor_name: Option<String>,
+}
+
+// Document row with the owning contractor embedded by PostgREST
+#[derive(Debug, Deserialize)]
+struct DocumentWithContractorRow {
+    #[serde(flatten)]
+    document: DocumentUpload,
+    contractors: Option<ContractorNameRow>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContractorNameRow {
+    first_name: Option<String>,
+    last_name: Option<String>,
+    business_name: Option<String>,
+}
+
+impl ContractorNameRow {
+    // Business name for companies, otherwise the individual's name
+    fn display_name(self) -> Option<String> {
+        if let Some(business_name) = self.business_name.filter(|n| !n.trim().is_empty()) {
+            return Some(business_name);
+        }
+        let name = [self.first_name, self.last_name]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+        if name.trim().is_empty() { None } else { Some(name) }
+    }
+}
+
+// Person details shared by beneficial owners and representatives
+struct PersonFields<'a> {
+    first_name: &'a str,
+    last_name: &'a str,
+    date_of_birth: &'a str,
+    email: Option<&'a str>,
+    street_address: &'a str,
+    city: &'a str,
+    postal_code: &'a str,
+    country: &'a str,
+}
+
+// Every problem with the fields, not just the first, so the form can flag them all at once.
+// Dates of birth are YYYY-MM-DD and must be before `today`; countries are ISO 3166-1 alpha-2.
+fn validate_person_fields(person: &PersonFields, today: chrono::NaiveDate) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let mut push = |field: &str, message: String| errors.push(FieldError { field: field.to_string(), message });
+
+    for (field, label, value) in [
+        ("first_name", "First name", person.first_name),
+        ("last_name", "Last name", person.last_name),
+        ("street_address", "Street address", person.street_address),
+        ("city", "City", person.city),
+        ("postal_code", "Postal code", person.postal_code),
+    ] {
+        if value.trim().is_empty() {
+            push(field, format!("{} is required", label));
+        }
+    }
+
+    let date_of_birth = person.date_of_birth.trim();
+    if date_of_birth.is_empty() {
+        push("date_of_birth", "Date of birth is required".to_string());
+    } else {
+        match chrono::NaiveDate::parse_from_str(date_of_birth, "%Y-%m-%d") {
+            Ok(date) if date >= today => push("date_of_birth", "Date of birth must be in the past".to_string()),
+            Ok(_) => {},
+            Err(_) => push("date_of_birth", format!("Date of birth must be a date like 1990-01-31, got '{}'", date_of_birth)),
+        }
+    }
+
+    let country = person.country.trim();
+    if country.is_empty() {
+        push("country", "Country is required".to_string());
+    } else if country.len() != 2 || !country.chars().all(|c| c.is_ascii_alphabetic()) {
+        push("country", format!("Country must be a two-letter code such as US, got '{}'", country));
+    }
+
+    if let Some(email) = person.email.map(str::trim).filter(|email| !email.is_empty()) {
+        let valid = email.split_once('@').is_some_and(|(local, domain)| {
+            !local.is_empty() && !domain.contains('@') && domain.contains('.')
+                && !domain.starts_with('.') && !domain.ends_with('.')
+        });
+        if !valid {
+            push("email", format!("Invalid email address: {}", email));
+        }
+    }
+
+    errors
+}
+
+fn validation_result(errors: Vec<FieldError>, what: &str) -> Result<(), DatabaseError> {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(DatabaseError::Validation {
+        message: format!("The {} has {} invalid field(s)", what, errors.len()),
+        errors,
+    })
+}
+
 // Database commands for new entities
 
-/// Create beneficial owner
+/// Create beneficial owner. The fields are checked before anything is sent, and every
+/// invalid one is listed in a `validation` error.
 #[command]
+#[allow(clippy::too_many_arguments)]
 pub async fn create_beneficial_owner(
     contractor_id: String,
     first_name: String,
@@ -1502,14 +4484,32 @@ pub async fn create_beneficial_owner(
     national_id_number: Option<String>,
     national_id_type: Option<String>,
     app: tauri::AppHandle,
-) -> Result<BeneficialOwner, String> {
+) -> Result<BeneficialOwner, DatabaseError> {
+    let mut errors = validate_person_fields(&PersonFields {
+        first_name: &first_name,
+        last_name: &last_name,
+        date_of_birth: &date_of_birth,
+        email: email.as_deref(),
+        street_address: &street_address,
+        city: &city,
+        postal_code: &postal_code,
+        country: &country,
+    }, chrono::Utc::now().date_naive());
+    if !(ownership_percentage > 0.0 && ownership_percentage <= 100.0) {
+        errors.push(FieldError {
+            field: "ownership_percentage".to_string(),
+            message: "Ownership percentage must be more than 0 and at most 100".to_string(),
+        });
+    }
+    validation_result(errors, "beneficial owner")?;
+
     let db_config = get_authenticated_db(&app).await?;
     let session_check = crate::session::check_session(app.clone()).await?;
     if !session_check {
-        return Err("Authentication required".to_string());
+        return Err(DatabaseError::not_authenticated());
     }
 
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     let payload = serde_json::json!({
         "contractor_id": contractor_id,
         "first_name": first_name,
@@ -1531,7 +4531,8 @@ pub async fn create_beneficial_owner(
     });
 
     let response = client
-        .post(&format!("{}/rest/v1/contractor_beneficial_owners", db_config.database_url))
+        .post(format!("{}/rest/v1/contractor_beneficial_owners", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -1543,7 +4544,7 @@ pub async fn create_beneficial_owner(
 
     if !response.status().is_success() {
         let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("Database error creating beneficial owner: {}", error_text));
+        return Err(format!("Database error creating beneficial owner: {}", error_text).into());
     }
 
     let beneficial_owners: Vec<BeneficialOwner> = response
@@ -1554,7 +4555,7 @@ pub async fn create_beneficial_owner(
     beneficial_owners
         .into_iter()
         .next()
-        .ok_or_else(|| "No beneficial owner returned from database".to_string())
+        .ok_or_else(|| "No beneficial owner returned from database".into())
 }
 
 /// Get beneficial owners for contractor
@@ -1569,9 +4570,10 @@ pub async fn get_beneficial_owners(
         return Err("Authentication required".to_string());
     }
 
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     let response = client
-        .get(&format!("{}/rest/v1/contractor_beneficial_owners", db_config.database_url))
+        .get(format!("{}/rest/v1/contractor_beneficial_owners", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .query(&[("contractor_id", format!("eq.{}", contractor_id))])
@@ -1592,8 +4594,10 @@ pub async fn get_beneficial_owners(
     Ok(beneficial_owners)
 }
 
-/// Create representative
+/// Create representative. The fields are checked before anything is sent, and every
+/// invalid one is listed in a `validation` error.
 #[command]
+#[allow(clippy::too_many_arguments)]
 pub async fn create_representative(
     contractor_id: String,
     first_name: String,
@@ -1612,14 +4616,29 @@ pub async fn create_representative(
     national_id_number: Option<String>,
     national_id_type: Option<String>,
     app: tauri::AppHandle,
-) -> Result<Representative, String> {
+) -> Result<Representative, DatabaseError> {
+    let mut errors = validate_person_fields(&PersonFields {
+        first_name: &first_name,
+        last_name: &last_name,
+        date_of_birth: &date_of_birth,
+        email: email.as_deref(),
+        street_address: &street_address,
+        city: &city,
+        postal_code: &postal_code,
+        country: &country,
+    }, chrono::Utc::now().date_naive());
+    if title.trim().is_empty() {
+        errors.push(FieldError { field: "title".to_string(), message: "Title is required".to_string() });
+    }
+    validation_result(errors, "representative")?;
+
     let db_config = get_authenticated_db(&app).await?;
     let session_check = crate::session::check_session(app.clone()).await?;
     if !session_check {
-        return Err("Authentication required".to_string());
+        return Err(DatabaseError::not_authenticated());
     }
 
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     let payload = serde_json::json!({
         "contractor_id": contractor_id,
         "first_name": first_name,
@@ -1641,7 +4660,8 @@ pub async fn create_representative(
     });
 
     let response = client
-        .post(&format!("{}/rest/v1/contractor_representatives", db_config.database_url))
+        .post(format!("{}/rest/v1/contractor_representatives", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -1651,59 +4671,188 @@ pub async fn create_representative(
         .await
         .map_err(|e| format!("Failed to create representative: {}", e))?;
 
-    if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("Database error creating representative: {}", error_text));
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Database error creating representative: {}", error_text).into());
+    }
+
+    let representatives: Vec<Representative> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse representative response: {}", e))?;
+
+    representatives
+        .into_iter()
+        .next()
+        .ok_or_else(|| "No representative returned from database".into())
+}
+
+/// Get representatives for contractor
+#[command]
+pub async fn get_representatives(
+    contractor_id: String,
+    app: tauri::AppHandle,
+) -> Result<Vec<Representative>, String> {
+    let db_config = get_authenticated_db(&app).await?;
+    let session_check = crate::session::check_session(app.clone()).await?;
+    if !session_check {
+        return Err("Authentication required".to_string());
+    }
+
+    let client = crate::http::client();
+    let response = client
+        .get(format!("{}/rest/v1/contractor_representatives", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("contractor_id", format!("eq.{}", contractor_id))])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch representatives: {}", e))?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Database error fetching representatives: {}", error_text));
+    }
+
+    let representatives: Vec<Representative> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse representatives response: {}", e))?;
+
+    Ok(representatives)
+}
+
+/// What a contractor still needs before it can be submitted for verification
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContractorCompleteness {
+    pub complete: bool,
+    pub missing: Vec<FieldError>,
+}
+
+/// Check that a contractor has what Stripe needs before verification: businesses need at
+/// least one representative with signing authority, and their beneficial owners can't
+/// own more than 100% between them. Individuals have no extra requirements.
+#[command]
+pub async fn validate_contractor_completeness(
+    contractor_id: String,
+    app: tauri::AppHandle,
+) -> Result<ContractorCompleteness, DatabaseError> {
+    let db_config = get_authenticated_db(&app).await?;
+    let session_check = crate::session::check_session(app.clone()).await?;
+    if !session_check {
+        return Err(DatabaseError::not_authenticated());
+    }
+
+    let response = crate::http::client()
+        .get(format!("{}/rest/v1/contractors", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("id", format!("eq.{}", contractor_id))])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to get contractor: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(response_error(response, "contractors").await);
+    }
+
+    let contractors: Vec<Contractor> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse contractor response: {}", e))?;
+    let contractor = contractors.into_iter().next()
+        .ok_or("Contractor not found")?;
+
+    let missing = if contractor.contractor_type == "business" {
+        let representatives = get_representatives(contractor_id.clone(), app.clone()).await?;
+        let beneficial_owners = get_beneficial_owners(contractor_id, app).await?;
+        business_completeness_gaps(&representatives, &beneficial_owners)
+    } else {
+        Vec::new()
+    };
+
+    Ok(ContractorCompleteness {
+        complete: missing.is_empty(),
+        missing,
+    })
+}
+
+fn business_completeness_gaps(
+    representatives: &[Representative],
+    beneficial_owners: &[BeneficialOwner],
+) -> Vec<FieldError> {
+    let mut missing = Vec::new();
+
+    if !representatives.iter().any(|representative| representative.is_authorized_signatory) {
+        missing.push(FieldError {
+            field: "representatives".to_string(),
+            message: "Add at least one representative with signing authority".to_string(),
+        });
+    }
+
+    let total_ownership: f64 = beneficial_owners.iter().map(|owner| owner.ownership_percentage).sum();
+    // Percentages are stored to two decimal places, so allow for rounding
+    if total_ownership > 100.0 + 1e-6 {
+        missing.push(FieldError {
+            field: "beneficial_owners".to_string(),
+            message: format!("Beneficial owners add up to {:.2}% ownership, which is more than 100%", total_ownership),
+        });
     }
 
-    let representatives: Vec<Representative> = response
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse representative response: {}", e))?;
+    missing
+}
 
-    representatives
-        .into_iter()
-        .next()
-        .ok_or_else(|| "No representative returned from database".to_string())
+/// Refuse with a `validation` error listing what is missing until
+/// validate_contractor_completeness passes
+pub(crate) async fn ensure_contractor_complete(
+    contractor_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<(), DatabaseError> {
+    let completeness = validate_contractor_completeness(contractor_id.to_string(), app.clone()).await?;
+    validation_result(completeness.missing, "contractor")
 }
 
-/// Get representatives for contractor
+/// Mark a contractor as submitted for review. Refused with a `validation` error listing
+/// what is missing until validate_contractor_completeness passes.
 #[command]
-pub async fn get_representatives(
+pub async fn submit_contractor_for_verification(
     contractor_id: String,
     app: tauri::AppHandle,
-) -> Result<Vec<Representative>, String> {
-    let db_config = get_authenticated_db(&app).await?;
-    let session_check = crate::session::check_session(app.clone()).await?;
-    if !session_check {
-        return Err("Authentication required".to_string());
-    }
+) -> Result<Contractor, DatabaseError> {
+    ensure_contractor_complete(&contractor_id, &app).await?;
 
-    let client = reqwest::Client::new();
-    let response = client
-        .get(&format!("{}/rest/v1/contractor_representatives", db_config.database_url))
+    let db_config = get_authenticated_db(&app).await?;
+    let response = crate::http::client()
+        .patch(format!("{}/rest/v1/contractors", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
-        .query(&[("contractor_id", format!("eq.{}", contractor_id))])
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=representation")
+        .query(&[("id", format!("eq.{}", contractor_id))])
+        .json(&serde_json::json!({ "kyc_status": "submitted" }))
         .send()
         .await
-        .map_err(|e| format!("Failed to fetch representatives: {}", e))?;
+        .map_err(|e| format!("Failed to submit contractor for verification: {}", e))?;
 
     if !response.status().is_success() {
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("Database error fetching representatives: {}", error_text));
+        return Err(response_error(response, "contractors").await);
     }
 
-    let representatives: Vec<Representative> = response
+    let contractors: Vec<Contractor> = response
         .json()
         .await
-        .map_err(|e| format!("Failed to parse representatives response: {}", e))?;
+        .map_err(|e| format!("Failed to parse contractor response: {}", e))?;
 
-    Ok(representatives)
+    contractors.into_iter().next()
+        .ok_or_else(|| "Contractor not found".into())
 }
 
 /// Create document upload record
 #[command]
+#[allow(clippy::too_many_arguments)]
 pub async fn create_document_upload(
     contractor_id: String,
     document_type: String,
@@ -1724,7 +4873,7 @@ pub async fn create_document_upload(
         return Err("Authentication required".to_string());
     }
 
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     let payload = serde_json::json!({
         "contractor_id": contractor_id,
         "document_type": document_type,
@@ -1742,7 +4891,8 @@ pub async fn create_document_upload(
     });
 
     let response = client
-        .post(&format!("{}/rest/v1/contractor_document_uploads", db_config.database_url))
+        .post(format!("{}/rest/v1/contractor_document_uploads", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -1780,9 +4930,10 @@ pub async fn get_document_uploads(
         return Err("Authentication required".to_string());
     }
 
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     let response = client
-        .get(&format!("{}/rest/v1/contractor_document_uploads", db_config.database_url))
+        .get(format!("{}/rest/v1/contractor_document_uploads", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .query(&[("contractor_id", format!("eq.{}", contractor_id))])
@@ -1803,6 +4954,187 @@ pub async fn get_document_uploads(
     Ok(document_uploads)
 }
 
+/// Get a single document upload record by id
+#[command]
+pub async fn get_document_upload(
+    document_id: String,
+    app: tauri::AppHandle,
+) -> Result<Option<DocumentUpload>, String> {
+    let db_config = get_authenticated_db(&app).await?;
+    let session_check = crate::session::check_session(app.clone()).await?;
+    if !session_check {
+        return Err("Authentication required".to_string());
+    }
+
+    let client = crate::http::client();
+    let response = client
+        .get(format!("{}/rest/v1/contractor_document_uploads", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("id", format!("eq.{}", document_id))])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch document upload: {}", e))?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Database error fetching document upload: {}", error_text));
+    }
+
+    let document_uploads: Vec<DocumentUpload> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse document upload response: {}", e))?;
+
+    Ok(document_uploads.into_iter().next())
+}
+
+/// Fail with `Forbidden` unless the signed-in user is an admin, as decided by the server's
+/// is_admin() check. Commands that act with the Stripe secret key rely on this alone, so
+/// it never trusts anything in the locally stored access token.
+pub(crate) async fn assert_admin(app: &tauri::AppHandle) -> Result<(), DatabaseError> {
+    let db_config = get_authenticated_db(app).await?;
+
+    let response = crate::http::client()
+        .post(format!("{}/rest/v1/rpc/is_admin", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .json(&serde_json::json!({}))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to check admin access: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(access_error(status, &error_text, "profiles")
+            .unwrap_or_else(|| format!("Failed to check admin access: {}", error_text).into()));
+    }
+
+    let is_admin: bool = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse admin check response: {}", e))?;
+
+    require_admin(is_admin)
+}
+
+// What is_admin() returned for the caller's token, as a Forbidden error for non-admins
+fn require_admin(is_admin: bool) -> Result<(), DatabaseError> {
+    if !is_admin {
+        return Err(DatabaseError::Forbidden {
+            message: "Admin access required".to_string(),
+            table: "profiles".to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+/// List pending documents across all contractors, oldest first, for the admin review queue
+#[command]
+pub async fn list_pending_documents(
+    limit: Option<u32>,
+    app: tauri::AppHandle,
+) -> Result<Vec<PendingDocument>, String> {
+    assert_admin(&app).await?;
+    let db_config = get_authenticated_db(&app).await?;
+
+    let client = crate::http::client();
+    let response = client
+        .get(format!("{}/rest/v1/contractor_document_uploads", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[
+            ("verification_status", "eq.pending".to_string()),
+            ("order", "created_at.asc".to_string()),
+            ("limit", limit.unwrap_or(50).min(200).to_string()),
+            ("select", "*,contractors(first_name,last_name,business_name)".to_string())
+        ])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch pending documents: {}", e))?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Database error fetching pending documents: {}", error_text));
+    }
+
+    let rows: Vec<DocumentWithContractorRow> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse pending documents response: {}", e))?;
+
+    Ok(rows.into_iter()
+        .map(|row| PendingDocument {
+            document: row.document,
+            contractor_name: row.contractors.and_then(ContractorNameRow::display_name),
+        })
+        .collect())
+}
+
+/// Record an admin's approve/reject decision on a contractor document. The database
+/// records the signed-in admin as the reviewer.
+#[command]
+pub async fn review_document(
+    document_id: String,
+    decision: String,
+    notes: Option<String>,
+    app: tauri::AppHandle,
+) -> Result<DocumentUpload, String> {
+    // verified_at is only meaningful for approvals, so clear it on rejection
+    let now = chrono::Utc::now().to_rfc3339();
+    let (verification_status, verified_at) = match decision.as_str() {
+        "approved" => ("verified", Some(now.clone())),
+        "rejected" => ("rejected", None),
+        other => return Err(format!("Invalid review decision '{}': expected 'approved' or 'rejected'", other)),
+    };
+
+    assert_admin(&app).await?;
+    let db_config = get_authenticated_db(&app).await?;
+
+    let payload = serde_json::json!({
+        "verification_status": verification_status,
+        "verified_at": verified_at,
+        "verification_notes": notes,
+        "reviewed_at": now,
+        "updated_at": now
+    });
+
+    let client = crate::http::client();
+    let response = client
+        .patch(format!("{}/rest/v1/contractor_document_uploads", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=representation")
+        .query(&[("id", format!("eq.{}", document_id))])
+        .json(&payload)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to review document: {}", e))?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Database error reviewing document: {}", error_text));
+    }
+
+    let document_uploads: Vec<DocumentUpload> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse document review response: {}", e))?;
+
+    document_uploads
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("Document {} not found", document_id))
+}
+
 /// Update document upload status
 #[command]
 pub async fn update_document_upload_status(
@@ -1820,7 +5152,7 @@ pub async fn update_document_upload_status(
         return Err("Authentication required".to_string());
     }
 
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     let mut payload = serde_json::json!({});
     
     if let Some(file_id) = stripe_file_id {
@@ -1841,7 +5173,8 @@ pub async fn update_document_upload_status(
     payload["updated_at"] = serde_json::Value::String(chrono::Utc::now().to_rfc3339());
 
     let response = client
-        .patch(&format!("{}/rest/v1/contractor_document_uploads", db_config.database_url))
+        .patch(format!("{}/rest/v1/contractor_document_uploads", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -1867,3 +5200,503 @@ pub async fn update_document_upload_status(
         .next()
         .ok_or_else(|| "No document upload returned from database".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn access_error_maps_403_from_contractors_to_forbidden() {
+        match access_error(403, "", "contractors") {
+            Some(DatabaseError::Forbidden { table, .. }) => assert_eq!(table, "contractors"),
+            other => panic!("expected Forbidden, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn access_error_distinguishes_rejected_tokens_from_rls_denials() {
+        assert!(matches!(access_error(401, "{}", "profiles"), Some(DatabaseError::NotAuthenticated { .. })));
+        assert!(matches!(
+            access_error(401, r#"{"code":"42501"}"#, "profiles"),
+            Some(DatabaseError::Forbidden { .. })
+        ));
+        assert!(matches!(
+            access_error(400, r#"{"code":"42501"}"#, "profiles"),
+            Some(DatabaseError::Forbidden { .. })
+        ));
+        assert!(access_error(500, "boom", "profiles").is_none());
+    }
+
+    #[test]
+    fn non_admin_is_rejected() {
+        assert!(matches!(require_admin(false), Err(DatabaseError::Forbidden { .. })));
+        assert!(require_admin(true).is_ok());
+    }
+
+    #[test]
+    fn rate_limit_window_counts_a_bulk_check_all_at_once() {
+        assert_eq!(next_rate_limit_window(0, 0, 5, 1_000, 20, 60_000), Ok((0, 5)));
+        assert_eq!(next_rate_limit_window(0, 18, 5, 1_000, 20, 60_000), Err(59));
+        assert_eq!(next_rate_limit_window(0, 20, 5, 60_000, 20, 60_000), Ok((60_000, 5)));
+    }
+
+    fn person<'a>(date_of_birth: &'a str, email: Option<&'a str>, country: &'a str) -> PersonFields<'a> {
+        PersonFields {
+            first_name: "Ada",
+            last_name: "Lovelace",
+            date_of_birth,
+            email,
+            street_address: "1 Main St",
+            city: "Springfield",
+            postal_code: "12345",
+            country,
+        }
+    }
+
+    fn today() -> chrono::NaiveDate {
+        chrono::NaiveDate::from_ymd_opt(2024, 6, 1).unwrap()
+    }
+
+    fn fields(errors: &[FieldError]) -> Vec<&str> {
+        errors.iter().map(|error| error.field.as_str()).collect()
+    }
+
+    #[test]
+    fn valid_person_has_no_errors() {
+        assert!(validate_person_fields(&person("1990-01-31", Some("ada@example.com"), "US"), today()).is_empty());
+    }
+
+    #[test]
+    fn person_errors_are_all_reported() {
+        let errors = validate_person_fields(&person("2030-01-01", Some("ada@"), "USA"), today());
+        assert_eq!(fields(&errors), vec!["date_of_birth", "country", "email"]);
+    }
+
+    #[test]
+    fn person_date_of_birth_must_parse() {
+        let errors = validate_person_fields(&person("31/01/1990", None, "US"), today());
+        assert_eq!(fields(&errors), vec!["date_of_birth"]);
+    }
+
+    fn representative(is_authorized_signatory: bool) -> Representative {
+        serde_json::from_value(serde_json::json!({
+            "id": "rep_1",
+            "contractor_id": "contractor_1",
+            "first_name": "Ada",
+            "last_name": "Lovelace",
+            "date_of_birth": "1990-01-31",
+            "street_address": "1 Main St",
+            "city": "Springfield",
+            "postal_code": "12345",
+            "country": "US",
+            "title": "Director",
+            "is_authorized_signatory": is_authorized_signatory,
+            "is_verified": false
+        }))
+        .unwrap()
+    }
+
+    fn beneficial_owner(ownership_percentage: f64) -> BeneficialOwner {
+        serde_json::from_value(serde_json::json!({
+            "id": "owner_1",
+            "contractor_id": "contractor_1",
+            "first_name": "Ada",
+            "last_name": "Lovelace",
+            "date_of_birth": "1990-01-31",
+            "street_address": "1 Main St",
+            "city": "Springfield",
+            "postal_code": "12345",
+            "country": "US",
+            "ownership_percentage": ownership_percentage,
+            "is_verified": false
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn business_without_signatory_is_incomplete() {
+        let gaps = business_completeness_gaps(&[representative(false)], &[beneficial_owner(50.0)]);
+        assert_eq!(fields(&gaps), vec!["representatives"]);
+
+        let gaps = business_completeness_gaps(&[], &[]);
+        assert_eq!(fields(&gaps), vec!["representatives"]);
+    }
+
+    #[test]
+    fn business_ownership_cannot_exceed_100_percent() {
+        let gaps = business_completeness_gaps(
+            &[representative(true)],
+            &[beneficial_owner(60.0), beneficial_owner(50.5)],
+        );
+        assert_eq!(fields(&gaps), vec!["beneficial_owners"]);
+    }
+
+    #[test]
+    fn complete_business_has_no_gaps() {
+        let gaps = business_completeness_gaps(
+            &[representative(false), representative(true)],
+            &[beneficial_owner(33.33), beneficial_owner(33.33), beneficial_owner(33.34)],
+        );
+        assert!(gaps.is_empty());
+    }
+
+    #[test]
+    fn export_keeps_purchases_but_masks_id_and_bank_numbers() {
+        let mut export = serde_json::json!({
+            "purchases": [{ "id": "purchase_1", "stripe_payment_intent_id": "pi_123", "amount_paid": 999 }],
+            "contractor": {
+                "profile": { "national_id_number": "123-45-6789" },
+                "kyc_form_data": {
+                    "nationalIdNumber": "123456789",
+                    "bankAccount": { "accountNumber": "000123456789", "routingNumber": "110000000" }
+                },
+                "bank_accounts": [{ "account_number": "****6789", "routing_number": "110000000" }]
+            }
+        });
+        mask_sensitive_numbers(&mut export);
+
+        assert_eq!(export["purchases"][0]["stripe_payment_intent_id"], "pi_123");
+        let bank_account = &export["contractor"]["kyc_form_data"]["bankAccount"];
+        assert_eq!(bank_account["accountNumber"], "****6789");
+        assert_eq!(bank_account["routingNumber"], "****0000");
+        assert_eq!(export["contractor"]["bank_accounts"][0]["account_number"], "****6789");
+
+        let text = export.to_string();
+        for secret in ["123-45-6789", "123456789", "000123456789", "110000000"] {
+            assert!(!text.contains(secret), "{} leaked into the export", secret);
+        }
+    }
+
+    #[test]
+    fn past_due_keeps_access_through_the_last_second_of_grace() {
+        let period_end = 1_700_000_000;
+        let grace = 7 * 24 * 60 * 60;
+        assert!(subscription_grants_access(Some("past_due"), Some(period_end), period_end + grace, grace));
+        assert!(!subscription_grants_access(Some("past_due"), Some(period_end), period_end + grace + 1, grace));
+        assert!(!subscription_grants_access(Some("past_due"), Some(period_end), period_end + 1, 0));
+        assert!(!subscription_grants_access(Some("past_due"), None, period_end, grace));
+    }
+
+    #[test]
+    fn only_active_and_trialing_grant_access_regardless_of_grace() {
+        assert!(subscription_grants_access(Some("active"), None, 0, 0));
+        assert!(subscription_grants_access(Some("trialing"), None, 0, 0));
+        for status in [Some("canceled"), Some("unpaid"), Some("incomplete"), None] {
+            assert!(!subscription_grants_access(status, Some(i64::MAX), 0, i64::MAX), "{:?}", status);
+        }
+    }
+
+    #[test]
+    fn frozen_spend_is_reported_as_tokens_frozen() {
+        let body = r#"{"code":"AU001","message":"Token spending is frozen while a payment dispute is open"}"#;
+        assert!(matches!(spend_error(400, body), TokenError::TokensFrozen { .. }));
+        assert!(matches!(spend_error(401, "{}"), TokenError::NotAuthenticated { .. }));
+        assert!(matches!(spend_error(400, r#"{"code":"P0001"}"#), TokenError::Other { .. }));
+    }
+
+    #[test]
+    fn rotation_reseals_only_the_encrypted_draft_fields() {
+        let mut kyc_data = serde_json::json!({
+            "firstName": "Ada",
+            "nationalIdNumber": "v1:old-id",
+            "bankAccount": {"accountNumber": "v1:old-account", "routingNumber": "110000000"},
+        });
+
+        let changed = reseal_kyc_fields(&mut kyc_data, |sealed| Ok(Some(sealed.replace("old", "new")))).unwrap();
+
+        assert!(changed);
+        assert_eq!(kyc_data["nationalIdNumber"], "v1:new-id");
+        assert_eq!(kyc_data["bankAccount"]["accountNumber"], "v1:new-account");
+        assert_eq!(kyc_data["bankAccount"]["routingNumber"], "110000000");
+        assert_eq!(kyc_data["firstName"], "Ada");
+    }
+
+    #[test]
+    fn draft_already_on_the_new_key_is_left_alone() {
+        let mut kyc_data = serde_json::json!({"nationalIdNumber": "v1:new-id", "bankAccount": {"accountNumber": ""}});
+        let before = kyc_data.clone();
+
+        assert!(!reseal_kyc_fields(&mut kyc_data, |_| Ok(None)).unwrap());
+        assert_eq!(kyc_data, before);
+    }
+
+    fn config_error(database_url: Option<&str>, access_token: Option<&str>, anon_key: Option<&str>) -> DbConfigError {
+        database_config(database_url.map(String::from), access_token.map(String::from), anon_key.map(String::from))
+            .unwrap_err()
+    }
+
+    #[test]
+    fn missing_database_url_means_not_configured() {
+        let error = config_error(None, Some("token"), Some("anon"));
+
+        assert!(matches!(error, DbConfigError::NoDatabaseUrl { .. }));
+        assert_eq!(serde_json::to_value(&error).unwrap()["kind"], "no_database_url");
+        assert!(matches!(DatabaseError::from(error), DatabaseError::NotConfigured { .. }));
+    }
+
+    #[test]
+    fn missing_anon_key_means_not_configured() {
+        let error = config_error(Some("https://example.supabase.co"), Some("token"), None);
+
+        assert!(matches!(error, DbConfigError::NoAnonKey { .. }));
+        assert_eq!(serde_json::to_value(&error).unwrap()["kind"], "no_anon_key");
+        assert!(matches!(DatabaseError::from(error), DatabaseError::NotConfigured { .. }));
+    }
+
+    #[test]
+    fn missing_access_token_means_signed_out() {
+        let error = config_error(Some("https://example.supabase.co"), None, Some("anon"));
+
+        assert!(matches!(error, DbConfigError::NoAccessToken { .. }));
+        assert_eq!(serde_json::to_value(&error).unwrap()["kind"], "no_access_token");
+        assert!(matches!(DatabaseError::from(error.clone()), DatabaseError::NotAuthenticated { .. }));
+        assert!(matches!(TokenError::from(error), TokenError::NotAuthenticated { .. }));
+    }
+
+    #[test]
+    fn complete_config_is_returned_as_is() {
+        let config = database_config(
+            Some("https://example.supabase.co".to_string()),
+            Some("token".to_string()),
+            Some("anon".to_string()),
+        )
+        .unwrap();
+
+        assert_eq!(config.database_url, "https://example.supabase.co");
+        assert_eq!(config.access_token, "token");
+        assert_eq!(config.anon_key, "anon");
+    }
+
+    #[test]
+    fn string_errors_become_other_and_display_the_message() {
+        let error = DatabaseError::from("Failed to parse profile");
+
+        assert_eq!(error, DatabaseError::Other { message: "Failed to parse profile".to_string() });
+        assert_eq!(String::from(error), "Failed to parse profile");
+        assert_eq!(
+            TokenError::InsufficientTokens { requested: 5, available: 2 }.to_string(),
+            "Insufficient tokens: requested 5, available 2"
+        );
+    }
+
+    #[test]
+    fn server_rate_limit_is_reported_with_its_retry_delay() {
+        let body = r#"{"code":"AU002","details":null,"hint":"42","message":"Too many username checks"}"#;
+        assert_eq!(username_check_error(400, body), UsernameCheckError::RateLimited { retry_after_secs: 42 });
+
+        let without_hint = r#"{"code":"AU002","details":null,"hint":null,"message":"Too many username checks"}"#;
+        assert_eq!(username_check_error(400, without_hint), UsernameCheckError::RateLimited { retry_after_secs: 60 });
+    }
+
+    #[test]
+    fn other_username_check_failures_are_not_rate_limits() {
+        let expired = r#"{"code":"PGRST301","message":"JWT expired"}"#;
+        assert!(matches!(username_check_error(401, expired), UsernameCheckError::NotAuthenticated { .. }));
+        assert!(matches!(username_check_error(500, "boom"), UsernameCheckError::Other { .. }));
+    }
+
+    fn sample_purchase(amount_paid: i64, currency: &str, tokens: Option<i64>, completed_at: &str) -> Purchase {
+        serde_json::from_value(serde_json::json!({
+            "id": "purchase",
+            "user_id": "user",
+            "stripe_payment_intent_id": "pi_test",
+            "stripe_price_id": "price_test",
+            "amount_paid": amount_paid,
+            "currency": currency,
+            "tokens_purchased": tokens,
+            "status": "completed",
+            "completed_at": completed_at,
+        })).unwrap()
+    }
+
+    #[test]
+    fn spend_is_summed_per_currency_and_bucketed_by_month() {
+        let now = chrono::DateTime::parse_from_rfc3339("2026-03-15T12:00:00Z").unwrap().with_timezone(&chrono::Utc);
+        let purchases = vec![
+            sample_purchase(1000, "USD", Some(100), "2026-03-01T09:00:00+00:00"),
+            sample_purchase(500, "usd", None, "2026-03-10T09:00:00+00:00"),
+            sample_purchase(2000, "aud", Some(250), "2025-04-30T23:00:00+00:00"),
+            sample_purchase(700, "usd", Some(50), "2024-12-01T00:00:00+00:00"),
+        ];
+        let summary = summarize_spend(&purchases, now);
+
+        assert_eq!(summary.total_spent_cents, BTreeMap::from([("aud".to_string(), 2000), ("usd".to_string(), 2200)]));
+        assert_eq!(summary.total_purchases, 4);
+        assert_eq!(summary.total_tokens_purchased, 400);
+
+        // Twelve months ending with the current one, across the year boundary
+        assert_eq!(summary.monthly.len(), 12);
+        assert_eq!(summary.monthly.first().unwrap().month, "2025-04");
+        let march = summary.monthly.last().unwrap();
+        assert_eq!(march.month, "2026-03");
+        assert_eq!(march.spent_cents, BTreeMap::from([("usd".to_string(), 1500)]));
+        assert_eq!((march.purchases, march.tokens_purchased), (2, 100));
+        assert_eq!(summary.monthly[0].spent_cents, BTreeMap::from([("aud".to_string(), 2000)]));
+        assert!(summary.monthly[1..11].iter().all(|month| month.purchases == 0 && month.spent_cents.is_empty()));
+    }
+
+    fn sample_package(id: &str, prices: &[(&str, i64, i64)]) -> PackageWithPrices {
+        serde_json::from_value(serde_json::json!({
+            "package": {
+                "id": id,
+                "name": format!("{} package", id),
+                "stripe_product_id": format!("prod_{}", id),
+                "is_active": true,
+                "sort_order": 0,
+            },
+            "prices": prices.iter().map(|(price_id, amount_cents, token_amount)| serde_json::json!({
+                "id": price_id,
+                "package_id": id,
+                "stripe_price_id": format!("price_{}", price_id),
+                "amount_cents": amount_cents,
+                "currency": "usd",
+                "interval_type": "one_time",
+                "interval_count": 1,
+                "token_amount": token_amount,
+                "is_active": true,
+            })).collect::<Vec<_>>(),
+        })).unwrap()
+    }
+
+    #[test]
+    fn package_prices_are_ranked_by_tokens_per_cent_with_free_prices_last() {
+        let packages = vec![
+            sample_package("starter", &[("starter_small", 500, 500), ("starter_free", 0, 50)]),
+            sample_package("pro", &[("pro_large", 2000, 4000), ("pro_medium", 1000, 1500)]),
+        ];
+        let values = rank_package_values(&packages);
+
+        let ranked: Vec<(usize, &str)> = values.iter().map(|value| (value.rank, value.package_price_id.as_str())).collect();
+        assert_eq!(ranked, vec![(1, "pro_large"), (2, "pro_medium"), (3, "starter_small"), (4, "starter_free")]);
+        assert_eq!(values[0].tokens_per_cent, Some(2.0));
+        assert_eq!(values[0].package_name, "pro package");
+        assert_eq!(values[3].tokens_per_cent, None);
+    }
+
+    fn filter(column: &str, op: FilterOp, value: &str) -> Filter {
+        Filter { column: column.to_string(), op, value: value.to_string() }
+    }
+
+    #[test]
+    fn supabase_query_only_reads_allowlisted_tables() {
+        let error = supabase_query_params("profiles", &[], None).unwrap_err();
+        assert!(error.contains("not available"), "{}", error);
+        assert!(supabase_query_params("payment_methods", &[], None).is_err());
+
+        let query = supabase_query_params("purchases", &[filter("status", FilterOp::Eq, "completed")], None).unwrap();
+        assert_eq!(query, vec![
+            ("select".to_string(), "*".to_string()),
+            ("status".to_string(), "eq.\"completed\"".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn supabase_query_rejects_selects_that_embed_other_tables() {
+        assert!(supabase_query_params("purchases", &[], Some("id,amount:amount_paid,created_at::date".to_string())).is_ok());
+        assert!(supabase_query_params("purchases", &[], Some("*,payment_methods(*)".to_string())).is_err());
+        assert!(supabase_query_params("purchases", &[], Some("id,profiles!inner(email)".to_string())).is_err());
+    }
+
+    #[test]
+    fn filter_values_are_quoted_and_columns_must_be_identifiers() {
+        let (column, value) = filter("status", FilterOp::In, "completed, refunded),or=(id.neq.0").to_query_pair().unwrap();
+        assert_eq!(column, "status");
+        assert_eq!(value, "in.(\"completed\",\"refunded)\",\"or=(id.neq.0\")");
+        assert_eq!(filter("note", FilterOp::Ilike, "say \"hi\"").to_query_pair().unwrap().1, "ilike.\"say \\\"hi\\\"\"");
+        assert_eq!(filter("completed_at", FilterOp::Is, "null").to_query_pair().unwrap().1, "is.null");
+
+        assert!(filter("completed_at", FilterOp::Is, "not.null").to_query_pair().is_err());
+        assert!(filter("status,or", FilterOp::Eq, "x").to_query_pair().is_err());
+        assert!(filter("", FilterOp::Eq, "x").to_query_pair().is_err());
+    }
+
+    fn sample_plan(features: Option<serde_json::Value>) -> SubscriptionPlan {
+        SubscriptionPlan {
+            id: "plan_pro".to_string(),
+            name: "Pro".to_string(),
+            description: None,
+            stripe_product_id: "prod_pro".to_string(),
+            features,
+            is_active: true,
+            sort_order: 1,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+
+    #[test]
+    fn plan_feature_flags_become_entitlements() {
+        let plan = sample_plan(Some(serde_json::json!({ "max_projects": 10, "priority_support": true })));
+        assert_eq!(entitlements_from_plan(&plan, 250), Entitlements {
+            plan_id: Some("plan_pro".to_string()),
+            plan_name: Some("Pro".to_string()),
+            max_projects: Some(10),
+            priority_support: true,
+            advanced_export: false,
+            tokens_remaining: 250,
+        });
+    }
+
+    #[test]
+    fn plan_feature_names_are_matched_loosely() {
+        let plan = sample_plan(Some(serde_json::json!([" Priority support", "Advanced Export", "Unlimited projects"])));
+        let entitlements = entitlements_from_plan(&plan, 0);
+        assert!(entitlements.priority_support && entitlements.advanced_export);
+        assert_eq!(entitlements.max_projects, None);
+    }
+
+    #[test]
+    fn paid_plan_without_features_is_unlimited_but_nothing_else() {
+        let entitlements = entitlements_from_plan(&sample_plan(None), 5);
+        assert_eq!(entitlements.max_projects, None);
+        assert!(!entitlements.priority_support && !entitlements.advanced_export);
+        assert_eq!(Entitlements::free(5).max_projects, Some(FREE_TIER_MAX_PROJECTS));
+    }
+
+    #[test]
+    fn pasted_database_urls_are_normalized() {
+        assert_eq!(normalize_database_url(" https://x.supabase.co/ \n"), "https://x.supabase.co");
+        assert_eq!(normalize_database_url("https://x.supabase.co/rest/v1/"), "https://x.supabase.co");
+        assert_eq!(normalize_database_url("https://x.supabase.co//rest/v1"), "https://x.supabase.co");
+        assert_eq!(normalize_database_url("https://x.supabase.co"), "https://x.supabase.co");
+        assert_eq!(normalize_database_url("http://localhost:54321/auth/v1"), "http://localhost:54321/auth/v1");
+    }
+
+    #[test]
+    fn timezones_are_parsed_by_iana_name() {
+        assert_eq!(parse_timezone(None), Ok(None));
+        assert_eq!(parse_timezone(Some(" Europe/Paris ")), Ok(Some(chrono_tz::Europe::Paris)));
+        assert_eq!(parse_timezone(Some("Mars/Olympus")), Err("Unknown timezone: Mars/Olympus".to_string()));
+    }
+
+    #[test]
+    fn timestamps_are_shown_in_local_time_with_the_zone_abbreviation() {
+        let paris = chrono_tz::Europe::Paris;
+        assert_eq!(localize_timestamp(Some("2025-03-01T13:30:00+00:00"), paris), Some("2025-03-01 14:30 CET".to_string()));
+        assert_eq!(localize_timestamp(Some("2025-07-01T23:30:00Z"), paris), Some("2025-07-02 01:30 CEST".to_string()));
+        assert_eq!(localize_timestamp(Some("yesterday"), paris), None);
+        assert_eq!(localize_timestamp(None, paris), None);
+    }
+
+    #[test]
+    fn token_price_map_rows_are_matched_by_amount_and_currency() {
+        let (app, dir) = crate::enhanced_store::tests::test_app();
+        let handle = app.handle().clone();
+        let (url, requests) = crate::http::tests::mock_server(vec![(200, serde_json::json!([
+            { "amount_cents": 1499, "currency": "AUD", "tokens": 1200 },
+            { "amount_cents": 1499, "currency": "usd", "tokens": 900 },
+        ]))]);
+        let database = handle.store("database.store").unwrap();
+        database.set("database_url", url);
+        database.set("anon_key", "anon");
+        handle.store("session.store").unwrap().set("sb-access-token", "access");
+
+        tauri::async_runtime::block_on(async {
+            assert_eq!(resolve_token_amount(1499, " aud", &handle).await, Ok(Some(1200)));
+            assert_eq!(resolve_token_amount(1499, "USD", &handle).await, Ok(Some(900)));
+            assert_eq!(resolve_token_amount(749, "aud", &handle).await, Ok(None));
+        });
+        assert_eq!(requests.lock().unwrap().len(), 1, "the map should be fetched once and cached");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,9 +1,118 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use tauri::command;
+use tauri::Manager;
 use tauri_plugin_store::StoreExt;
 
+// Number of backups kept per store when no retention has been configured
+const DEFAULT_BACKUP_RETENTION: usize = 5;
+
+// Must be passed to store_clear_all so it can't be triggered by accident
+const CLEAR_ALL_CONFIRM_TOKEN: &str = "clear-all-app-data";
+
+// Stores store_clear_all leaves alone: the user stays signed in, purchases that were
+// paid for but not yet recorded can still be retried, and clearing can't reset the
+// username check rate limit
+const PRESERVED_STORES: [&str; 5] = [
+    "session.store",
+    "database.store",
+    "auth_config.store",
+    "pending_purchases.store",
+    "rate_limits.store",
+];
+
+// Stores holding tokens, connection secrets and the username check rate limit. The generic
+// store commands refuse them, so the commands that own them are the only way to change them.
+const RESERVED_STORES: [&str; 4] = ["session", "database", "auth_config", "rate_limits"];
+
+// Keys a store needs; compact_store drops everything else, such as restore bookkeeping
+const LIVE_STORE_KEYS: [&str; 6] = ["data", "checksum", "version", "last_updated", "encrypted", "last_sync"];
+
+// Automatic backup defaults, used until the user configures them
+const DEFAULT_AUTO_BACKUP_STORES: [&str; 2] = ["app_data", "app_config"];
+const DEFAULT_AUTO_BACKUP_INTERVAL_SECS: u64 = 15 * 60;
+const MIN_AUTO_BACKUP_INTERVAL_SECS: u64 = 60;
+
+// Bumped on every start/stop so a running backup loop knows it has been superseded
+static AUTO_BACKUP_GENERATION: AtomicU64 = AtomicU64::new(0);
+static AUTO_BACKUP_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Error returned by the store commands, tagged by `kind` so the frontend can branch on it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StoreError {
+    /// The store is reserved for the session and database commands
+    Forbidden { message: String, store_id: String },
+    Other { message: String },
+}
+
+crate::errors::command_error!(StoreError { Forbidden, Other });
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AutoBackupStatus {
+    pub enabled: bool,
+    pub running: bool,
+    pub interval_secs: u64,
+    pub stores: Vec<String>,
+    pub allow_on_mobile: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupInfo {
+    pub backup_name: String,
+    pub created_at: u64,
+    pub size: u64,
+}
+
+/// One write in a store_transaction
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum StoreOp {
+    Set { store_id: String, data: Value },
+    Clear { store_id: String },
+}
+
+impl StoreOp {
+    fn store_id(&self) -> &str {
+        match self {
+            StoreOp::Set { store_id, .. } | StoreOp::Clear { store_id } => store_id,
+        }
+    }
+}
+
+/// Disk usage of one store and its backups, in bytes
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StoreUsage {
+    pub store_id: String,
+    /// 0 if only backups of the store are left on disk
+    pub size: u64,
+    pub backups_size: u64,
+    pub backup_count: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StorageUsage {
+    pub stores: Vec<StoreUsage>,
+    pub stores_size: u64,
+    pub backups_size: u64,
+    pub total_size: u64,
+    /// Files that couldn't be read, with the reason
+    pub skipped: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CompactResult {
+    pub store_id: String,
+    pub size_before: u64,
+    pub size_after: u64,
+    pub bytes_reclaimed: u64,
+    pub removed_keys: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StoreMetadata {
     pub store_id: String,
@@ -12,30 +121,81 @@ pub struct StoreMetadata {
     pub version: u32,
 }
 
+// Per-store locks so read-modify-write commands on the same store don't interleave
+static STORE_LOCKS: Mutex<Option<HashMap<String, Arc<Mutex<()>>>>> = Mutex::new(None);
+
+fn store_lock(store_id: &str) -> Arc<Mutex<()>> {
+    let mut locks = STORE_LOCKS.lock().unwrap_or_else(|e| e.into_inner());
+    locks
+        .get_or_insert_with(HashMap::new)
+        .entry(store_id.to_string())
+        .or_default()
+        .clone()
+}
+
+// Reserved stores, and backups of them, are off limits to the generic commands. Ids are
+// compared case-insensitively since store files may live on a case-insensitive filesystem.
+// Ids that could reach outside the store directory are rejected outright.
+fn ensure_store_accessible(store_id: &str) -> Result<(), StoreError> {
+    if !is_plain_name(store_id) {
+        return Err(format!("Invalid store name '{}'", store_id).into());
+    }
+
+    let normalized = store_id.trim().to_lowercase();
+    let reserved = RESERVED_STORES.iter().any(|reserved| {
+        normalized == *reserved || normalized.starts_with(&format!("{}_backup_", reserved))
+    });
+
+    if reserved {
+        return Err(StoreError::Forbidden {
+            message: format!("Store '{}' is reserved and can't be accessed through the store commands", store_id),
+            store_id: store_id.to_string(),
+        });
+    }
+
+    Ok(())
+}
+
+// Store and backup names become part of a file name, so no path separators or parent references
+fn is_plain_name(name: &str) -> bool {
+    !name.trim().is_empty() && !name.contains(['/', '\\']) && !name.contains("..")
+}
+
 /// Get data from a specific store
 #[command]
-pub async fn store_get(store_id: String, app: tauri::AppHandle) -> Result<Option<Value>, String> {
+pub async fn store_get<R: tauri::Runtime>(store_id: String, app: tauri::AppHandle<R>) -> Result<Option<Value>, StoreError> {
+    ensure_store_accessible(&store_id)?;
     let store_file = format!("{}.store", store_id);
     let store = app.store(&store_file).map_err(|e| e.to_string())?;
 
-    // Get the main data key for this store
-    let data = store.get("data");
-    
-    Ok(data.map(|v| v.clone()))
+    if !checksum_matches(&store) {
+        return Err(format!("Store '{}' is corrupted: data does not match its checksum", store_id).into());
+    }
+
+    // Get the main data key for this store, decrypted if it was stored encrypted
+    Ok(read_store_data(&store)?)
 }
 
-/// Set data in a specific store
+/// Set data in a specific store. With `encrypted` the data is encrypted with the vault key
+/// before it is written and store_get decrypts it transparently; without it the store
+/// keeps whichever mode it already had.
 #[command]
-pub async fn store_set(
+pub async fn store_set<R: tauri::Runtime>(
     store_id: String,
     data: Value,
-    app: tauri::AppHandle,
-) -> Result<(), String> {
+    encrypted: Option<bool>,
+    app: tauri::AppHandle<R>,
+) -> Result<(), StoreError> {
+    ensure_store_accessible(&store_id)?;
     let store_file = format!("{}.store", store_id);
     let store = app.store(&store_file).map_err(|e| e.to_string())?;
 
+    let lock = store_lock(&store_id);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
     // Store the data with metadata
-    store.set("data", data);
+    let encrypted = encrypted.unwrap_or_else(|| is_encrypted(&store));
+    write_store_data(&store, data, encrypted)?;
     store.set("last_updated", serde_json::json!(chrono::Utc::now().timestamp_millis() as u64));
     store.set("version", serde_json::json!(1u32));
 
@@ -44,12 +204,293 @@ pub async fn store_set(
     Ok(())
 }
 
+/// Get data from several stores in one call. Stores that are missing, corrupted or
+/// can't be opened map to `null` instead of failing the whole batch.
+#[command]
+pub async fn store_get_many<R: tauri::Runtime>(
+    store_ids: Vec<String>,
+    app: tauri::AppHandle<R>,
+) -> Result<HashMap<String, Option<Value>>, StoreError> {
+    for store_id in &store_ids {
+        ensure_store_accessible(store_id)?;
+    }
+
+    let mut results = HashMap::new();
+
+    for store_id in store_ids {
+        let store_file = format!("{}.store", store_id);
+        let data = app.store(&store_file)
+            .ok()
+            .filter(|store| checksum_matches(store))
+            .and_then(|store| read_store_data(&store).ok().flatten());
+        results.insert(store_id, data);
+    }
+
+    Ok(results)
+}
+
+/// Set data in several stores in one call, saving each store once
+#[command]
+pub async fn store_set_many<R: tauri::Runtime>(
+    entries: HashMap<String, Value>,
+    app: tauri::AppHandle<R>,
+) -> Result<(), StoreError> {
+    for store_id in entries.keys() {
+        ensure_store_accessible(store_id)?;
+    }
+
+    // Lock every store in a fixed order, as store_transaction does
+    let mut store_ids: Vec<&String> = entries.keys().collect();
+    store_ids.sort();
+    let locks: Vec<Arc<Mutex<()>>> = store_ids.iter().map(|id| store_lock(id)).collect();
+    let _guards: Vec<_> = locks.iter().map(|lock| lock.lock().unwrap_or_else(|e| e.into_inner())).collect();
+
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+
+    for (store_id, data) in entries {
+        let store_file = format!("{}.store", store_id);
+        let store = app.store(&store_file).map_err(|e| format!("{}: {}", store_id, e))?;
+
+        let encrypted = is_encrypted(&store);
+        write_store_data(&store, data, encrypted).map_err(|e| format!("{}: {}", store_id, e))?;
+        store.set("last_updated", serde_json::json!(now));
+        store.set("version", serde_json::json!(1u32));
+
+        store.save().map_err(|e| format!("{}: {}", store_id, e))?;
+    }
+
+    Ok(())
+}
+
+/// Apply several set/clear ops across stores as one unit. If any op fails, every
+/// store touched is put back the way it was before the transaction started.
+#[command]
+pub async fn store_transaction<R: tauri::Runtime>(ops: Vec<StoreOp>, app: tauri::AppHandle<R>) -> Result<(), StoreError> {
+    for op in &ops {
+        ensure_store_accessible(op.store_id())?;
+    }
+
+    // Lock every store involved, in a fixed order so concurrent transactions can't deadlock
+    let mut store_ids: Vec<String> = ops.iter().map(|op| op.store_id().to_string()).collect();
+    store_ids.sort();
+    store_ids.dedup();
+
+    let locks: Vec<Arc<Mutex<()>>> = store_ids.iter().map(|id| store_lock(id)).collect();
+    let _guards: Vec<_> = locks.iter().map(|lock| lock.lock().unwrap_or_else(|e| e.into_inner())).collect();
+
+    // Open everything up front so a missing store fails before anything is written
+    let mut stores = HashMap::new();
+    let mut snapshots = Vec::new();
+    for store_id in &store_ids {
+        let store = app.store(format!("{}.store", store_id)).map_err(|e| format!("{}: {}", store_id, e))?;
+        snapshots.push((store.clone(), store.entries()));
+        stores.insert(store_id.clone(), store);
+    }
+
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+    for (index, op) in ops.into_iter().enumerate() {
+        let store = &stores[op.store_id()];
+        let store_id = op.store_id().to_string();
+
+        let applied = match op {
+            StoreOp::Set { data, .. } => {
+                let encrypted = is_encrypted(store);
+                write_store_data(store, data, encrypted).map(|()| {
+                    store.set("last_updated", serde_json::json!(now));
+                    store.set("version", serde_json::json!(1u32));
+                })
+            }
+            StoreOp::Clear { .. } => {
+                store.clear();
+                Ok(())
+            }
+        };
+
+        if let Err(e) = applied.and_then(|()| store.save().map_err(|e| e.to_string())) {
+            let rollback = rollback_stores(&snapshots);
+            return Err(match rollback {
+                Ok(()) => format!("Transaction failed at op {} ({}): {}; rolled back", index, store_id, e),
+                Err(rollback_err) => format!(
+                    "Transaction failed at op {} ({}): {}; rollback also failed: {}",
+                    index, store_id, e, rollback_err
+                ),
+            }.into());
+        }
+    }
+
+    Ok(())
+}
+
+// A store and its entries from before a transaction touched it
+type StoreSnapshot<R> = (Arc<tauri_plugin_store::Store<R>>, Vec<(String, Value)>);
+
+fn rollback_stores<R: tauri::Runtime>(snapshots: &[StoreSnapshot<R>]) -> Result<(), String> {
+    let mut errors = Vec::new();
+
+    for (store, entries) in snapshots {
+        store.clear();
+        for (key, value) in entries {
+            store.set(key.clone(), value.clone());
+        }
+        if let Err(e) = store.save() {
+            errors.push(e.to_string());
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join(", "))
+    }
+}
+
+/// Get a single top-level key from a store's data object
+#[command]
+pub async fn store_get_key<R: tauri::Runtime>(
+    store_id: String,
+    key: String,
+    app: tauri::AppHandle<R>,
+) -> Result<Option<Value>, StoreError> {
+    ensure_store_accessible(&store_id)?;
+    let store_file = format!("{}.store", store_id);
+    let store = app.store(&store_file).map_err(|e| e.to_string())?;
+
+    Ok(read_store_data(&store)?.and_then(|data| data.get(&key).cloned()))
+}
+
+/// Set a single top-level key in a store's data object, leaving the other keys untouched
+#[command]
+pub async fn store_set_key<R: tauri::Runtime>(
+    store_id: String,
+    key: String,
+    value: Value,
+    app: tauri::AppHandle<R>,
+) -> Result<(), StoreError> {
+    ensure_store_accessible(&store_id)?;
+    let store_file = format!("{}.store", store_id);
+    let store = app.store(&store_file).map_err(|e| e.to_string())?;
+
+    let lock = store_lock(&store_id);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut data = store_data_object(&store, &store_id)?;
+    data.insert(key, value);
+    Ok(save_store_data(&store, data)?)
+}
+
+/// Set a key only if its current value equals `expected` (`null` matches a missing key).
+/// Returns whether the swap happened.
+#[command]
+pub async fn store_compare_and_swap<R: tauri::Runtime>(
+    store_id: String,
+    key: String,
+    expected: Value,
+    new: Value,
+    app: tauri::AppHandle<R>,
+) -> Result<bool, StoreError> {
+    ensure_store_accessible(&store_id)?;
+    let store_file = format!("{}.store", store_id);
+    let store = app.store(&store_file).map_err(|e| e.to_string())?;
+
+    let lock = store_lock(&store_id);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut data = store_data_object(&store, &store_id)?;
+    let current = data.get(&key).cloned().unwrap_or(Value::Null);
+    if current != expected {
+        return Ok(false);
+    }
+
+    data.insert(key, new);
+    save_store_data(&store, data)?;
+
+    Ok(true)
+}
+
+// Callers must hold the store's lock between reading and saving
+fn store_data_object<R: tauri::Runtime>(
+    store: &tauri_plugin_store::Store<R>,
+    store_id: &str,
+) -> Result<serde_json::Map<String, Value>, String> {
+    match read_store_data(store)? {
+        Some(Value::Object(map)) => Ok(map),
+        Some(_) => Err(format!("Store '{}' data is not an object", store_id)),
+        None => Ok(serde_json::Map::new()),
+    }
+}
+
+fn save_store_data<R: tauri::Runtime>(
+    store: &tauri_plugin_store::Store<R>,
+    data: serde_json::Map<String, Value>,
+) -> Result<(), String> {
+    let version = store.get("version").and_then(|v| v.as_u64()).unwrap_or(0) + 1;
+
+    write_store_data(store, Value::Object(data), is_encrypted(store))?;
+    store.set("last_updated", serde_json::json!(chrono::Utc::now().timestamp_millis() as u64));
+    store.set("version", serde_json::json!(version));
+
+    store.save().map_err(|e| e.to_string())
+}
+
+// Encrypted stores hold their data sealed by the vault and carry an `encrypted` marker;
+// stores without the marker are plaintext
+fn is_encrypted<R: tauri::Runtime>(store: &tauri_plugin_store::Store<R>) -> bool {
+    store.get("encrypted").and_then(|v| v.as_bool()).unwrap_or(false)
+}
+
+// The store's data as callers see it, decrypting encrypted stores
+fn read_store_data<R: tauri::Runtime>(store: &tauri_plugin_store::Store<R>) -> Result<Option<Value>, String> {
+    match store.get("data") {
+        Some(Value::String(sealed)) if is_encrypted(store) => crate::vault::open(&sealed).map(Some),
+        data => Ok(data),
+    }
+}
+
+// Write data and its checksum, sealing it first for encrypted stores. The checksum
+// covers what's on disk, so corruption checks don't need the key.
+fn write_store_data<R: tauri::Runtime>(
+    store: &tauri_plugin_store::Store<R>,
+    data: Value,
+    encrypted: bool,
+) -> Result<(), String> {
+    let data = if encrypted {
+        Value::String(crate::vault::seal(&data)?)
+    } else {
+        data
+    };
+
+    store.set("checksum", serde_json::json!(data_checksum(&data)));
+    store.set("data", data);
+    if encrypted {
+        store.set("encrypted", serde_json::json!(true));
+    } else {
+        store.delete("encrypted");
+    }
+
+    Ok(())
+}
+
+// SHA-256 of the serialized data, stored next to it to catch partial writes and disk corruption
+fn data_checksum(data: &Value) -> String {
+    format!("{:x}", Sha256::digest(data.to_string().as_bytes()))
+}
+
+// Stores written before checksums were added have none and are treated as intact
+fn checksum_matches<R: tauri::Runtime>(store: &tauri_plugin_store::Store<R>) -> bool {
+    match (store.get("data"), store.get("checksum")) {
+        (Some(data), Some(Value::String(checksum))) => data_checksum(&data) == checksum,
+        (Some(_), Some(_)) => false,
+        _ => true,
+    }
+}
+
 /// Get metadata for a specific store
 #[command]
-pub async fn store_get_metadata(
+pub async fn store_get_metadata<R: tauri::Runtime>(
     store_id: String,
-    app: tauri::AppHandle,
-) -> Result<StoreMetadata, String> {
+    app: tauri::AppHandle<R>,
+) -> Result<StoreMetadata, StoreError> {
+    ensure_store_accessible(&store_id)?;
     let store_file = format!("{}.store", store_id);
     let store = app.store(&store_file).map_err(|e| e.to_string())?;
 
@@ -78,11 +519,10 @@ pub async fn store_get_metadata(
 
 /// List all available stores
 #[command]
-pub async fn store_list(_app: tauri::AppHandle) -> Result<Vec<String>, String> {
+pub async fn store_list<R: tauri::Runtime>(_app: tauri::AppHandle<R>) -> Result<Vec<String>, String> {
     // This is a simplified implementation
     // In a real scenario, you'd scan the store directory
     let known_stores = vec![
-        "session".to_string(),
         "app_data".to_string(),
         "app_config".to_string(),
         "ui_state".to_string(),
@@ -94,7 +534,8 @@ pub async fn store_list(_app: tauri::AppHandle) -> Result<Vec<String>, String> {
 
 /// Clear a specific store
 #[command]
-pub async fn store_clear(store_id: String, app: tauri::AppHandle) -> Result<(), String> {
+pub async fn store_clear<R: tauri::Runtime>(store_id: String, app: tauri::AppHandle<R>) -> Result<(), StoreError> {
+    ensure_store_accessible(&store_id)?;
     let store_file = format!("{}.store", store_id);
     let store = app.store(&store_file).map_err(|e| e.to_string())?;
 
@@ -104,54 +545,599 @@ pub async fn store_clear(store_id: String, app: tauri::AppHandle) -> Result<(),
     Ok(())
 }
 
+/// Clear every store on disk, backups included, except the session, database connection,
+/// auth config, pending purchase and rate limit stores, so the user stays signed in, no
+/// paid purchase is forgotten and the username check limit isn't reset. `confirm_token`
+/// must be "clear-all-app-data". Returns the files that were cleared.
+#[command]
+pub async fn store_clear_all<R: tauri::Runtime>(confirm_token: String, app: tauri::AppHandle<R>) -> Result<Vec<String>, String> {
+    if confirm_token != CLEAR_ALL_CONFIRM_TOKEN {
+        return Err("Confirmation token does not match, no stores were cleared".to_string());
+    }
+
+    let entries = match std::fs::read_dir(store_dir(&app)?) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read store directory: {}", e)),
+    };
+
+    let mut store_files: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|file_name| file_name.ends_with(".store") && !PRESERVED_STORES.contains(&file_name.as_str()))
+        .collect();
+    store_files.sort();
+
+    for store_file in &store_files {
+        let store = app.store(store_file).map_err(|e| e.to_string())?;
+        store.clear();
+        store.save().map_err(|e| format!("Failed to clear {}: {}", store_file, e))?;
+    }
+
+    Ok(store_files)
+}
+
+/// Bytes used on disk by every store file, backups reported separately, for the
+/// "Storage used" settings panel. Files that can't be read are skipped and listed.
+#[command]
+pub async fn get_storage_usage<R: tauri::Runtime>(app: tauri::AppHandle<R>) -> Result<StorageUsage, String> {
+    let mut usage = StorageUsage {
+        stores: Vec::new(),
+        stores_size: 0,
+        backups_size: 0,
+        total_size: 0,
+        skipped: Vec::new(),
+    };
+
+    let entries = match std::fs::read_dir(store_dir(&app)?) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(usage),
+        Err(e) => return Err(format!("Failed to read store directory: {}", e)),
+    };
+
+    let mut per_store: HashMap<String, StoreUsage> = HashMap::new();
+    for entry in entries {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                usage.skipped.push(format!("Unreadable directory entry: {}", e));
+                continue;
+            },
+        };
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some(stem) = file_name.strip_suffix(".store") else {
+            continue;
+        };
+
+        let size = match entry.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(e) => {
+                usage.skipped.push(format!("{}: {}", file_name, e));
+                continue;
+            },
+        };
+
+        // Backups are named {store_id}_backup_{backup_name}.store
+        let (store_id, is_backup) = match stem.split_once("_backup_") {
+            Some((store_id, _)) => (store_id, true),
+            None => (stem, false),
+        };
+        let store_usage = per_store.entry(store_id.to_string()).or_insert_with(|| StoreUsage {
+            store_id: store_id.to_string(),
+            ..Default::default()
+        });
+
+        if is_backup {
+            store_usage.backups_size += size;
+            store_usage.backup_count += 1;
+            usage.backups_size += size;
+        } else {
+            store_usage.size += size;
+            usage.stores_size += size;
+        }
+    }
+
+    usage.total_size = usage.stores_size + usage.backups_size;
+    usage.stores = per_store.into_values().collect();
+    usage.stores.sort_by(|a, b| a.store_id.cmp(&b.store_id));
+    usage.skipped.sort();
+
+    Ok(usage)
+}
+
+/// Replace the sealed data of every encrypted store on disk, backups included, with what
+/// `reseal` returns for it (None leaves a store as it is). Used by vault key rotation.
+/// Returns the store files that were rewritten.
+pub(crate) fn reseal_encrypted_stores<R: tauri::Runtime>(
+    app: &tauri::AppHandle<R>,
+    reseal: impl Fn(&str) -> Result<Option<String>, String>,
+) -> Result<Vec<String>, String> {
+    let entries = match std::fs::read_dir(store_dir(app)?) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read store directory: {}", e)),
+    };
+
+    let mut store_files: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter(|file_name| file_name.ends_with(".store"))
+        .collect();
+    store_files.sort();
+
+    let mut resealed = Vec::new();
+    for store_file in store_files {
+        let store_id = store_file.trim_end_matches(".store");
+        let lock = store_lock(store_id);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        let store = app.store(&store_file).map_err(|e| e.to_string())?;
+        let sealed = match store.get("data") {
+            Some(Value::String(sealed)) if is_encrypted(&store) => sealed,
+            _ => continue,
+        };
+
+        if let Some(data) = reseal(&sealed).map_err(|e| format!("{}: {}", store_file, e))? {
+            let data = Value::String(data);
+            store.set("checksum", serde_json::json!(data_checksum(&data)));
+            store.set("data", data);
+            store.save().map_err(|e| format!("Failed to save {}: {}", store_file, e))?;
+            resealed.push(store_file);
+        }
+    }
+
+    Ok(resealed)
+}
+
+/// Move a store to a new id, e.g. to retire a legacy store name. Data and metadata are
+/// copied to the new store and the old store file is deleted. Refuses to overwrite a
+/// destination that already holds anything unless `force` is set.
+#[command]
+pub async fn rename_store<R: tauri::Runtime>(
+    old_id: String,
+    new_id: String,
+    force: Option<bool>,
+    app: tauri::AppHandle<R>,
+) -> Result<(), StoreError> {
+    if !is_plain_name(&old_id) || !is_plain_name(&new_id) {
+        return Err("Invalid store id".into());
+    }
+    if old_id == new_id {
+        return Err("Old and new store ids are the same".into());
+    }
+    ensure_store_accessible(&old_id)?;
+    ensure_store_accessible(&new_id)?;
+
+    // Lock both stores in a fixed order, as store_transaction does
+    let mut ids = [old_id.as_str(), new_id.as_str()];
+    ids.sort();
+    let locks: Vec<Arc<Mutex<()>>> = ids.iter().map(|id| store_lock(id)).collect();
+    let _guards: Vec<_> = locks.iter().map(|lock| lock.lock().unwrap_or_else(|e| e.into_inner())).collect();
+
+    let old_file = format!("{}.store", old_id);
+    if !store_dir(&app)?.join(&old_file).exists() {
+        return Err(format!("Store '{}' does not exist", old_id).into());
+    }
+
+    let old_store = app.store(&old_file).map_err(|e| e.to_string())?;
+    let new_store = app.store(format!("{}.store", new_id)).map_err(|e| e.to_string())?;
+
+    if !new_store.is_empty() && !force.unwrap_or(false) {
+        return Err(format!("Store '{}' already has data; pass force to overwrite it", new_id).into());
+    }
+
+    new_store.clear();
+    for (key, value) in old_store.entries() {
+        new_store.set(key, value);
+    }
+    new_store.save().map_err(|e| format!("Failed to save store '{}': {}", new_id, e))?;
+
+    // Unload the old store first so the plugin doesn't write it back to disk later
+    old_store.close_resource();
+    match std::fs::remove_file(store_dir(&app)?.join(&old_file)) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(format!("Copied to '{}' but failed to delete '{}': {}", new_id, old_id, e).into()),
+    }
+}
+
+/// Rewrite a store with only its live keys, dropping leftovers such as `restored_from`,
+/// so the file shrinks back to what its data needs. The data, version and checksum are
+/// kept as they are. Returns the bytes reclaimed.
+#[command]
+pub async fn compact_store<R: tauri::Runtime>(store_id: String, app: tauri::AppHandle<R>) -> Result<CompactResult, StoreError> {
+    ensure_store_accessible(&store_id)?;
+    let lock = store_lock(&store_id);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+    let store_file = format!("{}.store", store_id);
+    let store_path = store_dir(&app)?.join(&store_file);
+    let size_before = match std::fs::metadata(&store_path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(format!("Store '{}' does not exist", store_id).into());
+        },
+        Err(e) => return Err(format!("Failed to read store '{}': {}", store_id, e).into()),
+    };
+
+    let store = app.store(&store_file).map_err(|e| e.to_string())?;
+    if !checksum_matches(&store) {
+        return Err(format!("Store '{}' is corrupted, not compacting it", store_id).into());
+    }
+
+    let mut removed_keys: Vec<String> = store.keys()
+        .into_iter()
+        .filter(|key| !LIVE_STORE_KEYS.contains(&key.as_str()))
+        .collect();
+    removed_keys.sort();
+
+    for key in &removed_keys {
+        store.delete(key);
+    }
+    store.save().map_err(|e| format!("Failed to save store '{}': {}", store_id, e))?;
+
+    let size_after = std::fs::metadata(&store_path)
+        .map(|metadata| metadata.len())
+        .map_err(|e| format!("Compacted store '{}' but failed to read its size: {}", store_id, e))?;
+
+    Ok(CompactResult {
+        store_id,
+        size_before,
+        size_after,
+        bytes_reclaimed: size_before.saturating_sub(size_after),
+        removed_keys,
+    })
+}
+
 /// Backup a store to a specific location
 #[command]
-pub async fn store_backup(
+pub async fn store_backup<R: tauri::Runtime>(
     store_id: String,
     backup_name: String,
-    app: tauri::AppHandle,
-) -> Result<(), String> {
+    app: tauri::AppHandle<R>,
+) -> Result<(), StoreError> {
+    ensure_store_accessible(&store_id)?;
+    if !is_plain_name(&backup_name) {
+        return Err(format!("Invalid backup name '{}'", backup_name).into());
+    }
     let store_file = format!("{}.store", store_id);
     let backup_file = format!("{}_backup_{}.store", store_id, backup_name);
     
     let store = app.store(&store_file).map_err(|e| e.to_string())?;
     let backup_store = app.store(&backup_file).map_err(|e| e.to_string())?;
 
-    // Copy all data from original to backup
+    // Copy all data from original to backup, refusing to back up corrupted data
+    if !checksum_matches(&store) {
+        return Err(format!("Store '{}' is corrupted, not backing it up", store_id).into());
+    }
     if let Some(data) = store.get("data") {
+        backup_store.set("checksum", serde_json::json!(data_checksum(&data)));
         backup_store.set("data", data.clone());
     }
+    // Encrypted data is backed up still sealed
+    if is_encrypted(&store) {
+        backup_store.set("encrypted", serde_json::json!(true));
+    }
     
     backup_store.set("backup_timestamp", serde_json::json!(chrono::Utc::now().timestamp_millis()));
     backup_store.set("original_store", serde_json::json!(store_id));
     
     backup_store.save().map_err(|e| e.to_string())?;
 
+    // Drop the oldest backups beyond the configured retention
+    let retention = backup_retention(&app);
+    let backups = list_backups(&store_id, &app)?;
+    for stale in backups.iter().skip(retention) {
+        delete_backup_file(&store_id, &stale.backup_name, &app)?;
+    }
+
     Ok(())
 }
 
-/// Restore a store from backup
+/// List a store's backups, newest first
 #[command]
-pub async fn store_restore(
+pub async fn store_list_backups<R: tauri::Runtime>(
+    store_id: String,
+    app: tauri::AppHandle<R>,
+) -> Result<Vec<BackupInfo>, StoreError> {
+    ensure_store_accessible(&store_id)?;
+    Ok(list_backups(&store_id, &app)?)
+}
+
+/// Delete one backup of a store
+#[command]
+pub async fn store_delete_backup<R: tauri::Runtime>(
     store_id: String,
     backup_name: String,
-    app: tauri::AppHandle,
+    app: tauri::AppHandle<R>,
+) -> Result<(), StoreError> {
+    ensure_store_accessible(&store_id)?;
+    Ok(delete_backup_file(&store_id, &backup_name, &app)?)
+}
+
+/// Set how many backups store_backup keeps per store
+#[command]
+pub async fn store_set_backup_retention<R: tauri::Runtime>(
+    count: usize,
+    app: tauri::AppHandle<R>,
 ) -> Result<(), String> {
+    if count == 0 {
+        return Err("Backup retention must keep at least one backup".to_string());
+    }
+
+    let settings = app.store("settings.store").map_err(|e| e.to_string())?;
+    settings.set("backup_retention", serde_json::json!(count));
+    settings.save().map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+fn backup_retention<R: tauri::Runtime>(app: &tauri::AppHandle<R>) -> usize {
+    app.store("settings.store")
+        .ok()
+        .and_then(|settings| settings.get("backup_retention"))
+        .and_then(|v| v.as_u64())
+        .map(|count| count.max(1) as usize)
+        .unwrap_or(DEFAULT_BACKUP_RETENTION)
+}
+
+// Store files live in the app data directory, which is where the store plugin resolves relative paths
+fn store_dir<R: tauri::Runtime>(app: &tauri::AppHandle<R>) -> Result<std::path::PathBuf, String> {
+    app.path().app_data_dir().map_err(|e| e.to_string())
+}
+
+fn list_backups<R: tauri::Runtime>(store_id: &str, app: &tauri::AppHandle<R>) -> Result<Vec<BackupInfo>, String> {
+    let prefix = format!("{}_backup_", store_id);
+    let entries = match std::fs::read_dir(store_dir(app)?) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read store directory: {}", e)),
+    };
+
+    let mut backups: Vec<BackupInfo> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let backup_name = file_name.strip_prefix(&prefix)?.strip_suffix(".store")?.to_string();
+            let metadata = entry.metadata().ok()?;
+            let created_at = metadata.modified().ok()
+                .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0);
+
+            Some(BackupInfo {
+                backup_name,
+                created_at,
+                size: metadata.len(),
+            })
+        })
+        .collect();
+
+    backups.sort_by_key(|backup| std::cmp::Reverse(backup.created_at));
+    Ok(backups)
+}
+
+fn delete_backup_file<R: tauri::Runtime>(store_id: &str, backup_name: &str, app: &tauri::AppHandle<R>) -> Result<(), String> {
+    if !is_plain_name(store_id) || !is_plain_name(backup_name) {
+        return Err("Invalid store or backup name".to_string());
+    }
+
+    let backup_file = format!("{}_backup_{}.store", store_id, backup_name);
+
+    // Unload it first so the plugin doesn't write it back to disk later
+    if let Some(backup_store) = app.get_store(&backup_file) {
+        backup_store.close_resource();
+    }
+
+    match std::fs::remove_file(store_dir(app)?.join(&backup_file)) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Err(format!("Backup '{}' not found for store '{}'", backup_name, store_id))
+        }
+        Err(e) => Err(format!("Failed to delete backup: {}", e)),
+    }
+}
+
+/// Turn on periodic backups. Only stores that changed since their newest backup are
+/// backed up on each run, and the usual retention rotation applies.
+#[command]
+pub async fn store_start_auto_backup<R: tauri::Runtime>(
+    stores: Option<Vec<String>>,
+    interval_secs: Option<u64>,
+    allow_on_mobile: Option<bool>,
+    app: tauri::AppHandle<R>,
+) -> Result<AutoBackupStatus, StoreError> {
+    let settings = app.store("settings.store").map_err(|e| e.to_string())?;
+
+    if let Some(stores) = stores {
+        if stores.is_empty() {
+            return Err("Auto backup needs at least one store".into());
+        }
+        for store_id in &stores {
+            ensure_store_accessible(store_id)?;
+        }
+        settings.set("auto_backup_stores", serde_json::json!(stores));
+    }
+    if let Some(interval_secs) = interval_secs {
+        settings.set("auto_backup_interval_secs", serde_json::json!(validate_auto_backup_interval(interval_secs)?));
+    }
+    if let Some(allow_on_mobile) = allow_on_mobile {
+        settings.set("auto_backup_allow_on_mobile", serde_json::json!(allow_on_mobile));
+    }
+    settings.set("auto_backup_enabled", serde_json::json!(true));
+    settings.save().map_err(|e| e.to_string())?;
+
+    start_auto_backup(&app);
+    Ok(auto_backup_status(&app))
+}
+
+/// Turn off periodic backups
+#[command]
+pub async fn store_stop_auto_backup<R: tauri::Runtime>(app: tauri::AppHandle<R>) -> Result<AutoBackupStatus, String> {
+    let settings = app.store("settings.store").map_err(|e| e.to_string())?;
+    settings.set("auto_backup_enabled", serde_json::json!(false));
+    settings.save().map_err(|e| e.to_string())?;
+
+    stop_auto_backup();
+    Ok(auto_backup_status(&app))
+}
+
+/// Change how often periodic backups run, restarting the task if it is running
+#[command]
+pub async fn store_set_auto_backup_interval<R: tauri::Runtime>(
+    interval_secs: u64,
+    app: tauri::AppHandle<R>,
+) -> Result<AutoBackupStatus, String> {
+    let interval_secs = validate_auto_backup_interval(interval_secs)?;
+
+    let settings = app.store("settings.store").map_err(|e| e.to_string())?;
+    settings.set("auto_backup_interval_secs", serde_json::json!(interval_secs));
+    settings.save().map_err(|e| e.to_string())?;
+
+    if AUTO_BACKUP_RUNNING.load(Ordering::SeqCst) {
+        start_auto_backup(&app);
+    }
+    Ok(auto_backup_status(&app))
+}
+
+/// Get the periodic backup configuration and whether the task is running
+#[command]
+pub async fn store_get_auto_backup_status<R: tauri::Runtime>(app: tauri::AppHandle<R>) -> Result<AutoBackupStatus, String> {
+    Ok(auto_backup_status(&app))
+}
+
+/// Start periodic backups at launch if the user turned them on in a previous session
+pub fn load_auto_backup<R: tauri::Runtime>(app: &tauri::AppHandle<R>) {
+    if auto_backup_status(app).enabled {
+        start_auto_backup(app);
+    }
+}
+
+fn auto_backup_status<R: tauri::Runtime>(app: &tauri::AppHandle<R>) -> AutoBackupStatus {
+    let settings = app.store("settings.store").ok();
+    let setting = |key: &str| settings.as_ref().and_then(|s| s.get(key));
+
+    AutoBackupStatus {
+        enabled: setting("auto_backup_enabled").and_then(|v| v.as_bool()).unwrap_or(false),
+        running: AUTO_BACKUP_RUNNING.load(Ordering::SeqCst),
+        interval_secs: setting("auto_backup_interval_secs")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_AUTO_BACKUP_INTERVAL_SECS),
+        stores: setting("auto_backup_stores")
+            .and_then(|v| serde_json::from_value(v).ok())
+            .unwrap_or_else(|| DEFAULT_AUTO_BACKUP_STORES.iter().map(|s| s.to_string()).collect()),
+        allow_on_mobile: setting("auto_backup_allow_on_mobile").and_then(|v| v.as_bool()).unwrap_or(false),
+    }
+}
+
+fn validate_auto_backup_interval(interval_secs: u64) -> Result<u64, String> {
+    if interval_secs < MIN_AUTO_BACKUP_INTERVAL_SECS {
+        return Err(format!(
+            "Auto backup interval must be at least {} seconds",
+            MIN_AUTO_BACKUP_INTERVAL_SECS
+        ));
+    }
+    Ok(interval_secs)
+}
+
+// Replaces any loop already running. On mobile the task only runs when explicitly allowed,
+// since waking up every few minutes costs battery.
+fn start_auto_backup<R: tauri::Runtime>(app: &tauri::AppHandle<R>) {
+    let status = auto_backup_status(app);
+    if cfg!(mobile) && !status.allow_on_mobile {
+        stop_auto_backup();
+        return;
+    }
+
+    let generation = AUTO_BACKUP_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    AUTO_BACKUP_RUNNING.store(true, Ordering::SeqCst);
+
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(status.interval_secs)).await;
+            if AUTO_BACKUP_GENERATION.load(Ordering::SeqCst) != generation {
+                break;
+            }
+            run_auto_backup(&app).await;
+        }
+    });
+}
+
+fn stop_auto_backup() {
+    AUTO_BACKUP_GENERATION.fetch_add(1, Ordering::SeqCst);
+    AUTO_BACKUP_RUNNING.store(false, Ordering::SeqCst);
+}
+
+async fn run_auto_backup<R: tauri::Runtime>(app: &tauri::AppHandle<R>) {
+    // Re-read the store list each run so changes apply without a restart
+    let stores = auto_backup_status(app).stores;
+    let backup_name = format!("auto_{}", chrono::Utc::now().format("%Y%m%d%H%M%S"));
+
+    for store_id in stores {
+        let last_updated = app.store(format!("{}.store", store_id))
+            .ok()
+            .filter(|store| store.get("data").is_some())
+            .map(|store| store.get("last_updated").and_then(|v| v.as_u64()).unwrap_or(0));
+        let latest_backup_at = list_backups(&store_id, app)
+            .ok()
+            .and_then(|backups| backups.first().map(|b| b.created_at));
+
+        if !is_due_for_backup(last_updated, latest_backup_at) {
+            continue;
+        }
+
+        if let Err(e) = store_backup(store_id.clone(), backup_name.clone(), app.clone()).await {
+            eprintln!("Auto backup of store '{}' failed: {}", store_id, e);
+        }
+    }
+}
+
+// A store is due when it has data that changed after its newest backup.
+// `last_updated` is None when the store has no data at all.
+fn is_due_for_backup(last_updated: Option<u64>, latest_backup_at: Option<u64>) -> bool {
+    match (last_updated, latest_backup_at) {
+        (None, _) => false,
+        (Some(_), None) => true,
+        (Some(updated), Some(backed_up)) => updated > backed_up,
+    }
+}
+
+/// Restore a store from backup
+#[command]
+pub async fn store_restore<R: tauri::Runtime>(
+    store_id: String,
+    backup_name: String,
+    app: tauri::AppHandle<R>,
+) -> Result<(), StoreError> {
+    ensure_store_accessible(&store_id)?;
+    if !is_plain_name(&backup_name) {
+        return Err(format!("Invalid backup name '{}'", backup_name).into());
+    }
     let store_file = format!("{}.store", store_id);
     let backup_file = format!("{}_backup_{}.store", store_id, backup_name);
     
     let store = app.store(&store_file).map_err(|e| e.to_string())?;
     let backup_store = app.store(&backup_file).map_err(|e| e.to_string())?;
 
+    let lock = store_lock(&store_id);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
     // Copy data from backup to original
     if let Some(data) = backup_store.get("data") {
+        store.set("checksum", serde_json::json!(data_checksum(&data)));
         store.set("data", data.clone());
+        if is_encrypted(&backup_store) {
+            store.set("encrypted", serde_json::json!(true));
+        } else {
+            store.delete("encrypted");
+        }
         store.set("restored_from", serde_json::json!(backup_name));
         store.set("restored_at", serde_json::json!(chrono::Utc::now().timestamp_millis()));
         
         store.save().map_err(|e| e.to_string())?;
     } else {
-        return Err("Backup contains no data".to_string());
+        return Err("Backup contains no data".into());
     }
 
     Ok(())
@@ -159,11 +1145,12 @@ pub async fn store_restore(
 
 /// Sync store data with external source (placeholder for future implementation)
 #[command]
-pub async fn store_sync(
+pub async fn store_sync<R: tauri::Runtime>(
     store_id: String,
     _sync_endpoint: String,
-    app: tauri::AppHandle,
-) -> Result<HashMap<String, Value>, String> {
+    app: tauri::AppHandle<R>,
+) -> Result<HashMap<String, Value>, StoreError> {
+    ensure_store_accessible(&store_id)?;
     let store_file = format!("{}.store", store_id);
     let store = app.store(&store_file).map_err(|e| e.to_string())?;
 
@@ -184,7 +1171,8 @@ pub async fn store_sync(
 
 /// Validate store integrity
 #[command]
-pub async fn store_validate(store_id: String, app: tauri::AppHandle) -> Result<bool, String> {
+pub async fn store_validate<R: tauri::Runtime>(store_id: String, app: tauri::AppHandle<R>) -> Result<bool, StoreError> {
+    ensure_store_accessible(&store_id)?;
     let store_file = format!("{}.store", store_id);
     let store = app.store(&store_file).map_err(|e| e.to_string())?;
 
@@ -192,12 +1180,39 @@ pub async fn store_validate(store_id: String, app: tauri::AppHandle) -> Result<b
     let has_data = store.get("data").is_some();
     let has_timestamp = store.get("last_updated").is_some();
 
-    Ok(has_data && has_timestamp)
+    Ok(has_data && has_timestamp && checksum_matches(&store))
+}
+
+/// Restore a corrupted store from its newest intact backup.
+/// Returns the backup that was used, or `None` if the store was not corrupted.
+#[command]
+pub async fn store_repair<R: tauri::Runtime>(store_id: String, app: tauri::AppHandle<R>) -> Result<Option<String>, StoreError> {
+    ensure_store_accessible(&store_id)?;
+    let store_file = format!("{}.store", store_id);
+    let store = app.store(&store_file).map_err(|e| e.to_string())?;
+
+    if checksum_matches(&store) {
+        return Ok(None);
+    }
+
+    for backup in list_backups(&store_id, &app)? {
+        let backup_file = format!("{}_backup_{}.store", store_id, backup.backup_name);
+        let backup_store = app.store(&backup_file).map_err(|e| e.to_string())?;
+
+        if backup_store.get("data").is_none() || !checksum_matches(&backup_store) {
+            continue;
+        }
+
+        store_restore(store_id.clone(), backup.backup_name.clone(), app.clone()).await?;
+        return Ok(Some(backup.backup_name));
+    }
+
+    Err(format!("Store '{}' is corrupted and has no intact backup to repair it from", store_id).into())
 }
 
 /// Get store health information
 #[command]
-pub async fn store_health(app: tauri::AppHandle) -> Result<HashMap<String, Value>, String> {
+pub async fn store_health<R: tauri::Runtime>(app: tauri::AppHandle<R>) -> Result<HashMap<String, Value>, String> {
     let mut health = HashMap::new();
     
     // Check each known store
@@ -209,13 +1224,15 @@ pub async fn store_health(app: tauri::AppHandle) -> Result<HashMap<String, Value
         match app.store(&store_file) {
             Ok(store) => {
                 let has_data = store.get("data").is_some();
+                let intact = checksum_matches(&store);
                 let last_updated = store.get("last_updated").and_then(|v| v.as_u64()).unwrap_or(0);
                 
                 store_status.insert(store_id.to_string(), serde_json::json!({
                     "exists": true,
                     "has_data": has_data,
+                    "intact": intact,
                     "last_updated": last_updated,
-                    "healthy": has_data
+                    "healthy": has_data && intact
                 }));
             }
             Err(_) => {
@@ -232,3 +1249,374 @@ pub async fn store_health(app: tauri::AppHandle) -> Result<HashMap<String, Value
     
     Ok(health)
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    #[test]
+    fn reserved_stores_are_rejected() {
+        for store_id in ["session", "Session", " database ", "auth_config", "rate_limits"] {
+            assert!(matches!(ensure_store_accessible(store_id), Err(StoreError::Forbidden { .. })), "{}", store_id);
+        }
+    }
+
+    #[test]
+    fn backups_of_reserved_stores_are_rejected() {
+        assert!(matches!(
+            ensure_store_accessible("session_backup_1700000000"),
+            Err(StoreError::Forbidden { .. })
+        ));
+    }
+
+    #[test]
+    fn path_like_store_ids_are_rejected() {
+        for store_id in ["", "../session", "a/b", "a\\b", ".."] {
+            assert!(ensure_store_accessible(store_id).is_err(), "{}", store_id);
+        }
+    }
+
+    #[test]
+    fn ordinary_stores_are_accessible() {
+        assert!(ensure_store_accessible("app_data").is_ok());
+        assert!(ensure_store_accessible("sessions_archive").is_ok());
+    }
+
+    // A mock app with the store plugin, whose app data directory is a fresh temp dir. The
+    // data directory is resolved by joining the identifier, and joining an absolute path
+    // replaces the base, so the identifier is the temp dir itself.
+    pub(crate) fn test_app() -> (tauri::App<tauri::test::MockRuntime>, std::path::PathBuf) {
+        static NEXT_APP: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "aura-store-test-{}-{}",
+            std::process::id(),
+            NEXT_APP.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut context = tauri::test::mock_context(tauri::test::noop_assets());
+        context.config_mut().identifier = dir.to_string_lossy().to_string();
+        let app = tauri::test::mock_builder()
+            .plugin(tauri_plugin_store::Builder::new().build())
+            .build(context)
+            .unwrap();
+        (app, dir)
+    }
+
+    #[test]
+    fn rename_copies_the_store_then_deletes_the_old_file() {
+        let (app, dir) = test_app();
+        let handle = app.handle().clone();
+        tauri::async_runtime::block_on(async {
+            store_set("legacy_prefs".into(), serde_json::json!({"theme": "dark"}), None, handle.clone()).await.unwrap();
+
+            rename_store("legacy_prefs".into(), "prefs".into(), None, handle.clone()).await.unwrap();
+
+            assert!(!dir.join("legacy_prefs.store").exists());
+            assert!(dir.join("prefs.store").exists());
+            assert_eq!(store_get("prefs".into(), handle.clone()).await.unwrap(), Some(serde_json::json!({"theme": "dark"})));
+        });
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rename_does_not_overwrite_a_store_with_data_unless_forced() {
+        let (app, dir) = test_app();
+        let handle = app.handle().clone();
+        tauri::async_runtime::block_on(async {
+            store_set("old_prefs".into(), serde_json::json!({"theme": "dark"}), None, handle.clone()).await.unwrap();
+            store_set("new_prefs".into(), serde_json::json!({"theme": "light"}), None, handle.clone()).await.unwrap();
+
+            assert!(rename_store("old_prefs".into(), "new_prefs".into(), None, handle.clone()).await.is_err());
+            assert!(dir.join("old_prefs.store").exists());
+            assert_eq!(store_get("new_prefs".into(), handle.clone()).await.unwrap(), Some(serde_json::json!({"theme": "light"})));
+
+            rename_store("old_prefs".into(), "new_prefs".into(), Some(true), handle.clone()).await.unwrap();
+            assert_eq!(store_get("new_prefs".into(), handle.clone()).await.unwrap(), Some(serde_json::json!({"theme": "dark"})));
+        });
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn rename_rejects_path_like_ids() {
+        let (app, dir) = test_app();
+        let result = tauri::async_runtime::block_on(rename_store("../prefs".into(), "prefs".into(), None, app.handle().clone()));
+        assert!(result.is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn batch_read_maps_missing_stores_to_null() {
+        let (app, dir) = test_app();
+        let handle = app.handle().clone();
+        tauri::async_runtime::block_on(async {
+            let entries = HashMap::from([
+                ("batch_prefs".to_string(), serde_json::json!({"theme": "dark"})),
+                ("batch_recent".to_string(), serde_json::json!(["a", "b"])),
+            ]);
+            store_set_many(entries, handle.clone()).await.unwrap();
+
+            let ids = vec!["batch_prefs".to_string(), "batch_missing".to_string(), "batch_recent".to_string()];
+            let results = store_get_many(ids, handle.clone()).await.unwrap();
+
+            assert_eq!(results.len(), 3);
+            assert_eq!(results["batch_prefs"], Some(serde_json::json!({"theme": "dark"})));
+            assert_eq!(results["batch_missing"], None);
+            assert_eq!(results["batch_recent"], Some(serde_json::json!(["a", "b"])));
+        });
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn setting_one_key_leaves_its_siblings_intact() {
+        let (app, dir) = test_app();
+        let handle = app.handle().clone();
+        tauri::async_runtime::block_on(async {
+            store_set("keyed_prefs".into(), serde_json::json!({"theme": "dark", "font_size": 14}), None, handle.clone())
+                .await
+                .unwrap();
+
+            store_set_key("keyed_prefs".into(), "theme".into(), serde_json::json!("light"), handle.clone()).await.unwrap();
+            store_set_key("keyed_prefs".into(), "sidebar".into(), serde_json::json!(true), handle.clone()).await.unwrap();
+
+            assert_eq!(
+                store_get("keyed_prefs".into(), handle.clone()).await.unwrap(),
+                Some(serde_json::json!({"theme": "light", "font_size": 14, "sidebar": true}))
+            );
+            assert_eq!(
+                store_get_key("keyed_prefs".into(), "font_size".into(), handle.clone()).await.unwrap(),
+                Some(serde_json::json!(14))
+            );
+            assert_eq!(store_get_key("keyed_prefs".into(), "missing".into(), handle.clone()).await.unwrap(), None);
+        });
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn compare_and_swap_only_writes_over_the_expected_value() {
+        let (app, dir) = test_app();
+        let handle = app.handle().clone();
+        tauri::async_runtime::block_on(async {
+            let swap = |expected: Value, new: Value| {
+                store_compare_and_swap("cas_state".into(), "owner".into(), expected, new, handle.clone())
+            };
+
+            // A missing key matches null
+            assert!(swap(Value::Null, serde_json::json!("window-1")).await.unwrap());
+            assert!(swap(serde_json::json!("window-1"), serde_json::json!("window-2")).await.unwrap());
+
+            // The value changed underneath, so the stale swap is rejected and nothing is written
+            assert!(!swap(serde_json::json!("window-1"), serde_json::json!("window-3")).await.unwrap());
+            assert_eq!(
+                store_get_key("cas_state".into(), "owner".into(), handle.clone()).await.unwrap(),
+                Some(serde_json::json!("window-2"))
+            );
+        });
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn backup_beyond_the_retention_prunes_the_oldest() {
+        let (app, dir) = test_app();
+        let handle = app.handle().clone();
+        tauri::async_runtime::block_on(async {
+            store_set("rotated".into(), serde_json::json!({"n": 1}), None, handle.clone()).await.unwrap();
+            store_set_backup_retention(3, handle.clone()).await.unwrap();
+
+            for name in ["first", "second", "third", "fourth"] {
+                store_backup("rotated".into(), name.into(), handle.clone()).await.unwrap();
+                // Backups are ordered by file modification time
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+
+            let names: Vec<String> = store_list_backups("rotated".into(), handle.clone())
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|backup| backup.backup_name)
+                .collect();
+            assert_eq!(names, ["fourth", "third", "second"]);
+            assert!(!dir.join("rotated_backup_first.store").exists());
+        });
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn store_is_due_for_backup_only_after_it_changed() {
+        assert!(!is_due_for_backup(None, None));
+        assert!(!is_due_for_backup(None, Some(1_000)));
+        assert!(is_due_for_backup(Some(1_000), None));
+        assert!(is_due_for_backup(Some(2_000), Some(1_000)));
+        assert!(!is_due_for_backup(Some(1_000), Some(1_000)));
+        assert!(!is_due_for_backup(Some(1_000), Some(2_000)));
+    }
+
+    #[test]
+    fn tampered_data_fails_the_checksum_and_is_repaired_from_a_backup() {
+        let (app, dir) = test_app();
+        let handle = app.handle().clone();
+        tauri::async_runtime::block_on(async {
+            store_set("checked".into(), serde_json::json!({"balance": 10}), None, handle.clone()).await.unwrap();
+            store_backup("checked".into(), "good".into(), handle.clone()).await.unwrap();
+
+            let store = handle.store("checked.store").unwrap();
+            assert!(checksum_matches(&store));
+            store.set("data", serde_json::json!({"balance": 1_000_000}));
+
+            assert!(!checksum_matches(&store));
+            assert!(store_get("checked".into(), handle.clone()).await.is_err());
+            assert!(!store_validate("checked".into(), handle.clone()).await.unwrap());
+
+            assert_eq!(store_repair("checked".into(), handle.clone()).await.unwrap().as_deref(), Some("good"));
+            assert_eq!(
+                store_get("checked".into(), handle.clone()).await.unwrap(),
+                Some(serde_json::json!({"balance": 10}))
+            );
+        });
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn transaction_failing_part_way_rolls_every_store_back() {
+        let (app, dir) = test_app();
+        let handle = app.handle().clone();
+        tauri::async_runtime::block_on(async {
+            store_set("txn_session".into(), serde_json::json!({"user": "old"}), None, handle.clone()).await.unwrap();
+
+            // Open the second store, then put a directory where its file goes so saving it fails
+            handle.store("txn_config.store").unwrap();
+            std::fs::create_dir_all(dir.join("txn_config.store")).unwrap();
+
+            let ops = vec![
+                StoreOp::Set { store_id: "txn_session".into(), data: serde_json::json!({"user": "new"}) },
+                StoreOp::Set { store_id: "txn_config".into(), data: serde_json::json!({"url": "https://example.test"}) },
+            ];
+            let error = store_transaction(ops, handle.clone()).await.unwrap_err();
+            assert!(error.to_string().contains("op 1 (txn_config)"), "{}", error);
+
+            assert_eq!(
+                store_get("txn_session".into(), handle.clone()).await.unwrap(),
+                Some(serde_json::json!({"user": "old"}))
+            );
+            let on_disk: Value = serde_json::from_slice(&std::fs::read(dir.join("txn_session.store")).unwrap()).unwrap();
+            assert_eq!(on_disk["data"], serde_json::json!({"user": "old"}));
+        });
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn clear_all_with_a_wrong_token_clears_nothing() {
+        let (app, dir) = test_app();
+        let handle = app.handle().clone();
+        tauri::async_runtime::block_on(async {
+            store_set("prefs".into(), serde_json::json!({"theme": "dark"}), None, handle.clone()).await.unwrap();
+
+            let error = store_clear_all("clear-all".into(), handle.clone()).await.unwrap_err();
+            assert!(error.contains("no stores were cleared"), "{}", error);
+            assert_eq!(store_get("prefs".into(), handle.clone()).await.unwrap(), Some(serde_json::json!({"theme": "dark"})));
+        });
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn clear_all_keeps_the_session() {
+        let (app, dir) = test_app();
+        let handle = app.handle().clone();
+        tauri::async_runtime::block_on(async {
+            store_set("prefs".into(), serde_json::json!({"theme": "dark"}), None, handle.clone()).await.unwrap();
+            let session = handle.store("session.store").unwrap();
+            session.set("access_token", "token");
+            session.save().unwrap();
+
+            let cleared = store_clear_all(CLEAR_ALL_CONFIRM_TOKEN.into(), handle.clone()).await.unwrap();
+            assert_eq!(cleared, vec!["prefs.store".to_string()]);
+            assert_eq!(store_get("prefs".into(), handle.clone()).await.unwrap(), None);
+            assert_eq!(session.get("access_token"), Some(serde_json::json!("token")));
+        });
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn encrypted_store_round_trips_and_is_not_plaintext_on_disk() {
+        crate::vault::tests::use_mock_keychain();
+        let (app, dir) = test_app();
+        let handle = app.handle().clone();
+        let secret = serde_json::json!({"recoveryCode": "correct-horse-battery-staple"});
+        tauri::async_runtime::block_on(async {
+            store_set("secrets".into(), secret.clone(), Some(true), handle.clone()).await.unwrap();
+
+            let on_disk = std::fs::read_to_string(dir.join("secrets.store")).unwrap();
+            assert!(!on_disk.contains("correct-horse-battery-staple"), "{}", on_disk);
+            let file: Value = serde_json::from_str(&on_disk).unwrap();
+            assert_eq!(file["encrypted"], serde_json::json!(true));
+            assert!(crate::vault::is_sealed(file["data"].as_str().unwrap()));
+
+            assert_eq!(store_get("secrets".into(), handle.clone()).await.unwrap(), Some(secret.clone()));
+
+            // Leaving the flag out keeps the store encrypted
+            store_set("secrets".into(), serde_json::json!({"recoveryCode": "tr0ub4dor"}), None, handle.clone()).await.unwrap();
+            assert!(!std::fs::read_to_string(dir.join("secrets.store")).unwrap().contains("tr0ub4dor"));
+        });
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn storage_usage_sums_stores_and_backups_separately() {
+        let (app, dir) = test_app();
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file_name, size) in [
+            ("prefs.store", 100),
+            ("prefs_backup_1700000000000.store", 40),
+            ("prefs_backup_1700000001000.store", 60),
+            ("cart.store", 25),
+            ("orphan_backup_1700000000000.store", 10),
+            ("notes.txt", 999),
+        ] {
+            std::fs::write(dir.join(file_name), vec![b'x'; size]).unwrap();
+        }
+
+        let usage = tauri::async_runtime::block_on(get_storage_usage(app.handle().clone())).unwrap();
+
+        let stores: Vec<(&str, u64, u64, usize)> = usage.stores.iter()
+            .map(|store| (store.store_id.as_str(), store.size, store.backups_size, store.backup_count))
+            .collect();
+        assert_eq!(stores, vec![("cart", 25, 0, 0), ("orphan", 0, 10, 1), ("prefs", 100, 100, 2)]);
+        assert_eq!((usage.stores_size, usage.backups_size, usage.total_size), (125, 110, 235));
+        assert!(usage.skipped.is_empty());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn storage_usage_without_a_store_directory_is_empty() {
+        let (app, dir) = test_app();
+        let usage = tauri::async_runtime::block_on(get_storage_usage(app.handle().clone())).unwrap();
+        assert!(usage.stores.is_empty());
+        assert_eq!(usage.total_size, 0);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn compaction_drops_stale_keys_and_shrinks_the_file() {
+        let (app, dir) = test_app();
+        let handle = app.handle().clone();
+        tauri::async_runtime::block_on(async {
+            store_set("prefs".into(), serde_json::json!({"theme": "dark"}), None, handle.clone()).await.unwrap();
+            let store = handle.store("prefs.store").unwrap();
+            store.set("restored_from", "prefs_backup_1700000000000");
+            store.set("legacy_cache", "x".repeat(4096));
+            store.save().unwrap();
+            let checksum = store.get("checksum");
+
+            let result = compact_store("prefs".into(), handle.clone()).await.unwrap();
+
+            assert_eq!(result.removed_keys, vec!["legacy_cache".to_string(), "restored_from".to_string()]);
+            assert!(result.size_after < result.size_before);
+            assert_eq!(result.bytes_reclaimed, result.size_before - result.size_after);
+            assert_eq!(std::fs::metadata(dir.join("prefs.store")).unwrap().len(), result.size_after);
+            assert_eq!(store.get("checksum"), checksum);
+            assert_eq!(store.get("version"), Some(serde_json::json!(1)));
+            assert_eq!(store_get("prefs".into(), handle.clone()).await.unwrap(), Some(serde_json::json!({"theme": "dark"})));
+        });
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
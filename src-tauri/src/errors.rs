@@ -0,0 +1,38 @@
+/// Implement the conversions every command error enum needs: `From<String>` and `From<&str>`
+/// as its `Other` variant, so `?` works on string errors, and `From<Error> for String`
+/// through `Display`. Listing the variants also implements `Display` as each variant's
+/// `message`; an enum with a variant that has no message implements `Display` itself.
+macro_rules! command_error {
+    ($error:ident { $($variant:ident),+ $(,)? }) => {
+        impl std::fmt::Display for $error {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                match self {
+                    $($error::$variant { message, .. })|+ => write!(f, "{}", message),
+                }
+            }
+        }
+
+        $crate::errors::command_error!($error);
+    };
+    ($error:ident) => {
+        impl From<String> for $error {
+            fn from(message: String) -> Self {
+                $error::Other { message }
+            }
+        }
+
+        impl From<&str> for $error {
+            fn from(message: &str) -> Self {
+                $error::Other { message: message.to_string() }
+            }
+        }
+
+        impl From<$error> for String {
+            fn from(error: $error) -> Self {
+                error.to_string()
+            }
+        }
+    };
+}
+
+pub(crate) use command_error;
@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tauri_plugin_store::StoreExt;
+
+// Bounds for configured timeouts, so a typo can't make requests hang or fail instantly
+const MIN_TIMEOUT_SECS: u64 = 1;
+const MAX_TIMEOUT_SECS: u64 = 30 * 60;
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// Kinds of network operation, each with its own timeout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationClass {
+    /// Small database reads such as a balance or profile lookup
+    Read,
+    /// Database inserts and updates
+    Write,
+    /// File uploads, which can take minutes on a slow connection
+    Upload,
+    /// Stripe API calls
+    Stripe,
+}
+
+impl OperationClass {
+    const ALL: [OperationClass; 4] = [
+        OperationClass::Read,
+        OperationClass::Write,
+        OperationClass::Upload,
+        OperationClass::Stripe,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            OperationClass::Read => "read",
+            OperationClass::Write => "write",
+            OperationClass::Upload => "upload",
+            OperationClass::Stripe => "stripe",
+        }
+    }
+
+    fn default_secs(self) -> u64 {
+        match self {
+            OperationClass::Read => 10,
+            OperationClass::Write => 20,
+            OperationClass::Upload => 5 * 60,
+            OperationClass::Stripe => 30,
+        }
+    }
+
+    // e.g. AURA_HTTP_TIMEOUT_UPLOAD_SECS
+    fn env_var(self) -> String {
+        format!("AURA_HTTP_TIMEOUT_{}_SECS", self.name().to_uppercase())
+    }
+}
+
+/// HTTP client shared by all commands. Set a per-request timeout with `timeout`.
+pub(crate) fn client() -> &'static reqwest::Client {
+    CLIENT.get_or_init(reqwest::Client::new)
+}
+
+/// Timeout for an operation class: the in-app setting, then the
+/// AURA_HTTP_TIMEOUT_<CLASS>_SECS environment variable, then the built-in default
+pub(crate) fn timeout<R: tauri::Runtime>(class: OperationClass, app: &tauri::AppHandle<R>) -> Duration {
+    let stored = app.store("settings.store").ok()
+        .and_then(|settings| settings.get("http_timeouts"))
+        .and_then(|timeouts| timeouts.get(class.name()).and_then(|v| v.as_u64()));
+    let from_env = || crate::stripe::get_env_var(&class.env_var()).ok()
+        .and_then(|value| value.trim().parse::<u64>().ok());
+
+    let secs = stored.or_else(from_env).unwrap_or_else(|| class.default_secs());
+    Duration::from_secs(secs.clamp(MIN_TIMEOUT_SECS, MAX_TIMEOUT_SECS))
+}
+
+/// Run a Stripe (or other non-reqwest) call under its class timeout
+pub(crate) async fn with_timeout<T, E>(
+    class: OperationClass,
+    app: &tauri::AppHandle,
+    operation: impl std::future::Future<Output = Result<T, E>>,
+) -> Result<T, crate::stripe::StripeError>
+where
+    crate::stripe::StripeError: From<E>,
+{
+    let limit = timeout(class, app);
+    match tokio::time::timeout(limit, operation).await {
+        Ok(result) => result.map_err(crate::stripe::StripeError::from),
+        Err(_) => Err(timed_out(class, limit)),
+    }
+}
+
+/// Map a reqwest failure to StripeError, keeping timeouts and connection failures distinct
+/// so the frontend can offer a retry
+pub(crate) fn request_error(
+    class: OperationClass,
+    app: &tauri::AppHandle,
+    context: &str,
+    error: reqwest::Error,
+) -> crate::stripe::StripeError {
+    if error.is_timeout() {
+        timed_out(class, timeout(class, app))
+    } else if error.is_connect() || error.is_request() {
+        // The request never got an answer, so it may succeed once the connection is back
+        crate::stripe::StripeError::Network { message: format!("{}: {}", context, error) }
+    } else {
+        format!("{}: {}", context, error).into()
+    }
+}
+
+fn timed_out(class: OperationClass, limit: Duration) -> crate::stripe::StripeError {
+    let message = format!("The {} request timed out after {}s", class.name(), limit.as_secs());
+    crate::diagnostics::record_event("http", &message);
+    crate::stripe::StripeError::Timeout { message }
+}
+
+/// Effective timeout in seconds for each operation class
+#[tauri::command]
+pub async fn get_http_timeouts(
+    app: tauri::AppHandle,
+) -> Result<std::collections::HashMap<OperationClass, u64>, String> {
+    Ok(OperationClass::ALL.iter()
+        .map(|class| (*class, timeout(*class, &app).as_secs()))
+        .collect())
+}
+
+/// Override the timeout for one operation class, e.g. a longer upload timeout on slow networks
+#[tauri::command]
+pub async fn set_http_timeout(
+    class: OperationClass,
+    timeout_secs: u64,
+    app: tauri::AppHandle,
+) -> Result<u64, String> {
+    if !(MIN_TIMEOUT_SECS..=MAX_TIMEOUT_SECS).contains(&timeout_secs) {
+        return Err(format!(
+            "Timeout must be between {} and {} seconds",
+            MIN_TIMEOUT_SECS, MAX_TIMEOUT_SECS
+        ));
+    }
+
+    let settings = app.store("settings.store").map_err(|e| e.to_string())?;
+    let mut timeouts = settings.get("http_timeouts")
+        .filter(|v| v.is_object())
+        .unwrap_or_else(|| serde_json::json!({}));
+    timeouts[class.name()] = serde_json::json!(timeout_secs);
+    settings.set("http_timeouts", timeouts);
+    settings.save().map_err(|e| e.to_string())?;
+
+    Ok(timeout_secs)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::sync::{Arc, Mutex};
+
+    type ReceivedRequests = Arc<Mutex<Vec<(String, String)>>>;
+
+    /// A local HTTP server answering each request with the next of `responses` (status and
+    /// JSON body), and with a 500 once they run out. Returns its URL and the requests it
+    /// received, each as "METHOD /path?query" and the body.
+    pub(crate) fn mock_server(responses: Vec<(u16, serde_json::Value)>) -> (String, ReceivedRequests) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+
+        std::thread::spawn(move || {
+            let mut responses = responses.into_iter();
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+
+                let request = request_line.split_whitespace().take(2).collect::<Vec<_>>().join(" ");
+                received.lock().unwrap().push((request, String::from_utf8_lossy(&body).to_string()));
+
+                let (status, body) = responses.next().unwrap_or((500, serde_json::json!({ "msg": "unexpected request" })));
+                let body = body.to_string();
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+            }
+        });
+
+        (url, requests)
+    }
+
+    #[test]
+    fn upload_gets_a_longer_timeout_than_a_read() {
+        let (app, dir) = crate::enhanced_store::tests::test_app();
+        let read = timeout(OperationClass::Read, app.handle());
+        let upload = timeout(OperationClass::Upload, app.handle());
+
+        assert_eq!(read, Duration::from_secs(OperationClass::Read.default_secs()));
+        assert_eq!(upload, Duration::from_secs(OperationClass::Upload.default_secs()));
+        assert!(upload > read);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn stored_timeouts_override_the_default_within_bounds() {
+        let (app, dir) = crate::enhanced_store::tests::test_app();
+        let settings = app.handle().store("settings.store").unwrap();
+        settings.set("http_timeouts", serde_json::json!({ "read": 45, "upload": 0 }));
+
+        assert_eq!(timeout(OperationClass::Read, app.handle()), Duration::from_secs(45));
+        assert_eq!(timeout(OperationClass::Upload, app.handle()), Duration::from_secs(MIN_TIMEOUT_SECS));
+        assert_eq!(timeout(OperationClass::Write, app.handle()), Duration::from_secs(OperationClass::Write.default_secs()));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,3 +1,5 @@
+// Shared conversions for command error types
+mod errors;
 // Session management module
 mod session;
 // Database management module
@@ -6,10 +8,20 @@ mod database;
 mod enhanced_store;
 // Stripe payment processing module
 mod stripe;
-
-// Import required for environment variable loading
-#[cfg(not(target_os = "ios"))]
-use dotenv;
+// Stripe webhook handling module
+mod webhooks;
+// Network reachability module
+mod connectivity;
+// Cancellation of long-running commands
+mod cancellation;
+// Support diagnostics module
+mod diagnostics;
+// Shared HTTP client and per-operation timeouts
+mod http;
+// Encryption key for encrypted stores
+mod vault;
+// Release feed checks
+mod updates;
 
 // Load environment variables with cross-platform handling
 fn load_environment_variables() {
@@ -30,7 +42,7 @@ fn load_environment_variables() {
         
         // Try each path until one works
         for path in &env_paths {
-            if let Ok(_) = dotenv::from_path(path) {
+            if dotenv::from_path(path).is_ok() {
                 #[cfg(debug_assertions)]
                 println!("Loaded runtime environment variables from: {}", path);
                 loaded = true;
@@ -65,21 +77,8 @@ fn validate_stripe_environment() {
     let mut missing_vars = Vec::new();
     
     for var in &required_vars {
-        // Check both runtime and compile-time environment variables
-        let runtime_var = std::env::var(var).ok();
-        let compile_time_var = match *var {
-            "STRIPE_SECRET_KEY" => {
-                let val = env!("STRIPE_SECRET_KEY");
-                if val.is_empty() { None } else { Some(val.to_string()) }
-            },
-            "STRIPE_PUBLISHABLE_KEY" => {
-                let val = env!("STRIPE_PUBLISHABLE_KEY");
-                if val.is_empty() { None } else { Some(val.to_string()) }
-            },
-            _ => None,
-        };
-        
-        if runtime_var.is_none() && compile_time_var.is_none() {
+        // Checks runtime and compile-time variables, including the per-mode keys
+        if stripe::get_stripe_key(var).is_err() {
             missing_vars.push(*var);
         }
     }
@@ -113,6 +112,26 @@ pub fn run() {
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_http::init())
         .plugin(tauri_plugin_opener::init())
+        .manage(cancellation::CancellationRegistry::default())
+        .setup(|app| {
+            // Restore the test/live Stripe mode chosen in a previous session
+            stripe::load_stripe_mode(app.handle());
+            stripe::load_default_currency(app.handle());
+            // Resume periodic store backups if they were turned on
+            enhanced_store::load_auto_backup(app.handle());
+            // Finish a vault key rotation that was interrupted
+            vault::resume_key_rotation(app.handle());
+            // Record purchases that were made while offline once the connection is back
+            stripe::start_pending_purchase_flusher(app.handle());
+            // Tell the frontend when the device goes online or offline
+            connectivity::start_connectivity_monitor(app.handle());
+            Ok(())
+        })
+        .on_window_event(|_window, event| {
+            if let tauri::WindowEvent::Focused(focused) = event {
+                connectivity::set_foreground(*focused);
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             // Session management commands
             session::store_tokens,
@@ -120,19 +139,43 @@ pub fn run() {
             session::get_tokens,
             session::logout,
             session::update_tokens,
+            session::get_auth_user,
+            session::sign_in_with_password,
+            session::sign_up_with_password,
+            session::exchange_oauth_code,
             // Database management commands
             database::init_database,
             database::get_user_profile,
+            database::get_public_profile,
+            database::get_profile_by_username,
             database::update_user_profile,
             database::create_user_profile,
             database::check_username_availability,
+            database::check_usernames_availability,
             database::get_database_status,
+            database::diagnose_config,
+            database::delete_account,
+            database::export_user_data,
             database::update_subscription_status,
+            database::is_subscription_active,
+            database::get_entitlements,
+            database::set_subscription_grace_period,
             database::get_subscription_plans_with_prices,
             database::get_packages_with_prices,
+            database::get_package_value_comparison,
+            database::supabase_query,
+            database::seed_catalog,
             database::get_user_purchases,
+            database::get_spend_summary,
+            database::get_token_balance,
+            database::spend_tokens,
+            database::reconcile_token_balance,
+            database::get_token_ledger,
+            database::admin_adjust_tokens,
+            database::get_subscription_history,
             // Contractor KYC database commands
             database::save_kyc_form_data,
+            database::delete_kyc_form_data,
             database::load_kyc_form_data,
             database::create_contractor_profile,
             database::get_contractor_profile,
@@ -142,9 +185,16 @@ pub fn run() {
             // Representative commands
             database::create_representative,
             database::get_representatives,
+            database::validate_contractor_completeness,
+            database::submit_contractor_for_verification,
             // Document upload commands
             database::create_document_upload,
             database::get_document_uploads,
+            database::get_document_upload,
+            database::list_pending_documents,
+            database::list_webhook_events,
+            database::set_webhook_payload_storage,
+            database::review_document,
             database::update_document_upload_status,
             // Payment method database commands
             database::store_payment_method,
@@ -155,55 +205,97 @@ pub fn run() {
             // Enhanced store management commands
             enhanced_store::store_get,
             enhanced_store::store_set,
+            enhanced_store::store_get_many,
+            enhanced_store::store_set_many,
+            enhanced_store::store_transaction,
+            enhanced_store::store_get_key,
+            enhanced_store::store_set_key,
+            enhanced_store::store_compare_and_swap,
             enhanced_store::store_get_metadata,
             enhanced_store::store_list,
             enhanced_store::store_clear,
+            enhanced_store::store_clear_all,
+            enhanced_store::get_storage_usage,
+            enhanced_store::compact_store,
+            enhanced_store::rename_store,
             enhanced_store::store_backup,
             enhanced_store::store_restore,
+            enhanced_store::store_list_backups,
+            enhanced_store::store_delete_backup,
+            enhanced_store::store_set_backup_retention,
+            enhanced_store::store_start_auto_backup,
+            enhanced_store::store_stop_auto_backup,
+            enhanced_store::store_set_auto_backup_interval,
+            enhanced_store::store_get_auto_backup_status,
             enhanced_store::store_sync,
             enhanced_store::store_validate,
+            enhanced_store::store_repair,
             enhanced_store::store_health,
+            // Vault commands
+            vault::rotate_key,
             // Stripe payment processing commands
             stripe::get_stripe_publishable_key,
+            stripe::is_stripe_configured,
+            stripe::get_stripe_mode,
+            stripe::set_stripe_mode,
+            stripe::get_default_currency,
+            stripe::set_default_currency,
+            stripe::format_amount,
             stripe::fix_payment_method_attachments,
             stripe::create_payment_intent,
             stripe::create_stripe_customer,
             stripe::initialize_stripe_customer,
+            stripe::sync_customer_metadata,
             stripe::get_or_create_customer,
             stripe::create_subscription,
+            stripe::create_subscription_with_items,
+            stripe::create_gift_subscription,
             stripe::cancel_subscription,
             stripe::get_subscription_status,
+            stripe::get_dunning_state,
+            stripe::get_renewal_summary,
+            stripe::get_metered_subscription_item,
+            stripe::report_usage,
             stripe::sync_subscription_status,
             stripe::sync_all_user_subscriptions,
             stripe::setup_stripe_product,
+            stripe::setup_product_with_prices,
             stripe::create_price_for_product,
             stripe::get_product_with_prices,
             // Payment method management commands
             stripe::create_setup_intent,
+            stripe::payment_method_from_setup_intent,
             stripe::get_customer_payment_methods,
             stripe::list_payment_methods,
-            stripe::delete_payment_method,
-            stripe::set_default_payment_method,
             // Integrated payment method commands (Stripe + Database)
             stripe::create_and_store_payment_method,
+            stripe::validate_payment_method,
             stripe::store_payment_method_after_setup,
             stripe::get_stored_payment_methods,
             stripe::set_default_payment_method_integrated,
             stripe::delete_payment_method_integrated,
+            stripe::prune_unusable_payment_methods,
             stripe::create_payment_intent_with_stored_method,
+            stripe::create_cart_payment_intent,
             // Purchase completion commands
             stripe::record_purchase,
+            stripe::flush_pending_purchases,
+            stripe::get_pending_purchase_count,
             stripe::complete_purchase,
             stripe::verify_payment_intent,
-            stripe::create_missing_package,
             stripe::create_missing_package_price,
             stripe::debug_get_product_id_from_price,
             stripe::debug_database_schema,
             stripe::sync_stripe_prices_to_database,
+            stripe::audit_price_sync,
+            stripe::archive_price,
+            stripe::archive_product,
             // Stripe Connect commands
             stripe::create_connect_account,
             stripe::create_account_onboarding_link,
+            stripe::refresh_onboarding_link,
             stripe::get_connect_account_status,
+            stripe::await_connect_verification,
             stripe::update_connect_account_kyc,
             stripe::get_contractor_status,
             // URL opening command
@@ -212,13 +304,32 @@ pub fn run() {
             stripe::debug_stripe_connect_status,
             // API onboarding commands
             stripe::update_connect_account_business,
+            stripe::update_connect_business_profile,
             stripe::add_connect_account_bank_account,
+            stripe::replace_connect_bank_account,
+            stripe::get_bank_account_status,
             stripe::get_connect_account_requirements,
             // Stripe File API commands
             stripe::upload_file_to_stripe,
             stripe::upload_contractor_document,
+            stripe::retry_document_upload,
             stripe::get_stripe_file,
-            stripe::delete_stripe_file
+            stripe::download_stripe_file,
+            stripe::delete_stripe_file,
+            // Stripe webhook commands
+            webhooks::handle_stripe_webhook,
+            stripe::replay_event,
+            // Connectivity commands
+            connectivity::get_connectivity,
+            // Cancellation commands
+            cancellation::cancel_request,
+            // Diagnostics commands
+            diagnostics::get_debug_snapshot,
+            // HTTP timeout commands
+            http::get_http_timeouts,
+            http::set_http_timeout,
+            // Update commands
+            updates::get_update_info
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -15,9 +15,74 @@ pub struct TokensResponse {
     pub refresh_token: String,
 }
 
+/// The signed-in Supabase user from /auth/v1/user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthUser {
+    pub id: String,
+    pub email: Option<String>,
+    pub phone: Option<String>,
+    #[serde(default)]
+    pub user_metadata: serde_json::Value,
+    #[serde(default)]
+    pub app_metadata: serde_json::Value,
+    pub created_at: Option<String>,
+    pub last_sign_in_at: Option<String>,
+}
+
+/// Error returned by sign-in commands, tagged by `kind` so the frontend can branch on it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuthError {
+    /// Wrong email or password - show it on the form, don't retry
+    InvalidCredentials { message: String },
+    /// Sign-up with an email that already has an account - offer sign-in instead
+    EmailAlreadyRegistered { message: String },
+    /// The OAuth or magic-link code expired, was already used, or doesn't match the
+    /// verifier - restart the sign-in flow
+    InvalidCode { message: String },
+    Other { message: String },
+}
+
+crate::errors::command_error!(AuthError { InvalidCredentials, EmailAlreadyRegistered, InvalidCode, Other });
+
+impl AuthError {
+    fn invalid_code() -> Self {
+        AuthError::InvalidCode {
+            message: "This sign-in link has expired or was already used - please sign in again".to_string(),
+        }
+    }
+
+    fn email_already_registered() -> Self {
+        AuthError::EmailAlreadyRegistered {
+            message: "An account with this email already exists - sign in instead".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignUpResult {
+    pub user: AuthUser,
+    /// None when Supabase requires the email to be confirmed before the first sign-in,
+    /// in which case there is no session yet to create the profile with
+    pub profile: Option<crate::database::Profile>,
+    pub confirmation_required: bool,
+}
+
+/// Tokens and user returned by Supabase's /auth/v1/token endpoint
+#[derive(Deserialize)]
+struct AuthTokenResponse {
+    access_token: String,
+    refresh_token: String,
+    user: AuthUser,
+}
+
+// How long a fetched auth user is reused before asking Supabase again
+const AUTH_USER_CACHE_TTL_SECS: i64 = 60;
+const AUTH_USER_CACHE_KEY: &str = "auth-user";
+
 /// Store authentication tokens in the secure store
 #[command]
-pub async fn store_tokens(tokens: TokensRequest, app: tauri::AppHandle) -> Result<(), String> {
+pub async fn store_tokens<R: tauri::Runtime>(tokens: TokensRequest, app: tauri::AppHandle<R>) -> Result<(), String> {
     let access_token = tokens.access_token;
     let refresh_token = tokens.refresh_token;
 
@@ -25,6 +90,8 @@ pub async fn store_tokens(tokens: TokensRequest, app: tauri::AppHandle) -> Resul
 
     store.set("sb-access-token", serde_json::json!(access_token));
     store.set("sb-refresh-token", serde_json::json!(refresh_token));
+    // New tokens may belong to a different user or carry updated claims
+    store.delete(AUTH_USER_CACHE_KEY);
 
     store.save().map_err(|e| e.to_string())?;
 
@@ -33,7 +100,7 @@ pub async fn store_tokens(tokens: TokensRequest, app: tauri::AppHandle) -> Resul
 
 /// Check if a session exists in the store
 #[command]
-pub async fn check_session(app: tauri::AppHandle) -> Result<bool, String> {
+pub async fn check_session<R: tauri::Runtime>(app: tauri::AppHandle<R>) -> Result<bool, String> {
     let store = app.store("session.store").map_err(|e| e.to_string())?;
 
     let has_access = store.get("sb-access-token").is_some();
@@ -46,7 +113,7 @@ pub async fn check_session(app: tauri::AppHandle) -> Result<bool, String> {
 
 /// Retrieve stored tokens
 #[command]
-pub async fn get_tokens(app: tauri::AppHandle) -> Result<TokensResponse, String> {
+pub async fn get_tokens<R: tauri::Runtime>(app: tauri::AppHandle<R>) -> Result<TokensResponse, String> {
     let store = app.store("session.store").map_err(|e| e.to_string())?;
 
     let access_token = store
@@ -67,11 +134,12 @@ pub async fn get_tokens(app: tauri::AppHandle) -> Result<TokensResponse, String>
 
 /// Clear stored session data (logout)
 #[command]
-pub async fn logout(app: tauri::AppHandle) -> Result<(), String> {
+pub async fn logout<R: tauri::Runtime>(app: tauri::AppHandle<R>) -> Result<(), String> {
     let store = app.store("session.store").map_err(|e| e.to_string())?;
 
     store.delete("sb-access-token");
     store.delete("sb-refresh-token");
+    store.delete(AUTH_USER_CACHE_KEY);
     store.save().map_err(|e| e.to_string())?;
 
     Ok(())
@@ -79,7 +147,411 @@ pub async fn logout(app: tauri::AppHandle) -> Result<(), String> {
 
 /// Update stored tokens (for token refresh)
 #[command]
-pub async fn update_tokens(tokens: TokensRequest, app: tauri::AppHandle) -> Result<(), String> {
+pub async fn update_tokens<R: tauri::Runtime>(tokens: TokensRequest, app: tauri::AppHandle<R>) -> Result<(), String> {
     // This is essentially the same as store_tokens, but semantically different
     store_tokens(tokens, app).await
 }
+
+/// Get the signed-in Supabase user, reusing the last response for a short while so
+/// commands that need the user's id or email don't each call Supabase
+#[command]
+pub async fn get_auth_user<R: tauri::Runtime>(app: tauri::AppHandle<R>) -> Result<AuthUser, String> {
+    let store = app.store("session.store").map_err(|e| e.to_string())?;
+    let now = chrono::Utc::now().timestamp();
+
+    if let Some(cached) = store.get(AUTH_USER_CACHE_KEY) {
+        let fresh = cached["cached_at"].as_i64()
+            .is_some_and(|cached_at| now - cached_at < AUTH_USER_CACHE_TTL_SECS);
+        if fresh {
+            if let Ok(user) = serde_json::from_value::<AuthUser>(cached["user"].clone()) {
+                return Ok(user);
+            }
+        }
+    }
+
+    let db_config = crate::database::get_authenticated_db(&app).await?;
+
+    let response = crate::http::client()
+        .get(format!("{}/auth/v1/user", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch auth user: {}", e))?;
+
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to fetch auth user: {}", error_text));
+    }
+
+    let user: AuthUser = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse auth user response: {}", e))?;
+
+    cache_auth_user(&app, &user)?;
+
+    Ok(user)
+}
+
+fn cache_auth_user<R: tauri::Runtime>(app: &tauri::AppHandle<R>, user: &AuthUser) -> Result<(), String> {
+    let store = app.store("session.store").map_err(|e| e.to_string())?;
+    store.set(AUTH_USER_CACHE_KEY, serde_json::json!({
+        "user": user,
+        "cached_at": chrono::Utc::now().timestamp()
+    }));
+    store.save().map_err(|e| e.to_string())
+}
+
+/// Supabase URL and anon key saved by init_database. Signing in happens before there
+/// is a session, so this can't go through get_authenticated_db.
+fn supabase_config<R: tauri::Runtime>(app: &tauri::AppHandle<R>) -> Result<(String, String), String> {
+    let store = app.store("database.store").map_err(|e| e.to_string())?;
+    let database_url = store
+        .get("database_url")
+        .and_then(|v| v.as_str().map(String::from))
+        .ok_or_else(|| "Database not initialized".to_string())?;
+    let anon_key = store
+        .get("anon_key")
+        .and_then(|v| v.as_str().map(String::from))
+        .ok_or_else(|| "No anon key found in database store".to_string())?;
+    Ok((database_url, anon_key))
+}
+
+// Supabase reports auth failures as `error_code` (newer GoTrue) or `error` (older), with the
+// human-readable text in `msg` or `error_description`
+fn auth_error_from_response(status: u16, body: &str) -> AuthError {
+    let value: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+    let code = value["error_code"].as_str().or_else(|| value["error"].as_str()).unwrap_or_default();
+    let message = value["msg"].as_str()
+        .or_else(|| value["error_description"].as_str())
+        .map(String::from)
+        .unwrap_or_else(|| format!("Authentication failed: HTTP {} {}", status, body));
+
+    match code {
+        "invalid_credentials" | "invalid_grant" => AuthError::InvalidCredentials {
+            message: "Incorrect email or password".to_string(),
+        },
+        "user_already_exists" | "email_exists" => AuthError::email_already_registered(),
+        "flow_state_not_found" | "flow_state_expired" | "bad_code_verifier" | "bad_oauth_state" => AuthError::invalid_code(),
+        _ if message == "User already registered" => AuthError::email_already_registered(),
+        _ => AuthError::Other { message },
+    }
+}
+
+/// Store the tokens from a successful sign-in and prime the auth user cache
+async fn complete_sign_in<R: tauri::Runtime>(response: AuthTokenResponse, app: tauri::AppHandle<R>) -> Result<AuthUser, AuthError> {
+    store_tokens(
+        TokensRequest {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token,
+        },
+        app.clone(),
+    ).await?;
+    cache_auth_user(&app, &response.user)?;
+    Ok(response.user)
+}
+
+/// Sign in with email and password, keeping the session in the secure store
+#[command]
+pub async fn sign_in_with_password<R: tauri::Runtime>(
+    email: String,
+    password: String,
+    app: tauri::AppHandle<R>,
+) -> Result<AuthUser, AuthError> {
+    let (database_url, anon_key) = supabase_config(&app)?;
+
+    let response = crate::http::client()
+        .post(format!("{}/auth/v1/token", database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
+        .header("apikey", &anon_key)
+        .query(&[("grant_type", "password")])
+        .json(&serde_json::json!({
+            "email": email.trim(),
+            "password": password
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to sign in: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(auth_error_from_response(status, &error_text));
+    }
+
+    let tokens: AuthTokenResponse = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse sign-in response: {}", e))?;
+
+    complete_sign_in(tokens, app).await
+}
+
+/// Register with email and password, then sign in and make sure the user has a profile.
+/// With email confirmation enabled Supabase returns no session, so the profile is left to
+/// the first sign-in.
+#[command]
+pub async fn sign_up_with_password<R: tauri::Runtime>(
+    email: String,
+    password: String,
+    full_name: Option<String>,
+    app: tauri::AppHandle<R>,
+) -> Result<SignUpResult, AuthError> {
+    let (database_url, anon_key) = supabase_config(&app)?;
+
+    let response = crate::http::client()
+        .post(format!("{}/auth/v1/signup", database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
+        .header("apikey", &anon_key)
+        .json(&serde_json::json!({
+            "email": email.trim(),
+            "password": password,
+            "data": { "full_name": full_name }
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to sign up: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(auth_error_from_response(status, &error_text));
+    }
+
+    let body: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse sign-up response: {}", e))?;
+
+    // Without a session the body is the user itself
+    if body.get("access_token").is_none() {
+        // When confirmation is on, Supabase hides existing accounts behind a user with no identities
+        if body["identities"].as_array().is_some_and(|identities| identities.is_empty()) {
+            return Err(AuthError::email_already_registered());
+        }
+        let user: AuthUser = serde_json::from_value(body)
+            .map_err(|e| format!("Failed to parse sign-up user: {}", e))?;
+        return Ok(SignUpResult {
+            user,
+            profile: None,
+            confirmation_required: true,
+        });
+    }
+
+    let tokens: AuthTokenResponse = serde_json::from_value(body)
+        .map_err(|e| format!("Failed to parse sign-up response: {}", e))?;
+    let user = complete_sign_in(tokens, app.clone()).await?;
+
+    // The on_auth_user_created trigger normally creates the profile; fall back to creating it
+    // here so sign-up also works against databases without the trigger
+    let profile = match crate::database::get_user_profile(user.id.clone(), app.clone()).await? {
+        Some(profile) => profile,
+        None => crate::database::create_user_profile(user.id.clone(), full_name, None, Some(false), app).await?,
+    };
+
+    Ok(SignUpResult {
+        user,
+        profile: Some(profile),
+        confirmation_required: false,
+    })
+}
+
+/// Finish an OAuth or magic-link sign-in using the PKCE flow: exchange the `code` from the
+/// redirect, with the verifier generated when the flow started, for a session.
+/// (Implicit-flow redirects carry the tokens themselves and go through store_tokens.)
+#[command]
+pub async fn exchange_oauth_code<R: tauri::Runtime>(
+    code: String,
+    code_verifier: String,
+    app: tauri::AppHandle<R>,
+) -> Result<AuthUser, AuthError> {
+    let (database_url, anon_key) = supabase_config(&app)?;
+
+    let response = crate::http::client()
+        .post(format!("{}/auth/v1/token", database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
+        .header("apikey", &anon_key)
+        .query(&[("grant_type", "pkce")])
+        .json(&serde_json::json!({
+            "auth_code": code,
+            "code_verifier": code_verifier
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to exchange sign-in code: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        let error_text = response.text().await.unwrap_or_default();
+        // Older GoTrue versions report a bad code as a 404 or invalid_grant with no error_code
+        return Err(match auth_error_from_response(status, &error_text) {
+            AuthError::InvalidCredentials { .. } => AuthError::invalid_code(),
+            AuthError::Other { .. } if status == 404 => AuthError::invalid_code(),
+            other => other,
+        });
+    }
+
+    let tokens: AuthTokenResponse = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse sign-in response: {}", e))?;
+
+    complete_sign_in(tokens, app).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Point the mock app at `database_url`, signed in with a session
+    fn connect<R: tauri::Runtime>(app: &tauri::AppHandle<R>, database_url: &str) {
+        let database = app.store("database.store").unwrap();
+        database.set("database_url", database_url);
+        database.set("anon_key", "anon");
+        let session = app.store("session.store").unwrap();
+        session.set("sb-access-token", "access");
+        session.set("sb-refresh-token", "refresh");
+    }
+
+    fn sample_user(id: &str) -> serde_json::Value {
+        serde_json::json!({ "id": id, "email": "ada@example.com" })
+    }
+
+    #[test]
+    fn auth_user_is_cached_until_the_tokens_change() {
+        let (app, dir) = crate::enhanced_store::tests::test_app();
+        let handle = app.handle().clone();
+        let (url, requests) = crate::http::tests::mock_server(vec![(200, sample_user("user-1")), (200, sample_user("user-2"))]);
+        connect(&handle, &url);
+
+        tauri::async_runtime::block_on(async {
+            assert_eq!(get_auth_user(handle.clone()).await.unwrap().id, "user-1");
+            assert_eq!(get_auth_user(handle.clone()).await.unwrap().id, "user-1");
+            assert_eq!(requests.lock().unwrap().len(), 1, "the second call should be served from the cache");
+
+            let tokens = TokensRequest { access_token: "new-access".into(), refresh_token: "new-refresh".into() };
+            update_tokens(tokens, handle.clone()).await.unwrap();
+            assert_eq!(get_auth_user(handle.clone()).await.unwrap().id, "user-2");
+        });
+
+        assert_eq!(requests.lock().unwrap()[0].0, "GET /auth/v1/user");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn invalid_credentials_are_reported_as_such() {
+        let newer = r#"{"code":400,"error_code":"invalid_credentials","msg":"Invalid login credentials"}"#;
+        let older = r#"{"error":"invalid_grant","error_description":"Invalid login credentials"}"#;
+        for body in [newer, older] {
+            assert_eq!(auth_error_from_response(400, body), AuthError::InvalidCredentials {
+                message: "Incorrect email or password".to_string(),
+            });
+        }
+    }
+
+    #[test]
+    fn other_auth_failures_keep_supabases_message() {
+        assert_eq!(
+            auth_error_from_response(429, r#"{"error_code":"over_request_rate_limit","msg":"Too many requests"}"#),
+            AuthError::Other { message: "Too many requests".to_string() }
+        );
+        assert_eq!(
+            auth_error_from_response(502, "Bad Gateway"),
+            AuthError::Other { message: "Authentication failed: HTTP 502 Bad Gateway".to_string() }
+        );
+        assert_eq!(auth_error_from_response(422, r#"{"error_code":"user_already_exists"}"#), AuthError::email_already_registered());
+    }
+
+    fn sample_session(user_id: &str) -> serde_json::Value {
+        serde_json::json!({ "access_token": "access", "refresh_token": "refresh", "user": sample_user(user_id) })
+    }
+
+    #[test]
+    fn sign_up_creates_the_profile_when_the_trigger_did_not() {
+        let (app, dir) = crate::enhanced_store::tests::test_app();
+        let handle = app.handle().clone();
+        let (url, requests) = crate::http::tests::mock_server(vec![
+            (200, sample_session("user-1")),
+            (200, serde_json::json!([])),
+            (201, serde_json::json!([{ "id": "user-1", "full_name": "Ada Lovelace", "onboarding_complete": false }])),
+        ]);
+        connect(&handle, &url);
+
+        let result = tauri::async_runtime::block_on(sign_up_with_password(
+            "ada@example.com".into(),
+            "correct horse".into(),
+            Some("Ada Lovelace".into()),
+            handle.clone(),
+        )).unwrap();
+
+        assert!(!result.confirmation_required);
+        assert_eq!(result.profile.unwrap().full_name.as_deref(), Some("Ada Lovelace"));
+        let requests = requests.lock().unwrap();
+        let paths: Vec<&str> = requests.iter().map(|(request, _)| request.as_str()).collect();
+        assert_eq!(paths, ["POST /auth/v1/signup", "GET /rest/v1/profiles?id=eq.user-1&select=*", "POST /rest/v1/profiles"]);
+        let created: serde_json::Value = serde_json::from_str(&requests[2].1).unwrap();
+        assert_eq!(created, serde_json::json!({ "id": "user-1", "full_name": "Ada Lovelace", "onboarding_complete": false }));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn sign_up_awaiting_confirmation_creates_no_profile() {
+        let (app, dir) = crate::enhanced_store::tests::test_app();
+        let handle = app.handle().clone();
+        let mut user = sample_user("user-1");
+        user["identities"] = serde_json::json!([{ "provider": "email" }]);
+        let (url, requests) = crate::http::tests::mock_server(vec![(200, user)]);
+        connect(&handle, &url);
+
+        let result = tauri::async_runtime::block_on(sign_up_with_password(
+            "ada@example.com".into(),
+            "correct horse".into(),
+            None,
+            handle.clone(),
+        )).unwrap();
+
+        assert!(result.confirmation_required && result.profile.is_none());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn pkce_code_is_exchanged_for_a_stored_session() {
+        let (app, dir) = crate::enhanced_store::tests::test_app();
+        let handle = app.handle().clone();
+        let (url, requests) = crate::http::tests::mock_server(vec![(200, sample_session("user-1"))]);
+        connect(&handle, &url);
+        handle.store("session.store").unwrap().clear();
+
+        let user = tauri::async_runtime::block_on(exchange_oauth_code("code-123".into(), "verifier-456".into(), handle.clone())).unwrap();
+
+        assert_eq!(user.id, "user-1");
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].0, "POST /auth/v1/token?grant_type=pkce");
+        let body: serde_json::Value = serde_json::from_str(&requests[0].1).unwrap();
+        assert_eq!(body, serde_json::json!({ "auth_code": "code-123", "code_verifier": "verifier-456" }));
+        assert!(tauri::async_runtime::block_on(check_session(handle.clone())).unwrap());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn expired_or_reused_pkce_codes_are_invalid_codes() {
+        let (app, dir) = crate::enhanced_store::tests::test_app();
+        let handle = app.handle().clone();
+        let (url, _) = crate::http::tests::mock_server(vec![
+            (404, serde_json::json!({ "error_code": "flow_state_not_found", "msg": "invalid flow state, no valid flow state found" })),
+            (400, serde_json::json!({ "error": "invalid_grant", "error_description": "Invalid code" })),
+        ]);
+        connect(&handle, &url);
+
+        tauri::async_runtime::block_on(async {
+            for _ in 0..2 {
+                let error = exchange_oauth_code("used".into(), "verifier".into(), handle.clone()).await.unwrap_err();
+                assert_eq!(error, AuthError::invalid_code());
+            }
+        });
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,9 +1,11 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::str::FromStr;
-use chrono;
+use tauri::Emitter;
+use tauri_plugin_store::StoreExt;
 
-/// Calculate token amount based on price (matching the SQL function)
+/// Calculate token amount based on price (matching the SQL function).
+/// Only used when neither token_price_map nor the package price has an amount.
 fn get_token_amount_from_price(price_cents: i64) -> i64 {
     match price_cents {
         149 => 100,      // A$1.49 = 100 tokens
@@ -15,6 +17,14 @@ fn get_token_amount_from_price(price_cents: i64) -> i64 {
         _ => 100,        // Default fallback
     }
 }
+
+/// Tokens for one purchase line: token_price_map overrides the price's token_amount, and
+/// the built-in table is the last resort
+fn purchase_token_amount(mapped_tokens: Option<i64>, price_tokens: Option<i64>, amount_paid: i64) -> i64 {
+    mapped_tokens
+        .or(price_tokens)
+        .unwrap_or_else(|| get_token_amount_from_price(amount_paid))
+}
 use stripe::{
     Client, CreateCustomer, CreatePaymentIntent, CreateSubscription, CreatePrice, CreateProduct,
     Customer, PaymentIntent, Subscription, Price, Product, Currency, UpdateSubscription,
@@ -25,6 +35,22 @@ use stripe::{
     AccountId,
 };
 
+// Backoff used while waiting for the purchase trigger to credit tokens (~3s total)
+const PURCHASE_VERIFY_MAX_ATTEMPTS: u32 = 5;
+const PURCHASE_VERIFY_INITIAL_DELAY_MS: u64 = 100;
+
+// Backoff used while waiting for Stripe to verify a Connect account
+const CONNECT_VERIFY_INITIAL_DELAY_SECS: u64 = 2;
+const CONNECT_VERIFY_MAX_DELAY_SECS: u64 = 30;
+const CONNECT_VERIFY_MAX_TIMEOUT_SECS: u64 = 600;
+
+// Each cart unit becomes its own purchase row, so keep carts small
+const MAX_CART_UNITS: u32 = 50;
+// Stripe rejects metadata values longer than this
+const STRIPE_METADATA_VALUE_LIMIT: usize = 500;
+// Stripe only keeps events retrievable for this long
+const STRIPE_EVENT_RETENTION_DAYS: i64 = 30;
+
 
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -40,6 +66,78 @@ pub struct SubscriptionResponse {
     pub status: String,
     pub current_period_end: i64,
     pub price_id: String,
+    /// When the free trial ends, for subscriptions created with one
+    pub trial_end: Option<i64>,
+    /// Every item on the subscription, the base plan first
+    pub items: Vec<SubscriptionItemResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionItemRequest {
+    pub price_id: String,
+    pub quantity: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionItemResponse {
+    pub item_id: String,
+    pub price_id: String,
+    pub quantity: Option<u64>,
+}
+
+pub(crate) fn subscription_item_responses(subscription: &Subscription) -> Vec<SubscriptionItemResponse> {
+    subscription.items.data.iter()
+        .map(|item| SubscriptionItemResponse {
+            item_id: item.id.to_string(),
+            price_id: item.price.as_ref().map(|price| price.id.to_string()).unwrap_or_default(),
+            quantity: item.quantity,
+        })
+        .collect()
+}
+
+/// Where an unpaid renewal invoice is in Stripe's retry schedule
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DunningState {
+    pub subscription_id: String,
+    pub invoice_id: String,
+    pub attempt_count: u64,
+    /// When Stripe will retry the charge next, None once retries are exhausted
+    pub next_payment_attempt: Option<i64>,
+    pub final_attempt: bool,
+}
+
+/// What the user's next renewal will look like
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenewalSummary {
+    pub subscription_id: String,
+    pub status: String,
+    /// When the current period ends, i.e. when the subscription renews or, if it is
+    /// set to cancel, when it ends
+    pub renews_at: i64,
+    pub cancel_at_period_end: bool,
+    /// None when the subscription won't renew or the amount couldn't be worked out
+    pub amount_cents: Option<i64>,
+    pub currency: Option<String>,
+    pub price_id: Option<String>,
+    pub plan_name: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GiftSubscriptionResponse {
+    pub subscription_id: String,
+    pub recipient_customer_id: String,
+    pub payment_intent_id: String,
+    pub status: String,
+    pub current_period_end: i64,
+    pub price_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UsageRecordResponse {
+    pub usage_record_id: String,
+    pub subscription_item_id: String,
+    pub quantity: u64,
+    pub timestamp: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -48,6 +146,65 @@ pub struct SubscriptionSyncResult {
     pub errors: Vec<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PurchaseRecordResult {
+    pub purchases: Vec<serde_json::Value>,
+    pub is_new_purchase: bool,
+    pub token_balance: Option<VerifiedTokenBalance>,
+    /// The database couldn't be reached, so the purchase was queued for flush_pending_purchases
+    pub queued: bool,
+}
+
+/// A record_purchase call that failed while offline, replayed by flush_pending_purchases
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingPurchase {
+    pub user_id: String,
+    pub stripe_payment_intent_id: String,
+    pub stripe_price_id: String,
+    pub amount_paid: i64,
+    pub currency: String,
+    pub queued_at: String,
+    pub last_error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingPurchaseFlushResult {
+    pub recorded: usize,
+    /// Purchases that failed for a reason retrying won't fix, removed from the queue
+    pub dropped: Vec<PendingPurchase>,
+    pub pending: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CartItem {
+    pub price_id: String,
+    pub quantity: u32,
+}
+
+// Priced cart line as stored in the payment intent's `cart` metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CartLine {
+    price_id: String,
+    quantity: u32,
+    unit_amount: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CartPaymentIntentResponse {
+    pub client_secret: String,
+    pub payment_intent_id: String,
+    pub total_amount: i64,
+    pub currency: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerifiedTokenBalance {
+    pub total_tokens: i64,
+    pub tokens_remaining: i64,
+    pub tokens_used: i64,
+    pub total_purchases: i64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProductPrice {
     pub id: String,
@@ -57,6 +214,49 @@ pub struct ProductPrice {
     pub interval_count: i64,
 }
 
+/// One price to create in setup_product_with_prices
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProductPriceInput {
+    pub amount: i64, // Amount in cents
+    pub currency: String,
+    pub interval: String, // "month", "year" or "one_time"
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProductSetupResponse {
+    pub product_id: String,
+    pub price_ids: Vec<String>,
+    /// Result of syncing the prices into package_prices, when requested
+    pub sync_result: Option<String>,
+}
+
+/// A price whose amount or currency differs between Stripe and package_prices
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PriceMismatch {
+    pub stripe_price_id: String,
+    pub stripe_amount_cents: i64,
+    pub stripe_currency: String,
+    pub database_amount_cents: i64,
+    pub database_currency: String,
+}
+
+/// Divergence between a product's active Stripe prices and its active package_prices
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PriceSyncAudit {
+    pub only_in_stripe: Vec<String>,
+    pub only_in_database: Vec<String>,
+    pub mismatched: Vec<PriceMismatch>,
+    pub in_sync: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveResult {
+    pub id: String,
+    pub active: bool,
+    /// Matching packages / package_prices rows that were marked inactive
+    pub database_rows_updated: usize,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProductWithPrices {
     pub id: String,
@@ -69,12 +269,17 @@ pub struct ProductWithPrices {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConnectAccountResponse {
     pub account_id: String,
-    pub onboarding_url: String,
     pub requirements_completed: bool,
     pub charges_enabled: bool,
     pub payouts_enabled: bool,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OnboardingLinkResponse {
+    pub url: String,
+    pub expires_at: i64,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConnectAccountStatus {
     pub account_id: String,
@@ -84,6 +289,16 @@ pub struct ConnectAccountStatus {
     pub requirements_pending: Vec<String>,
     pub requirements_eventually_due: Vec<String>,
     pub requirements_currently_due: Vec<String>,
+    pub disabled_reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectVerificationProgress {
+    pub account_id: String,
+    pub attempt: u32,
+    pub elapsed_secs: u64,
+    pub charges_enabled: bool,
+    pub disabled_reason: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -119,20 +334,223 @@ pub struct KycTosAcceptance {
     pub user_agent: String,
 }
 
-// Initialize Stripe client with secret key from environment or manual input
-fn get_stripe_client() -> Result<Client, String> {
-    // Try multiple sources for environment variables to ensure mobile compatibility
-    let secret_key = get_env_var("STRIPE_SECRET_KEY")?;
+/// Which set of Stripe keys the app talks to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StripeMode {
+    Test,
+    Live,
+}
+
+impl StripeMode {
+    // Debug builds default to test keys so development never touches live data
+    fn default_for_build() -> Self {
+        if cfg!(debug_assertions) { StripeMode::Test } else { StripeMode::Live }
+    }
+
+    fn env_suffix(self) -> &'static str {
+        match self {
+            StripeMode::Test => "TEST",
+            StripeMode::Live => "LIVE",
+        }
+    }
+}
+
+// Mode selected at runtime; None until loaded from settings.store or set explicitly
+static STRIPE_MODE: std::sync::RwLock<Option<StripeMode>> = std::sync::RwLock::new(None);
+
+// Currency used when a request or Stripe price doesn't carry a usable one.
+// None until loaded from settings.store / AURA_DEFAULT_CURRENCY, in which case USD is used.
+static DEFAULT_CURRENCY: std::sync::RwLock<Option<Currency>> = std::sync::RwLock::new(None);
+
+// Purchases recorded while offline wait here until flush_pending_purchases replays them
+const PENDING_PURCHASES_STORE: &str = "pending_purchases.store";
+const PENDING_PURCHASE_FLUSH_INTERVAL_SECS: u64 = 60;
+static PENDING_PURCHASES_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+static PENDING_PURCHASE_FLUSH_RUNNING: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StripeConfigStatus {
+    pub mode: StripeMode,
+    pub configured: bool,
+    pub has_secret_key: bool,
+    pub has_publishable_key: bool,
+    pub has_webhook_secret: bool,
+}
+
+/// Error returned by Stripe commands, tagged by `kind` so the frontend can branch on it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StripeError {
+    /// Stripe keys are missing, so payment features should be hidden
+    NotConfigured { message: String },
+    /// No session, or the access token was rejected - sign in again
+    NotAuthenticated { message: String },
+    /// init_database hasn't stored the Supabase URL or anon key
+    DatabaseNotConfigured { message: String },
+    /// The card was declined or is invalid - show the message to the user, don't retry
+    Card {
+        message: String,
+        code: Option<String>,
+        decline_code: Option<String>,
+    },
+    /// The request was rejected by Stripe (bad ids, missing parameters, etc.)
+    InvalidRequest { message: String },
+    /// Too many requests - safe to retry after a short delay
+    RateLimited { message: String },
+    /// Stripe returned an API or authentication error
+    Api { message: String, http_status: u16 },
+    /// Stripe could not be reached - safe to retry
+    Network { message: String },
+    /// The payment method or other resource belongs to a different user
+    Forbidden { message: String },
+    /// Stopped early by cancel_request
+    Cancelled { message: String },
+    /// The request took longer than its operation's timeout - safe to retry
+    Timeout { message: String },
+    /// Any other failure (database, validation, unexpected Stripe responses)
+    Other { message: String },
+}
+
+impl StripeError {
+    pub(crate) fn not_configured(var_name: &str) -> Self {
+        StripeError::NotConfigured {
+            message: format!("Stripe is not configured: {} is missing", var_name),
+        }
+    }
     
-    if secret_key.is_empty() {
-        return Err("STRIPE_SECRET_KEY is empty".to_string());
+    fn cancelled(what: &str) -> Self {
+        StripeError::Cancelled {
+            message: format!("{} was cancelled", what),
+        }
+    }
+}
+
+crate::errors::command_error!(StripeError {
+    NotConfigured,
+    NotAuthenticated,
+    DatabaseNotConfigured,
+    Card,
+    InvalidRequest,
+    RateLimited,
+    Api,
+    Network,
+    Forbidden,
+    Cancelled,
+    Timeout,
+    Other,
+});
+
+impl From<stripe::StripeError> for StripeError {
+    fn from(error: stripe::StripeError) -> Self {
+        match error {
+            stripe::StripeError::Stripe(request_error) => {
+                let message = request_error.message.clone()
+                    .unwrap_or_else(|| request_error.to_string());
+                match request_error.error_type {
+                    stripe::ErrorType::Card => StripeError::Card {
+                        message,
+                        code: request_error.code.map(|c| c.to_string()),
+                        decline_code: request_error.decline_code,
+                    },
+                    stripe::ErrorType::InvalidRequest | stripe::ErrorType::Validation => {
+                        StripeError::InvalidRequest { message }
+                    },
+                    stripe::ErrorType::RateLimit => StripeError::RateLimited { message },
+                    stripe::ErrorType::Connection => StripeError::Network { message },
+                    _ if request_error.http_status == 429 => StripeError::RateLimited { message },
+                    _ => StripeError::Api {
+                        message,
+                        http_status: request_error.http_status,
+                    },
+                }
+            },
+            stripe::StripeError::ClientError(message) => StripeError::Network { message },
+            stripe::StripeError::Timeout => StripeError::Network {
+                message: "Timed out communicating with Stripe".to_string(),
+            },
+            other => StripeError::Other { message: other.to_string() },
+        }
     }
+}
+
+impl From<crate::database::PaymentMethodError> for StripeError {
+    fn from(error: crate::database::PaymentMethodError) -> Self {
+        match error {
+            crate::database::PaymentMethodError::Forbidden { message } => StripeError::Forbidden { message },
+            crate::database::PaymentMethodError::NotAuthenticated { message } => StripeError::NotAuthenticated { message },
+            crate::database::PaymentMethodError::NotConfigured { message } => StripeError::DatabaseNotConfigured { message },
+            crate::database::PaymentMethodError::Other { message } => StripeError::Other { message },
+        }
+    }
+}
+
+impl From<crate::database::DbConfigError> for StripeError {
+    fn from(error: crate::database::DbConfigError) -> Self {
+        crate::database::DatabaseError::from(error).into()
+    }
+}
+
+impl From<crate::database::DatabaseError> for StripeError {
+    fn from(error: crate::database::DatabaseError) -> Self {
+        match error {
+            crate::database::DatabaseError::Forbidden { message, .. } => StripeError::Forbidden { message },
+            crate::database::DatabaseError::NotAuthenticated { message } => StripeError::NotAuthenticated { message },
+            crate::database::DatabaseError::NotConfigured { message } => StripeError::DatabaseNotConfigured { message },
+            crate::database::DatabaseError::Validation { errors, .. } => StripeError::InvalidRequest {
+                message: errors.into_iter().map(|error| error.message).collect::<Vec<_>>().join("; "),
+            },
+            other => StripeError::Other { message: other.to_string() },
+        }
+    }
+}
+
+// Retries after a 429 before giving up; Stripe's limits are per second, so short waits recover
+const RATE_LIMIT_RETRIES: u32 = 3;
+const RATE_LIMIT_BASE_DELAY_MS: u64 = 500;
+
+fn is_rate_limited(error: &stripe::StripeError) -> bool {
+    matches!(
+        error,
+        stripe::StripeError::Stripe(request_error)
+            if request_error.error_type == stripe::ErrorType::RateLimit || request_error.http_status == 429
+    )
+}
+
+/// Run a Stripe call, retrying with exponential backoff (plus a little jitter so parallel
+/// commands don't retry in lockstep) while Stripe rate limits it. Other errors, and the
+/// last rate limit error once retries run out, are returned as-is.
+pub(crate) async fn with_rate_limit_retry<T, F, Fut>(mut call: F) -> Result<T, stripe::StripeError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, stripe::StripeError>>,
+{
+    let mut attempt = 0;
+    loop {
+        match call().await {
+            Err(e) if is_rate_limited(&e) && attempt < RATE_LIMIT_RETRIES => {
+                let jitter_ms = chrono::Utc::now().timestamp_subsec_millis() as u64 % 250;
+                let delay_ms = RATE_LIMIT_BASE_DELAY_MS * 2u64.pow(attempt) + jitter_ms;
+                crate::diagnostics::record_event("stripe", &format!("Rate limited, retrying in {}ms", delay_ms));
+                tokio::time::sleep(std::time::Duration::from_millis(delay_ms)).await;
+                attempt += 1;
+            },
+            result => return result,
+        }
+    }
+}
+
+// Initialize Stripe client with secret key from environment or manual input
+pub(crate) fn get_stripe_client() -> Result<Client, StripeError> {
+    // Try multiple sources for environment variables to ensure mobile compatibility
+    let secret_key = get_stripe_key("STRIPE_SECRET_KEY")
+        .map_err(|_| StripeError::not_configured("STRIPE_SECRET_KEY"))?;
     
     Ok(Client::new(secret_key))
 }
 
 // Helper function to get environment variables from multiple sources
-fn get_env_var(var_name: &str) -> Result<String, String> {
+pub(crate) fn get_env_var(var_name: &str) -> Result<String, String> {
     // First try runtime environment variable (works on desktop)
     if let Ok(value) = std::env::var(var_name) {
         if !value.is_empty() {
@@ -144,6 +562,18 @@ fn get_env_var(var_name: &str) -> Result<String, String> {
     let compile_time_value = match var_name {
         "STRIPE_SECRET_KEY" => env!("STRIPE_SECRET_KEY"),
         "STRIPE_PUBLISHABLE_KEY" => env!("STRIPE_PUBLISHABLE_KEY"),
+        "STRIPE_WEBHOOK_SECRET" => env!("STRIPE_WEBHOOK_SECRET"),
+        "STRIPE_SECRET_KEY_TEST" => env!("STRIPE_SECRET_KEY_TEST"),
+        "STRIPE_SECRET_KEY_LIVE" => env!("STRIPE_SECRET_KEY_LIVE"),
+        "STRIPE_PUBLISHABLE_KEY_TEST" => env!("STRIPE_PUBLISHABLE_KEY_TEST"),
+        "STRIPE_PUBLISHABLE_KEY_LIVE" => env!("STRIPE_PUBLISHABLE_KEY_LIVE"),
+        "STRIPE_WEBHOOK_SECRET_TEST" => env!("STRIPE_WEBHOOK_SECRET_TEST"),
+        "STRIPE_WEBHOOK_SECRET_LIVE" => env!("STRIPE_WEBHOOK_SECRET_LIVE"),
+        "AURA_DEFAULT_CURRENCY" => env!("AURA_DEFAULT_CURRENCY"),
+        "AURA_HTTP_TIMEOUT_READ_SECS" => env!("AURA_HTTP_TIMEOUT_READ_SECS"),
+        "AURA_HTTP_TIMEOUT_WRITE_SECS" => env!("AURA_HTTP_TIMEOUT_WRITE_SECS"),
+        "AURA_HTTP_TIMEOUT_UPLOAD_SECS" => env!("AURA_HTTP_TIMEOUT_UPLOAD_SECS"),
+        "AURA_HTTP_TIMEOUT_STRIPE_SECS" => env!("AURA_HTTP_TIMEOUT_STRIPE_SECS"),
         _ => "",
     };
     
@@ -173,22 +603,238 @@ fn get_env_var(var_name: &str) -> Result<String, String> {
 
 
 // Get only publishable key for payment method operations (doesn't require product ID)
-fn get_stripe_publishable_key_only() -> Result<String, String> {
-    get_env_var("STRIPE_PUBLISHABLE_KEY")
+fn get_stripe_publishable_key_only() -> Result<String, StripeError> {
+    get_stripe_key("STRIPE_PUBLISHABLE_KEY")
+        .map_err(|_| StripeError::not_configured("STRIPE_PUBLISHABLE_KEY"))
+}
+
+/// Resolve a Stripe key for the current mode, e.g. STRIPE_SECRET_KEY_TEST,
+/// falling back to the mode-less variable for single-key setups
+pub(crate) fn get_stripe_key(base_var: &str) -> Result<String, String> {
+    let mode_var = format!("{}_{}", base_var, current_stripe_mode().env_suffix());
+    get_env_var(&mode_var).or_else(|_| get_env_var(base_var))
 }
 
+pub(crate) fn current_stripe_mode() -> StripeMode {
+    STRIPE_MODE.read()
+        .ok()
+        .and_then(|mode| *mode)
+        .unwrap_or_else(StripeMode::default_for_build)
+}
+
+/// Restore the persisted Stripe mode at startup
+pub(crate) fn load_stripe_mode(app: &tauri::AppHandle) {
+    let stored_mode = app.store("settings.store")
+        .ok()
+        .and_then(|store| store.get("stripe_mode"))
+        .and_then(|value| serde_json::from_value::<StripeMode>(value).ok());
+    
+    if let (Some(mode), Ok(mut current)) = (stored_mode, STRIPE_MODE.write()) {
+        *current = Some(mode);
+    }
+}
+
+#[tauri::command]
+pub async fn get_stripe_mode() -> Result<StripeMode, String> {
+    Ok(current_stripe_mode())
+}
+
+/// Switch between test and live Stripe keys without rebuilding
+#[tauri::command]
+pub async fn set_stripe_mode(
+    mode: StripeMode,
+    app: tauri::AppHandle,
+) -> Result<StripeMode, String> {
+    let store = app.store("settings.store").map_err(|e| e.to_string())?;
+    store.set("stripe_mode", serde_json::json!(mode));
+    store.save().map_err(|e| format!("Failed to save Stripe mode: {}", e))?;
+    
+    let mut current = STRIPE_MODE.write().map_err(|_| "Stripe mode lock poisoned".to_string())?;
+    *current = Some(mode);
+    
+    Ok(mode)
+}
+
+/// Load the default currency at startup: the saved setting wins over AURA_DEFAULT_CURRENCY.
+/// An invalid value is reported and ignored, leaving USD as the fallback.
+pub(crate) fn load_default_currency(app: &tauri::AppHandle) {
+    let stored = app.store("settings.store")
+        .ok()
+        .and_then(|store| store.get("default_currency"))
+        .and_then(|value| value.as_str().map(String::from));
+    
+    let Some(code) = stored.or_else(|| get_env_var("AURA_DEFAULT_CURRENCY").ok()) else {
+        return;
+    };
+    
+    match parse_currency(&code) {
+        Some(currency) => {
+            if let Ok(mut current) = DEFAULT_CURRENCY.write() {
+                *current = Some(currency);
+            }
+        },
+        None => crate::diagnostics::record_event(
+            "stripe",
+            &format!("Default currency '{}' is not a valid currency, falling back to USD", code),
+        ),
+    }
+}
+
+pub(crate) fn default_currency() -> Currency {
+    DEFAULT_CURRENCY.read()
+        .ok()
+        .and_then(|currency| *currency)
+        .unwrap_or(Currency::USD)
+}
+
+fn parse_currency(code: &str) -> Option<Currency> {
+    Currency::from_str(&code.trim().to_lowercase()).ok()
+}
+
+// Unknown or empty codes fall back to the configured default currency
+fn currency_or_default(code: &str) -> Currency {
+    parse_currency(code).unwrap_or_else(default_currency)
+}
+
+// Currencies Stripe charges in whole units, so amounts are not divided by 100
+const ZERO_DECIMAL_CURRENCIES: &[&str] = &[
+    "bif", "clp", "djf", "gnf", "jpy", "kmf", "krw", "mga",
+    "pyg", "rwf", "ugx", "vnd", "vuv", "xaf", "xof", "xpf",
+];
+
+/// Format an amount in the currency's smallest unit for display, e.g. 1499 usd -> "$14.99",
+/// 1500 jpy -> "¥1,500". Unknown currencies are prefixed with their uppercase code.
 #[tauri::command]
-pub async fn get_stripe_publishable_key() -> Result<String, String> {
+pub fn format_amount(amount_cents: i64, currency: String) -> String {
+    let code = currency.trim().to_lowercase();
+    let symbol = match code.as_str() {
+        "usd" => "$".to_string(),
+        "aud" => "A$".to_string(),
+        "cad" => "CA$".to_string(),
+        "nzd" => "NZ$".to_string(),
+        "eur" => "€".to_string(),
+        "gbp" => "£".to_string(),
+        "jpy" => "¥".to_string(),
+        "krw" => "₩".to_string(),
+        "inr" => "₹".to_string(),
+        _ => format!("{} ", code.to_uppercase()),
+    };
+    
+    let sign = if amount_cents < 0 { "-" } else { "" };
+    let amount = amount_cents.unsigned_abs();
+    let (whole, fraction) = if ZERO_DECIMAL_CURRENCIES.contains(&code.as_str()) {
+        (amount, None)
+    } else {
+        (amount / 100, Some(amount % 100))
+    };
+    
+    // Group the whole units in threes: 1234567 -> 1,234,567
+    let digits = whole.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    
+    match fraction {
+        Some(fraction) => format!("{}{}{}.{:02}", sign, symbol, grouped, fraction),
+        None => format!("{}{}{}", sign, symbol, grouped),
+    }
+}
+
+#[tauri::command]
+pub async fn get_default_currency() -> Result<String, String> {
+    Ok(default_currency().to_string())
+}
+
+/// Set the currency used when none is given, e.g. "aud"
+#[tauri::command]
+pub async fn set_default_currency(
+    currency: String,
+    app: tauri::AppHandle,
+) -> Result<String, StripeError> {
+    let parsed = parse_currency(&currency).ok_or_else(|| StripeError::InvalidRequest {
+        message: format!("'{}' is not a supported currency", currency),
+    })?;
+    
+    let store = app.store("settings.store").map_err(|e| e.to_string())?;
+    store.set("default_currency", serde_json::json!(parsed.to_string()));
+    store.save().map_err(|e| format!("Failed to save default currency: {}", e))?;
+    
+    let mut current = DEFAULT_CURRENCY.write().map_err(|_| "Default currency lock poisoned".to_string())?;
+    *current = Some(parsed);
+    
+    Ok(parsed.to_string())
+}
+
+/// Report which Stripe keys are available so the UI can hide payment features
+#[tauri::command]
+pub async fn is_stripe_configured() -> Result<StripeConfigStatus, String> {
+    let has_secret_key = get_stripe_key("STRIPE_SECRET_KEY").is_ok();
+    let has_publishable_key = get_stripe_key("STRIPE_PUBLISHABLE_KEY").is_ok();
+    let has_webhook_secret = get_stripe_key("STRIPE_WEBHOOK_SECRET").is_ok();
+    
+    Ok(StripeConfigStatus {
+        mode: current_stripe_mode(),
+        configured: has_secret_key && has_publishable_key,
+        has_secret_key,
+        has_publishable_key,
+        has_webhook_secret,
+    })
+}
+
+#[tauri::command]
+pub async fn get_stripe_publishable_key() -> Result<String, StripeError> {
     get_stripe_publishable_key_only()
 }
 
-/// Fix existing payment methods by properly attaching them to the customer
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentOutcome {
+    Attached,
+    AlreadyAttached,
+    NotFound,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaymentMethodAttachment {
+    pub payment_method_id: String,
+    pub outcome: AttachmentOutcome,
+    /// Why the method couldn't be attached, for not_found and failed
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaymentMethodAttachmentReport {
+    pub attached: usize,
+    pub already_attached: usize,
+    pub not_found: usize,
+    pub failed: usize,
+    pub methods: Vec<PaymentMethodAttachment>,
+}
+
+fn summarize_attachments(methods: Vec<PaymentMethodAttachment>) -> PaymentMethodAttachmentReport {
+    let count = |outcome: AttachmentOutcome| methods.iter().filter(|m| m.outcome == outcome).count();
+    PaymentMethodAttachmentReport {
+        attached: count(AttachmentOutcome::Attached),
+        already_attached: count(AttachmentOutcome::AlreadyAttached),
+        not_found: count(AttachmentOutcome::NotFound),
+        failed: count(AttachmentOutcome::Failed),
+        methods,
+    }
+}
+
+/// Fix existing payment methods by properly attaching them to the customer,
+/// reporting what happened to each stored method
 #[tauri::command]
 pub async fn fix_payment_method_attachments(
     customer_id: String,
     user_id: String,
     app: tauri::AppHandle,
-) -> Result<String, String> {
+) -> Result<PaymentMethodAttachmentReport, StripeError> {
     let client = get_stripe_client()?;
     
     // Get payment methods from database for this user
@@ -196,9 +842,10 @@ pub async fn fix_payment_method_attachments(
         format!("Failed to get database config: {}", e)
     })?;
     
-    let http_client = reqwest::Client::new();
+    let http_client = crate::http::client();
     let response = http_client
-        .get(&format!("{}/rest/v1/payment_methods", db_config.database_url))
+        .get(format!("{}/rest/v1/payment_methods", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .query(&[("user_id", format!("eq.{}", user_id))])
@@ -207,7 +854,7 @@ pub async fn fix_payment_method_attachments(
         .map_err(|e| format!("Database request failed: {}", e))?;
     
     if !response.status().is_success() {
-        return Err(format!("Database query failed: HTTP {}", response.status()));
+        return Err(format!("Database query failed: HTTP {}", response.status()).into());
     }
     
     let payment_methods: Vec<crate::database::PaymentMethod> = response
@@ -215,63 +862,70 @@ pub async fn fix_payment_method_attachments(
         .await
         .map_err(|e| format!("Failed to parse payment methods: {}", e))?;
     
-    let mut fixed_count = 0;
+    let customer_id_stripe = stripe::CustomerId::from_str(&customer_id).map_err(|e| {
+        format!("Invalid customer ID: {}", e)
+    })?;
+    
+    let mut methods = Vec::new();
     
     for pm in payment_methods {
-        let pm_id = stripe::PaymentMethodId::from_str(&pm.stripe_payment_method_id).map_err(|e| {
-            format!("Invalid payment method ID {}: {}", pm.stripe_payment_method_id, e)
-        })?;
+        let outcome = |outcome: AttachmentOutcome, message: Option<String>| PaymentMethodAttachment {
+            payment_method_id: pm.stripe_payment_method_id.clone(),
+            outcome,
+            message,
+        };
+        
+        let pm_id = match stripe::PaymentMethodId::from_str(&pm.stripe_payment_method_id) {
+            Ok(pm_id) => pm_id,
+            Err(e) => {
+                methods.push(outcome(AttachmentOutcome::Failed, Some(format!("Invalid payment method ID: {}", e))));
+                continue;
+            }
+        };
         
         // Check if payment method exists and get its current state
         let payment_method = match stripe::PaymentMethod::retrieve(&client, &pm_id, &[]).await {
             Ok(pm) => pm,
-            Err(_e) => {
-                // Payment method not found, skip to next one
+            Err(e) => {
+                methods.push(outcome(AttachmentOutcome::NotFound, Some(e.to_string())));
                 continue;
             }
         };
         
         // Attach payment method to customer if not already attached
-        if payment_method.customer.is_none() {
-            let customer_id_stripe = stripe::CustomerId::from_str(&customer_id).map_err(|e| {
-                format!("Invalid customer ID: {}", e)
-            })?;
-            
-            match stripe::PaymentMethod::attach(
-                &client,
-                &pm_id,
-                stripe::AttachPaymentMethod {
-                    customer: customer_id_stripe.clone(),
-                },
-            ).await {
-                Ok(_) => {
-
-                    fixed_count += 1;
+        if payment_method.customer.is_some() {
+            methods.push(outcome(AttachmentOutcome::AlreadyAttached, None));
+            continue;
+        }
+        
+        match stripe::PaymentMethod::attach(
+            &client,
+            &pm_id,
+            stripe::AttachPaymentMethod {
+                customer: customer_id_stripe.clone(),
+            },
+        ).await {
+            Ok(_) => {
+                methods.push(outcome(AttachmentOutcome::Attached, None));
+                
+                // Set as default payment method if it's marked as default in database
+                if pm.is_default {
+                    let mut customer_update = stripe::UpdateCustomer::new();
+                    customer_update.invoice_settings = Some(stripe::CustomerInvoiceSettings {
+                        default_payment_method: Some(pm_id.to_string()),
+                        ..Default::default()
+                    });
                     
-                    // Set as default payment method if it's marked as default in database
-                    if pm.is_default {
-                        let mut customer_update = stripe::UpdateCustomer::new();
-                        customer_update.invoice_settings = Some(stripe::CustomerInvoiceSettings {
-                            default_payment_method: Some(pm_id.to_string()),
-                            ..Default::default()
-                        });
-                        
-                        match stripe::Customer::update(&client, &customer_id_stripe, customer_update).await {
-                            Ok(_) => {},
-                            Err(_) => {},
-                        }
-                    }
-                },
-                Err(_e) => {
-                    // Failed to attach payment method, continue with next one
+                    let _ = stripe::Customer::update(&client, &customer_id_stripe, customer_update).await;
                 }
+            },
+            Err(e) => {
+                methods.push(outcome(AttachmentOutcome::Failed, Some(e.to_string())));
             }
-        } else {
-
         }
     }
     
-    Ok(format!("Fixed {} payment method attachments", fixed_count))
+    Ok(summarize_attachments(methods))
 }
 
 #[tauri::command]
@@ -279,15 +933,10 @@ pub async fn create_payment_intent(
     amount: i64, // Amount in cents
     currency: String,
     customer_id: Option<String>,
-) -> Result<PaymentIntentResponse, String> {
+) -> Result<PaymentIntentResponse, StripeError> {
     let client = get_stripe_client()?;
     
-    let currency_enum = match currency.to_lowercase().as_str() {
-        "usd" => Currency::USD,
-        "eur" => Currency::EUR,
-        "gbp" => Currency::GBP,
-        _ => Currency::USD,
-    };
+    let currency_enum = currency_or_default(&currency);
     let mut params = CreatePaymentIntent::new(amount, currency_enum);
     
     if let Some(customer) = customer_id {
@@ -298,8 +947,7 @@ pub async fn create_payment_intent(
     params.payment_method_types = Some(vec!["card".to_string()]);
     
     let payment_intent = PaymentIntent::create(&client, params)
-        .await
-        .map_err(|e| format!("Failed to create payment intent: {}", e))?;
+        .await?;
 
     Ok(PaymentIntentResponse {
         client_secret: payment_intent.client_secret.unwrap_or_default(),
@@ -311,7 +959,7 @@ pub async fn create_payment_intent(
 pub async fn create_stripe_customer(
     email: String,
     name: Option<String>,
-) -> Result<String, String> {
+) -> Result<String, StripeError> {
     let client = get_stripe_client()?;
     
     let mut params = CreateCustomer::new();
@@ -327,19 +975,73 @@ pub async fn create_stripe_customer(
     Ok(customer.id.to_string())
 }
 
+/// Copy the user's name, email and id from their profile onto their Stripe customer so
+/// the customer can be found from the Stripe dashboard. Returns the customer id, or
+/// None when the user has no Stripe customer yet.
+#[tauri::command]
+pub async fn sync_customer_metadata(
+    user_id: String,
+    app: tauri::AppHandle,
+) -> Result<Option<String>, StripeError> {
+    crate::database::ensure_session_user(&user_id, &app).await?;
+    
+    let profile = crate::database::get_user_profile(user_id.clone(), app.clone()).await?
+        .ok_or("Profile not found")?;
+    let customer_id = match profile.stripe_customer_id {
+        Some(customer_id) => customer_id,
+        None => return Ok(None),
+    };
+    let customer_id_parsed = CustomerId::from_str(&customer_id)
+        .map_err(|e| format!("Invalid customer ID: {}", e))?;
+    
+    let email = crate::database::get_auth_user_email(&user_id, &app).await?;
+    let name = profile.full_name.or(profile.username);
+    
+    let mut metadata = HashMap::new();
+    metadata.insert("user_id".to_string(), user_id.clone());
+    
+    let mut params = stripe::UpdateCustomer::new();
+    params.email = email.as_deref();
+    params.name = name.as_deref();
+    params.metadata = Some(metadata);
+    
+    Customer::update(&get_stripe_client()?, &customer_id_parsed, params).await?;
+    
+    Ok(Some(customer_id))
+}
+
+/// Return the user's Stripe customer, creating it with their account email and linking it
+/// to their profile if they don't have one yet
 #[tauri::command]
 pub async fn initialize_stripe_customer(
     user_id: String,
-) -> Result<String, String> {
-    // For now, we'll create a customer with a placeholder email
-    // In a real implementation, you'd get the email from the user profile
-    let placeholder_email = format!("user+{}@aura.app", user_id);
+    app: tauri::AppHandle,
+) -> Result<String, StripeError> {
+    let profile = crate::database::get_user_profile(user_id.clone(), app.clone()).await?
+        .ok_or("Profile not found")?;
+    if let Some(customer_id) = profile.stripe_customer_id {
+        return Ok(customer_id);
+    }
+    
+    // Only fall back to a placeholder address when the account genuinely has no email
+    let email = match crate::database::get_auth_user_email(&user_id, &app).await {
+        Ok(Some(email)) => email,
+        _ => format!("user+{}@aura.app", user_id),
+    };
     
-    let customer_result = get_or_create_customer(placeholder_email, None).await?;
+    let customer_result = get_or_create_customer(email, profile.full_name.or(profile.username)).await?;
     
     let customer_id = customer_result["id"].as_str()
         .ok_or("Failed to extract customer ID from response")?
         .to_string();
+    
+    crate::database::set_stripe_customer_id(&user_id, &customer_id, &app).await?;
+    
+    // Tag the customer with the user id for dashboard searches; the link is already saved
+    if let Err(e) = sync_customer_metadata(user_id, app).await {
+        crate::diagnostics::record_event("stripe", &format!("Failed to sync Stripe customer metadata: {}", e));
+    }
+    
     Ok(customer_id)
 }
 
@@ -347,7 +1049,7 @@ pub async fn initialize_stripe_customer(
 pub async fn get_or_create_customer(
     email: String,
     name: Option<String>,
-) -> Result<serde_json::Value, String> {
+) -> Result<serde_json::Value, StripeError> {
     let client = get_stripe_client()?;
     
     // First try to find existing customer by email
@@ -355,9 +1057,7 @@ pub async fn get_or_create_customer(
     list_params.email = Some(&email);
     list_params.limit = Some(1);
     
-    let customers = Customer::list(&client, &list_params)
-        .await
-        .map_err(|e| format!("Failed to search for customer: {}", e))?;
+    let customers = with_rate_limit_retry(|| Customer::list(&client, &list_params)).await?;
     
     if let Some(customer) = customers.data.first() {
         // Return existing customer
@@ -375,9 +1075,7 @@ pub async fn get_or_create_customer(
         params.name = Some(customer_name);
     }
     
-    let customer = Customer::create(&client, params)
-        .await
-        .map_err(|e| format!("Failed to create customer: {}", e))?;
+    let customer = with_rate_limit_retry(|| Customer::create(&client, params.clone())).await?;
 
     Ok(serde_json::json!({
         "id": customer.id.to_string(),
@@ -386,12 +1084,50 @@ pub async fn get_or_create_customer(
     }))
 }
 
+// Trial length for a new subscription: the price's trial, or a shorter one if asked
+fn effective_trial_days(requested: Option<u32>, price_trial_days: u32) -> u32 {
+    requested.map_or(price_trial_days, |days| days.min(price_trial_days))
+}
+
 #[tauri::command]
 pub async fn create_subscription(
     user_id: String,
     price_id: String,
+    trial_period_days: Option<u32>,
+    app: tauri::AppHandle,
+) -> Result<SubscriptionResponse, StripeError> {
+    let items = vec![SubscriptionItemRequest { price_id, quantity: Some(1) }];
+    create_subscription_with_items(user_id, items, trial_period_days, app).await
+}
+
+/// Subscribe to a base plan plus add-ons in one subscription. The first item is the base
+/// plan: it decides the trial and is what the profile's subscription_price_id tracks.
+/// `trial_period_days` can only shorten the base plan's trial.
+#[tauri::command]
+pub async fn create_subscription_with_items(
+    user_id: String,
+    items: Vec<SubscriptionItemRequest>,
+    trial_period_days: Option<u32>,
     app: tauri::AppHandle,
-) -> Result<SubscriptionResponse, String> {
+) -> Result<SubscriptionResponse, StripeError> {
+    let price_id = items.first()
+        .map(|item| item.price_id.clone())
+        .ok_or_else(|| StripeError::InvalidRequest {
+            message: "A subscription needs at least one item".to_string(),
+        })?;
+    for (index, item) in items.iter().enumerate() {
+        if item.quantity == Some(0) {
+            return Err(StripeError::InvalidRequest {
+                message: format!("Quantity for {} must be at least 1", item.price_id),
+            });
+        }
+        if items[..index].iter().any(|earlier| earlier.price_id == item.price_id) {
+            return Err(StripeError::InvalidRequest {
+                message: format!("Price {} is listed more than once", item.price_id),
+            });
+        }
+    }
+    
     let client = get_stripe_client()?;
     
     // Get customer ID from user profile
@@ -399,9 +1135,10 @@ pub async fn create_subscription(
         format!("Failed to get database config: {}", e)
     })?;
     
-    let http_client = reqwest::Client::new();
+    let http_client = crate::http::client();
     let profile_response = http_client
-        .get(&format!("{}/rest/v1/profiles", db_config.database_url))
+        .get(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .query(&[("id", format!("eq.{}", user_id))])
@@ -410,7 +1147,7 @@ pub async fn create_subscription(
         .map_err(|e| format!("Failed to fetch user profile: {}", e))?;
     
     if !profile_response.status().is_success() {
-        return Err(format!("Failed to fetch user profile: HTTP {}", profile_response.status()));
+        return Err(format!("Failed to fetch user profile: HTTP {}", profile_response.status()).into());
     }
     
     let profiles: Vec<crate::database::Profile> = profile_response
@@ -427,7 +1164,8 @@ pub async fn create_subscription(
     
     // Get payment methods from database for this user (reuse db_config from above)
     let response = http_client
-        .get(&format!("{}/rest/v1/payment_methods", db_config.database_url))
+        .get(format!("{}/rest/v1/payment_methods", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .query(&[("user_id", format!("eq.{}", user_id))])
@@ -436,7 +1174,7 @@ pub async fn create_subscription(
         .map_err(|e| format!("Database request failed: {}", e))?;
     
     if !response.status().is_success() {
-        return Err(format!("Database query failed: HTTP {}", response.status()));
+        return Err(format!("Database query failed: HTTP {}", response.status()).into());
     }
     
     let payment_methods: Vec<crate::database::PaymentMethod> = response
@@ -445,7 +1183,7 @@ pub async fn create_subscription(
         .map_err(|e| format!("Failed to parse payment methods: {}", e))?;
     
     if payment_methods.is_empty() {
-        return Err("No payment methods found. Please add a payment method first.".to_string());
+        return Err("No payment methods found. Please add a payment method first.".into());
     }
     
     // Find the default payment method or use the first one
@@ -458,9 +1196,7 @@ pub async fn create_subscription(
     })?;
     
     // Retrieve the payment method to check if it's attached
-    let payment_method = stripe::PaymentMethod::retrieve(&client, &pm_id, &[]).await.map_err(|e| {
-        format!("Failed to retrieve payment method: {}", e)
-    })?;
+    let payment_method = stripe::PaymentMethod::retrieve(&client, &pm_id, &[]).await?;
     
     // Attach payment method to customer if not already attached
     if payment_method.customer.is_none() {
@@ -470,9 +1206,7 @@ pub async fn create_subscription(
             stripe::AttachPaymentMethod {
                 customer: customer_id_parsed.clone(),
             },
-        ).await.map_err(|e| {
-            format!("Failed to attach payment method to customer: {}", e)
-        })?;
+        ).await?;
     }
     
     // Set as default payment method for the customer
@@ -482,30 +1216,39 @@ pub async fn create_subscription(
         ..Default::default()
     });
     
-    stripe::Customer::update(&client, &customer_id_parsed, customer_update).await.map_err(|e| {
-        format!("Failed to set default payment method: {}", e)
-    })?;
+    stripe::Customer::update(&client, &customer_id_parsed, customer_update).await?;
     
     // Now create the subscription with the properly attached payment method
     let payment_method_id_str = pm_id.to_string();
     let mut params = CreateSubscription::new(customer_id_parsed);
-    params.items = Some(vec![CreateSubscriptionItems {
-        price: Some(price_id.clone()),
-        quantity: Some(1),
-        ..Default::default()
-    }]);
+    params.items = Some(items.iter()
+        .map(|item| CreateSubscriptionItems {
+            price: Some(item.price_id.clone()),
+            quantity: Some(item.quantity.unwrap_or(1)),
+            ..Default::default()
+        })
+        .collect());
     
     // Explicitly specify the default payment method
     params.default_payment_method = Some(&payment_method_id_str);
     
+    // Start with a free trial when the price has one. The caller can shorten or skip it,
+    // but never make it longer than the price allows.
+    let price_trial_days = crate::database::get_subscription_price(&price_id, &app).await?
+        .map(|price| price.trial_period_days.max(0) as u32)
+        .unwrap_or(0);
+    let trial_period_days = effective_trial_days(trial_period_days, price_trial_days);
+    if trial_period_days > 0 {
+        params.trial_period_days = Some(trial_period_days);
+    }
+    
     // Add metadata to link subscription to user
     let mut metadata = HashMap::new();
     metadata.insert("user_id".to_string(), user_id.clone());
     params.metadata = Some(metadata);
     
     let subscription = Subscription::create(&client, params)
-        .await
-        .map_err(|e| format!("Failed to create subscription: {}", e))?;
+        .await?;
 
     // Update user profile in Supabase with subscription info
     let subscription_status = subscription.status.to_string();
@@ -513,13 +1256,31 @@ pub async fn create_subscription(
     
     // Use existing database module to update user profile
     crate::database::update_subscription_status(
-        user_id,
+        user_id.clone(),
         customer_id.clone(),
         subscription.id.to_string(),
         subscription_status.clone(),
         current_period_end,
-        app,
+        app.clone(),
     ).await?;
+    crate::database::update_subscription_trial_end(&user_id, subscription.trial_end, &app).await?;
+    crate::database::update_subscription_price(&user_id, &price_id, &app).await?;
+    
+    let item_responses = subscription_item_responses(&subscription);
+    crate::database::update_subscription_items(
+        &user_id,
+        serde_json::to_value(&item_responses).unwrap_or_default(),
+        &app,
+    ).await?;
+    
+    let _ = crate::database::record_subscription_event(
+        &user_id,
+        subscription.id.as_str(),
+        "created",
+        profile.subscription_price_id.as_deref(),
+        Some(&price_id),
+        &app,
+    ).await;
 
     Ok(SubscriptionResponse {
         subscription_id: subscription.id.to_string(),
@@ -527,29 +1288,291 @@ pub async fn create_subscription(
         status: subscription_status,
         current_period_end,
         price_id: price_id.clone(),
+        trial_end: subscription.trial_end,
+        items: item_responses,
+    })
+}
+
+/// Pay for one period of a subscription on behalf of another user.
+/// The gifter's default card is charged directly; the recipient's subscription is
+/// created on their own customer, marked paid out of band and set to end with the period.
+#[tauri::command]
+pub async fn create_gift_subscription(
+    gifter_user_id: String,
+    recipient_user_id: String,
+    price_id: String,
+    app: tauri::AppHandle,
+) -> Result<GiftSubscriptionResponse, StripeError> {
+    if gifter_user_id == recipient_user_id {
+        return Err("You cannot gift a subscription to yourself".into());
+    }
+    // The gifter's saved card is charged, so it must be the caller's own
+    crate::database::ensure_session_user(&gifter_user_id, &app).await?;
+    
+    let client = get_stripe_client()?;
+    
+    // Resolve the gifter's customer and default card
+    let gifter = crate::database::get_user_profile(gifter_user_id.clone(), app.clone()).await?
+        .ok_or("Gifter profile not found")?;
+    let gifter_customer_id: CustomerId = gifter.stripe_customer_id
+        .ok_or("You need a saved payment method before gifting a subscription")?
+        .parse()
+        .map_err(|_| "Invalid customer ID".to_string())?;
+    
+    let payment_methods = crate::database::get_user_payment_methods(gifter_user_id.clone(), app.clone()).await?;
+    let default_pm = payment_methods.iter().find(|pm| pm.is_default)
+        .or_else(|| payment_methods.first())
+        .ok_or("No payment methods found. Please add a payment method first.")?;
+    let pm_id = stripe::PaymentMethodId::from_str(&default_pm.stripe_payment_method_id).map_err(|e| {
+        format!("Invalid payment method ID {}: {}", default_pm.stripe_payment_method_id, e)
+    })?;
+    
+    // Resolve the recipient, creating their Stripe customer if they have never paid
+    let recipient = crate::database::get_user_profile(recipient_user_id.clone(), app.clone()).await?
+        .ok_or_else(|| StripeError::InvalidRequest {
+            message: format!("Recipient {} not found", recipient_user_id),
+        })?;
+    let recipient_customer_id = match recipient.stripe_customer_id {
+        Some(customer_id) => customer_id,
+        None => {
+            let mut params = CreateCustomer::new();
+            params.name = recipient.full_name.as_deref();
+            let mut metadata = HashMap::new();
+            metadata.insert("user_id".to_string(), recipient_user_id.clone());
+            params.metadata = Some(metadata);
+            Customer::create(&client, params).await?.id.to_string()
+        },
+    };
+    let recipient_customer_parsed: CustomerId = recipient_customer_id.parse()
+        .map_err(|_| "Invalid customer ID".to_string())?;
+    
+    let stripe_price_id = stripe::PriceId::from_str(&price_id)
+        .map_err(|e| format!("Invalid Stripe price ID: {}", e))?;
+    let price = Price::retrieve(&client, &stripe_price_id, &[]).await?;
+    let amount = price.unit_amount.ok_or("Price has no fixed unit amount")?;
+    let currency = price.currency.ok_or("Price has no currency")?;
+    
+    // Charge the gifter for the first period
+    let mut gift_metadata = HashMap::new();
+    gift_metadata.insert("gifter_user_id".to_string(), gifter_user_id.clone());
+    gift_metadata.insert("recipient_user_id".to_string(), recipient_user_id.clone());
+    gift_metadata.insert("price_id".to_string(), price_id.clone());
+    
+    let mut intent_params = CreatePaymentIntent::new(amount, currency);
+    intent_params.customer = Some(gifter_customer_id);
+    intent_params.payment_method = Some(pm_id);
+    intent_params.confirm = Some(true);
+    intent_params.off_session = Some(stripe::PaymentIntentOffSession::Exists(true));
+    intent_params.metadata = Some(gift_metadata.clone());
+    
+    let payment_intent = PaymentIntent::create(&client, intent_params).await?;
+    if payment_intent.status != stripe::PaymentIntentStatus::Succeeded {
+        return Err(format!("Gift payment not successful. Status: {:?}", payment_intent.status).into());
+    }
+    
+    let subscription = match create_prepaid_gift_subscription(
+        &client,
+        recipient_customer_parsed,
+        &price_id,
+        &payment_intent.id,
+        gift_metadata,
+    ).await {
+        Ok(subscription) => subscription,
+        Err(e) => {
+            // Don't keep the gifter's money if the recipient never got the subscription
+            refund_gift_payment(&client, &payment_intent.id).await;
+            return Err(e);
+        },
+    };
+    
+    let subscription_status = subscription.status.to_string();
+    let current_period_end = subscription.current_period_end;
+    let currency = currency.to_string();
+    
+    let gift = SubscriptionGift {
+        recipient_user_id: &recipient_user_id,
+        subscription_id: subscription.id.as_str(),
+        payment_intent_id: payment_intent.id.as_str(),
+        price_id: &price_id,
+        amount_paid: amount,
+        currency: &currency,
+        recipient_customer_id: &recipient_customer_id,
+        subscription_status: &subscription_status,
+        current_period_end,
+    };
+    if let Err(e) = record_subscription_gift(&gift, &app).await {
+        // The recipient would never see the subscription, so undo it and the charge
+        let _ = cancel_subscription_now(subscription.id.as_str()).await;
+        refund_gift_payment(&client, &payment_intent.id).await;
+        return Err(e.into());
+    }
+    
+    Ok(GiftSubscriptionResponse {
+        subscription_id: subscription.id.to_string(),
+        recipient_customer_id,
+        payment_intent_id: payment_intent.id.to_string(),
+        status: subscription_status,
+        current_period_end,
+        price_id,
     })
 }
 
+/// Create the recipient's subscription and settle its first invoice against the gift payment
+async fn create_prepaid_gift_subscription(
+    client: &Client,
+    customer_id: CustomerId,
+    price_id: &str,
+    payment_intent_id: &stripe::PaymentIntentId,
+    mut metadata: HashMap<String, String>,
+) -> Result<Subscription, StripeError> {
+    metadata.insert("gift_payment_intent_id".to_string(), payment_intent_id.to_string());
+    
+    let mut params = CreateSubscription::new(customer_id);
+    params.items = Some(vec![CreateSubscriptionItems {
+        price: Some(price_id.to_string()),
+        quantity: Some(1),
+        ..Default::default()
+    }]);
+    // The recipient has no card on file, so invoice instead of charging and stop after this period
+    params.collection_method = Some(stripe::CollectionMethod::SendInvoice);
+    params.days_until_due = Some(1);
+    params.cancel_at_period_end = Some(true);
+    params.metadata = Some(metadata);
+    
+    let subscription = Subscription::create(client, params).await?;
+    
+    let invoice_id = match &subscription.latest_invoice {
+        Some(stripe::Expandable::Id(id)) => id.clone(),
+        Some(stripe::Expandable::Object(invoice)) => invoice.id.clone(),
+        None => return Err("Gift subscription has no invoice".into()),
+    };
+    
+    let invoice = stripe::Invoice::retrieve(client, &invoice_id, &[]).await?;
+    if invoice.status == Some(stripe::InvoiceStatus::Draft) {
+        stripe::Invoice::finalize(
+            client,
+            &invoice_id,
+            stripe::FinalizeInvoiceParams { auto_advance: Some(false) },
+        ).await?;
+    }
+    
+    // Invoice::pay has no parameters, so post paid_out_of_band directly
+    client.post_form::<stripe::Invoice, _>(
+        &format!("/invoices/{}/pay", invoice_id),
+        [("paid_out_of_band", true)],
+    ).await?;
+    
+    // Re-read so the returned status reflects the paid invoice
+    Ok(Subscription::retrieve(client, &subscription.id, &[]).await?)
+}
+
+// Gift details written by the record_subscription_gift RPC
+struct SubscriptionGift<'a> {
+    recipient_user_id: &'a str,
+    subscription_id: &'a str,
+    payment_intent_id: &'a str,
+    price_id: &'a str,
+    amount_paid: i64,
+    currency: &'a str,
+    recipient_customer_id: &'a str,
+    subscription_status: &'a str,
+    current_period_end: i64,
+}
+
+// Store the gift relationship so both users can see it in their history. The gifter
+// can't write the recipient's profile under RLS, so the gift row and the recipient's
+// subscription fields go through one SECURITY DEFINER function.
+async fn record_subscription_gift(gift: &SubscriptionGift<'_>, app: &tauri::AppHandle) -> Result<(), String> {
+    let db_config = crate::database::get_authenticated_db(app).await?;
+    let http_client = crate::http::client();
+    
+    let response = http_client
+        .post(format!("{}/rest/v1/rpc/record_subscription_gift", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .json(&serde_json::json!({
+            "p_recipient_user_id": gift.recipient_user_id,
+            "p_stripe_subscription_id": gift.subscription_id,
+            "p_stripe_payment_intent_id": gift.payment_intent_id,
+            "p_stripe_price_id": gift.price_id,
+            "p_amount_paid": gift.amount_paid,
+            "p_currency": gift.currency,
+            "p_recipient_customer_id": gift.recipient_customer_id,
+            "p_subscription_status": gift.subscription_status,
+            "p_subscription_period_end": gift.current_period_end
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to record subscription gift: {}", e))?;
+    
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to record subscription gift: {} - {}", status, error_text));
+    }
+    
+    Ok(())
+}
+
+// Best effort: a failed refund is logged so support can issue it by hand
+async fn refund_gift_payment(client: &Client, payment_intent_id: &stripe::PaymentIntentId) {
+    let mut refund_params = stripe::CreateRefund::new();
+    refund_params.payment_intent = Some(payment_intent_id.clone());
+    if let Err(e) = stripe::Refund::create(client, refund_params).await {
+        crate::diagnostics::record_event(
+            "stripe",
+            &format!("Failed to refund gift payment {}: {}", payment_intent_id, e),
+        );
+    }
+}
+
 #[tauri::command]
 pub async fn cancel_subscription(
     subscription_id: String,
     user_id: String,
     app: tauri::AppHandle,
-) -> Result<String, String> {
+) -> Result<String, StripeError> {
     let client = get_stripe_client()?;
     
     // Cancel the subscription at period end
-    let mut params = UpdateSubscription::default();
-    params.cancel_at_period_end = Some(true);
+    let params = UpdateSubscription {
+        cancel_at_period_end: Some(true),
+        ..Default::default()
+    };
     
     let subscription = Subscription::update(&client, &subscription_id.parse().map_err(|_| "Invalid subscription ID".to_string())?, params)
         .await
         .map_err(|e| format!("Failed to cancel subscription: {}", e))?;
 
+    record_cancellation(user_id, subscription_id, &subscription, app).await?;
+
+    Ok("Subscription canceled successfully".to_string())
+}
+
+// Add the cancellation to the billing history (best effort) and mark the profile canceled
+async fn record_cancellation<R: tauri::Runtime>(
+    user_id: String,
+    subscription_id: String,
+    subscription: &Subscription,
+    app: tauri::AppHandle<R>,
+) -> Result<(), String> {
+    let _ = crate::database::record_subscription_event(
+        &user_id,
+        &subscription_id,
+        "canceled",
+        subscription.items.data.first()
+            .and_then(|item| item.price.as_ref())
+            .map(|price| price.id.as_str()),
+        None,
+        &app,
+    ).await;
+
     // Update user profile in Supabase
     crate::database::update_subscription_status(
         user_id,
-        match subscription.customer {
+        match &subscription.customer {
             stripe::Expandable::Id(id) => id.to_string(),
             stripe::Expandable::Object(customer) => customer.id.to_string(),
         },
@@ -557,15 +1580,92 @@ pub async fn cancel_subscription(
         "canceled".to_string(),
         subscription.current_period_end,
         app,
-    ).await?;
+    ).await
+}
 
-    Ok("Subscription canceled successfully".to_string())
+// Cancel a subscription right away instead of at period end, e.g. when the account is closed.
+// Already-ended subscriptions count as canceled.
+pub(crate) async fn cancel_subscription_now(subscription_id: &str) -> Result<(), StripeError> {
+    let client = get_stripe_client()?;
+    let subscription_id: stripe::SubscriptionId = subscription_id.parse()
+        .map_err(|_| "Invalid subscription ID".to_string())?;
+
+    let subscription = Subscription::retrieve(&client, &subscription_id, &[]).await?;
+    if matches!(
+        subscription.status,
+        stripe::SubscriptionStatus::Canceled | stripe::SubscriptionStatus::IncompleteExpired
+    ) {
+        return Ok(());
+    }
+
+    Subscription::cancel(&client, &subscription_id, stripe::CancelSubscription::new()).await?;
+    Ok(())
+}
+
+/// Report usage for a metered subscription item. `timestamp` is a unix time in seconds
+/// within the current billing period; it defaults to now.
+#[tauri::command]
+pub async fn report_usage(
+    subscription_item_id: String,
+    quantity: i64,
+    timestamp: Option<i64>,
+) -> Result<UsageRecordResponse, StripeError> {
+    if quantity < 0 {
+        return Err(StripeError::InvalidRequest {
+            message: format!("Usage quantity must not be negative, got {}", quantity),
+        });
+    }
+
+    let client = get_stripe_client()?;
+    let item_id: stripe::SubscriptionItemId = subscription_item_id.parse()
+        .map_err(|_| "Invalid subscription item ID".to_string())?;
+
+    let params = stripe::CreateUsageRecord {
+        quantity: quantity as u64,
+        action: Some(stripe::UsageRecordAction::Increment),
+        timestamp,
+    };
+
+    let record = stripe::UsageRecord::create(&client, &item_id, params).await?;
+
+    Ok(UsageRecordResponse {
+        usage_record_id: record.id.to_string(),
+        subscription_item_id: record.subscription_item,
+        quantity: record.quantity,
+        timestamp: record.timestamp,
+    })
+}
+
+/// Find the subscription item that usage should be reported against, i.e. the one
+/// whose price is metered
+#[tauri::command]
+pub async fn get_metered_subscription_item(
+    subscription_id: String,
+) -> Result<String, StripeError> {
+    let client = get_stripe_client()?;
+
+    let subscription = Subscription::retrieve(&client, &subscription_id.parse().map_err(|_| "Invalid subscription ID".to_string())?, &[])
+        .await?;
+
+    metered_subscription_item(&subscription).ok_or_else(|| StripeError::InvalidRequest {
+        message: format!("Subscription {} has no metered price", subscription_id),
+    })
+}
+
+fn metered_subscription_item(subscription: &Subscription) -> Option<String> {
+    subscription.items.data.iter()
+        .find(|item| {
+            item.price.as_ref()
+                .and_then(|price| price.recurring.as_ref())
+                .is_some_and(|recurring| recurring.usage_type == stripe::RecurringUsageType::Metered)
+        })
+        .map(|item| item.id.to_string())
 }
 
 #[tauri::command]
 pub async fn get_subscription_status(
     subscription_id: String,
-) -> Result<SubscriptionResponse, String> {
+) -> Result<SubscriptionResponse, StripeError> {
     let client = get_stripe_client()?;
     
     let subscription = Subscription::retrieve(&client, &subscription_id.parse().map_err(|_| "Invalid subscription ID".to_string())?, &[])
@@ -577,6 +1677,7 @@ pub async fn get_subscription_status(
         .and_then(|item| item.price.as_ref())
         .map(|price| price.id.to_string())
         .unwrap_or_else(|| "unknown".to_string());
+    let items = subscription_item_responses(&subscription);
 
     Ok(SubscriptionResponse {
         subscription_id: subscription.id.to_string(),
@@ -587,24 +1688,169 @@ pub async fn get_subscription_status(
         status: subscription.status.to_string(),
         current_period_end: subscription.current_period_end,
         price_id,
+        trial_end: subscription.trial_end,
+        items,
+    })
+}
+
+/// Retry progress for the user's subscription when its latest invoice is still unpaid,
+/// or None for a healthy subscription (or no subscription at all)
+#[tauri::command]
+pub async fn get_dunning_state(
+    user_id: String,
+    app: tauri::AppHandle,
+) -> Result<Option<DunningState>, StripeError> {
+    crate::database::ensure_session_user(&user_id, &app).await?;
+    
+    let profile = crate::database::get_user_profile(user_id, app).await?
+        .ok_or("Profile not found")?;
+    let Some(subscription_id) = profile.subscription_id else {
+        return Ok(None);
+    };
+    
+    let client = get_stripe_client()?;
+    let subscription_id: stripe::SubscriptionId = subscription_id.parse()
+        .map_err(|_| "Invalid subscription ID".to_string())?;
+    let subscription = Subscription::retrieve(&client, &subscription_id, &["latest_invoice"]).await?;
+    
+    if !matches!(
+        subscription.status,
+        stripe::SubscriptionStatus::PastDue | stripe::SubscriptionStatus::Unpaid
+    ) {
+        return Ok(None);
+    }
+    
+    let invoice = match subscription.latest_invoice {
+        Some(stripe::Expandable::Object(invoice)) => *invoice,
+        Some(stripe::Expandable::Id(invoice_id)) => stripe::Invoice::retrieve(&client, &invoice_id, &[]).await?,
+        None => return Ok(None),
+    };
+    
+    Ok(dunning_state_from_invoice(subscription.id.as_str(), &invoice))
+}
+
+// Stripe clears next_payment_attempt on an open invoice once it has no retries left
+fn dunning_state_from_invoice(subscription_id: &str, invoice: &stripe::Invoice) -> Option<DunningState> {
+    if invoice.status != Some(stripe::InvoiceStatus::Open) {
+        return None;
+    }
+    
+    Some(DunningState {
+        subscription_id: subscription_id.to_string(),
+        invoice_id: invoice.id.to_string(),
+        attempt_count: invoice.attempt_count.unwrap_or(0),
+        next_payment_attempt: invoice.next_payment_attempt,
+        final_attempt: invoice.next_payment_attempt.is_none(),
     })
 }
 
+/// Next renewal date, amount and plan for the signed-in user's subscription in one call,
+/// or None for users without a live subscription. The amount comes from Stripe's upcoming
+/// invoice, so it includes proration and discounts.
+#[tauri::command]
+pub async fn get_renewal_summary(
+    app: tauri::AppHandle,
+) -> Result<Option<RenewalSummary>, StripeError> {
+    let user = crate::session::get_auth_user(app.clone()).await?;
+    let profile = crate::database::get_user_profile(user.id, app.clone()).await?
+        .ok_or("Profile not found")?;
+    let Some(subscription_id) = profile.subscription_id else {
+        return Ok(None);
+    };
+
+    let client = get_stripe_client()?;
+    let subscription_id: stripe::SubscriptionId = subscription_id.parse()
+        .map_err(|_| "Invalid subscription ID".to_string())?;
+    let subscription = with_rate_limit_retry(|| Subscription::retrieve(&client, &subscription_id, &[])).await?;
+
+    if matches!(
+        subscription.status,
+        stripe::SubscriptionStatus::Canceled | stripe::SubscriptionStatus::IncompleteExpired
+    ) {
+        return Ok(None);
+    }
+
+    // Stripe has no upcoming invoice for a subscription that is set to cancel
+    let upcoming = if subscription.cancel_at_period_end {
+        None
+    } else {
+        client.get_query::<stripe::Invoice, _>(
+            "/invoices/upcoming",
+            [("subscription", subscription.id.as_str())],
+        ).await.ok()
+    };
+
+    let price_id = subscription.items.data.first()
+        .and_then(|item| item.price.as_ref())
+        .map(|price| price.id.to_string());
+
+    // Profiles subscribed before plan tracking only have the price
+    let plan_id = match (profile.subscription_plan_id, &price_id) {
+        (Some(plan_id), _) => Some(plan_id),
+        (None, Some(price_id)) => crate::database::get_subscription_price(price_id, &app).await?
+            .map(|price| price.subscription_plan_id),
+        (None, None) => None,
+    };
+    let plan_name = match plan_id {
+        Some(plan_id) => crate::database::get_subscription_plan(&plan_id, &app).await?.map(|plan| plan.name),
+        None => None,
+    };
+
+    Ok(Some(renewal_summary(&subscription, upcoming.as_ref(), price_id, plan_name)))
+}
+
+// Without an upcoming invoice the amount falls back to the items' list prices, except
+// for a subscription that is set to cancel, which won't be charged again
+fn renewal_summary(
+    subscription: &Subscription,
+    upcoming: Option<&stripe::Invoice>,
+    price_id: Option<String>,
+    plan_name: Option<String>,
+) -> RenewalSummary {
+    let (amount_cents, currency) = match upcoming {
+        Some(invoice) => (invoice.amount_due, invoice.currency.map(|currency| currency.to_string())),
+        None if subscription.cancel_at_period_end => (None, None),
+        None => {
+            let prices: Vec<(&stripe::Price, u64)> = subscription.items.data.iter()
+                .filter_map(|item| item.price.as_ref().map(|price| (price, item.quantity.unwrap_or(1))))
+                .collect();
+            let amount = prices.iter()
+                .map(|(price, quantity)| price.unit_amount.map(|unit| unit * *quantity as i64))
+                .sum::<Option<i64>>();
+            let currency = prices.first()
+                .and_then(|(price, _)| price.currency)
+                .map(|currency| currency.to_string());
+            (amount, currency)
+        },
+    };
+
+    RenewalSummary {
+        subscription_id: subscription.id.to_string(),
+        status: subscription.status.to_string(),
+        renews_at: subscription.current_period_end,
+        cancel_at_period_end: subscription.cancel_at_period_end,
+        amount_cents,
+        currency,
+        price_id,
+        plan_name,
+    }
+}
+
 #[tauri::command]
 pub async fn sync_subscription_status(
     user_id: String,
     subscription_id: String,
     app: tauri::AppHandle,
-) -> Result<SubscriptionResponse, String> {
+) -> Result<SubscriptionResponse, StripeError> {
     let client = get_stripe_client()?;
     
     // Get latest subscription status from Stripe
-    let subscription = Subscription::retrieve(&client, &subscription_id.parse().map_err(|_| "Invalid subscription ID".to_string())?, &[])
-        .await
-        .map_err(|e| format!("Failed to retrieve subscription: {}", e))?;
+    let parsed_subscription_id: stripe::SubscriptionId = subscription_id.parse()
+        .map_err(|_| "Invalid subscription ID".to_string())?;
+    let subscription = with_rate_limit_retry(|| Subscription::retrieve(&client, &parsed_subscription_id, &[])).await?;
 
     // Update user profile with latest subscription status
-    let customer_id = match subscription.customer {
+    let customer_id = match &subscription.customer {
         stripe::Expandable::Id(id) => id.to_string(),
         stripe::Expandable::Object(customer) => customer.id.to_string(),
     };
@@ -623,6 +1869,7 @@ pub async fn sync_subscription_status(
         .and_then(|item| item.price.as_ref())
         .map(|price| price.id.to_string())
         .unwrap_or_else(|| "unknown".to_string());
+    let items = subscription_item_responses(&subscription);
 
     Ok(SubscriptionResponse {
         subscription_id: subscription.id.to_string(),
@@ -630,6 +1877,8 @@ pub async fn sync_subscription_status(
         status: subscription.status.to_string(),
         current_period_end: subscription.current_period_end,
         price_id,
+        trial_end: subscription.trial_end,
+        items,
     })
 }
 
@@ -637,7 +1886,7 @@ pub async fn sync_subscription_status(
 pub async fn sync_all_user_subscriptions(
     user_id: String,
     app: tauri::AppHandle,
-) -> Result<SubscriptionSyncResult, String> {
+) -> Result<SubscriptionSyncResult, StripeError> {
     // Get user's current profile to find their subscription
     let profile = crate::database::get_user_profile(user_id.clone(), app.clone()).await
         .map_err(|e| format!("Failed to get user profile: {}", e))?
@@ -662,11 +1911,47 @@ pub async fn sync_all_user_subscriptions(
 
 
 
+/// Fetch one event from Stripe and run it through the webhook dispatcher again, e.g. after
+/// fixing a handler that failed on it. Admin only. The event carries the object as it was
+/// when the event fired, so replaying re-applies that state. Events older than Stripe's
+/// retention window can't be fetched and are rejected up front.
+#[tauri::command]
+pub async fn replay_event(
+    event_id: String,
+    app: tauri::AppHandle,
+) -> Result<crate::webhooks::WebhookHandlingResult, StripeError> {
+    crate::database::assert_admin(&app).await?;
+
+    let event_id = event_id.trim();
+    if !event_id.starts_with("evt_") {
+        return Err(StripeError::InvalidRequest {
+            message: format!("Invalid event ID: {}", event_id),
+        });
+    }
+
+    let client = get_stripe_client()?;
+    let path = format!("/events/{}", event_id);
+    let event: stripe::Event = with_rate_limit_retry(|| client.get(&path)).await?;
+
+    let age = chrono::Utc::now().timestamp() - event.created;
+    if age > STRIPE_EVENT_RETENTION_DAYS * 24 * 60 * 60 {
+        return Err(StripeError::InvalidRequest {
+            message: format!(
+                "Event {} is older than Stripe's {}-day event retention and can't be replayed",
+                event_id, STRIPE_EVENT_RETENTION_DAYS
+            ),
+        });
+    }
+
+    Ok(crate::webhooks::dispatch_stripe_event(event, app).await?)
+}
+
+
 // Fetch product with its associated prices
 #[tauri::command]
 pub async fn get_product_with_prices(
     product_id: String,
-) -> Result<ProductWithPrices, String> {
+) -> Result<ProductWithPrices, StripeError> {
     let client = get_stripe_client()?;
     
     // Get the product
@@ -699,7 +1984,7 @@ pub async fn get_product_with_prices(
         product_prices.push(ProductPrice {
             id: price.id.to_string(),
             amount: price.unit_amount.unwrap_or(0),
-            currency: price.currency.map(|c| c.to_string()).unwrap_or("usd".to_string()),
+            currency: price.currency.unwrap_or_else(default_currency).to_string(),
             interval,
             interval_count,
         });
@@ -720,7 +2005,7 @@ pub async fn create_price_for_product(
     amount: i64, // Amount in cents
     currency: String,
     interval: String, // "month" or "year"
-) -> Result<String, String> {
+) -> Result<String, StripeError> {
     let client = get_stripe_client()?;
     
     let mut params = CreatePrice::new(currency.parse().map_err(|_| "Invalid currency".to_string())?);
@@ -730,7 +2015,7 @@ pub async fn create_price_for_product(
         interval: match interval.as_str() {
             "month" => CreatePriceRecurringInterval::Month,
             "year" => CreatePriceRecurringInterval::Year,
-            _ => return Err("Invalid interval. Use 'month' or 'year'".to_string()),
+            _ => return Err("Invalid interval. Use 'month' or 'year'".into()),
         },
         ..Default::default()
     });
@@ -750,7 +2035,7 @@ pub async fn setup_stripe_product(
     amount: i64, // Amount in cents
     currency: String,
     interval: String, // "month" or "year"
-) -> Result<String, String> {
+) -> Result<String, StripeError> {
     let client = get_stripe_client()?;
     
     // Create product
@@ -762,12 +2047,7 @@ pub async fn setup_stripe_product(
         .map_err(|e| format!("Failed to create product: {}", e))?;
 
     // Create price
-    let currency_enum = match currency.to_lowercase().as_str() {
-        "usd" => Currency::USD,
-        "eur" => Currency::EUR,
-        "gbp" => Currency::GBP,
-        _ => Currency::USD,
-    };
+    let currency_enum = currency_or_default(&currency);
     let mut price_params = CreatePrice::new(currency_enum);
     let product_id_str = product.id.to_string();
     price_params.product = Some(IdOrCreate::Id(&product_id_str));
@@ -779,17 +2059,113 @@ pub async fn setup_stripe_product(
         _ => CreatePriceRecurringInterval::Month,
     };
     
-    price_params.recurring = Some(CreatePriceRecurring {
-        interval: interval_enum,
-        interval_count: Some(1),
-        ..Default::default()
-    });
+    price_params.recurring = Some(CreatePriceRecurring {
+        interval: interval_enum,
+        interval_count: Some(1),
+        ..Default::default()
+    });
+    
+    let price = Price::create(&client, price_params)
+        .await
+        .map_err(|e| format!("Failed to create price: {}", e))?;
+
+    Ok(format!("Product created successfully. Price ID: {}", price.id))
+}
+
+/// Create a product with several prices in one call, e.g. monthly and yearly plans or
+/// token tiers. With `sync_to_database` the prices are also synced into package_prices,
+/// which requires a package row with this product's stripe_product_id. Admin only. If a
+/// price can't be created the product is archived so no half-built product is left for sale.
+#[tauri::command]
+pub async fn setup_product_with_prices(
+    name: String,
+    description: String,
+    prices: Vec<ProductPriceInput>,
+    sync_to_database: Option<bool>,
+    app: tauri::AppHandle,
+) -> Result<ProductSetupResponse, StripeError> {
+    crate::database::assert_admin(&app).await?;
+    
+    if prices.is_empty() {
+        return Err(StripeError::InvalidRequest {
+            message: "At least one price is required".to_string(),
+        });
+    }
+    
+    // Validate everything up front so bad input doesn't leave a half-created product
+    let mut intervals = Vec::with_capacity(prices.len());
+    for price in &prices {
+        if price.amount < 0 {
+            return Err(StripeError::InvalidRequest {
+                message: format!("Price amount must not be negative, got {}", price.amount),
+            });
+        }
+        let interval = match price.interval.to_lowercase().as_str() {
+            "month" => Some(CreatePriceRecurringInterval::Month),
+            "year" => Some(CreatePriceRecurringInterval::Year),
+            "one_time" => None,
+            other => return Err(StripeError::InvalidRequest {
+                message: format!("Unsupported price interval '{}'", other),
+            }),
+        };
+        intervals.push(interval);
+    }
+    
+    let client = get_stripe_client()?;
+    
+    let mut product_params = CreateProduct::new(&name);
+    product_params.description = Some(&description);
+    
+    let product = Product::create(&client, product_params).await?;
+    let product_id = product.id.to_string();
+    
+    let mut price_ids = Vec::with_capacity(prices.len());
+    for (price, interval) in prices.iter().zip(intervals) {
+        let mut price_params = CreatePrice::new(currency_or_default(&price.currency));
+        price_params.product = Some(IdOrCreate::Id(&product_id));
+        price_params.unit_amount = Some(price.amount);
+        price_params.recurring = interval.map(|interval| CreatePriceRecurring {
+            interval,
+            interval_count: Some(1),
+            ..Default::default()
+        });
+        
+        match Price::create(&client, price_params).await {
+            Ok(created) => price_ids.push(created.id.to_string()),
+            Err(e) => {
+                let error = StripeError::from(e);
+                let mut archive_params = stripe::UpdateProduct::new();
+                archive_params.active = Some(false);
+                if let Err(archive_error) = Product::update(&client, &product.id, archive_params).await {
+                    // Leave the id in the error so the product can be cleaned up by hand
+                    return Err(StripeError::Other {
+                        message: format!(
+                            "{} (product {} was created but could not be archived: {})",
+                            error, product_id, archive_error
+                        ),
+                    });
+                }
+                println!("Archived product {} after a price failed to create: {}", product_id, error);
+                return Err(error);
+            },
+        }
+    }
+    
+    // The product and prices exist at this point, so a sync failure is reported rather than returned
+    let sync_result = if sync_to_database.unwrap_or(false) {
+        Some(match sync_stripe_prices_to_database(product_id.clone(), app).await {
+            Ok(message) => message,
+            Err(e) => format!("Sync failed: {}", e),
+        })
+    } else {
+        None
+    };
     
-    let price = Price::create(&client, price_params)
-        .await
-        .map_err(|e| format!("Failed to create price: {}", e))?;
-
-    Ok(format!("Product created successfully. Price ID: {}", price.id))
+    Ok(ProductSetupResponse {
+        product_id,
+        price_ids,
+        sync_result,
+    })
 }
 
 // Payment Method Management Commands
@@ -814,7 +2190,7 @@ pub struct SetupIntentResponse {
 #[tauri::command]
 pub async fn create_setup_intent(
     customer_id: String,
-) -> Result<SetupIntentResponse, String> {
+) -> Result<SetupIntentResponse, StripeError> {
     let client = get_stripe_client()?;
     
     let mut params = stripe::CreateSetupIntent::new();
@@ -822,8 +2198,7 @@ pub async fn create_setup_intent(
     params.payment_method_types = Some(vec!["card".to_string()]);
     
     let setup_intent = stripe::SetupIntent::create(&client, params)
-        .await
-        .map_err(|e| format!("Failed to create setup intent: {}", e))?;
+        .await?;
     
     Ok(SetupIntentResponse {
         client_secret: setup_intent.client_secret.unwrap_or_default(),
@@ -831,11 +2206,49 @@ pub async fn create_setup_intent(
     })
 }
 
+/// Card saved by a confirmed setup intent. Use the returned id for
+/// store_payment_method_after_setup instead of one reported by the client.
+#[tauri::command]
+pub async fn payment_method_from_setup_intent(
+    setup_intent_id: String,
+) -> Result<PaymentMethodResponse, StripeError> {
+    let client = get_stripe_client()?;
+    
+    let setup_intent_id = stripe::SetupIntentId::from_str(&setup_intent_id)
+        .map_err(|e| format!("Invalid setup intent ID: {}", e))?;
+    let setup_intent = stripe::SetupIntent::retrieve(&client, &setup_intent_id, &["payment_method"]).await?;
+    
+    if setup_intent.status != stripe::SetupIntentStatus::Succeeded {
+        return Err(StripeError::InvalidRequest {
+            message: format!("Setup intent {} has not succeeded (status: {})", setup_intent.id, setup_intent.status.as_str()),
+        });
+    }
+    
+    let payment_method = match setup_intent.payment_method {
+        Some(stripe::Expandable::Object(payment_method)) => *payment_method,
+        Some(stripe::Expandable::Id(payment_method_id)) => {
+            stripe::PaymentMethod::retrieve(&client, &payment_method_id, &[]).await?
+        },
+        None => return Err(format!("Setup intent {} has no payment method", setup_intent.id).into()),
+    };
+    
+    let card = payment_method.card.ok_or("Payment method does not have card details")?;
+    
+    Ok(PaymentMethodResponse {
+        id: payment_method.id.to_string(),
+        card_brand: card.brand,
+        card_last4: card.last4,
+        card_exp_month: card.exp_month as i64,
+        card_exp_year: card.exp_year as i64,
+        is_default: false,
+    })
+}
+
 // Get customer's payment methods
 #[tauri::command]
 pub async fn get_customer_payment_methods(
     customer_id: String,
-) -> Result<Vec<PaymentMethodResponse>, String> {
+) -> Result<Vec<PaymentMethodResponse>, StripeError> {
     let client = get_stripe_client()?;
     
     let mut params = stripe::ListPaymentMethods::new();
@@ -844,9 +2257,7 @@ pub async fn get_customer_payment_methods(
     })?);
     params.type_ = Some(stripe::PaymentMethodTypeFilter::Card);
     
-    let payment_methods = stripe::PaymentMethod::list(&client, &params)
-        .await
-        .map_err(|e| format!("Failed to fetch payment methods: {}", e))?;
+    let payment_methods = with_rate_limit_retry(|| stripe::PaymentMethod::list(&client, &params)).await?;
     
     let mut methods = Vec::new();
     for pm in payment_methods.data {
@@ -855,8 +2266,8 @@ pub async fn get_customer_payment_methods(
                 id: pm.id.to_string(),
                 card_brand: card.brand,
                 card_last4: card.last4,
-                card_exp_month: card.exp_month as i64,
-                card_exp_year: card.exp_year as i64,
+                card_exp_month: card.exp_month,
+                card_exp_year: card.exp_year,
                 is_default: false, // We'll determine this separately if needed
             });
         }
@@ -869,16 +2280,16 @@ pub async fn get_customer_payment_methods(
 #[tauri::command]
 pub async fn list_payment_methods(
     customer_id: String,
-) -> Result<Vec<PaymentMethodResponse>, String> {
+) -> Result<Vec<PaymentMethodResponse>, StripeError> {
 
     get_customer_payment_methods(customer_id).await
 }
 
-// Delete a payment method
-#[tauri::command]
-pub async fn delete_payment_method(
+// Detach a payment method. Not a command: callers must check ownership first, as
+// delete_payment_method_integrated does.
+pub(crate) async fn delete_payment_method(
     payment_method_id: String,
-) -> Result<String, String> {
+) -> Result<String, StripeError> {
     let client = get_stripe_client()?;
     
     let payment_method_id = stripe::PaymentMethodId::from_str(&payment_method_id)
@@ -891,12 +2302,12 @@ pub async fn delete_payment_method(
     Ok("Payment method deleted successfully".to_string())
 }
 
-// Set default payment method for customer
-#[tauri::command]
-pub async fn set_default_payment_method(
+// Set default payment method for customer. Not a command: callers must check ownership
+// first, as set_default_payment_method_integrated does.
+async fn set_default_payment_method(
     customer_id: String,
     payment_method_id: String,
-) -> Result<String, String> {
+) -> Result<String, StripeError> {
     let client = get_stripe_client()?;
     
     let customer_id = stripe::CustomerId::from_str(&customer_id)
@@ -911,8 +2322,7 @@ pub async fn set_default_payment_method(
     });
     
     stripe::Customer::update(&client, &customer_id, params)
-        .await
-        .map_err(|e| format!("Failed to set default payment method: {}", e))?;
+        .await?;
     
     Ok("Default payment method updated successfully".to_string())
 }
@@ -925,7 +2335,7 @@ pub async fn create_and_store_payment_method(
     customer_id: String,
     _user_id: String,
     _app: tauri::AppHandle,
-) -> Result<SetupIntentResponse, String> {
+) -> Result<SetupIntentResponse, StripeError> {
     // First create the setup intent
     let setup_intent = create_setup_intent(customer_id.clone()).await?;
     
@@ -934,6 +2344,58 @@ pub async fn create_and_store_payment_method(
     Ok(setup_intent)
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PaymentMethodValidation {
+    pub payment_method_id: String,
+    pub valid: bool,
+    /// Why the method can't be stored, when it isn't valid
+    pub reason: Option<String>,
+}
+
+fn payment_method_verdict(payment_method: &stripe::PaymentMethod, today: chrono::NaiveDate) -> Option<String> {
+    use chrono::Datelike;
+    
+    let card = match &payment_method.card {
+        Some(card) => card,
+        None => return Some("Only cards can be saved as payment methods".to_string()),
+    };
+    
+    // Cards are valid through the last day of their expiry month
+    if ((card.exp_year as i32), (card.exp_month as u32)) < (today.year(), today.month()) {
+        return Some(format!("Card expired in {:02}/{}", card.exp_month, card.exp_year));
+    }
+    
+    // Setup intents always attach to a customer, so a method without one has been detached
+    // and Stripe won't let it be used again
+    if payment_method.customer.is_none() {
+        return Some("Payment method has been detached and can't be reused".to_string());
+    }
+    
+    None
+}
+
+/// Check a payment method is usable before store_payment_method_after_setup saves it,
+/// so expired or detached cards are caught when they're added rather than at checkout
+#[tauri::command]
+pub async fn validate_payment_method(
+    payment_method_id: String,
+) -> Result<PaymentMethodValidation, StripeError> {
+    let client = get_stripe_client()?;
+    
+    let pm_id = stripe::PaymentMethodId::from_str(&payment_method_id).map_err(|e| {
+        format!("Invalid payment method ID: {}", e)
+    })?;
+    
+    let payment_method = stripe::PaymentMethod::retrieve(&client, &pm_id, &[]).await?;
+    let reason = payment_method_verdict(&payment_method, chrono::Utc::now().date_naive());
+    
+    Ok(PaymentMethodValidation {
+        payment_method_id,
+        valid: reason.is_none(),
+        reason,
+    })
+}
+
 /// Store payment method metadata after successful Stripe setup intent confirmation
 #[tauri::command]
 pub async fn store_payment_method_after_setup(
@@ -942,16 +2404,14 @@ pub async fn store_payment_method_after_setup(
     user_id: String,
     is_default: Option<bool>,
     app: tauri::AppHandle,
-) -> Result<crate::database::PaymentMethod, String> {
+) -> Result<crate::database::PaymentMethod, StripeError> {
     let client = get_stripe_client()?;
     
     let pm_id = stripe::PaymentMethodId::from_str(&payment_method_id).map_err(|e| {
         format!("Invalid payment method ID: {}", e)
     })?;
     
-    let payment_method = stripe::PaymentMethod::retrieve(&client, &pm_id, &[]).await.map_err(|e| {
-        format!("Stripe API error: {}", e)
-    })?;
+    let payment_method = stripe::PaymentMethod::retrieve(&client, &pm_id, &[]).await?;
     
     // Attach payment method to customer if not already attached
     if payment_method.customer.is_none() {
@@ -965,9 +2425,7 @@ pub async fn store_payment_method_after_setup(
             stripe::AttachPaymentMethod {
                 customer: customer_id_stripe,
             },
-        ).await.map_err(|e| {
-            format!("Failed to attach payment method to customer: {}", e)
-        })?;
+        ).await?;
     }
     
     // Set as default payment method for the customer if requested or if it's the first payment method
@@ -984,9 +2442,7 @@ pub async fn store_payment_method_after_setup(
             ..Default::default()
         });
         
-        stripe::Customer::update(&client, &customer_id_stripe, customer_update).await.map_err(|e| {
-            format!("Failed to set default payment method: {}", e)
-        })?;
+        stripe::Customer::update(&client, &customer_id_stripe, customer_update).await?;
     }
     
     // Extract card details for storage (non-sensitive metadata only)
@@ -1001,7 +2457,7 @@ pub async fn store_payment_method_after_setup(
             (brand, last4, exp_month, exp_year)
         },
         None => {
-            return Err("Payment method does not have card details".to_string());
+            return Err("Payment method does not have card details".into());
         },
     };
     
@@ -1025,13 +2481,14 @@ pub async fn store_payment_method_after_setup(
         format!("Failed to get database config: {}", e)
     })?;
     
-    let client = reqwest::Client::new();
+    let client = crate::http::client();
     let mut update_data = std::collections::HashMap::new();
     update_data.insert("stripe_customer_id", serde_json::json!(customer_id));
     update_data.insert("updated_at", serde_json::json!(chrono::Utc::now().to_rfc3339()));
     
     let response = client
-        .patch(&format!("{}/rest/v1/profiles", db_config.database_url))
+        .patch(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -1072,7 +2529,9 @@ pub async fn set_default_payment_method_integrated(
     payment_method_id: String,
     user_id: String,
     app: tauri::AppHandle,
-) -> Result<String, String> {
+) -> Result<String, StripeError> {
+    crate::database::assert_payment_method_owned(&payment_method_id, &user_id, &app).await?;
+    
     let client = get_stripe_client()?;
     
     // First, check if the payment method is attached to the customer
@@ -1085,14 +2544,14 @@ pub async fn set_default_payment_method_integrated(
             // Check if it's attached to the right customer
             match pm.customer {
                 Some(stripe::Expandable::Id(cust_id)) => {
-                    if cust_id.to_string() != customer_id {
+                    if cust_id != customer_id {
                         // Payment method exists but is attached to wrong customer or not attached
-                        return Err(format!("Payment method {} is not attached to customer {}", payment_method_id, customer_id));
+                        return Err(format!("Payment method {} is not attached to customer {}", payment_method_id, customer_id).into());
                     }
                 },
                 Some(stripe::Expandable::Object(customer)) => {
-                    if customer.id.to_string() != customer_id {
-                        return Err(format!("Payment method {} is attached to wrong customer", payment_method_id));
+                    if customer.id != customer_id {
+                        return Err(format!("Payment method {} is attached to wrong customer", payment_method_id).into());
                     }
                 },
                 None => {
@@ -1120,9 +2579,9 @@ pub async fn set_default_payment_method_integrated(
                                     user_id.clone(),
                                     app.clone(),
                                 ).await;
-                                return Err("Payment method is no longer usable and has been removed from your account. Please add a new payment method.".to_string());
+                                return Err("Payment method is no longer usable and has been removed from your account. Please add a new payment method.".into());
                             } else {
-                                return Err(format!("Failed to attach payment method to customer: {}", e));
+                                return Err(format!("Failed to attach payment method to customer: {}", e).into());
                             }
                         }
                     }
@@ -1130,7 +2589,7 @@ pub async fn set_default_payment_method_integrated(
             }
         },
         Err(e) => {
-            return Err(format!("Failed to retrieve payment method from Stripe: {}", e));
+            return Err(format!("Failed to retrieve payment method from Stripe: {}", e).into());
         }
     }
     
@@ -1149,13 +2608,103 @@ pub async fn set_default_payment_method_integrated(
     Ok("Payment method set as default successfully".to_string())
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PrunePaymentMethodsResult {
+    pub pruned: usize,
+    pub pruned_payment_method_ids: Vec<String>,
+    /// Set when the default was pruned and another method took its place
+    pub new_default_payment_method_id: Option<String>,
+}
+
+/// Remove the user's stored payment methods that can no longer be charged: deleted in Stripe,
+/// detached, expired, or not a card. Methods Stripe couldn't be reached for are kept.
+#[tauri::command]
+pub async fn prune_unusable_payment_methods(
+    user_id: String,
+    app: tauri::AppHandle,
+) -> Result<PrunePaymentMethodsResult, StripeError> {
+    let client = get_stripe_client()?;
+    let stored = crate::database::get_user_payment_methods(user_id.clone(), app.clone()).await?;
+    let today = chrono::Utc::now().date_naive();
+    
+    let mut pruned_payment_method_ids = Vec::new();
+    let mut default_pruned = false;
+    let mut remaining = Vec::new();
+    
+    for pm in stored {
+        let pm_id = match stripe::PaymentMethodId::from_str(&pm.stripe_payment_method_id) {
+            Ok(pm_id) => pm_id,
+            Err(_) => {
+                remaining.push(pm);
+                continue;
+            }
+        };
+        
+        let unusable_reason = match stripe::PaymentMethod::retrieve(&client, &pm_id, &[]).await {
+            Ok(payment_method) => payment_method_verdict(&payment_method, today),
+            Err(stripe::StripeError::Stripe(error)) if error.http_status == 404 => {
+                Some("Payment method no longer exists in Stripe".to_string())
+            },
+            Err(_) => None,
+        };
+        
+        let reason = match unusable_reason {
+            Some(reason) => reason,
+            None => {
+                remaining.push(pm);
+                continue;
+            }
+        };
+        
+        crate::diagnostics::record_event(
+            "stripe",
+            &format!("Pruning payment method {}: {}", pm.stripe_payment_method_id, reason),
+        );
+        
+        // An expired card can still be attached; detach it so it isn't offered at checkout
+        let _ = stripe::PaymentMethod::detach(&client, &pm_id).await;
+        crate::database::delete_payment_method_from_db(
+            pm.stripe_payment_method_id.clone(),
+            user_id.clone(),
+            app.clone(),
+        ).await?;
+        
+        default_pruned |= pm.is_default;
+        pruned_payment_method_ids.push(pm.stripe_payment_method_id);
+    }
+    
+    // Methods come back newest first after the default, so promote the newest survivor
+    let mut new_default_payment_method_id = None;
+    if default_pruned && !remaining.iter().any(|pm| pm.is_default) {
+        if let Some(pm) = remaining.first() {
+            set_default_payment_method(pm.stripe_customer_id.clone(), pm.stripe_payment_method_id.clone()).await?;
+            crate::database::update_payment_method(
+                pm.stripe_payment_method_id.clone(),
+                user_id.clone(),
+                Some(true), // is_default
+                None,       // is_active (don't change)
+                app.clone(),
+            ).await?;
+            new_default_payment_method_id = Some(pm.stripe_payment_method_id.clone());
+        }
+    }
+    
+    Ok(PrunePaymentMethodsResult {
+        pruned: pruned_payment_method_ids.len(),
+        pruned_payment_method_ids,
+        new_default_payment_method_id,
+    })
+}
+
 /// Delete payment method from both Stripe and database
 #[tauri::command]
 pub async fn delete_payment_method_integrated(
     payment_method_id: String,
     user_id: String,
     app: tauri::AppHandle,
-) -> Result<String, String> {
+) -> Result<String, StripeError> {
+    crate::database::assert_payment_method_owned(&payment_method_id, &user_id, &app).await?;
+    
     // Try to delete from Stripe first, but don't fail if it's already detached/orphaned
     match delete_payment_method(payment_method_id.clone()).await {
         Ok(_) => {
@@ -1163,7 +2712,8 @@ pub async fn delete_payment_method_integrated(
         },
         Err(e) => {
             // Check if it's an "already detached" or "not attached" error
-            if e.contains("not attached to a customer") || e.contains("detachment is impossible") {
+            let error_msg = e.to_string();
+            if error_msg.contains("not attached to a customer") || error_msg.contains("detachment is impossible") {
                 // Payment method is orphaned in Stripe, just remove from database
             } else {
                 // Some other Stripe error, propagate it
@@ -1190,7 +2740,7 @@ pub async fn create_payment_intent_with_stored_method(
     payment_method_id: String,
     user_id: String,
     app: tauri::AppHandle,
-) -> Result<PaymentIntentResponse, String> {
+) -> Result<PaymentIntentResponse, StripeError> {
     let client = get_stripe_client()?;
     
     // Get customer ID from the stored payment method
@@ -1212,8 +2762,7 @@ pub async fn create_payment_intent_with_stored_method(
     params.confirm = Some(true);
     
     let payment_intent = stripe::PaymentIntent::create(&client, params)
-        .await
-        .map_err(|e| format!("Failed to create payment intent: {}", e))?;
+        .await?;
     
     // Mark payment method as used in database
     let _ = crate::database::mark_payment_method_used(
@@ -1228,7 +2777,116 @@ pub async fn create_payment_intent_with_stored_method(
     })
 }
 
-/// Record a purchase in the database after successful payment
+/// Create one payment intent covering several package prices.
+/// The priced cart is stored in metadata so record_purchase can split it back into rows.
+#[tauri::command]
+pub async fn create_cart_payment_intent(
+    items: Vec<CartItem>,
+    user_id: String,
+    app: tauri::AppHandle,
+) -> Result<CartPaymentIntentResponse, StripeError> {
+    if items.is_empty() {
+        return Err("Cart is empty".into());
+    }
+    
+    // Summed as u64 so huge quantities can't wrap around below the limit
+    let total_units: u64 = items.iter().map(|item| u64::from(item.quantity)).sum();
+    if items.iter().any(|item| item.quantity == 0) || total_units > u64::from(MAX_CART_UNITS) {
+        return Err(format!("Cart quantities must be between 1 and {} units in total", MAX_CART_UNITS).into());
+    }
+    
+    let client = get_stripe_client()?;
+    
+    let mut lines = Vec::with_capacity(items.len());
+    let mut cart_currency: Option<Currency> = None;
+    for item in &items {
+        let price_id = stripe::PriceId::from_str(&item.price_id)
+            .map_err(|e| format!("Invalid Stripe price ID {}: {}", item.price_id, e))?;
+        let price = Price::retrieve(&client, &price_id, &[]).await?;
+        
+        let unit_amount = price.unit_amount
+            .ok_or_else(|| format!("Price {} has no fixed unit amount", item.price_id))?;
+        let currency = price.currency
+            .ok_or_else(|| format!("Price {} has no currency", item.price_id))?;
+        
+        match cart_currency {
+            Some(existing) if existing != currency => {
+                return Err(StripeError::InvalidRequest {
+                    message: format!(
+                        "All cart items must share a currency: {} is {}, expected {}",
+                        item.price_id, currency, existing
+                    ),
+                });
+            },
+            _ => cart_currency = Some(currency),
+        }
+        
+        lines.push(CartLine {
+            price_id: item.price_id.clone(),
+            quantity: item.quantity,
+            unit_amount,
+        });
+    }
+    
+    let currency = cart_currency.ok_or("Cart is empty")?;
+    let total_amount = cart_total(&lines)?;
+    
+    let cart_metadata = serde_json::to_string(&lines)
+        .map_err(|e| format!("Failed to serialize cart: {}", e))?;
+    if cart_metadata.len() > STRIPE_METADATA_VALUE_LIMIT {
+        return Err("Cart has too many items to store on the payment intent".into());
+    }
+    
+    let mut params = CreatePaymentIntent::new(total_amount, currency);
+    params.payment_method_types = Some(vec!["card".to_string()]);
+    
+    // Attach the user's Stripe customer so saved cards can be used
+    if let Ok(Some(profile)) = crate::database::get_user_profile(user_id.clone(), app.clone()).await {
+        if let Some(customer_id) = profile.stripe_customer_id {
+            params.customer = Some(customer_id.parse().map_err(|_| "Invalid customer ID".to_string())?);
+        }
+    }
+    
+    let mut metadata = HashMap::new();
+    metadata.insert("user_id".to_string(), user_id);
+    metadata.insert("cart".to_string(), cart_metadata);
+    params.metadata = Some(metadata);
+    
+    let payment_intent = PaymentIntent::create(&client, params).await?;
+    
+    Ok(CartPaymentIntentResponse {
+        client_secret: payment_intent.client_secret.unwrap_or_default(),
+        payment_intent_id: payment_intent.id.to_string(),
+        total_amount,
+        currency: currency.to_string(),
+    })
+}
+
+/// Sum a priced cart, guarding against overflow from absurd quantities
+fn cart_total(lines: &[CartLine]) -> Result<i64, StripeError> {
+    lines.iter().try_fold(0i64, |total, line| {
+        line.unit_amount
+            .checked_mul(line.quantity as i64)
+            .and_then(|line_total| total.checked_add(line_total))
+            .ok_or_else(|| "Cart total is too large".into())
+    })
+}
+
+/// Read the priced cart written by create_cart_payment_intent, if this is a cart payment
+fn cart_from_metadata(metadata: &HashMap<String, String>) -> Result<Option<Vec<CartLine>>, StripeError> {
+    match metadata.get("cart") {
+        Some(cart) => serde_json::from_str(cart)
+            .map(Some)
+            .map_err(|e| format!("Failed to parse cart metadata: {}", e).into()),
+        None => Ok(None),
+    }
+}
+
+/// Record a purchase in the database after successful payment. The payment intent must
+/// have succeeded, and the price, amount and currency must match what it actually charged.
+/// Cart payment intents are recorded as one purchase row per unit in the cart.
+/// If Stripe or the database can't be reached the purchase is queued instead and
+/// the result has `queued` set; see flush_pending_purchases.
 #[tauri::command]
 pub async fn record_purchase(
     user_id: String,
@@ -1237,12 +2895,308 @@ pub async fn record_purchase(
     amount_paid: i64,
     currency: String,
     app: tauri::AppHandle,
-) -> Result<String, String> {
-    let db_config = crate::database::get_authenticated_db(&app).await.map_err(|e| {
-        format!("Failed to get database config: {}", e)
-    })?;
+) -> Result<PurchaseRecordResult, StripeError> {
+    let pending = PendingPurchase {
+        user_id,
+        stripe_payment_intent_id,
+        stripe_price_id,
+        amount_paid,
+        currency,
+        queued_at: chrono::Utc::now().to_rfc3339(),
+        last_error: String::new(),
+    };
+    
+    match record_purchase_now(&pending, &app).await {
+        Err(e) if is_retryable_purchase_error(&e) => {
+            enqueue_pending_purchase(PendingPurchase { last_error: e.to_string(), ..pending }, &app)?;
+            Ok(PurchaseRecordResult {
+                purchases: Vec::new(),
+                is_new_purchase: false,
+                token_balance: None,
+                queued: true,
+            })
+        },
+        result => result,
+    }
+}
+
+/// Replay the signed-in user's purchases queued while offline. Recording is idempotent
+/// per payment intent, so a purchase that did reach the database before failing is not
+/// credited twice. Other accounts' queued purchases wait until that account signs in.
+#[tauri::command]
+pub async fn flush_pending_purchases(
+    app: tauri::AppHandle,
+) -> Result<PendingPurchaseFlushResult, StripeError> {
+    let user = crate::session::get_auth_user(app.clone()).await
+        .map_err(|message| StripeError::NotAuthenticated { message })?;
+    
+    if PENDING_PURCHASE_FLUSH_RUNNING.swap(true, std::sync::atomic::Ordering::SeqCst) {
+        return Ok(PendingPurchaseFlushResult {
+            recorded: 0,
+            dropped: Vec::new(),
+            pending: load_pending_purchases(&app, &user.id).len(),
+        });
+    }
+    
+    let queued = load_pending_purchases(&app, &user.id);
+    let replay = replay_pending_purchases(queued, |pending| {
+        let pending = pending.clone();
+        let app = app.clone();
+        async move { record_purchase_now(&pending, &app).await.map(|_| ()) }
+    }).await;
+    
+    let remaining = update_pending_purchases(&app, &user.id, |queue| {
+        queue.retain(|pending| !replay.done.contains(&pending.stripe_payment_intent_id));
+    });
+    PENDING_PURCHASE_FLUSH_RUNNING.store(false, std::sync::atomic::Ordering::SeqCst);
+    
+    Ok(PendingPurchaseFlushResult {
+        recorded: replay.recorded,
+        dropped: replay.dropped,
+        pending: remaining?,
+    })
+}
+
+/// Number of the signed-in user's purchases waiting to be recorded
+#[tauri::command]
+pub async fn get_pending_purchase_count(app: tauri::AppHandle) -> Result<usize, StripeError> {
+    let user = crate::session::get_auth_user(app.clone()).await
+        .map_err(|message| StripeError::NotAuthenticated { message })?;
+    Ok(load_pending_purchases(&app, &user.id).len())
+}
+
+/// Retry queued purchases in the background, so they're recorded once the device is back online
+pub fn start_pending_purchase_flusher(app: &tauri::AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            tokio::time::sleep(std::time::Duration::from_secs(PENDING_PURCHASE_FLUSH_INTERVAL_SECS)).await;
+            if get_pending_purchase_count(app.clone()).await.unwrap_or(0) > 0 {
+                let _ = flush_pending_purchases(app.clone()).await;
+            }
+        }
+    });
+}
+
+// Failures that retrying later can fix: no connection, throttling, Stripe outages, and
+// being signed out or refused (401/403), which a fresh sign-in may resolve. Validation,
+// card and other database errors are final.
+fn is_retryable_purchase_error(error: &StripeError) -> bool {
+    match error {
+        StripeError::Network { .. } | StripeError::RateLimited { .. } | StripeError::Timeout { .. } => true,
+        StripeError::NotAuthenticated { .. } | StripeError::Forbidden { .. } => true,
+        StripeError::DatabaseNotConfigured { .. } => true,
+        StripeError::Api { http_status, .. } => *http_status >= 500 || matches!(*http_status, 401 | 403),
+        _ => false,
+    }
+}
+
+struct PendingPurchaseReplay {
+    recorded: usize,
+    /// Payment intents to take off the queue, recorded or dropped
+    done: Vec<String>,
+    dropped: Vec<PendingPurchase>,
+}
+
+// Record queued purchases in order, stopping at the first retryable failure so the
+// rest wait for the next flush
+async fn replay_pending_purchases<F, Fut>(queued: Vec<PendingPurchase>, mut record: F) -> PendingPurchaseReplay
+where
+    F: FnMut(&PendingPurchase) -> Fut,
+    Fut: std::future::Future<Output = Result<(), StripeError>>,
+{
+    let mut replay = PendingPurchaseReplay {
+        recorded: 0,
+        done: Vec::new(),
+        dropped: Vec::new(),
+    };
+    
+    for pending in queued {
+        match record(&pending).await {
+            Ok(()) => {
+                replay.recorded += 1;
+                replay.done.push(pending.stripe_payment_intent_id.clone());
+            },
+            Err(e) if is_retryable_purchase_error(&e) => break,
+            Err(e) => {
+                replay.done.push(pending.stripe_payment_intent_id.clone());
+                replay.dropped.push(PendingPurchase { last_error: e.to_string(), ..pending });
+            },
+        }
+    }
+    
+    replay
+}
+
+// Each user's queue is kept under its own key, so one account never replays another's
+fn pending_purchases_key(user_id: &str) -> String {
+    format!("purchases:{}", user_id)
+}
+
+fn load_pending_purchases(app: &tauri::AppHandle, user_id: &str) -> Vec<PendingPurchase> {
+    app.store(PENDING_PURCHASES_STORE).ok()
+        .and_then(|store| store.get(pending_purchases_key(user_id)))
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+// Read-modify-write of a user's queue under PENDING_PURCHASES_LOCK. Returns the new queue length.
+fn update_pending_purchases(
+    app: &tauri::AppHandle,
+    user_id: &str,
+    update: impl FnOnce(&mut Vec<PendingPurchase>),
+) -> Result<usize, StripeError> {
+    let _guard = PENDING_PURCHASES_LOCK.lock().map_err(|_| "Pending purchase queue lock poisoned")?;
+    
+    let mut queue = load_pending_purchases(app, user_id);
+    update(&mut queue);
+    
+    let store = app.store(PENDING_PURCHASES_STORE).map_err(|e| e.to_string())?;
+    if queue.is_empty() {
+        store.delete(pending_purchases_key(user_id));
+    } else {
+        store.set(pending_purchases_key(user_id), serde_json::json!(queue));
+    }
+    store.save().map_err(|e| format!("Failed to save pending purchases: {}", e))?;
+    
+    Ok(queue.len())
+}
+
+fn enqueue_pending_purchase(pending: PendingPurchase, app: &tauri::AppHandle) -> Result<usize, StripeError> {
+    let user_id = pending.user_id.clone();
+    update_pending_purchases(app, &user_id, |queue| push_pending_purchase(queue, pending))
+}
+
+// One queue entry per payment intent; a repeat failure just refreshes the error
+fn push_pending_purchase(queue: &mut Vec<PendingPurchase>, pending: PendingPurchase) {
+    match queue.iter_mut().find(|queued| queued.stripe_payment_intent_id == pending.stripe_payment_intent_id) {
+        Some(queued) => queued.last_error = pending.last_error,
+        None => queue.push(pending),
+    }
+}
+
+async fn record_purchase_now(
+    pending: &PendingPurchase,
+    app: &tauri::AppHandle,
+) -> Result<PurchaseRecordResult, StripeError> {
+    let PendingPurchase {
+        user_id,
+        stripe_payment_intent_id,
+        stripe_price_id,
+        amount_paid,
+        currency,
+        ..
+    } = pending.clone();
+    let app = app.clone();
+    
+    let client = get_stripe_client()?;
+    let payment_intent_id = stripe::PaymentIntentId::from_str(&stripe_payment_intent_id)
+        .map_err(|e| StripeError::InvalidRequest { message: format!("Invalid payment intent ID: {}", e) })?;
+    let payment_intent = crate::http::with_timeout(
+        crate::http::OperationClass::Stripe,
+        &app,
+        stripe::PaymentIntent::retrieve(&client, &payment_intent_id, &[]),
+    ).await?;
+    
+    // The caller's price and amount are only a claim; they must match what was paid
+    let verified = verify_paid_intent(&payment_intent, &client, &app).await?;
+    if verified.amount_paid != amount_paid
+        || !verified.currency.eq_ignore_ascii_case(&currency)
+        || verified.stripe_price_id != stripe_price_id
+    {
+        return Err(StripeError::InvalidRequest {
+            message: format!(
+                "Purchase of {} for {} {} does not match payment intent {} ({} for {} {})",
+                stripe_price_id, amount_paid, currency, stripe_payment_intent_id,
+                verified.stripe_price_id, verified.amount_paid, verified.currency
+            ),
+        });
+    }
+    
+    let cart = match verified.cart {
+        Some(cart) => cart,
+        None => {
+            return record_purchase_line(
+                user_id,
+                stripe_payment_intent_id,
+                verified.stripe_price_id,
+                verified.amount_paid,
+                verified.currency,
+                0,
+                app,
+            ).await;
+        },
+    };
+    
+    let mut result = PurchaseRecordResult {
+        purchases: Vec::new(),
+        is_new_purchase: false,
+        token_balance: None,
+        queued: false,
+    };
+    
+    let units = cart.iter()
+        .flat_map(|line| std::iter::repeat_n(line, line.quantity as usize));
+    for (line_item_index, line) in units.enumerate() {
+        let line_result = record_purchase_line(
+            user_id.clone(),
+            stripe_payment_intent_id.clone(),
+            line.price_id.clone(),
+            line.unit_amount,
+            verified.currency.clone(),
+            line_item_index as u32,
+            app.clone(),
+        ).await?;
+        
+        result.purchases.extend(line_result.purchases);
+        result.is_new_purchase |= line_result.is_new_purchase;
+        if line_result.token_balance.is_some() {
+            result.token_balance = line_result.token_balance;
+        }
+    }
     
-    let http_client = reqwest::Client::new();
+    Ok(result)
+}
+
+/// Record a single purchase row for one line of a payment intent
+pub(crate) async fn record_purchase_line(
+    user_id: String,
+    stripe_payment_intent_id: String,
+    stripe_price_id: String,
+    amount_paid: i64,
+    currency: String,
+    line_item_index: u32,
+    app: tauri::AppHandle,
+) -> Result<PurchaseRecordResult, StripeError> {
+    let db_config = crate::database::get_authenticated_db(&app).await?;
+    
+    let http_client = crate::http::client();
+    
+    // A retried call for the same payment intent must not credit tokens twice
+    let existing_response = http_client
+        .get(format!("{}/rest/v1/purchases", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("stripe_payment_intent_id", format!("eq.{}", stripe_payment_intent_id))])
+        .query(&[("line_item_index", format!("eq.{}", line_item_index))])
+        .query(&[("select", "id,status")])
+        .send()
+        .await
+        .map_err(|e| crate::http::request_error(crate::http::OperationClass::Read, &app, "Failed to query existing purchase", e))?;
+    
+    if !existing_response.status().is_success() {
+        let status = existing_response.status();
+        let error_text = existing_response.text().await.unwrap_or_default();
+        return Err(crate::database::access_error(status.as_u16(), &error_text, "purchases")
+            .map(StripeError::from)
+            .unwrap_or_else(|| format!("Failed to query existing purchase: HTTP {} - {}", status, error_text).into()));
+    }
+    
+    let existing_purchases: Vec<serde_json::Value> = existing_response.json().await.map_err(|e| {
+        format!("Failed to parse existing purchase response: {}", e)
+    })?;
+    let is_new_purchase = existing_purchases.is_empty();
     
     // First, get the product ID from Stripe to find the package
     
@@ -1251,14 +3205,12 @@ pub async fn record_purchase(
         format!("Invalid Stripe price ID: {}", e)
     })?;
     
-    let stripe_price = stripe::Price::retrieve(&stripe_client, &price_id, &[]).await.map_err(|e| {
-        format!("Failed to retrieve price from Stripe: {}", e)
-    })?;
+    let stripe_price = stripe::Price::retrieve(&stripe_client, &price_id, &[]).await?;
     
     let stripe_product_id = match stripe_price.product {
         Some(stripe::Expandable::Id(id)) => id.to_string(),
         Some(stripe::Expandable::Object(product)) => product.id.to_string(),
-        None => return Err("Price has no associated product".to_string()),
+        None => return Err("Price has no associated product".into()),
     };
     
     // Look up the package by stripe_product_id
@@ -1267,11 +3219,12 @@ pub async fn record_purchase(
     
     let package_response = http_client
         .get(&package_query_url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .send()
         .await
-        .map_err(|e| format!("Failed to query package data: {}", e))?;
+        .map_err(|e| crate::http::request_error(crate::http::OperationClass::Read, &app, "Failed to query package data", e))?;
     
     let package_response_text = package_response.text().await.map_err(|e| {
         format!("Failed to read package response: {}", e)
@@ -1295,7 +3248,8 @@ pub async fn record_purchase(
         });
         
         let create_package_response = http_client
-            .post(&format!("{}/rest/v1/packages", db_config.database_url))
+            .post(format!("{}/rest/v1/packages", db_config.database_url))
+            .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
             .header("Authorization", format!("Bearer {}", db_config.access_token))
             .header("apikey", &db_config.anon_key)
             .header("Content-Type", "application/json")
@@ -1303,12 +3257,12 @@ pub async fn record_purchase(
             .json(&create_package_data)
             .send()
             .await
-            .map_err(|e| format!("Failed to create package HTTP request: {}", e))?;
+            .map_err(|e| crate::http::request_error(crate::http::OperationClass::Write, &app, "Failed to create package", e))?;
         
         if !create_package_response.status().is_success() {
             let status = create_package_response.status();
             let error_text = create_package_response.text().await.unwrap_or_default();
-            return Err(format!("Failed to create package: HTTP {} - {}", status, error_text));
+            return Err(format!("Failed to create package: HTTP {} - {}", status, error_text).into());
         }
         
         let created_package_text = create_package_response.text().await.map_err(|e| format!("Failed to read created package response: {}", e))?;
@@ -1316,7 +3270,7 @@ pub async fn record_purchase(
         let created_package_array = created_package_data.as_array().ok_or("Created package response is not an array")?;
         
         if created_package_array.is_empty() {
-            return Err("Failed to get created package data".to_string());
+            return Err("Failed to get created package data".into());
         }
         
         // Extract the package ID from the newly created package
@@ -1336,26 +3290,30 @@ pub async fn record_purchase(
     
     let package_price_response = http_client
         .get(&package_price_query_url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .send()
         .await
-        .map_err(|e| format!("Failed to query package price: {}", e))?;
+        .map_err(|e| crate::http::request_error(crate::http::OperationClass::Read, &app, "Failed to query package price", e))?;
     
     let package_price_text = package_price_response.text().await.map_err(|e| format!("Failed to read package price response: {}", e))?;
     let package_price_data: serde_json::Value = serde_json::from_str(&package_price_text).map_err(|e| format!("Failed to parse package price response: {}", e))?;
     let package_price_array = package_price_data.as_array().ok_or("Package price response is not an array")?;
     
+    // The map is optional, so failing to read it falls back rather than failing the purchase
+    let mapped_tokens = crate::database::resolve_token_amount(amount_paid, &currency, &app).await
+        .ok()
+        .flatten();
+    
     // Get package_price_id and token_amount from the database
     let (package_price_id, token_amount) = if !package_price_array.is_empty() {
         let price_record = &package_price_array[0];
         let price_id = price_record["id"].as_str().ok_or("Missing package price id")?.to_string();
-        let tokens = price_record["token_amount"].as_i64().unwrap_or_else(|| {
-            get_token_amount_from_price(amount_paid)
-        });
+        let tokens = purchase_token_amount(mapped_tokens, price_record["token_amount"].as_i64(), amount_paid);
         (Some(price_id), tokens)
     } else {
-        (None, get_token_amount_from_price(amount_paid))
+        (None, purchase_token_amount(mapped_tokens, None, amount_paid))
     };
 
     
@@ -1363,6 +3321,7 @@ pub async fn record_purchase(
     let mut purchase_data = serde_json::json!({
         "user_id": user_id,
         "stripe_payment_intent_id": stripe_payment_intent_id,
+        "line_item_index": line_item_index,
         "stripe_price_id": stripe_price_id,
         "stripe_product_id": stripe_product_id,
         "package_id": package_id,
@@ -1378,23 +3337,40 @@ pub async fn record_purchase(
         purchase_data["package_price_id"] = serde_json::json!(price_id);
     }
     
+    // Snapshot the purchase count so we can tell when the trigger has credited this one
+    let baseline_purchases = if is_new_purchase {
+        verify_profile_update_after_purchase(&user_id, &app).await
+            .map(|balance| balance.total_purchases)
+            .ok()
+    } else {
+        None
+    };
+    
     let request_url = format!("{}/rest/v1/purchases", db_config.database_url);
     
-    let response = http_client
+    // Upsert on the payment intent line so concurrent retries collapse into one row.
+    // The purchase stats trigger only credits tokens on insert or a transition to
+    // completed, so merging onto an already completed row never credits again.
+    let write_class = crate::http::OperationClass::Write;
+    let response = crate::http::client()
         .post(&request_url)
+        .timeout(crate::http::timeout(write_class, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
-        .header("Prefer", "return=representation")
+        .header("Prefer", "return=representation,resolution=merge-duplicates")
+        .query(&[("on_conflict", "stripe_payment_intent_id,line_item_index")])
         .json(&purchase_data)
         .send()
         .await
-        .map_err(|e| format!("Database request failed: {}", e))?;
+        .map_err(|e| crate::http::request_error(write_class, &app, "Database request failed", e))?;
     
     if !response.status().is_success() {
         let status = response.status();
         let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("Failed to record purchase: HTTP {} - {}", status, error_text));
+        return Err(crate::database::access_error(status.as_u16(), &error_text, "purchases")
+            .map(StripeError::from)
+            .unwrap_or_else(|| format!("Failed to record purchase: HTTP {} - {}", status, error_text).into()));
     }
     
     let response_text = response.text().await.map_err(|e| {
@@ -1405,29 +3381,68 @@ pub async fn record_purchase(
         format!("Failed to parse purchase response: {} - Response: {}", e, response_text)
     })?;
     
-    // Sleep briefly to allow database triggers to complete
-    std::thread::sleep(std::time::Duration::from_millis(100));
+    let purchase = result.as_array()
+        .and_then(|rows| rows.first().cloned())
+        .ok_or("Purchase response did not contain a purchase row")?;
+    
+    // Wait for the purchase stats trigger to credit the profile
+    let token_balance = wait_for_purchase_credit(&user_id, baseline_purchases, &app).await;
+    
+    Ok(PurchaseRecordResult {
+        purchases: vec![purchase],
+        is_new_purchase,
+        token_balance,
+        queued: false,
+    })
+}
+
+/// Poll the profile until the purchase count moves past `baseline_purchases`,
+/// backing off between attempts. Returns the last balance read, or None if the
+/// profile could never be read.
+async fn wait_for_purchase_credit(
+    user_id: &str,
+    baseline_purchases: Option<i64>,
+    app: &tauri::AppHandle,
+) -> Option<VerifiedTokenBalance> {
+    let mut delay = std::time::Duration::from_millis(PURCHASE_VERIFY_INITIAL_DELAY_MS);
+    let mut last_balance = None;
     
-    // Verify the purchase was recorded and profile was updated
-    let _ = verify_profile_update_after_purchase(&user_id, &app).await;
+    for _ in 0..PURCHASE_VERIFY_MAX_ATTEMPTS {
+        tokio::time::sleep(delay).await;
+        
+        if let Ok(balance) = verify_profile_update_after_purchase(user_id, app).await {
+            let credited = match baseline_purchases {
+                Some(baseline) => balance.total_purchases > baseline,
+                None => true,
+            };
+            
+            if credited {
+                return Some(balance);
+            }
+            last_balance = Some(balance);
+        }
+        
+        delay *= 2;
+    }
     
-    Ok(format!("Purchase recorded successfully: {}", result))
+    last_balance
 }
 
-/// Verify that profile was updated after purchase
+/// Read the profile's token balance and purchase count
 async fn verify_profile_update_after_purchase(
     user_id: &str,
     app: &tauri::AppHandle,
-) -> Result<String, String> {
+) -> Result<VerifiedTokenBalance, String> {
     let db_config = crate::database::get_authenticated_db(app).await?;
-    let http_client = reqwest::Client::new();
+    let http_client = crate::http::client();
     
     let response = http_client
-        .get(&format!("{}/rest/v1/profiles", db_config.database_url))
+        .get(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .query(&[("id", format!("eq.{}", user_id))])
-        .query(&[("select", "total_tokens,tokens_remaining,tokens_used,total_purchases,last_purchase_at")])
+        .query(&[("select", "total_tokens,tokens_remaining,tokens_used,total_purchases")])
         .send()
         .await
         .map_err(|e| format!("Profile verification request failed: {}", e))?;
@@ -1443,12 +3458,12 @@ async fn verify_profile_update_after_purchase(
     
     if let Some(profiles) = profile_data.as_array() {
         if let Some(profile) = profiles.first() {
-            return Ok(format!(
-                "Profile updated - Tokens: {} remaining, {} total, {} purchases", 
-                profile.get("tokens_remaining").and_then(|v| v.as_i64()).unwrap_or(0),
-                profile.get("total_tokens").and_then(|v| v.as_i64()).unwrap_or(0),
-                profile.get("total_purchases").and_then(|v| v.as_i64()).unwrap_or(0)
-            ));
+            return Ok(VerifiedTokenBalance {
+                total_tokens: profile.get("total_tokens").and_then(|v| v.as_i64()).unwrap_or(0),
+                tokens_remaining: profile.get("tokens_remaining").and_then(|v| v.as_i64()).unwrap_or(0),
+                tokens_used: profile.get("tokens_used").and_then(|v| v.as_i64()).unwrap_or(0),
+                total_purchases: profile.get("total_purchases").and_then(|v| v.as_i64()).unwrap_or(0),
+            });
         }
     }
     
@@ -1461,7 +3476,7 @@ pub async fn complete_purchase(
     payment_intent_id: String,
     user_id: String,
     app: tauri::AppHandle,
-) -> Result<String, String> {
+) -> Result<String, StripeError> {
 
     
     let client = get_stripe_client()?;
@@ -1471,24 +3486,11 @@ pub async fn complete_purchase(
         .map_err(|e| format!("Invalid payment intent ID: {}", e))?;
     
     let payment_intent = stripe::PaymentIntent::retrieve(&client, &payment_intent_stripe_id, &[])
-        .await
-        .map_err(|e| format!("Failed to retrieve payment intent: {}", e))?;
-    
-    // Check if payment was successful
-    if payment_intent.status != stripe::PaymentIntentStatus::Succeeded {
-        return Err(format!("Payment not successful. Status: {:?}", payment_intent.status));
-    }
-    
-    // Get metadata or charges to find the price information
-    let amount_paid = payment_intent.amount;
-    let currency = payment_intent.currency.to_string();
+        .await?;
+    let VerifiedPayment { stripe_price_id, amount_paid, currency, .. } =
+        verify_paid_intent(&payment_intent, &client, &app).await?;
     
-    // For now, we'll need to pass the price_id separately or store it in metadata
-    // In a real implementation, you'd store the price_id in the payment intent metadata
-    let stripe_price_id = payment_intent.metadata.get("price_id").cloned()
-        .unwrap_or_else(|| "unknown_price".to_string());
-    
-    // Record the purchase in the database
+    // Record the purchase in the database; tokens are credited from package_prices by the DB trigger
     record_purchase(
         user_id,
         payment_intent_id,
@@ -1502,11 +3504,131 @@ pub async fn complete_purchase(
 }
 
 
+// What a payment intent actually paid for, read from Stripe rather than from the client
+struct VerifiedPayment {
+    /// The cart's first price for cart payments
+    stripe_price_id: String,
+    amount_paid: i64,
+    currency: String,
+    cart: Option<Vec<CartLine>>,
+}
+
+/// Check that a payment intent succeeded and that its captured charge matches the package
+/// prices in its metadata. Only the returned values may be used to credit tokens.
+async fn verify_paid_intent(
+    payment_intent: &stripe::PaymentIntent,
+    client: &Client,
+    app: &tauri::AppHandle,
+) -> Result<VerifiedPayment, StripeError> {
+    if payment_intent.status != stripe::PaymentIntentStatus::Succeeded {
+        return Err(StripeError::InvalidRequest {
+            message: format!("Payment not successful. Status: {:?}", payment_intent.status),
+        });
+    }
+    
+    // Take the amount from the charge Stripe actually captured, not the intent the client configured
+    let charge = match &payment_intent.latest_charge {
+        Some(stripe::Expandable::Object(charge)) => (**charge).clone(),
+        Some(stripe::Expandable::Id(charge_id)) => {
+            stripe::Charge::retrieve(client, charge_id, &[]).await?
+        },
+        None => return Err("Payment intent has no charge".into()),
+    };
+    
+    if !charge.paid || charge.refunded {
+        return Err(format!("Charge {} is not in a paid state", charge.id).into());
+    }
+    
+    let amount_paid = charge.amount_captured;
+    let currency = charge.currency.to_string();
+    
+    // Price ids in metadata are only trusted once the captured charge matches them
+    let cart = cart_from_metadata(&payment_intent.metadata)?;
+    let stripe_price_id = match &cart {
+        Some(cart) => {
+            if cart_total(cart)? != amount_paid {
+                return Err(StripeError::InvalidRequest {
+                    message: format!("Charged {} {} does not match the cart total", amount_paid, currency),
+                });
+            }
+            for line in cart {
+                verify_charge_matches_package_price(&line.price_id, line.unit_amount, &currency, app).await?;
+            }
+            cart.first().map(|line| line.price_id.clone()).ok_or("Cart metadata is empty")?
+        },
+        None => {
+            let stripe_price_id = payment_intent.metadata.get("price_id").cloned()
+                .ok_or("Payment intent metadata is missing price_id")?;
+            verify_charge_matches_package_price(&stripe_price_id, amount_paid, &currency, app).await?;
+            stripe_price_id
+        },
+    };
+    
+    Ok(VerifiedPayment {
+        stripe_price_id,
+        amount_paid,
+        currency,
+        cart,
+    })
+}
+
+/// Reject a purchase whose captured amount or currency doesn't match the known package price
+async fn verify_charge_matches_package_price(
+    stripe_price_id: &str,
+    amount_paid: i64,
+    currency: &str,
+    app: &tauri::AppHandle,
+) -> Result<(), StripeError> {
+    let db_config = crate::database::get_authenticated_db(app).await?;
+    
+    let http_client = crate::http::client();
+    let response = http_client
+        .get(format!("{}/rest/v1/package_prices", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("stripe_price_id", format!("eq.{}", stripe_price_id))])
+        .query(&[("select", "amount_cents,currency")])
+        .send()
+        .await
+        .map_err(|e| crate::http::request_error(crate::http::OperationClass::Read, app, "Failed to query package price", e))?;
+    
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(crate::database::access_error(status.as_u16(), &error_text, "package_prices")
+            .map(StripeError::from)
+            .unwrap_or_else(|| format!("Failed to query package price: HTTP {} - {}", status, error_text).into()));
+    }
+    
+    let prices: Vec<serde_json::Value> = response.json().await.map_err(|e| {
+        format!("Failed to parse package price response: {}", e)
+    })?;
+    
+    let price = prices.first().ok_or_else(|| StripeError::InvalidRequest {
+        message: format!("Unknown package price: {}", stripe_price_id),
+    })?;
+    
+    let expected_amount = price["amount_cents"].as_i64().unwrap_or(-1);
+    let expected_currency = price["currency"].as_str().unwrap_or_default();
+    
+    if expected_amount != amount_paid || !expected_currency.eq_ignore_ascii_case(currency) {
+        return Err(StripeError::InvalidRequest {
+            message: format!(
+                "Charged {} {} does not match package price {} ({} {})",
+                amount_paid, currency, stripe_price_id, expected_amount, expected_currency
+            ),
+        });
+    }
+    
+    Ok(())
+}
+
 /// Verify payment intent status
 #[tauri::command]
 pub async fn verify_payment_intent(
     payment_intent_id: String,
-) -> Result<serde_json::Value, String> {
+) -> Result<serde_json::Value, StripeError> {
 
     
     let client = get_stripe_client()?;
@@ -1515,8 +3637,7 @@ pub async fn verify_payment_intent(
         .map_err(|e| format!("Invalid payment intent ID: {}", e))?;
     
     let payment_intent = stripe::PaymentIntent::retrieve(&client, &payment_intent_stripe_id, &[])
-        .await
-        .map_err(|e| format!("Failed to retrieve payment intent: {}", e))?;
+        .await?;
     
     Ok(serde_json::json!({
         "id": payment_intent.id.to_string(),
@@ -1528,50 +3649,71 @@ pub async fn verify_payment_intent(
     }))
 }
 
-/// Create the missing package_price record directly
+/// Add a missing package_price row for a price on an existing package. Admin only.
+/// `token_amount` is what a purchase grants and must be positive.
+/// For whole packages use database::seed_catalog instead.
 #[tauri::command]
 pub async fn create_missing_package_price(
+    stripe_product_id: String,
+    stripe_price_id: String,
+    amount_cents: i64,
+    currency: String,
+    token_amount: i64,
     app: tauri::AppHandle,
 ) -> Result<String, String> {
-
+    crate::database::assert_admin(&app).await?;
+    
+    if token_amount <= 0 {
+        return Err(format!("Token amount must be positive, got {}", token_amount));
+    }
     
     let db_config = crate::database::get_authenticated_db(&app).await.map_err(|e| {
         format!("Failed to get database config: {}", e)
     })?;
     
-    let http_client = reqwest::Client::new();
+    let http_client = crate::http::client();
     
     // First get the package ID
     let package_response = http_client
-        .get(&format!("{}/rest/v1/packages?select=id&stripe_product_id=eq.prod_SqniwA0Verdhlk", db_config.database_url))
+        .get(format!("{}/rest/v1/packages", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
+        .query(&[("stripe_product_id", format!("eq.{}", stripe_product_id))])
+        .query(&[("select", "id")])
         .send()
         .await
         .map_err(|e| format!("Failed to get package: {}", e))?;
     
-    let package_text = package_response.text().await.map_err(|e| format!("Failed to read package response: {}", e))?;
-    let package_data: serde_json::Value = serde_json::from_str(&package_text).map_err(|e| format!("Failed to parse package response: {}", e))?;
-    let package_array = package_data.as_array().ok_or("Package response is not an array")?;
-    
-    if package_array.is_empty() {
-        return Err("Package not found - run create_missing_package first".to_string());
+    if !package_response.status().is_success() {
+        let status = package_response.status();
+        let error_text = package_response.text().await.unwrap_or_default();
+        return Err(format!("Failed to get package: HTTP {} - {}", status, error_text));
     }
     
-    let package_id = package_array[0]["id"].as_str().ok_or("Missing package id")?;
+    let package_array: Vec<serde_json::Value> = package_response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse package response: {}", e))?;
+    
+    let package_id = package_array.first()
+        .and_then(|package| package["id"].as_str())
+        .ok_or_else(|| format!("No package found with stripe_product_id {} - seed it with seed_catalog first", stripe_product_id))?;
     
     // Create the package_price
     let price_data = serde_json::json!({
         "package_id": package_id,
-        "stripe_price_id": "price_1Rv67RQdTny8lgOgb2EwXy2v",
-        "amount_cents": 15999,
-        "currency": "aud",
+        "stripe_price_id": stripe_price_id,
+        "amount_cents": amount_cents,
+        "currency": currency.to_lowercase(),
         "interval_type": "one_time",
+        "token_amount": token_amount,
         "is_active": true
     });
     
     let response = http_client
-        .post(&format!("{}/rest/v1/package_prices", db_config.database_url))
+        .post(format!("{}/rest/v1/package_prices", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -1592,56 +3734,11 @@ pub async fn create_missing_package_price(
     Ok(format!("Package price created successfully: {}", response_text))
 }
 
-/// Create the missing package directly using SQL
-#[tauri::command]
-pub async fn create_missing_package(
-    app: tauri::AppHandle,
-) -> Result<String, String> {
-
-    
-    let db_config = crate::database::get_authenticated_db(&app).await.map_err(|e| {
-        format!("Failed to get database config: {}", e)
-    })?;
-    
-    let http_client = reqwest::Client::new();
-    
-    // Create the package
-    let package_data = serde_json::json!({
-        "name": "Token Packages",
-        "description": "Flexible token packages with bulk discounts",
-        "stripe_product_id": "prod_SqniwA0Verdhlk",
-        "token_amount": 100,
-        "bonus_percentage": 0,
-        "features": ["Flexible token amounts", "Bulk discounts", "All features", "Priority support"]
-    });
-    
-    let response = http_client
-        .post(&format!("{}/rest/v1/packages", db_config.database_url))
-        .header("Authorization", format!("Bearer {}", db_config.access_token))
-        .header("apikey", &db_config.anon_key)
-        .header("Content-Type", "application/json")
-        .header("Prefer", "return=representation")
-        .json(&package_data)
-        .send()
-        .await
-        .map_err(|e| format!("Failed to create package: {}", e))?;
-    
-    if !response.status().is_success() {
-        let status = response.status();
-        let error_text = response.text().await.unwrap_or_default();
-        return Err(format!("Failed to create package: HTTP {} - {}", status, error_text));
-    }
-    
-    let response_text = response.text().await.map_err(|e| format!("Failed to read response: {}", e))?;
-    
-    Ok(format!("Package created successfully: {}", response_text))
-}
-
 /// Debug function to get Stripe product ID from a known price ID
 #[tauri::command]
 pub async fn debug_get_product_id_from_price(
     price_id: String,
-) -> Result<String, String> {
+) -> Result<String, StripeError> {
 
     
     let stripe_client = get_stripe_client()?;
@@ -1656,7 +3753,7 @@ pub async fn debug_get_product_id_from_price(
     let product_id = match stripe_price.product {
         Some(stripe::Expandable::Id(id)) => id.to_string(),
         Some(stripe::Expandable::Object(product)) => product.id.to_string(),
-        None => return Err("Price has no associated product".to_string()),
+        None => return Err("Price has no associated product".into()),
     };
     
     let amount = stripe_price.unit_amount.unwrap_or(0);
@@ -1677,11 +3774,12 @@ pub async fn debug_database_schema(
         format!("Failed to get database config: {}", e)
     })?;
     
-    let http_client = reqwest::Client::new();
+    let http_client = crate::http::client();
     
     // Check if purchases table exists
     let response = http_client
-        .get(&format!("{}/rest/v1/purchases?limit=0", db_config.database_url))
+        .get(format!("{}/rest/v1/purchases?limit=0", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .send()
@@ -1692,7 +3790,8 @@ pub async fn debug_database_schema(
     
     // Check profiles table structure
     let profile_response = http_client
-        .get(&format!("{}/rest/v1/profiles?select=total_tokens,tokens_remaining,tokens_used&limit=1", db_config.database_url))
+        .get(format!("{}/rest/v1/profiles?select=total_tokens,tokens_remaining,tokens_used&limit=1", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .send()
@@ -1709,7 +3808,7 @@ pub async fn debug_database_schema(
 pub async fn sync_stripe_prices_to_database(
     stripe_product_id: String,
     app: tauri::AppHandle,
-) -> Result<String, String> {
+) -> Result<String, StripeError> {
 
     
     let stripe_client = get_stripe_client()?;
@@ -1717,7 +3816,7 @@ pub async fn sync_stripe_prices_to_database(
         format!("Failed to get database config: {}", e)
     })?;
     
-    let http_client = reqwest::Client::new();
+    let http_client = crate::http::client();
     
     // First, find the package in our database by stripe_product_id
     let package_query_url = format!("{}/rest/v1/packages?select=id,name&stripe_product_id=eq.{}", 
@@ -1725,6 +3824,7 @@ pub async fn sync_stripe_prices_to_database(
     
     let package_response = http_client
         .get(&package_query_url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .send()
@@ -1737,7 +3837,7 @@ pub async fn sync_stripe_prices_to_database(
     let package_array = package_data.as_array().ok_or("Package response is not an array")?;
     
     if package_array.is_empty() {
-        return Err(format!("No package found with stripe_product_id: {}", stripe_product_id));
+        return Err(format!("No package found with stripe_product_id: {}", stripe_product_id).into());
     }
     
     let package = &package_array[0];
@@ -1757,33 +3857,11 @@ pub async fn sync_stripe_prices_to_database(
     
     // Insert each price into the database
     for price in prices.data {
-        let interval_type = if let Some(recurring) = &price.recurring {
-            match recurring.interval {
-                stripe::RecurringInterval::Day => "day",
-                stripe::RecurringInterval::Week => "week", 
-                stripe::RecurringInterval::Month => "month",
-                stripe::RecurringInterval::Year => "year",
-            }
-        } else {
-            "one_time"
-        };
-        
-        let interval_count = price.recurring.as_ref()
-            .map(|r| r.interval_count as i64)
-            .unwrap_or(1);
-        
-        let price_data = serde_json::json!({
-            "package_id": package_id,
-            "stripe_price_id": price.id.to_string(),
-            "amount_cents": price.unit_amount.unwrap_or(0),
-            "currency": price.currency.map(|c| c.to_string()).unwrap_or("usd".to_string()),
-            "interval_type": interval_type,
-            "interval_count": interval_count,
-            "is_active": true
-        });
+        let price_data = package_price_row(package_id, &price);
         
         let response = http_client
-            .post(&format!("{}/rest/v1/package_prices", db_config.database_url))
+            .post(format!("{}/rest/v1/package_prices", db_config.database_url))
+            .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
             .header("Authorization", format!("Bearer {}", db_config.access_token))
             .header("apikey", &db_config.anon_key)
             .header("Content-Type", "application/json")
@@ -1801,6 +3879,225 @@ pub async fn sync_stripe_prices_to_database(
     Ok(format!("Synced {} prices for package '{}'", synced_count, package_name))
 }
 
+/// package_prices row mirroring a Stripe price. token_amount is left out so a sync
+/// never overwrites the tokens configured for an existing tier.
+pub(crate) fn package_price_row(package_id: &str, price: &stripe::Price) -> serde_json::Value {
+    let interval_type = if let Some(recurring) = &price.recurring {
+        match recurring.interval {
+            stripe::RecurringInterval::Day => "day",
+            stripe::RecurringInterval::Week => "week", 
+            stripe::RecurringInterval::Month => "month",
+            stripe::RecurringInterval::Year => "year",
+        }
+    } else {
+        "one_time"
+    };
+    
+    let interval_count = price.recurring.as_ref()
+        .map(|r| r.interval_count as i64)
+        .unwrap_or(1);
+    
+    serde_json::json!({
+        "package_id": package_id,
+        "stripe_price_id": price.id.to_string(),
+        "amount_cents": price.unit_amount.unwrap_or(0),
+        "currency": price.currency.unwrap_or_else(default_currency).to_string(),
+        "interval_type": interval_type,
+        "interval_count": interval_count,
+        "is_active": price.active.unwrap_or(true),
+        "updated_at": chrono::Utc::now().to_rfc3339()
+    })
+}
+
+/// Compare a product's active Stripe prices with its active package_prices rows without
+/// changing anything, e.g. to check what sync_stripe_prices_to_database would do
+#[tauri::command]
+pub async fn audit_price_sync(
+    stripe_product_id: String,
+    app: tauri::AppHandle,
+) -> Result<PriceSyncAudit, StripeError> {
+    let stripe_client = get_stripe_client()?;
+    
+    let mut list_params = stripe::ListPrices::new();
+    list_params.product = Some(stripe::IdOrCreate::Id(&stripe_product_id));
+    list_params.active = Some(true);
+    list_params.limit = Some(100);
+    
+    let stripe_prices: Vec<(String, i64, String)> = stripe::Price::list(&stripe_client, &list_params)
+        .await?
+        .data
+        .into_iter()
+        .map(|price| (
+            price.id.to_string(),
+            price.unit_amount.unwrap_or(0),
+            price.currency.unwrap_or_else(default_currency).to_string(),
+        ))
+        .collect();
+    
+    let db_config = crate::database::get_authenticated_db(&app).await.map_err(|e| {
+        format!("Failed to get database config: {}", e)
+    })?;
+    
+    let response = crate::http::client()
+        .get(format!("{}/rest/v1/package_prices", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[
+            ("select", "stripe_price_id,amount_cents,currency,packages!inner(stripe_product_id)".to_string()),
+            ("packages.stripe_product_id", format!("eq.{}", stripe_product_id)),
+            ("is_active", "eq.true".to_string()),
+        ])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to query package prices: {}", e))?;
+    
+    if !response.status().is_success() {
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Database error fetching package prices: {}", error_text).into());
+    }
+    
+    let rows: Vec<serde_json::Value> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse package prices response: {}", e))?;
+    let database_prices: Vec<(String, i64, String)> = rows.iter()
+        .filter_map(|row| Some((
+            row["stripe_price_id"].as_str()?.to_string(),
+            row["amount_cents"].as_i64()?,
+            row["currency"].as_str()?.to_string(),
+        )))
+        .collect();
+    
+    Ok(compare_price_sets(&stripe_prices, &database_prices))
+}
+
+// Both sides are (stripe_price_id, amount_cents, currency)
+fn compare_price_sets(
+    stripe_prices: &[(String, i64, String)],
+    database_prices: &[(String, i64, String)],
+) -> PriceSyncAudit {
+    let mut audit = PriceSyncAudit::default();
+    
+    for (price_id, amount, currency) in stripe_prices {
+        match database_prices.iter().find(|(id, _, _)| id == price_id) {
+            None => audit.only_in_stripe.push(price_id.clone()),
+            Some((_, db_amount, db_currency)) => {
+                if db_amount != amount || !db_currency.eq_ignore_ascii_case(currency) {
+                    audit.mismatched.push(PriceMismatch {
+                        stripe_price_id: price_id.clone(),
+                        stripe_amount_cents: *amount,
+                        stripe_currency: currency.clone(),
+                        database_amount_cents: *db_amount,
+                        database_currency: db_currency.clone(),
+                    });
+                }
+            },
+        }
+    }
+    
+    audit.only_in_database = database_prices.iter()
+        .filter(|(id, _, _)| !stripe_prices.iter().any(|(stripe_id, _, _)| stripe_id == id))
+        .map(|(id, _, _)| id.clone())
+        .collect();
+    
+    audit.in_sync = audit.only_in_stripe.is_empty()
+        && audit.only_in_database.is_empty()
+        && audit.mismatched.is_empty();
+    audit
+}
+
+/// Retire a price so it can no longer be bought, and hide it from package_prices
+#[tauri::command]
+pub async fn archive_price(
+    price_id: String,
+    app: tauri::AppHandle,
+) -> Result<ArchiveResult, StripeError> {
+    crate::database::assert_admin(&app).await?;
+    let client = get_stripe_client()?;
+    
+    let parsed_id: stripe::PriceId = price_id.parse()
+        .map_err(|_| "Invalid price ID".to_string())?;
+    let mut params = stripe::UpdatePrice::new();
+    params.active = Some(false);
+    
+    let price = Price::update(&client, &parsed_id, params).await?;
+    let database_rows_updated = set_catalog_row_active("package_prices", "stripe_price_id", &price_id, false, &app).await?;
+    
+    Ok(ArchiveResult {
+        id: price.id.to_string(),
+        active: price.active.unwrap_or(false),
+        database_rows_updated,
+    })
+}
+
+/// Retire a product so none of its prices can be bought, and hide its package
+#[tauri::command]
+pub async fn archive_product(
+    product_id: String,
+    app: tauri::AppHandle,
+) -> Result<ArchiveResult, StripeError> {
+    crate::database::assert_admin(&app).await?;
+    let client = get_stripe_client()?;
+    
+    let parsed_id: stripe::ProductId = product_id.parse()
+        .map_err(|_| "Invalid product ID".to_string())?;
+    let mut params = stripe::UpdateProduct::new();
+    params.active = Some(false);
+    
+    let product = Product::update(&client, &parsed_id, params).await?;
+    let database_rows_updated = set_catalog_row_active("packages", "stripe_product_id", &product_id, false, &app).await?;
+    
+    Ok(ArchiveResult {
+        id: product.id.to_string(),
+        active: product.active.unwrap_or(false),
+        database_rows_updated,
+    })
+}
+
+/// Mirror a Stripe active flag onto the catalog row keyed by its Stripe id
+async fn set_catalog_row_active(
+    table: &str,
+    stripe_id_column: &str,
+    stripe_id: &str,
+    active: bool,
+    app: &tauri::AppHandle,
+) -> Result<usize, String> {
+    let db_config = crate::database::get_authenticated_db(app).await.map_err(|e| {
+        format!("Failed to get database config: {}", e)
+    })?;
+    
+    let response = crate::http::client()
+        .patch(format!("{}/rest/v1/{}", db_config.database_url, table))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=representation")
+        .query(&[(stripe_id_column, format!("eq.{}", stripe_id))])
+        .query(&[("select", "id")])
+        .json(&serde_json::json!({
+            "is_active": active,
+            "updated_at": chrono::Utc::now().to_rfc3339()
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to update {}: {}", table, e))?;
+    
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to update {}: {} - {}", table, status, error_text));
+    }
+    
+    let rows: Vec<serde_json::Value> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse {} update response: {}", table, e))?;
+    
+    Ok(rows.len())
+}
+
 // ============================================================================
 // STRIPE CONNECT FUNCTIONALITY
 // ============================================================================
@@ -1812,14 +4109,14 @@ pub async fn create_connect_account(
     contractor_type: String, // "individual" or "business"
     email: String,
     app: tauri::AppHandle,
-) -> Result<ConnectAccountResponse, String> {
+) -> Result<ConnectAccountResponse, StripeError> {
     let client = get_stripe_client()?;
     
     // Determine account type
     let account_type = match contractor_type.as_str() {
         "individual" => AccountType::Express,
         "business" => AccountType::Express,
-        _ => return Err("Invalid contractor type. Must be 'individual' or 'business'".to_string()),
+        _ => return Err("Invalid contractor type. Must be 'individual' or 'business'".into()),
     };
     
     let business_type = match contractor_type.as_str() {
@@ -1871,9 +4168,6 @@ pub async fn create_connect_account(
     
     let account_id = account.id.to_string();
     
-    // Create onboarding link
-    let onboarding_url = create_account_onboarding_link(account_id.clone()).await?;
-    
     // Store in database
     println!("🔄 Storing Connect account in database...");
     store_connect_account_in_db(
@@ -1891,20 +4185,32 @@ pub async fn create_connect_account(
     
     Ok(ConnectAccountResponse {
         account_id,
-        onboarding_url,
         requirements_completed: false,
         charges_enabled: account.charges_enabled.unwrap_or(false),
         payouts_enabled: account.payouts_enabled.unwrap_or(false),
     })
 }
 
-/// Create an account onboarding link for Stripe Connect
+/// Create an account onboarding link for the signed-in contractor's Connect account.
+/// Opening onboarding submits the contractor to Stripe, so it is refused with an
+/// `invalid_request` error until validate_contractor_completeness passes.
 #[tauri::command]
 pub async fn create_account_onboarding_link(
     account_id: String,
-) -> Result<String, String> {
+    app: tauri::AppHandle,
+) -> Result<String, StripeError> {
     let client = get_stripe_client()?;
     
+    let user = crate::session::get_auth_user(app.clone()).await?;
+    let contractor = crate::database::get_contractor_profile(user.id, app.clone()).await?
+        .ok_or("Contractor profile not found")?;
+    if contractor.stripe_connect_account_id.as_deref() != Some(account_id.as_str()) {
+        return Err(StripeError::Forbidden {
+            message: "This Connect account does not belong to you".to_string(),
+        });
+    }
+    crate::database::ensure_contractor_complete(&contractor.id, &app).await?;
+    
     let account_id = AccountId::from_str(&account_id)
         .map_err(|e| format!("Invalid account ID: {}", e))?;
     
@@ -1924,11 +4230,71 @@ pub async fn create_account_onboarding_link(
     Ok(account_link.url)
 }
 
+/// Regenerate an onboarding link for a contractor whose previous link has expired
+#[tauri::command]
+pub async fn refresh_onboarding_link(
+    user_id: String,
+    refresh_url: String,
+    return_url: String,
+    app: tauri::AppHandle,
+) -> Result<OnboardingLinkResponse, StripeError> {
+    let client = get_stripe_client()?;
+    
+    crate::database::ensure_session_user(&user_id, &app).await?;
+    
+    // Resolve the contractor's Connect account from the database
+    let contractor = crate::database::get_contractor_profile(user_id, app.clone()).await?
+        .ok_or("Contractor profile not found")?;
+    crate::database::ensure_contractor_complete(&contractor.id, &app).await?;
+    let account_id = contractor.stripe_connect_account_id
+        .ok_or("Contractor does not have a Stripe Connect account")?;
+    
+    let account_id = AccountId::from_str(&account_id)
+        .map_err(|e| format!("Invalid account ID: {}", e))?;
+    
+    let account = Account::retrieve(&client, &account_id, &[])
+        .await
+        .map_err(|e| format!("Failed to retrieve account: {}", e))?;
+    
+    // A fully onboarded account has nothing left to collect, so a new link is pointless
+    if is_fully_onboarded(&account) {
+        return Err(format!("Connect account {} is already fully onboarded", account_id).into());
+    }
+    
+    let mut params = stripe::CreateAccountLink::new(
+        account_id,
+        stripe::AccountLinkType::AccountOnboarding,
+    );
+    params.refresh_url = Some(&refresh_url);
+    params.return_url = Some(&return_url);
+    
+    let account_link = stripe::AccountLink::create(&client, params)
+        .await
+        .map_err(|e| format!("Failed to create onboarding link: {}", e))?;
+    
+    Ok(OnboardingLinkResponse {
+        url: account_link.url,
+        expires_at: account_link.expires_at,
+    })
+}
+
+// Details submitted, charges and payouts enabled, and nothing currently due
+fn is_fully_onboarded(account: &Account) -> bool {
+    let currently_due_empty = account.requirements.as_ref()
+        .and_then(|r| r.currently_due.as_ref())
+        .is_none_or(|v| v.is_empty());
+
+    account.details_submitted.unwrap_or(false)
+        && account.charges_enabled.unwrap_or(false)
+        && account.payouts_enabled.unwrap_or(false)
+        && currently_due_empty
+}
+
 /// Get Connect account status and requirements
 #[tauri::command]
 pub async fn get_connect_account_status(
     account_id: String,
-) -> Result<ConnectAccountStatus, String> {
+) -> Result<ConnectAccountStatus, StripeError> {
     let client = get_stripe_client()?;
     
     let account_id = AccountId::from_str(&account_id)
@@ -1944,20 +4310,77 @@ pub async fn get_connect_account_status(
         account_id: account.id.to_string(),
         charges_enabled: account.charges_enabled.unwrap_or(false),
         payouts_enabled: account.payouts_enabled.unwrap_or(false),
-        requirements_completed: requirements.currently_due.as_ref().map_or(true, |v| v.is_empty()) && 
-                               requirements.eventually_due.as_ref().map_or(true, |v| v.is_empty()),
+        requirements_completed: requirements.currently_due.as_ref().is_none_or(|v| v.is_empty()) && 
+                               requirements.eventually_due.as_ref().is_none_or(|v| v.is_empty()),
         requirements_pending: requirements.pending_verification.unwrap_or_default(),
         requirements_eventually_due: requirements.eventually_due.unwrap_or_default(),
         requirements_currently_due: requirements.currently_due.unwrap_or_default(),
+        disabled_reason: requirements.disabled_reason,
     })
 }
 
+/// Poll a contractor's Connect account after onboarding until charges are enabled,
+/// Stripe rejects the account, or `timeout_secs` elapses. Emits
+/// `connect-verification-progress` after every check and returns the last status seen.
+#[tauri::command]
+pub async fn await_connect_verification(
+    user_id: String,
+    timeout_secs: u64,
+    request_id: Option<String>,
+    registry: tauri::State<'_, crate::cancellation::CancellationRegistry>,
+    app: tauri::AppHandle,
+) -> Result<ConnectAccountStatus, StripeError> {
+    crate::database::ensure_session_user(&user_id, &app).await?;
+    
+    let request = registry.register(request_id.as_deref());
+    
+    let contractor = crate::database::get_contractor_profile(user_id, app.clone()).await?
+        .ok_or("Contractor profile not found")?;
+    let account_id = contractor.stripe_connect_account_id
+        .ok_or("Contractor does not have a Stripe Connect account")?;
+    
+    let started = std::time::Instant::now();
+    let timeout = std::time::Duration::from_secs(timeout_secs.min(CONNECT_VERIFY_MAX_TIMEOUT_SECS));
+    let mut delay = std::time::Duration::from_secs(CONNECT_VERIFY_INITIAL_DELAY_SECS);
+    let mut attempt = 0;
+    
+    loop {
+        attempt += 1;
+        let status = get_connect_account_status(account_id.clone()).await?;
+        
+        let _ = app.emit("connect-verification-progress", ConnectVerificationProgress {
+            account_id: status.account_id.clone(),
+            attempt,
+            elapsed_secs: started.elapsed().as_secs(),
+            charges_enabled: status.charges_enabled,
+            disabled_reason: status.disabled_reason.clone(),
+        });
+        
+        // Rejections (rejected.fraud, rejected.terms_of_service, ...) never recover on their own
+        let rejected = status.disabled_reason.as_deref()
+            .is_some_and(|reason| reason.starts_with("rejected"));
+        if status.charges_enabled || rejected {
+            return Ok(status);
+        }
+        
+        let remaining = timeout.saturating_sub(started.elapsed());
+        if remaining.is_zero() {
+            return Ok(status);
+        }
+        
+        if !request.token.sleep(delay.min(remaining)).await {
+            return Err(StripeError::cancelled("Connect verification"));
+        }
+        delay = (delay * 2).min(std::time::Duration::from_secs(CONNECT_VERIFY_MAX_DELAY_SECS));
+    }
+}
+
 /// Update Connect account with KYC information
 #[tauri::command]
 pub async fn update_connect_account_kyc(
     account_id: String,
     kyc_data: KycFormData,
-) -> Result<String, String> {
+) -> Result<String, StripeError> {
     let client = get_stripe_client()?;
     
     let account_id = AccountId::from_str(&account_id)
@@ -1990,12 +4413,13 @@ async fn store_connect_account_in_db(
         format!("Failed to get database config: {}", e)
     })?;
     
-    let http_client = reqwest::Client::new();
+    let http_client = crate::http::client();
     
     // First, get the user's profile to get profile_id
     println!("🔍 Fetching user profile for user_id: {}", user_id);
     let profile_response = http_client
-        .get(&format!("{}/rest/v1/profiles", db_config.database_url))
+        .get(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .query(&[("id", format!("eq.{}", user_id))])
@@ -2032,7 +4456,8 @@ async fn store_connect_account_in_db(
     println!("📋 Creating contractor record with data: {:?}", contractor_data);
     
     let response = http_client
-        .post(&format!("{}/rest/v1/contractors", db_config.database_url))
+        .post(format!("{}/rest/v1/contractors", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -2058,7 +4483,8 @@ async fn store_connect_account_in_db(
     });
     
     let profile_response = http_client
-        .patch(&format!("{}/rest/v1/profiles", db_config.database_url))
+        .patch(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .header("Content-Type", "application/json")
@@ -2087,10 +4513,11 @@ pub async fn get_contractor_status(
         format!("Failed to get database config: {}", e)
     })?;
     
-    let http_client = reqwest::Client::new();
+    let http_client = crate::http::client();
     
     let response = http_client
-        .get(&format!("{}/rest/v1/contractor_kyc_status", db_config.database_url))
+        .get(format!("{}/rest/v1/contractor_kyc_status", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, &app))
         .header("Authorization", format!("Bearer {}", db_config.access_token))
         .header("apikey", &db_config.anon_key)
         .query(&[("user_id", format!("eq.{}", user_id))])
@@ -2119,7 +4546,7 @@ pub async fn open_url_in_browser(_app: tauri::AppHandle, url: String) -> Result<
 
 /// Debug Stripe Connect account creation capabilities
 #[tauri::command]
-pub async fn debug_stripe_connect_status() -> Result<serde_json::Value, String> {
+pub async fn debug_stripe_connect_status() -> Result<serde_json::Value, StripeError> {
     let client = get_stripe_client()?;
     
     // Try to create a minimal test account to see what error we get
@@ -2171,6 +4598,143 @@ pub async fn update_connect_account_business(
     Err("API-based onboarding not yet implemented. Please use hosted onboarding.".to_string())
 }
 
+// Merchant category codes contractors can pick, covering the trades and services Aura supports
+const KNOWN_MCCS: &[&str] = &[
+    "0742", // Veterinary services
+    "0780", // Landscaping and horticultural services
+    "1520", // General contractors - residential and commercial
+    "1711", // Heating, plumbing and air conditioning contractors
+    "1731", // Electrical contractors
+    "1740", // Masonry, stonework, tile setting, plastering and insulation
+    "1750", // Carpentry contractors
+    "1761", // Roofing, siding and sheet metal contractors
+    "1771", // Concrete work contractors
+    "1799", // Special trade contractors
+    "4121", // Taxicabs and limousines
+    "4214", // Motor freight carriers and trucking
+    "5734", // Computer software stores
+    "5817", // Digital goods - applications
+    "5818", // Digital goods - large digital goods merchant
+    "7230", // Beauty and barber shops
+    "7299", // Miscellaneous personal services
+    "7333", // Commercial photography, art and graphics
+    "7349", // Cleaning, maintenance and janitorial services
+    "7372", // Computer programming and data processing
+    "7392", // Management, consulting and public relations services
+    "7399", // Business services
+    "7542", // Car washes
+    "7623", // Air conditioning and refrigeration repair
+    "7629", // Electrical and small appliance repair
+    "7699", // Miscellaneous repair shops
+    "8011", // Doctors
+    "8099", // Medical services and health practitioners
+    "8111", // Legal services and attorneys
+    "8299", // Schools and educational services
+    "8931", // Accounting, auditing and bookkeeping services
+    "8999", // Professional services
+];
+
+fn validate_mcc(mcc: &str) -> Result<(), StripeError> {
+    if KNOWN_MCCS.contains(&mcc) {
+        Ok(())
+    } else {
+        Err(StripeError::InvalidRequest {
+            message: format!("Unsupported merchant category code: {}", mcc),
+        })
+    }
+}
+
+/// Update the MCC, website and support email on a contractor's Connect account after
+/// onboarding, and mirror them onto the contractor record. Fields left as None are unchanged.
+#[tauri::command]
+pub async fn update_connect_business_profile(
+    user_id: String,
+    mcc: Option<String>,
+    url: Option<String>,
+    support_email: Option<String>,
+    app: tauri::AppHandle,
+) -> Result<crate::database::Contractor, StripeError> {
+    crate::database::ensure_session_user(&user_id, &app).await?;
+    
+    let mcc = mcc.map(|mcc| mcc.trim().to_string());
+    let url = url.map(|url| url.trim().to_string());
+    let support_email = support_email.map(|email| email.trim().to_string());
+
+    if let Some(mcc) = &mcc {
+        validate_mcc(mcc)?;
+    }
+    if let Some(email) = &support_email {
+        if !email.contains('@') {
+            return Err(StripeError::InvalidRequest {
+                message: format!("Invalid support email: {}", email),
+            });
+        }
+    }
+    if mcc.is_none() && url.is_none() && support_email.is_none() {
+        return Err(StripeError::InvalidRequest {
+            message: "Nothing to update: provide an MCC, URL or support email".to_string(),
+        });
+    }
+
+    let contractor = crate::database::get_contractor_profile(user_id, app.clone()).await?
+        .ok_or("Contractor profile not found")?;
+    let account_id = contractor.stripe_connect_account_id
+        .ok_or("Contractor does not have a Stripe Connect account")?;
+
+    let client = get_stripe_client()?;
+    let stripe_account_id = AccountId::from_str(&account_id)
+        .map_err(|e| format!("Invalid account ID: {}", e))?;
+
+    let mut update_params = UpdateAccount::new();
+    update_params.business_profile = Some(stripe::BusinessProfile {
+        mcc: mcc.clone(),
+        url: url.clone(),
+        support_email: support_email.clone(),
+        ..Default::default()
+    });
+
+    Account::update(&client, &stripe_account_id, update_params).await?;
+
+    let mut contractor_update = serde_json::Map::new();
+    if let Some(mcc) = &mcc {
+        contractor_update.insert("industry_mcc_code".to_string(), serde_json::json!(mcc));
+    }
+    if let Some(url) = &url {
+        contractor_update.insert("business_website_url".to_string(), serde_json::json!(url));
+    }
+    if let Some(email) = &support_email {
+        contractor_update.insert("business_support_email".to_string(), serde_json::json!(email));
+    }
+    contractor_update.insert("updated_at".to_string(), serde_json::json!(chrono::Utc::now().to_rfc3339()));
+
+    let db_config = crate::database::get_authenticated_db(&app).await?;
+
+    let response = crate::http::client()
+        .patch(format!("{}/rest/v1/contractors", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=representation")
+        .query(&[("id", format!("eq.{}", contractor.id))])
+        .json(&contractor_update)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to update contractor: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(crate::database::response_error(response, "contractors").await.into());
+    }
+
+    let contractors: Vec<crate::database::Contractor> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse contractor response: {}", e))?;
+
+    contractors.into_iter().next()
+        .ok_or_else(|| format!("Contractor {} was not updated", contractor.id).into())
+}
+
 /// Add bank account to Connect account
 #[tauri::command]
 pub async fn add_connect_account_bank_account(
@@ -2186,11 +4750,239 @@ pub async fn add_connect_account_bank_account(
     Err("Bank account setup not yet implemented. Please use hosted onboarding.".to_string())
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BankAccountReplacement {
+    pub bank_account_id: String,
+    pub last4: Option<String>,
+    pub bank_name: Option<String>,
+    pub removed_bank_account_ids: Vec<String>,
+}
+
+/// Strip the spaces and dashes people type into bank numbers, then check the routing number
+/// (9-digit ABA with checksum in the US, 6-digit BSB in Australia) and account number format
+fn normalize_bank_numbers(
+    country: &str,
+    routing_number: &str,
+    account_number: &str,
+) -> Result<(String, String), StripeError> {
+    let clean = |value: &str| value.chars().filter(|c| !c.is_whitespace() && *c != '-').collect::<String>();
+    let routing = clean(routing_number);
+    let account = clean(account_number);
+    let invalid = |message: String| StripeError::InvalidRequest { message };
+
+    if !routing.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid("Routing number must only contain digits".to_string()));
+    }
+    match country.to_uppercase().as_str() {
+        "US" => {
+            if routing.len() != 9 {
+                return Err(invalid("US routing numbers are 9 digits".to_string()));
+            }
+            let digits: Vec<u32> = routing.chars().filter_map(|c| c.to_digit(10)).collect();
+            let checksum = 3 * (digits[0] + digits[3] + digits[6])
+                + 7 * (digits[1] + digits[4] + digits[7])
+                + (digits[2] + digits[5] + digits[8]);
+            if !checksum.is_multiple_of(10) {
+                return Err(invalid(format!("{} is not a valid US routing number", routing)));
+            }
+        },
+        "AU" if routing.len() != 6 => return Err(invalid("BSB numbers are 6 digits".to_string())),
+        _ if !(4..=11).contains(&routing.len()) => {
+            return Err(invalid("Routing number must be between 4 and 11 digits".to_string()));
+        },
+        _ => {},
+    }
+
+    if !account.chars().all(|c| c.is_ascii_digit()) || !(4..=17).contains(&account.len()) {
+        return Err(invalid("Account number must be between 4 and 17 digits".to_string()));
+    }
+
+    Ok((routing, account))
+}
+
+/// Replace the bank account a contractor is paid out to. The new account is added and made
+/// the default before the old ones are deleted, since Stripe won't delete the default account.
+#[tauri::command]
+pub async fn replace_connect_bank_account(
+    user_id: String,
+    new_bank_account: crate::database::ContractorBankAccount,
+    app: tauri::AppHandle,
+) -> Result<BankAccountReplacement, StripeError> {
+    crate::database::ensure_session_user(&user_id, &app).await?;
+    
+    let contractor = crate::database::get_contractor_profile(user_id, app.clone()).await?
+        .ok_or("Contractor profile not found")?;
+    let account_id = contractor.stripe_connect_account_id
+        .ok_or("Contractor does not have a Stripe Connect account")?;
+
+    let client = get_stripe_client()?;
+    let stripe_account_id = AccountId::from_str(&account_id)
+        .map_err(|e| format!("Invalid account ID: {}", e))?;
+    let account = Account::retrieve(&client, &stripe_account_id, &[]).await?;
+
+    let country = account.country.clone().unwrap_or_else(|| "US".to_string());
+    let currency = account.default_currency.unwrap_or_else(default_currency);
+    let (routing_number, account_number) = normalize_bank_numbers(
+        &country,
+        &new_bank_account.routing_number,
+        &new_bank_account.account_number,
+    )?;
+
+    let external_accounts_path = format!("/accounts/{}/external_accounts", account_id);
+    let existing = client.get_query::<stripe::List<stripe::BankAccount>, _>(
+        &external_accounts_path,
+        [("object", "bank_account"), ("limit", "100")],
+    ).await?;
+
+    let account_holder_type = match contractor.contractor_type.as_str() {
+        "business" => "company",
+        _ => "individual",
+    };
+    let created = client.post_form::<stripe::BankAccount, _>(
+        &external_accounts_path,
+        [
+            ("external_account[object]", "bank_account".to_string()),
+            ("external_account[country]", country.clone()),
+            ("external_account[currency]", currency.to_string()),
+            ("external_account[routing_number]", routing_number.clone()),
+            ("external_account[account_number]", account_number.clone()),
+            ("external_account[account_holder_name]", new_bank_account.account_holder_name.clone()),
+            ("external_account[account_holder_type]", account_holder_type.to_string()),
+        ],
+    ).await?;
+
+    // Payouts must have somewhere to go before the old account can be removed
+    client.post_form::<stripe::BankAccount, _>(
+        &format!("{}/{}", external_accounts_path, created.id),
+        [("default_for_currency", true)],
+    ).await?;
+
+    let mut removed_bank_account_ids = Vec::new();
+    for old in existing.data.iter().filter(|old| old.id != created.id) {
+        client.delete::<serde_json::Value>(&format!("{}/{}", external_accounts_path, old.id)).await?;
+        removed_bank_account_ids.push(old.id.to_string());
+    }
+
+    // Only the last four digits are kept locally; Stripe holds the full number
+    let db_config = crate::database::get_authenticated_db(&app).await?;
+    let bank_row = serde_json::json!({
+        "contractor_id": contractor.id,
+        "account_holder_name": new_bank_account.account_holder_name,
+        "account_number": created.last4.as_ref().map(|last4| format!("****{}", last4)),
+        "routing_number": routing_number,
+        "bank_name": created.bank_name.clone().unwrap_or(new_bank_account.bank_name),
+        "account_type": new_bank_account.account_type,
+        "stripe_bank_account_id": created.id.to_string(),
+        "stripe_bank_account_status": created.status.map(|status| status.to_string()),
+        "is_verified": false,
+        "verified_at": null,
+        "updated_at": chrono::Utc::now().to_rfc3339()
+    });
+
+    let http_client = crate::http::client();
+    let response = http_client
+        .delete(format!("{}/rest/v1/contractor_bank_accounts", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("contractor_id", format!("eq.{}", contractor.id))])
+        .send()
+        .await
+        .map_err(|e| format!("Failed to remove old bank account records: {}", e))?;
+    if !response.status().is_success() {
+        return Err(crate::database::response_error(response, "contractor_bank_accounts").await.into());
+    }
+
+    let response = http_client
+        .post(format!("{}/rest/v1/contractor_bank_accounts", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, &app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=minimal")
+        .json(&bank_row)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to store bank account record: {}", e))?;
+    if !response.status().is_success() {
+        return Err(crate::database::response_error(response, "contractor_bank_accounts").await.into());
+    }
+
+    Ok(BankAccountReplacement {
+        bank_account_id: created.id.to_string(),
+        last4: created.last4,
+        bank_name: created.bank_name,
+        removed_bank_account_ids,
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BankAccountStatusInfo {
+    pub bank_account_id: String,
+    pub last4: Option<String>,
+    pub bank_name: Option<String>,
+    /// new, validated, verified, verification_failed or errored
+    pub status: String,
+    /// False once verification failed or a payout bounced; Stripe stops payouts until fixed
+    pub payouts_ready: bool,
+    pub failure_reasons: Vec<String>,
+}
+
+fn bank_account_status_info(bank_account: &stripe::BankAccount) -> BankAccountStatusInfo {
+    let status = bank_account.status
+        .map(|status| status.to_string())
+        .unwrap_or_else(|| "new".to_string());
+
+    let failure_reasons = [&bank_account.requirements, &bank_account.future_requirements]
+        .into_iter()
+        .flatten()
+        .flat_map(|requirements| requirements.errors.iter().flatten())
+        .map(|error| error.reason.clone())
+        .collect::<Vec<_>>();
+
+    BankAccountStatusInfo {
+        bank_account_id: bank_account.id.to_string(),
+        last4: bank_account.last4.clone(),
+        bank_name: bank_account.bank_name.clone(),
+        payouts_ready: status != "verification_failed" && status != "errored",
+        status,
+        failure_reasons,
+    }
+}
+
+/// Verification status of the bank account a contractor is paid out to, so they know
+/// whether payouts will arrive. Returns None when no bank account has been added yet.
+#[tauri::command]
+pub async fn get_bank_account_status(
+    user_id: String,
+    app: tauri::AppHandle,
+) -> Result<Option<BankAccountStatusInfo>, StripeError> {
+    crate::database::ensure_session_user(&user_id, &app).await?;
+    
+    let contractor = crate::database::get_contractor_profile(user_id, app).await?
+        .ok_or("Contractor profile not found")?;
+    let account_id = contractor.stripe_connect_account_id
+        .ok_or("Contractor does not have a Stripe Connect account")?;
+
+    let client = get_stripe_client()?;
+    let bank_accounts = client.get_query::<stripe::List<stripe::BankAccount>, _>(
+        &format!("/accounts/{}/external_accounts", account_id),
+        [("object", "bank_account"), ("limit", "100")],
+    ).await?;
+
+    // Payouts go to the default account, so report that one when there are several
+    let bank_account = bank_accounts.data.iter()
+        .find(|bank_account| bank_account.default_for_currency == Some(true))
+        .or_else(|| bank_accounts.data.first());
+
+    Ok(bank_account.map(bank_account_status_info))
+}
+
 /// Get Connect account requirements and status
 #[tauri::command]
 pub async fn get_connect_account_requirements(
     account_id: String,
-) -> Result<serde_json::Value, String> {
+) -> Result<serde_json::Value, StripeError> {
     let client = get_stripe_client()?;
     
     let account_id = AccountId::from_str(&account_id)
@@ -2227,14 +5019,23 @@ pub struct FileUploadResponse {
     pub url: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StripeFileDownload {
+    pub file_id: String,
+    pub filename: Option<String>,
+    pub content_type: String,
+    pub bytes: Vec<u8>,
+}
+
 /// Upload file to Stripe File API
 #[tauri::command]
 pub async fn upload_file_to_stripe(
     file_path: String,
     purpose: String, // "identity_document", "additional_verification", etc.
     filename: String,
-) -> Result<FileUploadResponse, String> {
-    let client = get_stripe_client()?;
+) -> Result<FileUploadResponse, StripeError> {
+    // Fails early when Stripe isn't configured
+    get_stripe_client()?;
     
     // Read file content
     let file_content = std::fs::read(&file_path)
@@ -2258,21 +5059,31 @@ pub async fn upload_file_to_stripe(
 
 /// Upload document for contractor KYC
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn upload_contractor_document(
     contractor_id: String,
     file_path: String,
     document_type: String, // "identity_document", "address_verification", etc.
     document_purpose: String, // "account_requirement", "identity_verification", etc.
     filename: String,
+    request_id: Option<String>,
+    registry: tauri::State<'_, crate::cancellation::CancellationRegistry>,
     app: tauri::AppHandle,
-) -> Result<crate::database::DocumentUpload, String> {
+) -> Result<crate::database::DocumentUpload, StripeError> {
+    let request = registry.register(request_id.as_deref());
+    
     // First upload to Stripe
-    let stripe_response = upload_file_to_stripe(
-        file_path.clone(),
-        document_purpose.clone(),
-        filename.clone(),
+    let stripe_response = crate::http::with_timeout(
+        crate::http::OperationClass::Upload,
+        &app,
+        upload_file_to_stripe(file_path.clone(), document_purpose.clone(), filename.clone()),
     ).await?;
     
+    // The file is already on Stripe, but nothing references it until the record exists
+    if request.token.is_cancelled() {
+        return Err(StripeError::cancelled("Document upload"));
+    }
+    
     // Calculate file hash for integrity
     let file_content = std::fs::read(&file_path)
         .map_err(|e| format!("Failed to read file for hash: {}", e))?;
@@ -2321,14 +5132,81 @@ pub async fn upload_contractor_document(
         None, // verification_status unchanged
         None, // verification_notes unchanged
         app.clone(),
-    ).await
+    ).await.map_err(StripeError::from)
+}
+
+/// Retry a failed Stripe upload using the locally stored copy of the document
+#[tauri::command]
+pub async fn retry_document_upload(
+    document_id: String,
+    app: tauri::AppHandle,
+) -> Result<crate::database::DocumentUpload, StripeError> {
+    let document = crate::database::get_document_upload(document_id.clone(), app.clone()).await?
+        .ok_or("Document upload not found")?;
+    
+    let local_file_path = retry_upload_source(&document)?;
+    
+    let upload = crate::http::with_timeout(
+        crate::http::OperationClass::Upload,
+        &app,
+        upload_file_to_stripe(local_file_path, document.document_purpose, document.file_name),
+    ).await;
+    
+    match upload {
+        Ok(stripe_response) => {
+            crate::database::update_document_upload_status(
+                document_id,
+                Some(stripe_response.file_id),
+                Some("uploaded".to_string()),
+                None, // no error
+                None, // verification_status unchanged
+                None, // verification_notes unchanged
+                app,
+            ).await.map_err(StripeError::from)
+        },
+        Err(e) => {
+            // Record the new failure so the row reflects the latest attempt
+            let _ = crate::database::update_document_upload_status(
+                document_id,
+                None,
+                Some("failed".to_string()),
+                Some(e.to_string()),
+                None,
+                None,
+                app,
+            ).await;
+            Err(e)
+        }
+    }
+}
+
+// Only failed uploads are retried, and only from a local copy that's still on disk
+fn retry_upload_source(document: &crate::database::DocumentUpload) -> Result<String, StripeError> {
+    if document.stripe_upload_status != "failed" {
+        return Err(StripeError::InvalidRequest {
+            message: format!(
+                "Only failed uploads can be retried; this document is {}",
+                document.stripe_upload_status
+            ),
+        });
+    }
+    
+    match &document.local_file_path {
+        Some(path) if std::path::Path::new(path).is_file() => Ok(path.clone()),
+        Some(_) => Err(StripeError::InvalidRequest {
+            message: "The local copy of this document is gone - please select the file again".to_string(),
+        }),
+        None => Err(StripeError::InvalidRequest {
+            message: "No local file path stored for this document - please select the file again".to_string(),
+        }),
+    }
 }
 
 /// Get uploaded file from Stripe
 #[tauri::command]
 pub async fn get_stripe_file(
     file_id: String,
-) -> Result<serde_json::Value, String> {
+) -> Result<serde_json::Value, StripeError> {
     let client = get_stripe_client()?;
     
     let file_id = stripe::FileId::from_str(&file_id)
@@ -2348,6 +5226,89 @@ pub async fn get_stripe_file(
     }))
 }
 
+/// Download a Stripe file's contents so an uploaded document can be previewed.
+/// Stripe only serves file contents through a file link, which it refuses to create
+/// for sensitive purposes such as identity documents.
+#[tauri::command]
+pub async fn download_stripe_file(
+    file_id: String,
+    app: tauri::AppHandle,
+) -> Result<StripeFileDownload, StripeError> {
+    let client = get_stripe_client()?;
+    
+    let file_id = stripe::FileId::from_str(&file_id)
+        .map_err(|e| format!("Invalid file ID: {}", e))?;
+    
+    let file = stripe::File::retrieve(&client, &file_id, &[]).await?;
+    
+    if is_restricted_file_purpose(file.purpose) {
+        return Err(StripeError::InvalidRequest {
+            message: format!(
+                "File {} has purpose {} and cannot be downloaded; Stripe restricts access to these documents",
+                file.id, file.purpose
+            ),
+        });
+    }
+    
+    // Short-lived link, only needed for the download below
+    let mut link_params = stripe::CreateFileLink::new(file_id);
+    link_params.expires_at = Some(chrono::Utc::now().timestamp() + 60);
+    let file_link = stripe::FileLink::create(&client, link_params).await?;
+    let link_url = file_link.url.ok_or("Stripe did not return a URL for the file link")?;
+    
+    // Files are as large as uploads, so they get the upload timeout
+    let response = crate::http::client()
+        .get(&link_url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Upload, &app))
+        .send()
+        .await
+        .map_err(|e| StripeError::Network { message: format!("Failed to download file: {}", e) })?;
+    
+    if !response.status().is_success() {
+        return Err(format!("Failed to download file: HTTP {}", response.status()).into());
+    }
+    
+    let content_type = response.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from)
+        .unwrap_or_else(|| mime_type_for_file_type(file.type_.as_deref()).to_string());
+    
+    let bytes = response.bytes()
+        .await
+        .map_err(|e| StripeError::Network { message: format!("Failed to read file contents: {}", e) })?;
+    
+    Ok(StripeFileDownload {
+        file_id: file.id.to_string(),
+        filename: file.filename,
+        content_type,
+        bytes: bytes.to_vec(),
+    })
+}
+
+// Purposes Stripe will not create file links for
+fn is_restricted_file_purpose(purpose: stripe::FilePurpose) -> bool {
+    matches!(
+        purpose,
+        stripe::FilePurpose::IdentityDocument
+            | stripe::FilePurpose::AdditionalVerification
+            | stripe::FilePurpose::DocumentProviderIdentityDocument
+            | stripe::FilePurpose::PciDocument
+            | stripe::FilePurpose::Selfie
+            | stripe::FilePurpose::TaxDocumentUserUpload
+    )
+}
+
+fn mime_type_for_file_type(file_type: Option<&str>) -> &'static str {
+    match file_type {
+        Some("pdf") => "application/pdf",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("csv") => "text/csv",
+        _ => "application/octet-stream",
+    }
+}
+
 /// Delete file from Stripe (cleanup)
 #[tauri::command]
 pub async fn delete_stripe_file(
@@ -2360,3 +5321,260 @@ pub async fn delete_stripe_file(
     
     Ok("File deleted successfully".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn onboarded_account() -> Account {
+        Account {
+            details_submitted: Some(true),
+            charges_enabled: Some(true),
+            payouts_enabled: Some(true),
+            requirements: Some(stripe::AccountRequirements {
+                currently_due: Some(Vec::new()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn onboarded_account_needs_no_new_link() {
+        assert!(is_fully_onboarded(&onboarded_account()));
+    }
+
+    #[test]
+    fn account_with_work_left_still_needs_onboarding() {
+        let mut account = onboarded_account();
+        account.requirements = Some(stripe::AccountRequirements {
+            currently_due: Some(vec!["external_account".to_string()]),
+            ..Default::default()
+        });
+        assert!(!is_fully_onboarded(&account));
+
+        let account = Account { payouts_enabled: Some(false), ..onboarded_account() };
+        assert!(!is_fully_onboarded(&account));
+
+        assert!(!is_fully_onboarded(&Account::default()));
+    }
+
+    #[test]
+    fn bank_numbers_are_cleaned_of_spaces_and_dashes() {
+        let (routing, account) = normalize_bank_numbers("us", "0110-000 15", "000 123-456 789").unwrap();
+        assert_eq!(routing, "011000015");
+        assert_eq!(account, "000123456789");
+    }
+
+    #[test]
+    fn us_routing_number_checksum_is_enforced() {
+        assert!(normalize_bank_numbers("US", "021000021", "123456789").is_ok());
+        assert!(matches!(
+            normalize_bank_numbers("US", "011000016", "123456789"),
+            Err(StripeError::InvalidRequest { .. })
+        ));
+        assert!(normalize_bank_numbers("US", "01100001", "123456789").is_err());
+    }
+
+    #[test]
+    fn australian_bsb_must_be_six_digits() {
+        assert!(normalize_bank_numbers("AU", "082-902", "12345678").is_ok());
+        assert!(normalize_bank_numbers("AU", "08290", "12345678").is_err());
+    }
+
+    #[test]
+    fn bank_numbers_must_be_digits_of_a_sensible_length() {
+        assert!(normalize_bank_numbers("GB", "10-88-00", "00012345").is_ok());
+        assert!(normalize_bank_numbers("GB", "1088OO", "00012345").is_err());
+        assert!(normalize_bank_numbers("GB", "108800", "123").is_err());
+        assert!(normalize_bank_numbers("GB", "108800", "123456789012345678").is_err());
+    }
+
+    fn pending(user_id: &str, payment_intent_id: &str) -> PendingPurchase {
+        PendingPurchase {
+            user_id: user_id.to_string(),
+            stripe_payment_intent_id: payment_intent_id.to_string(),
+            stripe_price_id: "price_1".to_string(),
+            amount_paid: 999,
+            currency: "usd".to_string(),
+            queued_at: "2024-06-01T00:00:00Z".to_string(),
+            last_error: "offline".to_string(),
+        }
+    }
+
+    #[test]
+    fn queued_purchases_are_replayed_until_the_connection_drops_again() {
+        let mut queue = Vec::new();
+        push_pending_purchase(&mut queue, pending("user_1", "pi_1"));
+        push_pending_purchase(&mut queue, pending("user_1", "pi_2"));
+        push_pending_purchase(&mut queue, PendingPurchase { last_error: "still offline".to_string(), ..pending("user_1", "pi_1") });
+        push_pending_purchase(&mut queue, pending("user_1", "pi_3"));
+        push_pending_purchase(&mut queue, pending("user_1", "pi_4"));
+        assert_eq!(queue.len(), 4);
+        assert_eq!(queue[0].last_error, "still offline");
+
+        let replay = tauri::async_runtime::block_on(replay_pending_purchases(queue.clone(), |pending| {
+            let result = match pending.stripe_payment_intent_id.as_str() {
+                "pi_1" => Ok(()),
+                "pi_2" => Err(StripeError::InvalidRequest { message: "amount mismatch".to_string() }),
+                _ => Err(StripeError::Network { message: "offline".to_string() }),
+            };
+            async move { result }
+        }));
+
+        assert_eq!(replay.recorded, 1);
+        assert_eq!(replay.done, vec!["pi_1", "pi_2"]);
+        assert_eq!(replay.dropped.len(), 1);
+        assert_eq!(replay.dropped[0].last_error, "amount mismatch");
+
+        queue.retain(|pending| !replay.done.contains(&pending.stripe_payment_intent_id));
+        let left: Vec<&str> = queue.iter().map(|pending| pending.stripe_payment_intent_id.as_str()).collect();
+        assert_eq!(left, vec!["pi_3", "pi_4"]);
+    }
+
+    #[test]
+    fn auth_failures_keep_purchases_queued() {
+        assert!(is_retryable_purchase_error(&StripeError::NotAuthenticated { message: String::new() }));
+        assert!(is_retryable_purchase_error(&StripeError::Forbidden { message: String::new() }));
+        assert!(is_retryable_purchase_error(&StripeError::Api { message: String::new(), http_status: 401 }));
+        assert!(!is_retryable_purchase_error(&StripeError::Api { message: String::new(), http_status: 400 }));
+        assert!(!is_retryable_purchase_error(&StripeError::Other { message: "error sending request".to_string() }));
+    }
+
+    fn failed_upload(local_file_path: Option<String>) -> crate::database::DocumentUpload {
+        serde_json::from_value(serde_json::json!({
+            "id": "doc_1",
+            "contractor_id": "contractor_1",
+            "document_type": "identity_document",
+            "document_purpose": "identity_document",
+            "file_name": "passport.png",
+            "stripe_upload_status": "failed",
+            "stripe_upload_error": "Network error",
+            "local_file_path": local_file_path,
+            "verification_status": "pending"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn failed_upload_is_retried_from_its_local_copy() {
+        let path = std::env::temp_dir().join(format!("aura_retry_upload_{}.png", std::process::id()));
+        std::fs::write(&path, b"not really a png").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let source = retry_upload_source(&failed_upload(Some(path.clone())));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(source.unwrap(), path);
+    }
+
+    #[test]
+    fn upload_without_a_local_copy_cannot_be_retried() {
+        assert!(matches!(retry_upload_source(&failed_upload(None)), Err(StripeError::InvalidRequest { .. })));
+
+        let gone = std::env::temp_dir().join("aura_retry_upload_missing.png").to_string_lossy().to_string();
+        assert!(matches!(retry_upload_source(&failed_upload(Some(gone))), Err(StripeError::InvalidRequest { .. })));
+    }
+
+    #[test]
+    fn only_failed_uploads_are_retried() {
+        let document = crate::database::DocumentUpload {
+            stripe_upload_status: "uploaded".to_string(),
+            ..failed_upload(Some("/tmp/passport.png".to_string()))
+        };
+        assert!(matches!(retry_upload_source(&document), Err(StripeError::InvalidRequest { .. })));
+    }
+
+    fn rate_limit_error() -> stripe::StripeError {
+        stripe::StripeError::Stripe(stripe::RequestError {
+            http_status: 429,
+            error_type: stripe::ErrorType::RateLimit,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn rate_limited_call_is_retried_until_it_succeeds() {
+        let mut calls = 0;
+        let result = tauri::async_runtime::block_on(with_rate_limit_retry(|| {
+            calls += 1;
+            let result = if calls == 1 { Err(rate_limit_error()) } else { Ok("done") };
+            async move { result }
+        }));
+
+        assert_eq!(result.unwrap(), "done");
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn other_stripe_errors_are_not_retried() {
+        let mut calls = 0;
+        let result: Result<(), _> = tauri::async_runtime::block_on(with_rate_limit_retry(|| {
+            calls += 1;
+            async { Err(stripe::StripeError::Timeout) }
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn token_price_map_takes_precedence_over_the_price_and_the_built_in_table() {
+        assert_eq!(purchase_token_amount(Some(1200), Some(1000), 1499), 1200);
+        assert_eq!(purchase_token_amount(None, Some(1100), 1499), 1100);
+        assert_eq!(purchase_token_amount(None, None, 1499), 1000);
+        assert_eq!(purchase_token_amount(Some(0), Some(1000), 1499), 0);
+    }
+
+    #[test]
+    fn cancel_writes_a_canceled_event_and_marks_the_profile() {
+        let (app, dir) = crate::enhanced_store::tests::test_app();
+        let handle = app.handle().clone();
+        let (url, requests) = crate::http::tests::mock_server(vec![(201, serde_json::Value::Null), (204, serde_json::Value::Null)]);
+        let database = handle.store("database.store").unwrap();
+        database.set("database_url", url);
+        database.set("anon_key", "anon");
+        handle.store("session.store").unwrap().set("sb-access-token", "access");
+        let subscription: Subscription = serde_json::from_value(serde_json::json!({
+            "id": "sub_1Ot9xQ2eZvKYlo2C",
+            "object": "subscription",
+            "automatic_tax": {"enabled": false},
+            "billing_cycle_anchor": 1710000000,
+            "cancel_at_period_end": true,
+            "created": 1710000000,
+            "currency": "usd",
+            "current_period_end": 1712678400,
+            "current_period_start": 1710000000,
+            "customer": "cus_PjB8nWQ4cq1Zx0",
+            "items": {"object": "list", "data": [
+                {"id": "si_base", "object": "subscription_item", "price": {"id": "price_pro_monthly", "object": "price"}, "quantity": 1}
+            ], "has_more": false, "url": "/v1/subscription_items"},
+            "livemode": false,
+            "metadata": {},
+            "start_date": 1710000000,
+            "status": "active"
+        })).unwrap();
+
+        tauri::async_runtime::block_on(record_cancellation(
+            "user-1".into(),
+            "sub_1Ot9xQ2eZvKYlo2C".into(),
+            &subscription,
+            handle.clone(),
+        )).unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].0, "POST /rest/v1/subscription_events");
+        let event: serde_json::Value = serde_json::from_str(&requests[0].1).unwrap();
+        assert_eq!(event, serde_json::json!({
+            "user_id": "user-1",
+            "subscription_id": "sub_1Ot9xQ2eZvKYlo2C",
+            "action": "canceled",
+            "old_price_id": "price_pro_monthly",
+            "new_price_id": null
+        }));
+        assert_eq!(requests[1].0, "PATCH /rest/v1/profiles?id=eq.user-1");
+        let profile: serde_json::Value = serde_json::from_str(&requests[1].1).unwrap();
+        assert_eq!(profile["subscription_status"], "canceled");
+        assert_eq!(profile["subscription_period_end"], 1712678400);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,184 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tauri_plugin_store::StoreExt;
+
+// Environment variable naming the release feed, unless one is set in the app settings
+const UPDATE_FEED_ENV_VAR: &str = "AURA_UPDATE_FEED_URL";
+
+/// Release feed served as JSON, in the same shape as a Tauri updater manifest
+#[derive(Debug, Deserialize)]
+struct ReleaseFeed {
+    version: String,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    pub_date: Option<String>,
+    #[serde(default)]
+    platforms: HashMap<String, PlatformRelease>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PlatformRelease {
+    url: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateStatus {
+    UpdateAvailable,
+    UpToDate,
+    /// The feed couldn't be reached or read, so it's not known whether an update exists
+    Unknown,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UpdateInfo {
+    pub status: UpdateStatus,
+    pub update_available: bool,
+    pub current_version: String,
+    pub latest_version: Option<String>,
+    pub notes: Option<String>,
+    pub pub_date: Option<String>,
+    /// Download for this platform, if the release has one
+    pub download_url: Option<String>,
+    pub platform: String,
+    /// Why the status is unknown
+    pub error: Option<String>,
+}
+
+/// Check the release feed for a version newer than `current_version`. Only reports what's
+/// available; nothing is downloaded or installed. A feed that can't be reached or parsed
+/// gives an "unknown" status rather than an error, so callers can check quietly on startup.
+#[tauri::command]
+pub async fn get_update_info(
+    current_version: String,
+    app: tauri::AppHandle,
+) -> Result<UpdateInfo, String> {
+    let current = parse_version(&current_version)
+        .ok_or_else(|| format!("Invalid current version: {}", current_version))?;
+    let platform = platform_key();
+
+    let mut info = UpdateInfo {
+        status: UpdateStatus::Unknown,
+        update_available: false,
+        current_version,
+        latest_version: None,
+        notes: None,
+        pub_date: None,
+        download_url: None,
+        platform: platform.clone(),
+        error: None,
+    };
+
+    let feed = match fetch_feed(&app).await {
+        Ok(feed) => feed,
+        Err(e) => {
+            info.error = Some(e);
+            return Ok(info);
+        },
+    };
+
+    let latest = match parse_version(&feed.version) {
+        Some(latest) => latest,
+        None => {
+            info.error = Some(format!("Release feed has an invalid version: {}", feed.version));
+            return Ok(info);
+        },
+    };
+
+    let newer = latest > current;
+    info.status = if newer { UpdateStatus::UpdateAvailable } else { UpdateStatus::UpToDate };
+    info.update_available = newer;
+    info.download_url = feed.platforms.get(&platform).map(|release| release.url.clone());
+    info.latest_version = Some(feed.version);
+    info.notes = feed.notes;
+    info.pub_date = feed.pub_date;
+
+    Ok(info)
+}
+
+async fn fetch_feed(app: &tauri::AppHandle) -> Result<ReleaseFeed, String> {
+    let feed_url = feed_url(app).ok_or("No release feed is configured")?;
+
+    let response = crate::http::client()
+        .get(&feed_url)
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach the release feed: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("Release feed returned {}", response.status()));
+    }
+
+    response.json::<ReleaseFeed>().await
+        .map_err(|e| format!("Failed to parse the release feed: {}", e))
+}
+
+// The in-app setting, then the environment variable
+fn feed_url(app: &tauri::AppHandle) -> Option<String> {
+    let stored = app.store("settings.store").ok()
+        .and_then(|settings| settings.get("update_feed_url"))
+        .and_then(|url| url.as_str().map(str::to_string));
+
+    stored.or_else(|| crate::stripe::get_env_var(UPDATE_FEED_ENV_VAR).ok())
+        .map(|url| url.trim().to_string())
+        .filter(|url| !url.is_empty())
+}
+
+// Platform key as used by Tauri updater manifests, e.g. "darwin-aarch64" or "windows-x86_64"
+fn platform_key() -> String {
+    let os = match std::env::consts::OS {
+        "macos" => "darwin",
+        os => os,
+    };
+    format!("{}-{}", os, std::env::consts::ARCH)
+}
+
+// "v1.2.3" or "1.2.3-beta.1" -> (1, 2, 3); pre-release and build suffixes are ignored
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let core = version.trim().trim_start_matches('v')
+        .split(['-', '+'])
+        .next()?;
+    let mut parts = core.split('.').map(|part| part.parse::<u64>());
+
+    let major = parts.next()?.ok()?;
+    let minor = parts.next().unwrap_or(Ok(0)).ok()?;
+    let patch = parts.next().unwrap_or(Ok(0)).ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some((major, minor, patch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_version_ignores_prefix_and_suffixes() {
+        assert_eq!(parse_version("v1.2.3"), Some((1, 2, 3)));
+        assert_eq!(parse_version(" 1.2.3-beta.1 "), Some((1, 2, 3)));
+        assert_eq!(parse_version("1.2.3+build.7"), Some((1, 2, 3)));
+    }
+
+    #[test]
+    fn parse_version_fills_missing_parts_with_zero() {
+        assert_eq!(parse_version("2"), Some((2, 0, 0)));
+        assert_eq!(parse_version("2.1"), Some((2, 1, 0)));
+    }
+
+    #[test]
+    fn parse_version_rejects_malformed_versions() {
+        assert_eq!(parse_version(""), None);
+        assert_eq!(parse_version("1.2.3.4"), None);
+        assert_eq!(parse_version("1.x.3"), None);
+        assert_eq!(parse_version("latest"), None);
+    }
+
+    #[test]
+    fn parsed_versions_compare_numerically() {
+        assert!(parse_version("1.10.0") > parse_version("1.9.9"));
+    }
+}
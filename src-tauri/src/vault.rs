@@ -0,0 +1,268 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::Engine;
+use serde_json::Value;
+use std::sync::Mutex;
+use tauri::command;
+
+// Keychain entry holding the store encryption key
+const KEYCHAIN_SERVICE: &str = "com.aura.app";
+const KEYCHAIN_ACCOUNT: &str = "store-encryption-key";
+// Holds the replacement key while a rotation is in progress
+const KEYCHAIN_PENDING_ACCOUNT: &str = "store-encryption-key-pending";
+
+// Prefix on sealed values, so a future format can be told apart
+const SEALED_PREFIX: &str = "v1:";
+
+const NONCE_LEN: usize = 12;
+
+// The key is read from the keychain once per launch
+static KEY_CACHE: Mutex<Option<[u8; 32]>> = Mutex::new(None);
+
+// Only one rotation may run at a time
+static ROTATION_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+/// Encrypt a store value with the vault key. The result is a string that only
+/// `open` with the same key turns back into the value. During a key rotation new
+/// data is sealed with the pending key, so it doesn't need re-encrypting afterwards.
+pub(crate) fn seal(value: &Value) -> Result<String, String> {
+    let key = match pending_key()? {
+        Some(pending) => pending,
+        None => vault_key()?,
+    };
+    seal_with_key(value, &key)
+}
+
+/// Decrypt a value produced by `seal`. While a key rotation is unfinished, stores it
+/// already re-encrypted are opened with the pending key.
+pub(crate) fn open(sealed: &str) -> Result<Value, String> {
+    match open_with_key(sealed, &vault_key()?) {
+        Ok(value) => Ok(value),
+        Err(e) => match pending_key()? {
+            Some(pending) => open_with_key(sealed, &pending),
+            None => Err(e),
+        },
+    }
+}
+
+/// Whether a string looks like the output of `seal`
+pub(crate) fn is_sealed(value: &str) -> bool {
+    value.starts_with(SEALED_PREFIX)
+}
+
+/// Replace the vault key with a fresh one and re-encrypt every encrypted store, backups
+/// included, e.g. after a suspected compromise. The new key is saved as pending before
+/// any store is touched, so if the app stops mid-rotation nothing becomes unreadable and
+/// the rotation is finished on the next launch. The signed-in user's saved KYC draft is
+/// re-encrypted first; if that fails the rotation stops before any store is touched.
+/// Returns the store files re-encrypted.
+#[command]
+pub async fn rotate_key(app: tauri::AppHandle) -> Result<Vec<String>, String> {
+    let _guard = ROTATION_LOCK.lock().await;
+
+    let current = vault_key()?;
+    // A pending key is left over from an interrupted rotation, so finish with it
+    let next = match pending_key()? {
+        Some(pending) => pending,
+        None => {
+            let key: [u8; 32] = Aes256Gcm::generate_key(OsRng).into();
+            save_key(KEYCHAIN_PENDING_ACCOUNT, &key)?;
+            key
+        },
+    };
+
+    crate::database::reseal_kyc_draft(&app, |sealed| reseal(sealed, &current, &next)).await?;
+    finish_rotation(current, next, &app)
+}
+
+/// Finish a key rotation that was interrupted, so the old key can be retired. The KYC
+/// draft is re-encrypted if someone is signed in; a draft that can't be is left to load
+/// as unavailable rather than holding up the rotation.
+pub fn resume_key_rotation(app: &tauri::AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        let _guard = ROTATION_LOCK.lock().await;
+        if let Ok(Some(next)) = pending_key() {
+            let current = match vault_key() {
+                Ok(current) => current,
+                Err(e) => {
+                    eprintln!("Failed to resume vault key rotation: {}", e);
+                    return;
+                },
+            };
+            if let Err(e) = crate::database::reseal_kyc_draft(&app, |sealed| reseal(sealed, &current, &next)).await {
+                eprintln!("Failed to re-encrypt the KYC draft while resuming vault key rotation: {}", e);
+            }
+            if let Err(e) = finish_rotation(current, next, &app) {
+                eprintln!("Failed to resume vault key rotation: {}", e);
+            }
+        }
+    });
+}
+
+// Re-seal each encrypted store with `next`, then make it the current key. Every step can
+// be repeated: stores that already open with `next` are skipped, and the pending key is
+// only removed once it has replaced the current one.
+fn finish_rotation(current: [u8; 32], next: [u8; 32], app: &tauri::AppHandle) -> Result<Vec<String>, String> {
+    let resealed = crate::enhanced_store::reseal_encrypted_stores(app, |sealed| reseal(sealed, &current, &next))?;
+
+    save_key(KEYCHAIN_ACCOUNT, &next)?;
+    if let Ok(mut cache) = KEY_CACHE.lock() {
+        *cache = Some(next);
+    }
+    match keychain_entry(KEYCHAIN_PENDING_ACCOUNT)?.delete_credential() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(resealed),
+        Err(e) => Err(format!("Rotated the vault key but failed to clear the pending key: {}", e)),
+    }
+}
+
+// A value sealed with `current` re-sealed with `next`, or None if it already opens with `next`
+fn reseal(sealed: &str, current: &[u8; 32], next: &[u8; 32]) -> Result<Option<String>, String> {
+    if open_with_key(sealed, next).is_ok() {
+        return Ok(None);
+    }
+    let value = open_with_key(sealed, current)?;
+    seal_with_key(&value, next).map(Some)
+}
+
+fn seal_with_key(value: &Value, key: &[u8; 32]) -> Result<String, String> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, value.to_string().as_bytes())
+        .map_err(|e| format!("Failed to encrypt store data: {}", e))?;
+
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(format!("{}{}", SEALED_PREFIX, base64::engine::general_purpose::STANDARD.encode(sealed)))
+}
+
+fn open_with_key(sealed: &str, key: &[u8; 32]) -> Result<Value, String> {
+    let encoded = sealed
+        .strip_prefix(SEALED_PREFIX)
+        .ok_or("Encrypted store data is in an unknown format")?;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| format!("Encrypted store data is not valid base64: {}", e))?;
+    if bytes.len() < NONCE_LEN {
+        return Err("Encrypted store data is truncated".to_string());
+    }
+
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Failed to decrypt store data: wrong key or tampered data".to_string())?;
+
+    serde_json::from_slice(&plaintext).map_err(|e| format!("Decrypted store data is not valid JSON: {}", e))
+}
+
+// The key lives in the OS keychain rather than next to the stores it protects,
+// and is created the first time anything is encrypted
+fn vault_key() -> Result<[u8; 32], String> {
+    let mut cache = KEY_CACHE.lock().map_err(|_| "Vault key lock poisoned")?;
+    if let Some(key) = *cache {
+        return Ok(key);
+    }
+
+    let key = match read_key(KEYCHAIN_ACCOUNT)? {
+        Some(key) => key,
+        None => {
+            let key: [u8; 32] = Aes256Gcm::generate_key(OsRng).into();
+            save_key(KEYCHAIN_ACCOUNT, &key)?;
+            key
+        },
+    };
+
+    *cache = Some(key);
+    Ok(key)
+}
+
+// The replacement key of an unfinished rotation, if there is one
+fn pending_key() -> Result<Option<[u8; 32]>, String> {
+    read_key(KEYCHAIN_PENDING_ACCOUNT)
+}
+
+fn read_key(account: &str) -> Result<Option<[u8; 32]>, String> {
+    match keychain_entry(account)?.get_password() {
+        Ok(encoded) => decode_key(&encoded).map(Some),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(format!("Failed to read the vault key from the keychain: {}", e)),
+    }
+}
+
+fn save_key(account: &str, key: &[u8; 32]) -> Result<(), String> {
+    keychain_entry(account)?
+        .set_password(&base64::engine::general_purpose::STANDARD.encode(key))
+        .map_err(|e| format!("Failed to save the vault key to the keychain: {}", e))
+}
+
+fn keychain_entry(account: &str) -> Result<keyring::Entry, String> {
+    // keyring has no Android backend and would silently fall back to an in-memory store,
+    // losing the key (and every encrypted store) on restart
+    if cfg!(target_os = "android") {
+        return Err("Encrypted stores are not supported on this platform".to_string());
+    }
+
+    keyring::Entry::new(KEYCHAIN_SERVICE, account)
+        .map_err(|e| format!("Failed to open the keychain: {}", e))
+}
+
+fn decode_key(encoded: &str) -> Result<[u8; 32], String> {
+    base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| "The vault key in the keychain is invalid".to_string())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const CURRENT: [u8; 32] = [1; 32];
+    const PENDING: [u8; 32] = [2; 32];
+
+    /// Keep vault keys in memory instead of the OS keychain, which tests can't reach. The
+    /// key created by the first seal is cached for the rest of the test run.
+    pub(crate) fn use_mock_keychain() {
+        keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+    }
+
+    #[test]
+    fn sealed_values_only_open_with_their_own_key() {
+        let value = serde_json::json!({"accountNumber": "000123456789"});
+
+        let sealed = seal_with_key(&value, &CURRENT).unwrap();
+        assert!(is_sealed(&sealed));
+        assert_eq!(open_with_key(&sealed, &CURRENT).unwrap(), value);
+        assert!(open_with_key(&sealed, &PENDING).is_err());
+
+        let resealed = reseal(&sealed, &CURRENT, &PENDING).unwrap().unwrap();
+        assert_eq!(open_with_key(&resealed, &PENDING).unwrap(), value);
+        assert!(open_with_key(&resealed, &CURRENT).is_err());
+    }
+
+    #[test]
+    fn interrupted_rotation_resumes_where_it_stopped() {
+        let values = [serde_json::json!("first"), serde_json::json!("second"), serde_json::json!("third")];
+        let mut sealed: Vec<String> = values.iter().map(|v| seal_with_key(v, &CURRENT).unwrap()).collect();
+
+        // The app stopped after the first value was re-sealed
+        sealed[0] = reseal(&sealed[0], &CURRENT, &PENDING).unwrap().unwrap();
+
+        // Resuming skips it and re-seals the rest
+        let resumed: Vec<Option<String>> = sealed.iter().map(|s| reseal(s, &CURRENT, &PENDING).unwrap()).collect();
+        assert!(resumed[0].is_none());
+        for (i, value) in values.iter().enumerate() {
+            let after = resumed[i].as_ref().unwrap_or(&sealed[i]);
+            assert_eq!(&open_with_key(after, &PENDING).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn value_sealed_with_neither_key_is_an_error() {
+        let sealed = seal_with_key(&serde_json::json!("x"), &[3; 32]).unwrap();
+        assert!(reseal(&sealed, &CURRENT, &PENDING).is_err());
+    }
+}
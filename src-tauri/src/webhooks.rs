@@ -0,0 +1,1125 @@
+use serde::{Deserialize, Serialize};
+use stripe::{Event, EventObject, EventType, Webhook};
+use std::str::FromStr;
+use tauri::Emitter;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookHandlingResult {
+    pub event_id: String,
+    pub event_type: String,
+    pub handled: bool,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentFailedNotice {
+    pub user_id: String,
+    pub invoice_id: String,
+    pub failure_reason: String,
+    pub attempt_count: u64,
+    pub next_payment_attempt: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapabilitiesUpdatedNotice {
+    pub contractor_id: String,
+    pub account_id: String,
+    pub capabilities: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrialWillEndNotice {
+    pub user_id: String,
+    pub subscription_id: String,
+    pub trial_end: Option<i64>,
+}
+
+/// Verify a Stripe webhook payload and dispatch it to the matching handler. Missing
+/// Stripe keys are reported as `not_configured` before anything is dispatched, since the
+/// handlers' own errors are plain strings.
+#[tauri::command]
+pub async fn handle_stripe_webhook(
+    payload: String,
+    signature: String,
+    app: tauri::AppHandle,
+) -> Result<WebhookHandlingResult, crate::stripe::StripeError> {
+    let webhook_secret = crate::stripe::get_stripe_key("STRIPE_WEBHOOK_SECRET")
+        .map_err(|_| crate::stripe::StripeError::not_configured("STRIPE_WEBHOOK_SECRET"))?;
+    crate::stripe::get_stripe_client()?;
+
+    let event = Webhook::construct_event(&payload, &signature, &webhook_secret)
+        .map_err(|e| crate::stripe::StripeError::InvalidRequest {
+            message: format!("Failed to verify webhook signature: {}", e),
+        })?;
+
+    Ok(dispatch_stripe_event(event, app).await?)
+}
+
+/// Route a verified Stripe event to its handler and record the outcome in the
+/// stripe_events audit log. The full payload is only kept when storage is opted in.
+pub async fn dispatch_stripe_event(
+    event: Event,
+    app: tauri::AppHandle,
+) -> Result<WebhookHandlingResult, String> {
+    let event_id = event.id.to_string();
+    let event_type = event_type_name(&event.type_);
+    let stripe_created = event.created;
+    let payload = if crate::database::webhook_payload_storage_enabled(&app) {
+        serde_json::to_value(&event).ok()
+    } else {
+        None
+    };
+
+    let result = route_stripe_event(event, &app).await;
+
+    let (status, message) = match &result {
+        Ok(result) if result.handled => ("handled", result.message.as_str()),
+        Ok(result) => ("ignored", result.message.as_str()),
+        Err(e) => ("failed", e.as_str()),
+    };
+    if status == "failed" {
+        crate::diagnostics::record_event("webhook", &format!("{} {} failed: {}", event_type, event_id, message));
+    }
+    // The audit log must never turn a processed event into a failed delivery
+    let _ = crate::database::record_webhook_event(
+        &event_id,
+        &event_type,
+        stripe_created,
+        status,
+        message,
+        payload,
+        &app,
+    ).await;
+
+    result
+}
+
+async fn route_stripe_event(
+    event: Event,
+    app: &tauri::AppHandle,
+) -> Result<WebhookHandlingResult, String> {
+    let event_id = event.id.to_string();
+    let event_type = event_type_name(&event.type_);
+    let connect_account = event.account.clone();
+
+    let handled = match (event.type_, event.data.object) {
+        (EventType::CheckoutSessionCompleted, EventObject::CheckoutSession(session)) => {
+            Some(handle_checkout_session_completed(session, app).await?)
+        },
+        (EventType::InvoicePaymentFailed, EventObject::Invoice(invoice)) => {
+            Some(handle_invoice_payment_failed(invoice, app).await?)
+        },
+        (EventType::ChargeDisputeCreated, EventObject::Dispute(dispute)) => {
+            Some(handle_charge_dispute_created(dispute, app).await?)
+        },
+        (EventType::CustomerSubscriptionUpdated, EventObject::Subscription(subscription)) => {
+            Some(handle_subscription_updated(subscription, app).await?)
+        },
+        (EventType::CustomerSubscriptionTrialWillEnd, EventObject::Subscription(subscription)) => {
+            Some(handle_subscription_trial_will_end(subscription, app).await?)
+        },
+        (EventType::PriceCreated | EventType::PriceUpdated, EventObject::Price(price)) => {
+            Some(handle_catalog_price_changed(price, app).await?)
+        },
+        (EventType::ProductCreated | EventType::ProductUpdated, EventObject::Product(product)) => {
+            Some(handle_catalog_product_changed(product, app).await?)
+        },
+        // stripe-rs parses the capability object as AccountCapabilities, which drops its
+        // id and status, so the handler re-reads them from the account instead
+        (EventType::CapabilityUpdated, _) => {
+            let account_id = connect_account
+                .ok_or("capability.updated event has no Connect account")?;
+            Some(handle_capability_updated(&account_id, app).await?)
+        },
+        _ => None,
+    };
+
+    Ok(match handled {
+        Some(message) => WebhookHandlingResult {
+            event_id,
+            event_type,
+            handled: true,
+            message,
+        },
+        None => WebhookHandlingResult {
+            message: format!("No handler registered for {}", event_type),
+            event_id,
+            event_type,
+            handled: false,
+        },
+    })
+}
+
+/// Finalize a hosted Checkout purchase for the user in the session's `user_id` metadata.
+/// Payment sessions are recorded as purchases and subscription sessions are synced onto
+/// the profile; each session is applied once, so redelivered events are acknowledged only.
+async fn handle_checkout_session_completed(
+    session: stripe::CheckoutSession,
+    app: &tauri::AppHandle,
+) -> Result<String, String> {
+    let user_id = session.metadata.as_ref()
+        .and_then(|metadata| metadata.get("user_id"))
+        .cloned()
+        .ok_or_else(|| format!("Checkout session {} has no user_id metadata", session.id))?;
+
+    if crate::database::is_checkout_session_processed(session.id.as_str(), app).await? {
+        return Ok(format!("Checkout session {} was already processed", session.id));
+    }
+
+    match session.mode {
+        stripe::CheckoutSessionMode::Payment => finalize_checkout_payment(session, &user_id, app).await,
+        stripe::CheckoutSessionMode::Subscription => finalize_checkout_subscription(session, &user_id, app).await,
+        mode => Ok(format!("Skipped {} mode checkout session {}", mode, session.id)),
+    }
+}
+
+/// Record one purchase row per unit bought, the same way cart payments are recorded
+async fn finalize_checkout_payment(
+    session: stripe::CheckoutSession,
+    user_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<String, String> {
+    // Delayed payment methods complete the session before the funds arrive
+    if session.payment_status != stripe::CheckoutSessionPaymentStatus::Paid {
+        return Ok(format!("Checkout session {} is not paid yet ({})", session.id, session.payment_status));
+    }
+
+    let payment_intent_id = match &session.payment_intent {
+        Some(stripe::Expandable::Id(id)) => id.to_string(),
+        Some(stripe::Expandable::Object(payment_intent)) => payment_intent.id.to_string(),
+        None => return Err(format!("Checkout session {} has no payment intent", session.id)),
+    };
+
+    // Webhook payloads never include line items, so re-read the session with them expanded
+    let client = crate::stripe::get_stripe_client()?;
+    let session = stripe::CheckoutSession::retrieve(&client, &session.id, &["line_items"])
+        .await
+        .map_err(|e| format!("Failed to retrieve checkout session: {}", e))?;
+
+    let line_items = session.line_items.map(|list| list.data).unwrap_or_default();
+    let units = checkout_line_units(&line_items)?;
+    let currency = session.currency
+        .unwrap_or_else(crate::stripe::default_currency)
+        .to_string();
+
+    for (line_item_index, (price_id, unit_amount)) in units.iter().enumerate() {
+        crate::stripe::record_purchase_line(
+            user_id.to_string(),
+            payment_intent_id.clone(),
+            price_id.clone(),
+            *unit_amount,
+            currency.clone(),
+            line_item_index as u32,
+            app.clone(),
+        ).await?;
+    }
+
+    crate::database::record_checkout_session(
+        session.id.as_str(),
+        user_id,
+        "payment",
+        Some(&payment_intent_id),
+        None,
+        app,
+    ).await?;
+
+    Ok(format!(
+        "Checkout session {} recorded {} purchase(s) for {}",
+        session.id, units.len(), user_id
+    ))
+}
+
+/// Price and unit amount of every unit in a Checkout Session, one entry per unit of quantity
+fn checkout_line_units(line_items: &[stripe::CheckoutSessionItem]) -> Result<Vec<(String, i64)>, String> {
+    let mut units = Vec::new();
+    for item in line_items {
+        let price = item.price.as_ref()
+            .ok_or_else(|| format!("Checkout line item {} has no price", item.id))?;
+        let quantity = item.quantity.unwrap_or(1).max(1);
+        let unit_amount = price.unit_amount.unwrap_or(item.amount_total / quantity as i64);
+        units.extend(std::iter::repeat_n((price.id.to_string(), unit_amount), quantity as usize));
+    }
+    Ok(units)
+}
+
+/// Store the subscription Checkout created on the profile: status, trial, plan and items
+async fn finalize_checkout_subscription(
+    session: stripe::CheckoutSession,
+    user_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<String, String> {
+    let subscription_id = match &session.subscription {
+        Some(stripe::Expandable::Id(id)) => id.clone(),
+        Some(stripe::Expandable::Object(subscription)) => subscription.id.clone(),
+        None => return Err(format!("Checkout session {} has no subscription", session.id)),
+    };
+
+    let client = crate::stripe::get_stripe_client()?;
+    let subscription = stripe::Subscription::retrieve(&client, &subscription_id, &[])
+        .await
+        .map_err(|e| format!("Failed to retrieve subscription: {}", e))?;
+
+    let customer_id = match &subscription.customer {
+        stripe::Expandable::Id(id) => id.to_string(),
+        stripe::Expandable::Object(customer) => customer.id.to_string(),
+    };
+
+    crate::database::update_subscription_status(
+        user_id.to_string(),
+        customer_id,
+        subscription.id.to_string(),
+        subscription.status.to_string(),
+        subscription.current_period_end,
+        app.clone(),
+    ).await?;
+    crate::database::update_subscription_trial_end(user_id, subscription.trial_end, app).await?;
+
+    let price_id = base_plan_price_id(&subscription);
+    if let Some(price_id) = &price_id {
+        crate::database::update_subscription_price(user_id, price_id, app).await?;
+    }
+    crate::database::update_subscription_items(
+        user_id,
+        serde_json::to_value(crate::stripe::subscription_item_responses(&subscription)).unwrap_or_default(),
+        app,
+    ).await?;
+
+    let _ = crate::database::record_subscription_event(
+        user_id,
+        subscription.id.as_str(),
+        "created",
+        None,
+        price_id.as_deref(),
+        app,
+    ).await;
+
+    crate::database::record_checkout_session(
+        session.id.as_str(),
+        user_id,
+        "subscription",
+        None,
+        Some(subscription.id.as_str()),
+        app,
+    ).await?;
+
+    Ok(format!(
+        "Checkout session {} started subscription {} ({}) for {}",
+        session.id, subscription.id, subscription.status, user_id
+    ))
+}
+
+/// Mirror a price created or edited in the Stripe dashboard into package_prices.
+/// Prices on products that were never seeded into the catalog are ignored. Stripe doesn't
+/// know how many tokens a price is worth, so a price without tokens configured is stored
+/// inactive and can't be bought until an admin sets its token_amount.
+async fn handle_catalog_price_changed(
+    price: stripe::Price,
+    app: &tauri::AppHandle,
+) -> Result<String, String> {
+    let stripe_product_id = match &price.product {
+        Some(stripe::Expandable::Id(id)) => id.to_string(),
+        Some(stripe::Expandable::Object(product)) => product.id.to_string(),
+        None => return Err(format!("Price {} has no associated product", price.id)),
+    };
+
+    let package_id = match crate::database::find_catalog_package_id(&stripe_product_id, app).await? {
+        Some(package_id) => package_id,
+        None => return Ok(format!("Skipped price {}: product {} is not in the catalog", price.id, stripe_product_id)),
+    };
+
+    let mut row = crate::stripe::package_price_row(&package_id, &price);
+    match crate::database::find_package_price_tokens(price.id.as_str(), app).await? {
+        Some(tokens) if tokens > 0 => {},
+        Some(_) => row["is_active"] = serde_json::json!(false),
+        None => {
+            row["token_amount"] = serde_json::json!(0);
+            row["is_active"] = serde_json::json!(false);
+        },
+    }
+    let (_, created) = crate::database::upsert_catalog_rows("package_prices", "stripe_price_id", vec![row], app).await?;
+
+    Ok(format!("{} price {} for product {}", if created > 0 { "Created" } else { "Updated" }, price.id, stripe_product_id))
+}
+
+/// Mirror name, description and active state changes on a catalog product into packages.
+/// Products that were never seeded into the catalog are ignored.
+async fn handle_catalog_product_changed(
+    product: stripe::Product,
+    app: &tauri::AppHandle,
+) -> Result<String, String> {
+    if crate::database::find_catalog_package_id(&product.id, app).await?.is_none() {
+        return Ok(format!("Skipped product {}: not in the catalog", product.id));
+    }
+
+    let row = serde_json::json!({
+        "stripe_product_id": product.id.to_string(),
+        "name": product.name,
+        "description": product.description,
+        "is_active": product.active.unwrap_or(true),
+        "updated_at": chrono::Utc::now().to_rfc3339()
+    });
+    crate::database::upsert_catalog_rows("packages", "stripe_product_id", vec![row], app).await?;
+
+    Ok(format!("Updated package for product {}", product.id))
+}
+
+/// Mirror the Connect account's capability statuses onto its contractor record.
+/// Capabilities change independently of the account (e.g. transfers going inactive
+/// while card_payments stays active), so the app can explain what's blocked.
+async fn handle_capability_updated(
+    account_id: &str,
+    app: &tauri::AppHandle,
+) -> Result<String, String> {
+    let client = crate::stripe::get_stripe_client()?;
+    let stripe_account_id = stripe::AccountId::from_str(account_id)
+        .map_err(|e| format!("Invalid account ID: {}", e))?;
+    let account = stripe::Account::retrieve(&client, &stripe_account_id, &[])
+        .await
+        .map_err(|e| format!("Failed to retrieve account: {}", e))?;
+
+    let capabilities = capability_statuses(&account.capabilities.unwrap_or_default());
+
+    let db_config = crate::database::get_authenticated_db(app).await.map_err(|e| {
+        format!("Failed to get database config: {}", e)
+    })?;
+
+    let response = crate::http::client()
+        .patch(format!("{}/rest/v1/contractors", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=representation")
+        .query(&[("stripe_connect_account_id", format!("eq.{}", account_id))])
+        .query(&[("select", "id")])
+        .json(&serde_json::json!({
+            "stripe_capabilities": capabilities,
+            "stripe_capabilities_updated_at": chrono::Utc::now().to_rfc3339(),
+            "updated_at": chrono::Utc::now().to_rfc3339()
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send contractor update request: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(crate::database::response_error(response, "contractors").await.into());
+    }
+
+    let contractors: Vec<serde_json::Value> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse contractor update response: {}", e))?;
+
+    let contractor_id = contractors.first()
+        .and_then(|c| c["id"].as_str())
+        .ok_or_else(|| format!("No contractor found for Connect account {}", account_id))?
+        .to_string();
+
+    let _ = app.emit("connect-capabilities-updated", CapabilitiesUpdatedNotice {
+        contractor_id: contractor_id.clone(),
+        account_id: account_id.to_string(),
+        capabilities: capabilities.clone(),
+    });
+
+    let summary = capabilities.iter()
+        .map(|(name, status)| format!("{}={}", name, status.as_str().unwrap_or("unknown")))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Ok(format!("Contractor {} capabilities updated: {}", contractor_id, summary))
+}
+
+/// The capabilities Aura requests for Connect accounts, keyed by name.
+/// Capabilities that haven't been requested are left out rather than reported as inactive.
+fn capability_statuses(capabilities: &stripe::AccountCapabilities) -> serde_json::Map<String, serde_json::Value> {
+    [
+        ("card_payments", capabilities.card_payments),
+        ("transfers", capabilities.transfers),
+    ]
+    .into_iter()
+    .filter_map(|(name, status)| {
+        status.map(|status| (name.to_string(), serde_json::Value::String(status.to_string())))
+    })
+    .collect()
+}
+
+// Stripe's EventType Display impl includes JSON quotes, so serialize it instead
+fn event_type_name(event_type: &EventType) -> String {
+    serde_json::to_value(event_type)
+        .ok()
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Flag the subscriber's profile as past_due when a renewal payment fails.
+/// Tokens are never credited here; the user keeps access until Stripe gives up retrying.
+async fn handle_invoice_payment_failed(
+    invoice: stripe::Invoice,
+    app: &tauri::AppHandle,
+) -> Result<String, String> {
+    let customer_id = match &invoice.customer {
+        Some(stripe::Expandable::Id(id)) => id.to_string(),
+        Some(stripe::Expandable::Object(customer)) => customer.id.to_string(),
+        None => return Err("Invoice has no associated customer".to_string()),
+    };
+
+    let failure_reason = invoice_failure_reason(&invoice).await;
+
+    let db_config = crate::database::get_authenticated_db(app).await.map_err(|e| {
+        format!("Failed to get database config: {}", e)
+    })?;
+
+    let http_client = crate::http::client();
+    let update_data = past_due_update(&failure_reason, &chrono::Utc::now().to_rfc3339());
+
+    let response = http_client
+        .patch(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=representation")
+        .query(&[("stripe_customer_id", format!("eq.{}", customer_id))])
+        .query(&[("select", "id")])
+        .json(&update_data)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send profile update request: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to flag profile as past_due: {} - {}", status, error_text));
+    }
+
+    let profiles: Vec<serde_json::Value> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse profile update response: {}", e))?;
+
+    let user_id = profiles.first()
+        .and_then(|p| p["id"].as_str())
+        .ok_or_else(|| format!("No profile found for Stripe customer {}", customer_id))?
+        .to_string();
+
+    // Let an open app show a banner before the subscription is downgraded
+    let _ = app.emit("subscription-payment-failed", PaymentFailedNotice {
+        user_id: user_id.clone(),
+        invoice_id: invoice.id.to_string(),
+        failure_reason: failure_reason.clone(),
+        attempt_count: invoice.attempt_count.unwrap_or(0),
+        next_payment_attempt: invoice.next_payment_attempt,
+    });
+
+    Ok(format!("Profile {} flagged as past_due: {}", user_id, failure_reason))
+}
+
+/// Mirror a subscription change made in Stripe (e.g. a plan switch in the Billing Portal)
+/// onto the subscriber's profile: status, period end, price, the plan it belongs to and
+/// every item on the subscription, add-ons included.
+async fn handle_subscription_updated(
+    subscription: stripe::Subscription,
+    app: &tauri::AppHandle,
+) -> Result<String, String> {
+    let customer_id = match &subscription.customer {
+        stripe::Expandable::Id(id) => id.to_string(),
+        stripe::Expandable::Object(customer) => customer.id.to_string(),
+    };
+
+    let mut update_data = serde_json::json!({
+        "subscription_id": subscription.id.to_string(),
+        "subscription_status": subscription.status.to_string(),
+        "subscription_period_end": subscription.current_period_end,
+        "updated_at": chrono::Utc::now().to_rfc3339()
+    });
+
+    // The plan comes from the base item; add-ons are stored with the other items below
+    let price_id = base_plan_price_id(&subscription);
+    if let Some(price_id) = &price_id {
+        let price_fields = crate::database::subscription_price_fields(price_id, app).await?;
+        if let (Some(update), Some(fields)) = (update_data.as_object_mut(), price_fields.as_object()) {
+            update.extend(fields.clone());
+        }
+    }
+
+    let db_config = crate::database::get_authenticated_db(app).await.map_err(|e| {
+        format!("Failed to get database config: {}", e)
+    })?;
+
+    // Read the current price before overwriting it so plan switches land in the history
+    let previous_price_id = crate::http::client()
+        .get(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Read, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .query(&[("stripe_customer_id", format!("eq.{}", customer_id))])
+        .query(&[("select", "subscription_price_id")])
+        .send()
+        .await
+        .ok()
+        .filter(|response| response.status().is_success());
+    let previous_price_id = match previous_price_id {
+        Some(response) => response.json::<Vec<serde_json::Value>>().await.ok()
+            .and_then(|profiles| profiles.first()?["subscription_price_id"].as_str().map(String::from)),
+        None => None,
+    };
+
+    let response = crate::http::client()
+        .patch(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=representation")
+        .query(&[("stripe_customer_id", format!("eq.{}", customer_id))])
+        .query(&[("select", "id")])
+        .json(&update_data)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send profile update request: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to update subscription on profile: {} - {}", status, error_text));
+    }
+
+    let profiles: Vec<serde_json::Value> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse profile update response: {}", e))?;
+
+    let user_id = profiles.first()
+        .and_then(|p| p["id"].as_str())
+        .ok_or_else(|| format!("No profile found for Stripe customer {}", customer_id))?;
+
+    // Add-ons can be added or removed without the plan changing
+    crate::database::update_subscription_items(
+        user_id,
+        serde_json::to_value(crate::stripe::subscription_item_responses(&subscription)).unwrap_or_default(),
+        app,
+    ).await?;
+
+    if let (Some(old_price_id), Some(new_price_id)) = (&previous_price_id, &price_id) {
+        if old_price_id != new_price_id {
+            let old_amount = crate::database::get_subscription_price(old_price_id, app).await.ok().flatten()
+                .map(|price| price.amount_cents);
+            let new_amount = crate::database::get_subscription_price(new_price_id, app).await.ok().flatten()
+                .map(|price| price.amount_cents);
+            let action = if new_amount >= old_amount { "upgraded" } else { "downgraded" };
+            let _ = crate::database::record_subscription_event(
+                user_id,
+                subscription.id.as_str(),
+                action,
+                Some(old_price_id),
+                Some(new_price_id),
+                app,
+            ).await;
+        }
+    }
+
+    Ok(format!(
+        "Profile {} subscription updated to {} on price {}",
+        user_id,
+        subscription.status,
+        price_id.as_deref().unwrap_or("unknown")
+    ))
+}
+
+/// Flag the subscriber's profile when Stripe warns that a trial ends soon (three days
+/// before, by default) so the app can prompt before the first charge.
+async fn handle_subscription_trial_will_end(
+    subscription: stripe::Subscription,
+    app: &tauri::AppHandle,
+) -> Result<String, String> {
+    let customer_id = match &subscription.customer {
+        stripe::Expandable::Id(id) => id.to_string(),
+        stripe::Expandable::Object(customer) => customer.id.to_string(),
+    };
+
+    let db_config = crate::database::get_authenticated_db(app).await.map_err(|e| {
+        format!("Failed to get database config: {}", e)
+    })?;
+
+    let http_client = crate::http::client();
+    let update_data = serde_json::json!({
+        "subscription_trial_end": subscription.trial_end,
+        "subscription_trial_ending": true,
+        "updated_at": chrono::Utc::now().to_rfc3339()
+    });
+
+    let response = http_client
+        .patch(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=representation")
+        .query(&[("stripe_customer_id", format!("eq.{}", customer_id))])
+        .query(&[("select", "id")])
+        .json(&update_data)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send profile update request: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to flag ending trial: {} - {}", status, error_text));
+    }
+
+    let profiles: Vec<serde_json::Value> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse profile update response: {}", e))?;
+
+    let user_id = profiles.first()
+        .and_then(|p| p["id"].as_str())
+        .ok_or_else(|| format!("No profile found for Stripe customer {}", customer_id))?
+        .to_string();
+
+    let _ = app.emit("subscription-trial-will-end", TrialWillEndNotice {
+        user_id: user_id.clone(),
+        subscription_id: subscription.id.to_string(),
+        trial_end: subscription.trial_end,
+    });
+
+    Ok(format!(
+        "Profile {} flagged as trial ending at {}",
+        user_id,
+        subscription.trial_end.map(|t| t.to_string()).unwrap_or_else(|| "unknown".to_string())
+    ))
+}
+
+// The base plan is the subscription's first item; any others are add-ons
+fn base_plan_price_id(subscription: &stripe::Subscription) -> Option<String> {
+    subscription.items.data.first()
+        .and_then(|item| item.price.as_ref())
+        .map(|price| price.id.to_string())
+}
+
+// The profile fields set when a renewal payment fails
+fn past_due_update(failure_reason: &str, now: &str) -> serde_json::Value {
+    serde_json::json!({
+        "subscription_status": "past_due",
+        "subscription_payment_failure_reason": failure_reason,
+        "subscription_payment_failed_at": now,
+        "updated_at": now
+    })
+}
+
+/// Best-effort human readable reason for a failed invoice payment
+async fn invoice_failure_reason(invoice: &stripe::Invoice) -> String {
+    let payment_error = match &invoice.payment_intent {
+        Some(stripe::Expandable::Object(payment_intent)) => payment_intent.last_payment_error.clone(),
+        Some(stripe::Expandable::Id(id)) => {
+            // The webhook payload only carries the id, so look up the payment error
+            match crate::stripe::get_stripe_client() {
+                Ok(client) => stripe::PaymentIntent::retrieve(&client, id, &[])
+                    .await
+                    .ok()
+                    .and_then(|pi| pi.last_payment_error),
+                Err(_) => None,
+            }
+        },
+        None => None,
+    };
+
+    failure_reason(payment_error.as_deref(), invoice)
+}
+
+// The payment error's message or decline code, then the invoice's finalization error
+fn failure_reason(payment_error: Option<&stripe::ApiErrors>, invoice: &stripe::Invoice) -> String {
+    payment_error
+        .and_then(|e| e.message.clone().or_else(|| e.decline_code.clone()))
+        .or_else(|| invoice.last_finalization_error.as_ref().and_then(|e| e.message.clone()))
+        .unwrap_or_else(|| "Payment failed".to_string())
+}
+
+fn dispute_charge_id(dispute: &stripe::Dispute) -> String {
+    match &dispute.charge {
+        stripe::Expandable::Id(id) => id.to_string(),
+        stripe::Expandable::Object(charge) => charge.id.to_string(),
+    }
+}
+
+// The payment intent when Stripe included it on the dispute; otherwise it has to be
+// looked up from the charge
+fn dispute_payment_intent_id(dispute: &stripe::Dispute) -> Option<String> {
+    match &dispute.payment_intent {
+        Some(stripe::Expandable::Id(id)) => Some(id.to_string()),
+        Some(stripe::Expandable::Object(payment_intent)) => Some(payment_intent.id.to_string()),
+        None => None,
+    }
+}
+
+// Every purchase row paid by the disputed payment intent, returning who it belongs to
+fn disputed_purchases_filter(payment_intent_id: &str) -> [(&'static str, String); 2] {
+    [
+        ("stripe_payment_intent_id", format!("eq.{}", payment_intent_id)),
+        ("select", "id,user_id".to_string()),
+    ]
+}
+
+fn disputed_purchase_update(now: &str) -> serde_json::Value {
+    serde_json::json!({
+        "status": "disputed",
+        "updated_at": now
+    })
+}
+
+// The disputes row, linked to the first of the purchases marked disputed
+fn dispute_row(
+    dispute: &stripe::Dispute,
+    charge_id: &str,
+    payment_intent_id: Option<&str>,
+    purchases: &[serde_json::Value],
+) -> serde_json::Value {
+    let purchase = purchases.first();
+    serde_json::json!({
+        "stripe_dispute_id": dispute.id.to_string(),
+        "stripe_charge_id": charge_id,
+        "stripe_payment_intent_id": payment_intent_id,
+        "purchase_id": purchase.and_then(|p| p["id"].as_str()),
+        "user_id": purchase.and_then(|p| p["user_id"].as_str()),
+        "amount": dispute.amount,
+        "currency": dispute.currency.to_string(),
+        "reason": dispute.reason,
+        "status": dispute.status.to_string()
+    })
+}
+
+/// Mark the disputed purchase, record the dispute and freeze the user's token spending
+/// until the dispute is resolved.
+async fn handle_charge_dispute_created(
+    dispute: stripe::Dispute,
+    app: &tauri::AppHandle,
+) -> Result<String, String> {
+    let charge_id = dispute_charge_id(&dispute);
+    let payment_intent_id = match dispute_payment_intent_id(&dispute) {
+        Some(payment_intent_id) => Some(payment_intent_id),
+        None => charge_payment_intent_id(&dispute.charge).await,
+    };
+
+    let db_config = crate::database::get_authenticated_db(app).await.map_err(|e| {
+        format!("Failed to get database config: {}", e)
+    })?;
+    let http_client = crate::http::client();
+
+    // Cart payments have several purchase rows per payment intent; all of them are disputed
+    let purchases: Vec<serde_json::Value> = match &payment_intent_id {
+        Some(payment_intent_id) => {
+            let response = http_client
+                .patch(format!("{}/rest/v1/purchases", db_config.database_url))
+                .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+                .header("Authorization", format!("Bearer {}", db_config.access_token))
+                .header("apikey", &db_config.anon_key)
+                .header("Content-Type", "application/json")
+                .header("Prefer", "return=representation")
+                .query(&disputed_purchases_filter(payment_intent_id))
+                .json(&disputed_purchase_update(&chrono::Utc::now().to_rfc3339()))
+                .send()
+                .await
+                .map_err(|e| format!("Failed to send purchase update request: {}", e))?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let error_text = response.text().await.unwrap_or_default();
+                return Err(format!("Failed to mark purchase as disputed: {} - {}", status, error_text));
+            }
+
+            response
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse purchase update response: {}", e))?
+        },
+        None => Vec::new(),
+    };
+
+    let user_id = purchases.first().and_then(|p| p["user_id"].as_str()).map(String::from);
+    let dispute_data = dispute_row(&dispute, &charge_id, payment_intent_id.as_deref(), &purchases);
+
+    // Upsert so a redelivered event doesn't fail on the unique dispute id
+    let response = http_client
+        .post(format!("{}/rest/v1/disputes", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=minimal,resolution=merge-duplicates")
+        .query(&[("on_conflict", "stripe_dispute_id")])
+        .json(&dispute_data)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send dispute insert request: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to record dispute: {} - {}", status, error_text));
+    }
+
+    let user_id = match user_id {
+        Some(user_id) => user_id,
+        None => return Ok(format!("Dispute {} recorded; no matching purchase found for charge {}", dispute.id, charge_id)),
+    };
+
+    let response = http_client
+        .patch(format!("{}/rest/v1/profiles", db_config.database_url))
+        .timeout(crate::http::timeout(crate::http::OperationClass::Write, app))
+        .header("Authorization", format!("Bearer {}", db_config.access_token))
+        .header("apikey", &db_config.anon_key)
+        .header("Content-Type", "application/json")
+        .header("Prefer", "return=minimal")
+        .query(&[("id", format!("eq.{}", user_id))])
+        .json(&serde_json::json!({
+            "tokens_frozen": true,
+            "updated_at": chrono::Utc::now().to_rfc3339()
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to send profile update request: {}", e))?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(format!("Failed to freeze token spending: {} - {}", status, error_text));
+    }
+
+    Ok(format!(
+        "Dispute {} recorded for {} purchase(s); token spending frozen for {}",
+        dispute.id, purchases.len(), user_id
+    ))
+}
+
+/// Look up the payment intent behind a charge when the dispute payload doesn't include it
+async fn charge_payment_intent_id(charge: &stripe::Expandable<stripe::Charge>) -> Option<String> {
+    let payment_intent = match charge {
+        stripe::Expandable::Object(charge) => charge.payment_intent.clone(),
+        stripe::Expandable::Id(id) => {
+            let client = crate::stripe::get_stripe_client().ok()?;
+            stripe::Charge::retrieve(&client, id, &[]).await.ok()?.payment_intent
+        },
+    };
+
+    payment_intent.map(|pi| match pi {
+        stripe::Expandable::Id(id) => id.to_string(),
+        stripe::Expandable::Object(payment_intent) => payment_intent.id.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line_item(price_id: &str, unit_amount: Option<i64>, quantity: Option<u64>, amount_total: i64) -> stripe::CheckoutSessionItem {
+        stripe::CheckoutSessionItem {
+            price: Some(stripe::Price {
+                id: price_id.parse().unwrap(),
+                unit_amount,
+                ..Default::default()
+            }),
+            quantity,
+            amount_total,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn each_unit_of_quantity_is_its_own_entry() {
+        let units = checkout_line_units(&[
+            line_item("price_small", Some(500), Some(2), 1000),
+            line_item("price_large", Some(2000), Some(1), 2000),
+        ])
+        .unwrap();
+        assert_eq!(units, vec![
+            ("price_small".to_string(), 500),
+            ("price_small".to_string(), 500),
+            ("price_large".to_string(), 2000),
+        ]);
+    }
+
+    #[test]
+    fn unit_amount_falls_back_to_the_line_total() {
+        let units = checkout_line_units(&[line_item("price_custom", None, Some(3), 1500)]).unwrap();
+        assert_eq!(units, vec![("price_custom".to_string(), 500); 3]);
+    }
+
+    #[test]
+    fn missing_quantity_counts_as_one() {
+        let units = checkout_line_units(&[line_item("price_small", Some(500), None, 500)]).unwrap();
+        assert_eq!(units.len(), 1);
+    }
+
+    #[test]
+    fn line_item_without_price_is_an_error() {
+        let item = stripe::CheckoutSessionItem::default();
+        assert!(checkout_line_units(&[item]).is_err());
+    }
+
+    // A charge.dispute.created payload, trimmed to the fields Stripe always sends
+    fn sample_dispute(payment_intent: Option<&str>) -> stripe::Dispute {
+        serde_json::from_value(serde_json::json!({
+            "id": "dp_1Ot9xQ2eZvKYlo2C",
+            "object": "dispute",
+            "amount": 1999,
+            "balance_transactions": [],
+            "charge": "ch_3Ot9xP2eZvKYlo2C",
+            "created": 1710000000,
+            "currency": "usd",
+            "evidence": {},
+            "evidence_details": {
+                "due_by": 1710864000,
+                "has_evidence": false,
+                "past_due": false,
+                "submission_count": 0
+            },
+            "is_charge_refundable": true,
+            "livemode": false,
+            "metadata": {},
+            "payment_intent": payment_intent,
+            "reason": "fraudulent",
+            "status": "needs_response"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn dispute_marks_every_purchase_of_its_payment_intent() {
+        let dispute = sample_dispute(Some("pi_3Ot9xP2eZvKYlo2C"));
+        let payment_intent_id = dispute_payment_intent_id(&dispute).unwrap();
+        assert_eq!(payment_intent_id, "pi_3Ot9xP2eZvKYlo2C");
+
+        let filter = disputed_purchases_filter(&payment_intent_id);
+        assert_eq!(filter[0], ("stripe_payment_intent_id", "eq.pi_3Ot9xP2eZvKYlo2C".to_string()));
+        assert_eq!(filter[1], ("select", "id,user_id".to_string()));
+
+        let update = disputed_purchase_update("2024-03-09T16:00:00Z");
+        assert_eq!(update["status"], "disputed");
+        assert_eq!(update.as_object().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn dispute_row_links_the_first_disputed_purchase() {
+        let dispute = sample_dispute(Some("pi_3Ot9xP2eZvKYlo2C"));
+        let purchases = vec![
+            serde_json::json!({ "id": "purchase_1", "user_id": "user_1" }),
+            serde_json::json!({ "id": "purchase_2", "user_id": "user_1" }),
+        ];
+
+        let row = dispute_row(&dispute, &dispute_charge_id(&dispute), Some("pi_3Ot9xP2eZvKYlo2C"), &purchases);
+        assert_eq!(row["stripe_dispute_id"], "dp_1Ot9xQ2eZvKYlo2C");
+        assert_eq!(row["stripe_charge_id"], "ch_3Ot9xP2eZvKYlo2C");
+        assert_eq!(row["purchase_id"], "purchase_1");
+        assert_eq!(row["user_id"], "user_1");
+        assert_eq!(row["amount"], 1999);
+        assert_eq!(row["reason"], "fraudulent");
+        assert_eq!(row["status"], "needs_response");
+    }
+
+    #[test]
+    fn dispute_without_payment_intent_needs_a_charge_lookup() {
+        let dispute = sample_dispute(None);
+        assert!(dispute_payment_intent_id(&dispute).is_none());
+
+        let row = dispute_row(&dispute, &dispute_charge_id(&dispute), None, &[]);
+        assert!(row["purchase_id"].is_null());
+        assert!(row["user_id"].is_null());
+    }
+
+    fn sample_invoice(finalization_error: Option<&str>) -> stripe::Invoice {
+        serde_json::from_value(serde_json::json!({
+            "id": "in_1Ot9xQ2eZvKYlo2C",
+            "object": "invoice",
+            "customer": "cus_PjB8nWQ4cq1Zx0",
+            "attempt_count": 2,
+            "next_payment_attempt": 1710086400,
+            "payment_intent": "pi_3Ot9xP2eZvKYlo2C",
+            "last_finalization_error": finalization_error.map(|message| serde_json::json!({
+                "message": message,
+                "type": "invalid_request_error"
+            }))
+        }))
+        .unwrap()
+    }
+
+    fn card_error(message: Option<&str>, decline_code: Option<&str>) -> stripe::ApiErrors {
+        stripe::ApiErrors {
+            message: message.map(String::from),
+            decline_code: decline_code.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn failure_reason_prefers_the_payment_error_message() {
+        let invoice = sample_invoice(Some("Tax location invalid"));
+        let error = card_error(Some("Your card has insufficient funds."), Some("insufficient_funds"));
+
+        assert_eq!(failure_reason(Some(&error), &invoice), "Your card has insufficient funds.");
+    }
+
+    #[test]
+    fn failure_reason_falls_back_to_the_decline_code_then_the_invoice() {
+        let invoice = sample_invoice(Some("Tax location invalid"));
+
+        assert_eq!(failure_reason(Some(&card_error(None, Some("expired_card"))), &invoice), "expired_card");
+        assert_eq!(failure_reason(Some(&card_error(None, None)), &invoice), "Tax location invalid");
+        assert_eq!(failure_reason(None, &invoice), "Tax location invalid");
+        assert_eq!(failure_reason(None, &sample_invoice(None)), "Payment failed");
+    }
+
+    #[test]
+    fn failed_payment_flags_the_profile_as_past_due() {
+        let invoice = sample_invoice(None);
+        let update = past_due_update(&failure_reason(None, &invoice), "2024-03-10T00:00:00+00:00");
+
+        assert_eq!(update, serde_json::json!({
+            "subscription_status": "past_due",
+            "subscription_payment_failure_reason": "Payment failed",
+            "subscription_payment_failed_at": "2024-03-10T00:00:00+00:00",
+            "updated_at": "2024-03-10T00:00:00+00:00"
+        }));
+    }
+
+    fn sample_subscription(items: serde_json::Value) -> stripe::Subscription {
+        serde_json::from_value(serde_json::json!({
+            "id": "sub_1Ot9xQ2eZvKYlo2C",
+            "object": "subscription",
+            "automatic_tax": {"enabled": false},
+            "billing_cycle_anchor": 1710000000,
+            "cancel_at_period_end": false,
+            "created": 1710000000,
+            "currency": "usd",
+            "current_period_end": 1712678400,
+            "current_period_start": 1710000000,
+            "customer": "cus_PjB8nWQ4cq1Zx0",
+            "items": {"object": "list", "data": items, "has_more": false, "url": "/v1/subscription_items"},
+            "livemode": false,
+            "metadata": {},
+            "start_date": 1710000000,
+            "status": "active"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn plan_comes_from_the_base_item_and_add_ons_are_kept() {
+        let subscription = sample_subscription(serde_json::json!([
+            {"id": "si_base", "object": "subscription_item", "price": {"id": "price_pro_monthly", "object": "price"}, "quantity": 1},
+            {"id": "si_seats", "object": "subscription_item", "price": {"id": "price_extra_seat", "object": "price"}, "quantity": 3}
+        ]));
+
+        assert_eq!(base_plan_price_id(&subscription).as_deref(), Some("price_pro_monthly"));
+
+        let items = crate::stripe::subscription_item_responses(&subscription);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].item_id, "si_seats");
+        assert_eq!(items[1].price_id, "price_extra_seat");
+        assert_eq!(items[1].quantity, Some(3));
+    }
+
+    #[test]
+    fn subscription_without_items_has_no_plan() {
+        assert!(base_plan_price_id(&sample_subscription(serde_json::json!([]))).is_none());
+    }
+}
@@ -1,6 +1,7 @@
 import { writable, derived } from 'svelte/store';
 import { invoke } from '@tauri-apps/api/core';
 import { get } from 'svelte/store';
+import { commandErrorKind, commandErrorMessage } from '$lib/utils';
 // Import centralizedAuth lazily to avoid initialization issues
 let centralizedAuth: any = null;
 
@@ -285,7 +286,7 @@ function createContractorStore() {
         update(state => ({ 
           ...state, 
           loading: false, 
-          error: `Initialization failed: ${error}`,
+          error: `Initialization failed: ${commandErrorMessage(error)}`,
           isInitialized: true,
         }));
       }
@@ -345,7 +346,9 @@ function createContractorStore() {
         // Update store to show save error to user
         update(state => ({ 
           ...state, 
-          error: `Failed to save form data: ${error}. Your progress may be lost.` 
+          error: commandErrorKind(error) === 'not_authenticated'
+            ? 'Your session has expired. Sign in again to keep saving your progress.'
+            : `Failed to save form data: ${commandErrorMessage(error)}. Your progress may be lost.`
         }));
       }
     },
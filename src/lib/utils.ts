@@ -11,3 +11,18 @@ export type WithoutChild<T> = T extends { child?: any } ? Omit<T, "child"> : T;
 export type WithoutChildren<T> = T extends { children?: any } ? Omit<T, "children"> : T;
 export type WithoutChildrenOrChild<T> = WithoutChildren<WithoutChild<T>>;
 export type WithElementRef<T, U extends HTMLElement = HTMLElement> = T & { ref?: U | null };
+
+// Commands reject with a typed error such as { kind: "forbidden", message }, or a plain string
+export function commandErrorMessage(error: unknown): string {
+	if (error && typeof error === "object" && "message" in error) {
+		return String((error as { message: unknown }).message);
+	}
+	return String(error);
+}
+
+export function commandErrorKind(error: unknown): string | undefined {
+	if (error && typeof error === "object" && "kind" in error) {
+		return String((error as { kind: unknown }).kind);
+	}
+	return undefined;
+}